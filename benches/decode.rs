@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use wasm_vm::binary::reader;
+
+fn push_var_u32(v:&mut Vec<u8>,mut n:u32){
+    loop {
+        let b = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            v.push(b);
+            return
+        }
+        v.push(b | 0x80);
+    }
+}
+
+fn push_sec(v:&mut Vec<u8>,id:u8,content:Vec<u8>){
+    v.push(id);
+    push_var_u32(v,content.len() as u32);
+    v.extend(content);
+}
+
+/// 生成一个大约2MB的模块,4000个函数,每个函数体500多个字节
+fn synthetic_module() -> Vec<u8>{
+    let func_count = 4000u32;
+    let mut v = vec![0x00,0x61,0x73,0x6d,0x01,0x00,0x00,0x00];
+    push_sec(&mut v,1,vec![0x01,0x60,0x00,0x00]);
+
+    let mut funcs = Vec::new();
+    push_var_u32(&mut funcs,func_count);
+    for _ in 0..func_count {
+        funcs.push(0x00);
+    }
+    push_sec(&mut v,3,funcs);
+
+    let mut body = vec![0x00];
+    for i in 0..170 {
+        body.extend_from_slice(&[0x41,(i % 64) as u8,0x1a]);
+    }
+    body.push(0x0b);
+    let mut code = Vec::new();
+    push_var_u32(&mut code,func_count);
+    for _ in 0..func_count {
+        push_var_u32(&mut code,body.len() as u32);
+        code.extend_from_slice(&body);
+    }
+    push_sec(&mut v,10,code);
+    v
+}
+
+fn decode(c:&mut Criterion){
+    let mut group = c.benchmark_group("decode");
+
+    let data = std::fs::read("./hw_rust.wasm").unwrap();
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("hw_rust",|b|b.iter(||reader::decode_bytes(&data).unwrap()));
+
+    // 先确认生成的模块能完整解码,免得测的是提前出错的路径
+    let data = synthetic_module();
+    assert!(data.len() > 2_000_000);
+    assert_eq!(reader::decode_bytes(&data).unwrap().code_sec.map(|v|v.len()),Some(4000));
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("synthetic",|b|b.iter(||reader::decode_bytes(&data).unwrap()));
+
+    group.finish();
+}
+
+criterion_group!(benches,decode);
+criterion_main!(benches);
@@ -0,0 +1,161 @@
+//! 0xFE前缀的原子指令
+//! 前缀后面的子操作码是leb128编码的u32,atomic.fence后面是一个保留的0字节,其余都带memarg
+
+use crate::binary::module::{VAL_TYPE_I32, VAL_TYPE_I64};
+
+pub const MemoryAtomicNotify:u32 = 0x00;
+pub const MemoryAtomicWait32:u32 = 0x01;
+pub const MemoryAtomicWait64:u32 = 0x02;
+pub const AtomicFence:u32 = 0x03;
+pub const I32AtomicLoad:u32 = 0x10;
+pub const I64AtomicLoad32U:u32 = 0x16;
+pub const I32AtomicStore:u32 = 0x17;
+pub const I64AtomicStore32:u32 = 0x1D;
+pub const I32AtomicRmwAdd:u32 = 0x1E;
+pub const I64AtomicRmw32CmpxchgU:u32 = 0x4E;
+
+/// 子操作码和指令名,按子操作码排列
+pub const ATOMIC_OPS:[(u32,&str);67] = [
+    (0x00,"memory.atomic.notify"),
+    (0x01,"memory.atomic.wait32"),
+    (0x02,"memory.atomic.wait64"),
+    (0x03,"atomic.fence"),
+    (0x10,"i32.atomic.load"),
+    (0x11,"i64.atomic.load"),
+    (0x12,"i32.atomic.load8_u"),
+    (0x13,"i32.atomic.load16_u"),
+    (0x14,"i64.atomic.load8_u"),
+    (0x15,"i64.atomic.load16_u"),
+    (0x16,"i64.atomic.load32_u"),
+    (0x17,"i32.atomic.store"),
+    (0x18,"i64.atomic.store"),
+    (0x19,"i32.atomic.store8"),
+    (0x1A,"i32.atomic.store16"),
+    (0x1B,"i64.atomic.store8"),
+    (0x1C,"i64.atomic.store16"),
+    (0x1D,"i64.atomic.store32"),
+    (0x1E,"i32.atomic.rmw.add"),
+    (0x1F,"i64.atomic.rmw.add"),
+    (0x20,"i32.atomic.rmw8.add_u"),
+    (0x21,"i32.atomic.rmw16.add_u"),
+    (0x22,"i64.atomic.rmw8.add_u"),
+    (0x23,"i64.atomic.rmw16.add_u"),
+    (0x24,"i64.atomic.rmw32.add_u"),
+    (0x25,"i32.atomic.rmw.sub"),
+    (0x26,"i64.atomic.rmw.sub"),
+    (0x27,"i32.atomic.rmw8.sub_u"),
+    (0x28,"i32.atomic.rmw16.sub_u"),
+    (0x29,"i64.atomic.rmw8.sub_u"),
+    (0x2A,"i64.atomic.rmw16.sub_u"),
+    (0x2B,"i64.atomic.rmw32.sub_u"),
+    (0x2C,"i32.atomic.rmw.and"),
+    (0x2D,"i64.atomic.rmw.and"),
+    (0x2E,"i32.atomic.rmw8.and_u"),
+    (0x2F,"i32.atomic.rmw16.and_u"),
+    (0x30,"i64.atomic.rmw8.and_u"),
+    (0x31,"i64.atomic.rmw16.and_u"),
+    (0x32,"i64.atomic.rmw32.and_u"),
+    (0x33,"i32.atomic.rmw.or"),
+    (0x34,"i64.atomic.rmw.or"),
+    (0x35,"i32.atomic.rmw8.or_u"),
+    (0x36,"i32.atomic.rmw16.or_u"),
+    (0x37,"i64.atomic.rmw8.or_u"),
+    (0x38,"i64.atomic.rmw16.or_u"),
+    (0x39,"i64.atomic.rmw32.or_u"),
+    (0x3A,"i32.atomic.rmw.xor"),
+    (0x3B,"i64.atomic.rmw.xor"),
+    (0x3C,"i32.atomic.rmw8.xor_u"),
+    (0x3D,"i32.atomic.rmw16.xor_u"),
+    (0x3E,"i64.atomic.rmw8.xor_u"),
+    (0x3F,"i64.atomic.rmw16.xor_u"),
+    (0x40,"i64.atomic.rmw32.xor_u"),
+    (0x41,"i32.atomic.rmw.xchg"),
+    (0x42,"i64.atomic.rmw.xchg"),
+    (0x43,"i32.atomic.rmw8.xchg_u"),
+    (0x44,"i32.atomic.rmw16.xchg_u"),
+    (0x45,"i64.atomic.rmw8.xchg_u"),
+    (0x46,"i64.atomic.rmw16.xchg_u"),
+    (0x47,"i64.atomic.rmw32.xchg_u"),
+    (0x48,"i32.atomic.rmw.cmpxchg"),
+    (0x49,"i64.atomic.rmw.cmpxchg"),
+    (0x4A,"i32.atomic.rmw8.cmpxchg_u"),
+    (0x4B,"i32.atomic.rmw16.cmpxchg_u"),
+    (0x4C,"i64.atomic.rmw8.cmpxchg_u"),
+    (0x4D,"i64.atomic.rmw16.cmpxchg_u"),
+    (0x4E,"i64.atomic.rmw32.cmpxchg_u"),
+];
+
+/// 读改写的运算,每种运算按 i32 i64 i32_8 i32_16 i64_8 i64_16 i64_32 排7条
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum RmwOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Xchg,
+    Cmpxchg,
+}
+
+pub fn name(sub:u32) -> Option<&'static str>{
+    ATOMIC_OPS.binary_search_by_key(&sub,|(op,_)|*op).ok().map(|i|ATOMIC_OPS[i].1)
+}
+
+pub fn sub_opcode(name:&str) -> Option<u32>{
+    ATOMIC_OPS.iter().find(|(_,n)|*n == name).map(|(op,_)|*op)
+}
+
+/// load/store/rmw在各自一组7条里的位置
+fn shape(sub:u32) -> usize{
+    match sub {
+        I32AtomicLoad..=I64AtomicRmw32CmpxchgU => ((sub - I32AtomicLoad) % 7) as usize,
+        _ => 0,
+    }
+}
+
+pub fn rmw_op(sub:u32) -> Option<RmwOp>{
+    if !(I32AtomicRmwAdd..=I64AtomicRmw32CmpxchgU).contains(&sub) {
+        return None;
+    }
+    let ops = [RmwOp::Add,RmwOp::Sub,RmwOp::And,RmwOp::Or,RmwOp::Xor,RmwOp::Xchg,RmwOp::Cmpxchg];
+    Some(ops[((sub - I32AtomicRmwAdd) / 7) as usize])
+}
+
+/// 访问的字节数,原子指令的对齐必须正好等于它
+pub fn mem_size(sub:u32) -> u32{
+    match sub {
+        MemoryAtomicNotify|MemoryAtomicWait32 => 4,
+        MemoryAtomicWait64 => 8,
+        AtomicFence => 0,
+        _ => [4,8,1,2,1,2,4][shape(sub)],
+    }
+}
+
+pub fn natural_align(sub:u32) -> u32{
+    mem_size(sub).trailing_zeros()
+}
+
+/// load/store/rmw操作的值类型
+pub fn val_type(sub:u32) -> u8{
+    match shape(sub) {
+        1|4|5|6 => VAL_TYPE_I64,
+        _ => VAL_TYPE_I32,
+    }
+}
+
+/// 指令的类型 (参数,结果)
+pub fn signature(sub:u32) -> (Vec<u8>,Vec<u8>){
+    const I:u8 = VAL_TYPE_I32;
+    const L:u8 = VAL_TYPE_I64;
+    let t = val_type(sub);
+    match sub {
+        MemoryAtomicNotify => (vec![I,I],vec![I]),
+        MemoryAtomicWait32 => (vec![I,I,L],vec![I]),
+        MemoryAtomicWait64 => (vec![I,L,L],vec![I]),
+        AtomicFence => (vec![],vec![]),
+        I32AtomicLoad..=I64AtomicLoad32U => (vec![I],vec![t]),
+        I32AtomicStore..=I64AtomicStore32 => (vec![I,t],vec![]),
+        _ if rmw_op(sub) == Some(RmwOp::Cmpxchg) => (vec![I,t,t],vec![t]),
+        _ => (vec![I,t],vec![t]),
+    }
+}
@@ -4,7 +4,10 @@ use std::convert::TryInto;
 use std::fmt::{Debug, Formatter};
 use std::fmt;
 use std::any::Any;
+use std::sync::{Arc, Weak};
 use crate::utils;
+use crate::binary::module::{BlockType, FuncType};
+use crate::binary::opcodes::{self, Opcode};
 
 #[derive(Clone, Debug)]
 pub enum ArgsEnum{
@@ -12,6 +15,19 @@ pub enum ArgsEnum{
     IfArgs(IfArgs),
     BrTableArgs(BrTableArgs),
     MemArg(MemArg),
+    PrefixArgs(PrefixArgs),
+    CallIndirectArgs(CallIndirectArgs),
+    /// SIMD的立即数比较大,装箱避免所有指令都变大
+    SimdArgs(Box<SimdArgs>),
+    AtomicArgs(AtomicArgs),
+    /// try_table的块类型,catch子句和块内指令
+    TryTableArgs(Box<TryTableArgs>),
+    /// 函数引用,None是ref.null func
+    FuncRef(Option<FuncRef>),
+    /// 外部引用,None是ref.null extern
+    ExternRef(Option<ExternRef>),
+    /// 异常引用,None是ref.null exn
+    ExnRef(Option<ExnRef>),
     Bool(bool),
     U8(u8),
     I8(i8),
@@ -23,6 +39,7 @@ pub enum ArgsEnum{
     I64(i64),
     F32(f32),
     F64(f64),
+    V128(u128),
     NONE,
 }
 
@@ -127,6 +144,13 @@ impl ArgsEnum{
         }
     }
 
+    pub fn get_br_table_args(&self) -> BrTableArgs{
+        match self {
+            ArgsEnum::BrTableArgs(v) => {v.clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
     pub fn get_mem_args(&self) -> MemArg{
         match self {
             ArgsEnum::MemArg(v) => {v.clone()}
@@ -134,12 +158,94 @@ impl ArgsEnum{
         }
     }
 
+    pub fn get_prefix_args(&self) -> PrefixArgs{
+        match self {
+            ArgsEnum::PrefixArgs(v) => {v.clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    pub fn get_call_indirect_args(&self) -> CallIndirectArgs{
+        match self {
+            ArgsEnum::CallIndirectArgs(v) => {v.clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    pub fn get_simd_args(&self) -> SimdArgs{
+        match self {
+            ArgsEnum::SimdArgs(v) => {(**v).clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    pub fn get_atomic_args(&self) -> AtomicArgs{
+        match self {
+            ArgsEnum::AtomicArgs(v) => {v.clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    pub fn get_try_table_args(&self) -> TryTableArgs{
+        match self {
+            ArgsEnum::TryTableArgs(v) => {(**v).clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    pub fn get_v128(&self) -> u128{
+        match self {
+            ArgsEnum::V128(v) => {*v}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    pub fn get_func_ref(&self) -> Option<FuncRef>{
+        match self {
+            ArgsEnum::FuncRef(v) => {v.clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    pub fn get_extern_ref(&self) -> Option<ExternRef>{
+        match self {
+            ArgsEnum::ExternRef(v) => {v.clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    pub fn get_exn_ref(&self) -> Option<ExnRef>{
+        match self {
+            ArgsEnum::ExnRef(v) => {v.clone()}
+            v => {panic!("{:?}",v)}
+        }
+    }
+
+    /// 引用是不是null,不是引用时返回None
+    pub fn is_null_ref(&self) -> Option<bool>{
+        match self {
+            ArgsEnum::FuncRef(v) => Some(v.is_none()),
+            ArgsEnum::ExternRef(v) => Some(v.is_none()),
+            ArgsEnum::ExnRef(v) => Some(v.is_none()),
+            _ => None,
+        }
+    }
+
     pub fn get_type(&self) -> &str{
         match self {
             ArgsEnum::BlockArgs(_) => {"BlockArgs"}
             ArgsEnum::IfArgs(_) => {"IfArgs"}
             ArgsEnum::BrTableArgs(_) => {"BrTableArgs"}
             ArgsEnum::MemArg(_) => {"MemArg"}
+            ArgsEnum::PrefixArgs(_) => {"PrefixArgs"}
+            ArgsEnum::CallIndirectArgs(_) => {"CallIndirectArgs"}
+            ArgsEnum::SimdArgs(_) => {"SimdArgs"}
+            ArgsEnum::AtomicArgs(_) => {"AtomicArgs"}
+            ArgsEnum::TryTableArgs(_) => {"TryTableArgs"}
+            ArgsEnum::V128(_) => {"V128"}
+            ArgsEnum::FuncRef(_) => {"FuncRef"}
+            ArgsEnum::ExternRef(_) => {"ExternRef"}
+            ArgsEnum::ExnRef(_) => {"ExnRef"}
             ArgsEnum::Bool(_) => {"Bool"}
             ArgsEnum::U8(_) => {"U8"}
             ArgsEnum::U32(_) => {"U32"}
@@ -207,6 +313,28 @@ impl PartialEq for ArgsEnum{
                     if self.get_u16().eq(&other.get_u16()) {true }
                     else{false}
                 }
+                "PrefixArgs" => {
+                    let (a,b) = (self.get_prefix_args(),other.get_prefix_args());
+                    a.idx == b.idx && a.idx2 == b.idx2
+                }
+                "CallIndirectArgs" => {
+                    let (a,b) = (self.get_call_indirect_args(),other.get_call_indirect_args());
+                    a.type_idx == b.type_idx && a.table == b.table
+                }
+                "SimdArgs" => {
+                    let (a,b) = (self.get_simd_args(),other.get_simd_args());
+                    let mem_arg = |m:Option<self::MemArg>|m.map(|m|(m.align,m.offset,m.mem));
+                    a.lane == b.lane && a.v128 == b.v128 && mem_arg(a.mem_arg) == mem_arg(b.mem_arg)
+                }
+                "AtomicArgs" => {
+                    let (a,b) = (self.get_atomic_args(),other.get_atomic_args());
+                    let mem_arg = |m:Option<self::MemArg>|m.map(|m|(m.align,m.offset,m.mem));
+                    mem_arg(a.mem_arg) == mem_arg(b.mem_arg)
+                }
+                "V128" => self.get_v128() == other.get_v128(),
+                "FuncRef" => self.get_func_ref() == other.get_func_ref(),
+                "ExternRef" => self.get_extern_ref() == other.get_extern_ref(),
+                "ExnRef" => self.get_exn_ref() == other.get_exn_ref(),
                 "None" =>{
                     true
                 }
@@ -223,16 +351,18 @@ pub type Expr = Vec<Instruction>;
 #[derive(Clone, Debug)]
 pub struct Instruction  {
     pub opcode:Option<u8>,
+    /// 前缀指令的子操作码,单字节指令是0
+    pub sub:u32,
     pub args:Option<ArgsEnum>,
 }
 #[derive(Clone, Debug)]
 pub struct BlockArgs{
-    pub bt:Option<i32>,
+    pub bt:Option<BlockType>,
     pub instrs:Option<Vec<Instruction>>
 }
 #[derive(Clone, Debug)]
 pub struct IfArgs{
-    pub bt:Option<i32>,
+    pub bt:Option<BlockType>,
     pub instrs1:Option<Vec<Instruction>>,
     pub instrs2:Option<Vec<Instruction>>,
 }
@@ -241,36 +371,233 @@ pub struct BrTableArgs{
     pub labels:Option<Vec<u32>>,
     pub default:Option<u32>,
 }
+/// mem是内存索引,二进制里对齐标志的第6位置1时后面跟着内存索引
 #[derive(Clone, Debug)]
 pub struct MemArg{
     pub align:Option<u32>,
-    pub offset:Option<u32>,
+    pub offset:Option<u64>,
+    pub mem:Option<u32>,
+}
+pub const MEM_ARG_HAS_MEM:u32 = 0x40;
+/// 0xFC前缀的指令的立即数,饱和截断没有索引
+/// memory.init/data.drop的idx是数据段索引,memory.copy/memory.fill的idx是内存索引
+/// idx2是第二个索引: memory.init的内存索引,memory.copy的源内存索引
+#[derive(Clone, Debug)]
+pub struct PrefixArgs{
+    pub idx:Option<u32>,
+    pub idx2:Option<u32>,
+}
+/// 0xFD前缀的SIMD指令的立即数
+/// v128是v128.const的值,i8x16.shuffle的16个车道下标也按小端放在这里
+#[derive(Clone, Debug)]
+pub struct SimdArgs{
+    pub mem_arg:Option<MemArg>,
+    pub lane:Option<u8>,
+    pub v128:Option<u128>,
+}
+/// 0xFE前缀的原子指令的立即数,atomic.fence没有memarg
+#[derive(Clone, Debug)]
+pub struct AtomicArgs{
+    pub mem_arg:Option<MemArg>,
+}
+/// try_table的立即数,catches按顺序匹配
+#[derive(Clone, Debug)]
+pub struct TryTableArgs{
+    pub bt:Option<BlockType>,
+    pub catches:Option<Vec<Catch>>,
+    pub instrs:Option<Vec<Instruction>>,
+}
+/// catch子句,kind见CATCH_*,catch_all/catch_all_ref没有标签索引
+/// label 是从try_table外面算起的跳转标签
+#[derive(Clone, Debug, PartialEq)]
+pub struct Catch{
+    pub kind:u8,
+    pub tag:Option<u32>,
+    pub label:u32,
+}
+
+pub const CATCH:u8 = 0x00;
+pub const CATCH_REF:u8 = 0x01;
+pub const CATCH_ALL:u8 = 0x02;
+pub const CATCH_ALL_REF:u8 = 0x03;
+
+impl Catch {
+    /// 跳转时是否带上异常引用
+    pub fn is_ref(&self) -> bool{
+        self.kind == CATCH_REF || self.kind == CATCH_ALL_REF
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self.kind {
+            CATCH => "catch",
+            CATCH_REF => "catch_ref",
+            CATCH_ALL => "catch_all",
+            _ => "catch_all_ref",
+        }
+    }
+}
+/// call_indirect的类型索引和表索引
+#[derive(Clone, Debug)]
+pub struct CallIndirectArgs{
+    pub type_idx:Option<u32>,
+    pub table:Option<u32>,
+}
+
+/// 函数引用,记着函数是哪个实例的
+/// 表和全局变量可以在实例之间共享,引用放进别的实例的表里,调用的还是原来实例的函数
+/// 只拿着实例的弱引用,实例自己的表里存着自己的函数时不会互相拿着不放,实例释放后就不能再调用
+#[derive(Clone)]
+pub struct FuncRef{
+    idx:u32,
+    owner:Weak<dyn Any + Send + Sync>,
+}
+
+impl FuncRef {
+    pub fn new(idx:u32,owner:Weak<dyn Any + Send + Sync>) -> FuncRef{
+        FuncRef{ idx, owner }
+    }
+
+    /// 在所属实例里的函数索引
+    pub fn idx(&self) -> u32{
+        self.idx
+    }
+
+    /// 所属的实例,已经释放或者不是T时返回None
+    pub fn owner<T:Any + Send + Sync>(&self) -> Option<Arc<T>>{
+        self.owner.upgrade()?.downcast::<T>().ok()
+    }
+}
+
+impl Debug for FuncRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f,"FuncRef({},{:p})",self.idx,self.owner.as_ptr())
+    }
+}
+
+impl PartialEq for FuncRef {
+    fn eq(&self, other: &Self) -> bool {
+        self.idx == other.idx && Weak::ptr_eq(&self.owner,&other.owner)
+    }
+}
+
+/// 宿主传给模块的不透明对象,模块里只能传递和比较,不能查看内容
+/// 两个外部引用相等当且仅当指向同一个对象
+#[derive(Clone)]
+pub struct ExternRef(Arc<dyn Any + Send + Sync>);
+
+impl ExternRef {
+    pub fn new<T:Any + Send + Sync>(v:T) -> ExternRef{
+        ExternRef(Arc::new(v))
+    }
+
+    pub fn downcast_ref<T:Any>(&self) -> Option<&T>{
+        self.0.downcast_ref::<T>()
+    }
+}
+
+impl Debug for ExternRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f,"ExternRef({:p})",Arc::as_ptr(&self.0))
+    }
+}
+
+impl PartialEq for ExternRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0,&other.0)
+    }
+}
+
+/// 运行时的异常标签,每个定义或导入的标签各一个
+/// 两个标签相同当且仅当是同一个对象,类型一样的两个标签也不相同
+#[derive(Clone)]
+pub struct Tag(Arc<FuncType>);
+
+impl Tag {
+    pub fn new(ft:FuncType) -> Tag{
+        Tag(Arc::new(ft))
+    }
+
+    /// 参数就是异常携带的值的类型
+    pub fn func_type(&self) -> &FuncType{
+        &self.0
+    }
+}
+
+impl Debug for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f,"Tag({:p})",Arc::as_ptr(&self.0))
+    }
+}
+
+impl PartialEq for Tag {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0,&other.0)
+    }
+}
+
+/// 抛出的异常,标签加上携带的值
+#[derive(Debug)]
+pub struct Exception{
+    pub tag:Tag,
+    pub values:Vec<ArgsEnum>,
+}
+
+/// 异常引用,throw_ref重新抛出的还是同一个异常
+#[derive(Clone, Debug)]
+pub struct ExnRef(Arc<Exception>);
+
+impl ExnRef {
+    pub fn new(tag:Tag,values:Vec<ArgsEnum>) -> ExnRef{
+        ExnRef(Arc::new(Exception{ tag, values }))
+    }
+
+    pub fn tag(&self) -> &Tag{
+        &self.0.tag
+    }
+
+    pub fn values(&self) -> &[ArgsEnum]{
+        &self.0.values
+    }
+}
+
+impl PartialEq for ExnRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0,&other.0)
+    }
 }
 
 impl Instruction{
 
     pub fn new(opcode:u8,data:ArgsEnum) -> Instruction{
-        println!("{:?}",data.get_block_args());
         Instruction{
             opcode: Some(opcode),
+            sub: 0,
+            args: Some(data),
+        }
+
+    }
+
+    /// 0xFC/0xFD/0xFE前缀的指令
+    pub fn prefixed(prefix:u8,sub:u32,data:ArgsEnum) -> Instruction{
+        Instruction{
+            opcode: Some(prefix),
+            sub,
             args: Some(data),
         }
+    }
 
+    /// 完整的操作码
+    pub fn op(&self) -> Opcode{
+        let opcode = self.opcode.expect("the Instruction opcode is none");
+        Opcode::new(opcode,self.sub)
     }
 
     pub fn get_op_name(&self) -> &str{
-        match crate::binary::opcodes::OPCODE_MAP.get(){
-            None => {panic!("get opcode map none")}
-            Some(map) => {
-                match self.opcode {
-                    None => {panic!("the Instruction opcode is none")}
-                    Some(o) => {
-                        let v = map.get(&o).unwrap();
-                        v
-                    }
-                }
-            }
+        if opcodes::OPCODE_MAP.get().is_none() {
+            panic!("get opcode map none")
         }
+        let op = self.op();
+        opcodes::name(op).unwrap_or_else(||panic!("unknown opcode {}",op))
     }
 }
 
@@ -285,4 +612,30 @@ mod test {
         let i = binary::instruction::Instruction::new(0x02,ArgsEnum::BlockArgs(BlockArgs{ bt: None, instrs: None }));
         println!("{:?}",i.get_op_name());
     }
+
+    #[test]
+    fn test2(){
+        use crate::binary::{self, atomic, opcodes, simd};
+        use crate::binary::instruction::{Instruction, PrefixArgs, SimdArgs};
+        use crate::binary::opcodes::Opcode;
+
+        // 前缀指令的完整操作码是(前缀,子操作码),名字和操作码可以互查
+        binary::init();
+        let i = Instruction::prefixed(opcodes::MiscPrefix,opcodes::MemoryCopy,ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(0), idx2: Some(0) }));
+        assert_eq!(i.op(),Opcode::new(opcodes::MiscPrefix,opcodes::MemoryCopy));
+        assert_eq!(i.get_op_name(),"memory.copy");
+        let i = Instruction::prefixed(opcodes::SimdPrefix,simd::I8x16Splat,ArgsEnum::SimdArgs(Box::new(SimdArgs{ mem_arg: None, lane: None, v128: None })));
+        assert_eq!((i.op().to_string().as_str(),i.get_op_name()),("0xfd 15","i8x16.splat"));
+        let i = Instruction{ opcode: Some(opcodes::I32Add), sub: 0, args: None };
+        assert_eq!((i.op(),i.op().to_string()),(Opcode::byte(opcodes::I32Add),"0x6a".to_string()));
+
+        assert_eq!(opcodes::by_name("i64.trunc_sat_f64_u"),Some(Opcode::new(opcodes::MiscPrefix,opcodes::I64TruncSatF64U)));
+        assert_eq!(opcodes::by_name("i32.atomic.rmw.add"),Some(Opcode::new(opcodes::AtomicPrefix,atomic::I32AtomicRmwAdd)));
+        assert_eq!(opcodes::by_name("select"),Some(Opcode::byte(opcodes::Select)));
+        assert_eq!(opcodes::name(Opcode::new(opcodes::SimdPrefix,simd::V128Load64Zero)),Some("v128.load64_zero"));
+        // 子操作码相同但前缀不同是不同的指令
+        assert_eq!(opcodes::name(Opcode::new(opcodes::AtomicPrefix,simd::V128Load64Zero)),None);
+        assert_eq!(opcodes::name(Opcode::new(opcodes::MiscPrefix,0x0C)),None);
+        assert_eq!(opcodes::by_name("trunc_sat"),None);
+    }
 }
\ No newline at end of file
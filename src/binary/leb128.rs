@@ -2,7 +2,8 @@ use crate::common::common_error::CommonError;
 use anyhow::Context;
 
 /// 解码是高左低右
-pub fn decode_var_uint(data:&[u8],size:u8) -> anyhow::Result<(u64,usize),&'static str>{
+/// 数据读完了还没遇到结束字节时返回UnexpectedEof
+pub fn decode_var_uint(data:&[u8],size:u8) -> Result<(u64,usize),CommonError>{
     let mut result = 0u64;
     let mut count= 0u8;
 
@@ -11,23 +12,26 @@ pub fn decode_var_uint(data:&[u8],size:u8) -> anyhow::Result<(u64,usize),&'stati
         if count == size/7 {
             //如果最后一个字节(32位是5,64位是9)的最高单字节不是0,报错
             if d & 0x80 != 0 {
-                return Err("leb128 err");
+                return Err(CommonError::Leb128Error("integer representation too long".to_string()));
+            }
+            //最后一个字节里超出size的位必须是0
+            if (d as u32) >> (size - count*7) != 0 {
+                return Err(CommonError::Leb128Error("integer too large".to_string()));
             }
-
         }
         //data中,下标小的是低位,因此越往后的越要左移下标*7位
         result = result | ((d as u64) & 0x7f)<<(count * 7);
         count=count+1;
         if d&0x80 == 0 {
-            break;
+            return Ok((result,count as usize));
         }
 
     }
 
-    Ok((result,count as usize))
+    Err(CommonError::UnexpectedEof)
 }
 
-pub fn decode_var_int(data:&[u8], size:u8) -> anyhow::Result<(i64,usize),&'static str>{
+pub fn decode_var_int(data:&[u8], size:u8) -> Result<(i64,usize),CommonError>{
     let mut result = 0i64;
     let mut count= 0u8;
 
@@ -36,23 +40,57 @@ pub fn decode_var_int(data:&[u8], size:u8) -> anyhow::Result<(i64,usize),&'stati
         if count == size/7 {
             //如果最后一个字节(32位是5,64位是9)的最高单字节不是0,报错
             if d & 0x80 != 0 {
-                return Err("leb128 err");
+                return Err(CommonError::Leb128Error("integer representation too long".to_string()));
+            }
+            //最后一个字节里超出size的位必须和符号位一致
+            let mask = (0x7f_u8 << (size - count*7 - 1)) & 0x7f;
+            if d & mask != 0 && d & mask != mask {
+                return Err(CommonError::Leb128Error("integer too large".to_string()));
             }
-
         }
         //data中,下标小的是低位,因此越往后的越要左移下标*7位
         result = result | ((d as i64) & 0x7f)<<(count * 7);
         count=count+1;
         if datum&0x80 == 0 {
             //负数
-            if ((count-1)*7<size) && (d&0x40 != 0) {
+            if (count*7 < 64) && (d&0x40 != 0) {
                 result = result | (-1<<(count * 7));
             }
-            break;
+            return Ok((result,count as usize));
         }
     }
 
-    Ok((result,count as usize))
+    Err(CommonError::UnexpectedEof)
+}
+
+/// 编码是低位在前,每个字节7位,最高位表示后面还有没有字节
+pub fn encode_var_uint(v:u64) -> Vec<u8>{
+    let mut v = v;
+    let mut buf = Vec::new();
+    loop {
+        let b = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf.push(b);
+            return buf;
+        }
+        buf.push(b | 0x80);
+    }
+}
+
+/// 有符号数要等剩下的位全是符号位,并且当前字节的0x40和符号一致才能结束
+pub fn encode_var_int(v:i64) -> Vec<u8>{
+    let mut v = v;
+    let mut buf = Vec::new();
+    loop {
+        let b = (v & 0x7f) as u8;
+        v >>= 7;
+        if (v == 0 && b & 0x40 == 0) || (v == -1 && b & 0x40 != 0) {
+            buf.push(b);
+            return buf;
+        }
+        buf.push(b | 0x80);
+    }
 }
 
 #[cfg(test)]
@@ -70,4 +108,35 @@ mod test {
         let o = crate::binary::leb128::decode_var_int(v.as_slice(),32);
         println!("{:?}",o);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test3(){
+        use crate::binary::leb128::{decode_var_uint,decode_var_int};
+        use crate::common::common_error::CommonError;
+
+        // 没有结束字节
+        assert_eq!(decode_var_uint(&[0x80,0x80],32),Err(CommonError::UnexpectedEof));
+        assert_eq!(decode_var_int(&[0xff],32),Err(CommonError::UnexpectedEof));
+        // 超过5个字节
+        assert!(decode_var_uint(&[0x80,0x80,0x80,0x80,0x80,0x00],32).is_err());
+        // 第5个字节超出32位
+        assert!(decode_var_uint(&[0xff,0xff,0xff,0xff,0x1f],32).is_err());
+        assert_eq!(decode_var_uint(&[0xff,0xff,0xff,0xff,0x0f],32),Ok((u32::MAX as u64,5)));
+        assert!(decode_var_int(&[0xff,0xff,0xff,0xff,0x4f],32).is_err());
+        assert_eq!(decode_var_int(&[0x80,0x80,0x80,0x80,0x78],32),Ok((i32::MIN as i64,5)));
+        assert_eq!(decode_var_int(&[0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0x7f],64),Ok((-1,10)));
+    }
+
+    #[test]
+    fn test4(){
+        use crate::binary::leb128::{decode_var_int, decode_var_uint, encode_var_int, encode_var_uint};
+        assert_eq!(encode_var_uint(10000),vec![144,78]);
+        assert_eq!(encode_var_int(-1000),vec![0x98,0x78]);
+        for v in [0u64,1,127,128,624485,u32::MAX as u64,u64::MAX].iter() {
+            assert_eq!(decode_var_uint(&encode_var_uint(*v),64).unwrap().0,*v);
+        }
+        for v in [0i64,-1,63,64,-64,-65,-123456,i32::MIN as i64,i32::MAX as i64,i64::MIN,i64::MAX].iter() {
+            assert_eq!(decode_var_int(&encode_var_int(*v),64).unwrap().0,*v);
+        }
+    }
+}
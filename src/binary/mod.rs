@@ -3,6 +3,10 @@ pub mod leb128;
 pub mod reader;
 pub mod opcodes;
 pub mod instruction;
+pub mod stream;
+pub mod writer;
+pub mod simd;
+pub mod atomic;
 
 pub fn init(){
     opcodes::init();
@@ -1,4 +1,6 @@
-use crate::binary::instruction;
+use crate::binary::{instruction, opcodes, reader};
+use crate::common::common_error::{DecodeError, DecodeErrorKind};
+use once_cell::sync::OnceCell;
 use std::sync::Arc;
 
 /**
@@ -77,11 +79,14 @@ pub const IMPORT_TAG_FUNC:u8 = 0;
 pub const IMPORT_TAG_TABLE:u8 = 1;
 pub const IMPORT_TAG_MEM:u8 = 2;
 pub const IMPORT_TAG_GLOBAL:u8 = 3;
+/// 异常处理提案里的异常标签
+pub const IMPORT_TAG_TAG:u8 = 4;
 
 pub const EXPORT_TAG_FUNC:u8 = 0;
 pub const EXPORT_TAG_TABLE:u8 = 1;
 pub const EXPORT_TAG_MEM:u8 = 2;
 pub const EXPORT_TAG_GLOBAL:u8 = 3;
+pub const EXPORT_TAG_TAG:u8 = 4;
 
 pub const SEC_CUSTOM_ID:u8 = 0;
 pub const SEC_TYPE_ID:u8 = 1;
@@ -95,26 +100,74 @@ pub const SEC_START_ID:u8 = 8;
 pub const SEC_ELEM_ID:u8 = 9;
 pub const SEC_CODE_ID:u8 = 10;
 pub const SEC_DATA_ID:u8 = 11;
+pub const SEC_DATA_COUNT_ID:u8 = 12;
+pub const SEC_TAG_ID:u8 = 13;
+
+/// 非自定义段在二进制里的先后顺序
+/// 标签段在内存段和全局段之间,数据计数段在元素段和代码段之间
+pub fn sec_order(id:u8) -> Option<u8>{
+    match id {
+        SEC_CUSTOM_ID..=SEC_MEM_ID => Some(id),
+        SEC_TAG_ID => Some(SEC_MEM_ID + 1),
+        SEC_GLOBAL_ID..=SEC_ELEM_ID => Some(id + 1),
+        SEC_DATA_COUNT_ID => Some(SEC_ELEM_ID + 2),
+        SEC_CODE_ID|SEC_DATA_ID => Some(id + 2),
+        _ => None,
+    }
+}
+
+/// 标签的属性,目前只有0,表示异常
+pub const TAG_ATTRIBUTE_EXCEPTION:u8 = 0;
+
+/// 数据段开头的标志,0是内存0的主动段,1是被动段,2是带内存索引的主动段
+pub const DATA_FLAG_ACTIVE:u32 = 0;
+pub const DATA_FLAG_PASSIVE:u32 = 1;
+pub const DATA_FLAG_ACTIVE_MEM:u32 = 2;
 
 /// 类型
 pub const VAL_TYPE_I32:u8 = 0x7f;
 pub const VAL_TYPE_I64:u8 = 0x7e;
 pub const VAL_TYPE_F32:u8 = 0x7d;
 pub const VAL_TYPE_F64:u8 = 0x7c;
+/// SIMD的128位向量
+pub const VAL_TYPE_V128:u8 = 0x7b;
 
-pub const BLOCK_TYPE_I32:i32 = -1;
-pub const BLOCK_TYPE_I64:i32 = -2;
-pub const BLOCK_TYPE_F32:i32 = -3;
-pub const BLOCK_TYPE_F64:i32 = -4;
-pub const BLOCK_TYPE_EMPTY:i32 = -64;
+/// 块类型在二进制中是s33编码,负数表示空或单个值类型,非负数表示类型索引
+pub const BLOCK_TYPE_I32:i64 = -1;
+pub const BLOCK_TYPE_I64:i64 = -2;
+pub const BLOCK_TYPE_F32:i64 = -3;
+pub const BLOCK_TYPE_F64:i64 = -4;
+pub const BLOCK_TYPE_EMPTY:i64 = -64;
 
 pub const MUT_CONST:u8 = 0;
 pub const MUT_VAR:u8 = 1;
 pub const FT_TAG:u8 = 0x60;
+/// 引用类型,既可以做表的元素类型也可以做值类型
 pub const FUNC_REF:u8 = 0x70;
+pub const EXTERN_REF:u8 = 0x6f;
+/// 异常引用,catch_ref拿到的被捕获异常
+pub const EXN_REF:u8 = 0x69;
+
+/// 元素段开头的标志
+/// 第0位: 被动段或声明段,第1位: 主动段带表索引/声明段,第2位: 初始值是表达式
+pub const ELEM_FLAG_PASSIVE:u32 = 0x01;
+pub const ELEM_FLAG_EXPLICIT:u32 = 0x02;
+pub const ELEM_FLAG_EXPRS:u32 = 0x04;
+/// 函数索引形式的元素段用elemkind表示元素类型,只有0x00一种,即funcref
+pub const ELEM_KIND_FUNC_REF:u8 = 0x00;
+
+pub fn is_ref_type(t:u8) -> bool{
+    t == FUNC_REF || t == EXTERN_REF || t == EXN_REF
+}
+
+pub fn is_val_type(t:u8) -> bool{
+    matches!(t,VAL_TYPE_I32|VAL_TYPE_I64|VAL_TYPE_F32|VAL_TYPE_F64|VAL_TYPE_V128) || is_ref_type(t)
+}
 
 pub const PAGE_SIZE:usize = 65536;
 pub const MAX_PAGE_COUNT:usize = 65536;
+/// memory64的页数上限,2^48页正好是2^64字节
+pub const MAX_PAGE_COUNT_64:u64 = 1 << 48;
 
 /// wasm二进制格式的结构提映射
 /// 后改良加个Arc
@@ -128,12 +181,15 @@ pub struct Module{
     pub func_sec:Option<Vec<TypeIdx>>,//类型段
     pub table_sec:Option<Vec<TableType>>,//标签段
     pub mem_sec:Option<Vec<MemType>>,//内存段
+    pub tag_sec:Option<Vec<TagType>>,//标签段
     pub global_sec:Option<Vec<GlobalSec>>,//全局变量段
     pub export_sec:Option<Vec<Export>>,//导出段
     pub start_sec:Option<FuncIdx>,//起始段
     pub elem_sec:Option<Vec<Elem>>,//元素段
     pub code_sec:Option<Vec<Code>>,//代码段
     pub data_sec:Option<Vec<Data>>,//数据段
+    pub data_count_sec:Option<u32>,//数据计数段
+    pub name_sec:Option<NameSection>,//name自定义段解析结果,原始字节仍在custom_secs里
 }
 
 impl Module{
@@ -147,14 +203,144 @@ impl Module{
             func_sec: None,
             table_sec: None,
             mem_sec: None,
+            tag_sec: None,
             global_sec: None,
             export_sec: None,
             start_sec: None,
             elem_sec: None,
             code_sec: None,
-            data_sec: None
+            data_sec: None,
+            data_count_sec: None,
+            name_sec: None,
+        }
+    }
+
+    /// 加入自定义段,name段顺便解析出来
+    /// 自定义段的错误不影响模块本身,name段格式不对就当没有
+    pub fn add_custom_sec(&mut self,c:CustomSecs){
+        if c.name.as_deref() == Some(NAME_SEC_NAME) && self.name_sec.is_none() {
+            self.name_sec = reader::decode_name_sec(&c.bytes).ok();
+        }
+        self.custom_secs.get_or_insert_with(Vec::new).push(c);
+    }
+
+    pub fn get_module_name(&self) -> Option<&str>{
+        self.name_sec.as_ref()?.module_name.as_deref()
+    }
+
+    /// 函数名,FuncIdx包括导入函数
+    pub fn get_func_name(&self,idx:FuncIdx) -> Option<&str>{
+        lookup_name(&self.name_sec.as_ref()?.func_names,idx)
+    }
+
+    /// 局部变量名,LocalIdx包括参数
+    pub fn get_local_name(&self,func_idx:FuncIdx,idx:LocalIdx) -> Option<&str>{
+        lookup_indirect_name(&self.name_sec.as_ref()?.local_names,func_idx,idx)
+    }
+
+    pub fn get_label_name(&self,func_idx:FuncIdx,idx:u32) -> Option<&str>{
+        lookup_indirect_name(&self.name_sec.as_ref()?.label_names,func_idx,idx)
+    }
+
+    pub fn get_type_name(&self,idx:TypeIdx) -> Option<&str>{
+        lookup_name(&self.name_sec.as_ref()?.type_names,idx)
+    }
+
+    pub fn get_table_name(&self,idx:TableIdx) -> Option<&str>{
+        lookup_name(&self.name_sec.as_ref()?.table_names,idx)
+    }
+
+    pub fn get_mem_name(&self,idx:MemIdx) -> Option<&str>{
+        lookup_name(&self.name_sec.as_ref()?.mem_names,idx)
+    }
+
+    pub fn get_global_name(&self,idx:GlobalIdx) -> Option<&str>{
+        lookup_name(&self.name_sec.as_ref()?.global_names,idx)
+    }
+
+    pub fn get_elem_name(&self,idx:u32) -> Option<&str>{
+        lookup_name(&self.name_sec.as_ref()?.elem_names,idx)
+    }
+
+    pub fn get_data_name(&self,idx:u32) -> Option<&str>{
+        lookup_name(&self.name_sec.as_ref()?.data_names,idx)
+    }
+
+    /// 函数的显示名,没有名字时用索引,例如 $main 或 func[3]
+    pub fn display_func(&self,idx:FuncIdx) -> String{
+        match self.get_func_name(idx) {
+            Some(name) => format!("${}",name),
+            None => format!("func[{}]",idx),
         }
     }
+
+    /// 导入的函数个数,函数索引空间里导入函数排在前面
+    pub fn get_import_func_count(&self) -> u32{
+        self.import_sec.as_ref().map(|v|{
+            v.iter().filter(|i|i.import_desc.as_ref().and_then(|d|d.tag) == Some(IMPORT_TAG_FUNC)).count() as u32
+        }).unwrap_or(0)
+    }
+
+    /// 表索引空间,导入的表在前,定义的表在后
+    pub fn get_table_types(&self) -> Vec<&TableType>{
+        let imported = self.import_sec.iter().flatten()
+            .filter_map(|i|i.import_desc.as_ref()?.table.as_ref());
+        imported.chain(self.table_sec.iter().flatten()).collect()
+    }
+
+    /// 内存索引空间,导入内存在前,定义的内存在后
+    pub fn get_mem_types(&self) -> Vec<&MemType>{
+        let imported = self.import_sec.iter().flatten()
+            .filter_map(|i|i.import_desc.as_ref()?.mem.as_ref());
+        imported.chain(self.mem_sec.iter().flatten()).collect()
+    }
+
+    /// 全局变量索引空间,导入的全局变量在前,定义的在后
+    pub fn get_global_types(&self) -> Vec<&GlobalType>{
+        let imported = self.import_sec.iter().flatten()
+            .filter_map(|i|i.import_desc.as_ref()?.global.as_ref());
+        imported.chain(self.global_sec.iter().flatten().filter_map(|g|g.ty.as_ref())).collect()
+    }
+
+    /// 导入的全局变量个数
+    pub fn get_import_global_count(&self) -> u32{
+        self.import_sec.iter().flatten()
+            .filter(|i|i.import_desc.as_ref().and_then(|d|d.tag) == Some(IMPORT_TAG_GLOBAL)).count() as u32
+    }
+
+    /// 按名字查导出项,tag是EXPORT_TAG_*,返回对应索引空间里的索引
+    pub fn get_export(&self,tag:u8,name:&str) -> Option<u32>{
+        self.export_sec.as_ref()?.iter()
+            .find(|e|e.name.as_deref() == Some(name) && e.desc.as_ref().and_then(|d|d.tag) == Some(tag))?
+            .desc.as_ref()?.idx
+    }
+
+    /// 标签索引空间,导入标签在前,定义的标签在后
+    pub fn get_tag_types(&self) -> Vec<&TagType>{
+        let imported = self.import_sec.iter().flatten()
+            .filter_map(|i|i.import_desc.as_ref()?.tag_type.as_ref());
+        imported.chain(self.tag_sec.iter().flatten()).collect()
+    }
+
+    /// 标签的函数类型,参数就是异常携带的值
+    pub fn get_tag_func_type(&self,idx:TagIdx) -> Option<&FuncType>{
+        let ty = *self.get_tag_types().get(idx as usize)?;
+        self.type_sec.as_ref()?.get(ty.type_idx? as usize)
+    }
+
+    /// 函数索引对应的函数类型,先查导入函数再查函数段
+    pub fn get_func_type(&self,idx:FuncIdx) -> Option<&FuncType>{
+        let import_count = self.get_import_func_count();
+        let type_idx = if idx < import_count {
+            self.import_sec.as_ref()?.iter()
+                .filter_map(|i|i.import_desc.as_ref())
+                .filter(|d|d.tag == Some(IMPORT_TAG_FUNC))
+                .nth(idx as usize)?.fun_type?
+        } else {
+            *self.func_sec.as_ref()?.get((idx - import_count) as usize)?
+        };
+        self.type_sec.as_ref()?.get(type_idx as usize)
+    }
 }
 
 /// 导入段
@@ -173,6 +359,7 @@ pub struct ImportDesc{
     pub table:Option<TableType>,
     pub mem:Option<MemType>,
     pub global:Option<GlobalType>,
+    pub tag_type:Option<TagType>,
 }
 
 // #[derive(Debug,Clone)]
@@ -197,31 +384,114 @@ pub struct ExportDesc{
     pub idx:Option<u32>,
 }
 
+/// 元素段
+/// 主动段有table和offset;被动段和声明段都没有,声明段只用来声明ref.func要引用的函数
+/// 初始值要么是函数索引init,要么是常量表达式exprs(ref.func/ref.null)
+/// elem_type为None时是funcref,二进制里省略了元素类型
 #[derive(Debug,Clone)]
 pub struct Elem{
     pub table:Option<TableIdx>,
     pub offset:Option<instruction::Expr>,
     pub init:Option<Vec<FuncIdx>>,
+    pub elem_type:Option<u8>,
+    pub exprs:Option<Vec<instruction::Expr>>,
+    pub declarative:bool,
+}
+
+impl Elem {
+    /// 表索引为0、元素是函数索引的主动段,MVP里唯一的形式
+    pub fn new(table:TableIdx,offset:instruction::Expr,init:Vec<FuncIdx>) -> Elem{
+        Elem{ table: Some(table), offset: Some(offset), init: Some(init), elem_type: None, exprs: None, declarative: false }
+    }
+
+    pub fn get_elem_type(&self) -> u8{
+        self.elem_type.unwrap_or(FUNC_REF)
+    }
+
+    pub fn is_active(&self) -> bool{
+        self.offset.is_some()
+    }
+
+    pub fn len(&self) -> usize{
+        match &self.exprs {
+            Some(exprs) => exprs.len(),
+            None => self.init.as_ref().map(|v|v.len()).unwrap_or(0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
 }
 
+/// 代码段
+/// 延迟解码时expr为None,函数体的字节放在lazy里,第一次get_expr时才解码
 #[derive(Debug,Clone)]
 pub struct Code {
     pub locals:Option<Vec<Locals>>,
     pub expr:Option<instruction::Expr>,
+    pub lazy:Option<LazyExpr>,
 }
 
-impl Code {
-    pub fn get_local_count(&self) -> Option<u32>{
-        if self.locals.is_some() {
-            let mut n = 0u32;
-            for l in  self.locals.clone().unwrap().iter() {
-                n = n + l.n.clone().unwrap();
+/// 还没解码的函数体
+/// bytes 是局部变量之后的指令字节,offset 是bytes[0]在整个二进制中的绝对偏移
+/// 解码结果(包括错误)只算一次,之后都用缓存
+#[derive(Debug,Clone)]
+pub struct LazyExpr {
+    pub bytes:Arc<Vec<u8>>,
+    pub offset:usize,
+    pub func_idx:FuncIdx,
+    cache:OnceCell<Result<instruction::Expr,DecodeError>>,
+}
+
+impl LazyExpr {
+    pub fn new(bytes:Vec<u8>,offset:usize,func_idx:FuncIdx) -> LazyExpr{
+        LazyExpr{ bytes: Arc::new(bytes), offset, func_idx, cache: OnceCell::new() }
+    }
+
+    /// 是否已经解码过
+    pub fn is_decoded(&self) -> bool{
+        self.cache.get().is_some()
+    }
+
+    pub fn get(&self) -> Result<&instruction::Expr,DecodeError>{
+        self.cache.get_or_init(||{
+            if opcodes::OPCODE_MAP.get().is_none() {
+                opcodes::init();
             }
-            Some(n)
-        } else {
-            None
+            let mut reader = reader::WasmReader::with_base(self.bytes.as_slice(),self.offset);
+            reader.set_sec_id(Some(SEC_CODE_ID));
+            reader.set_func_idx(Some(self.func_idx));
+            let expr = reader.read_expr()?;
+            if reader.remaining() != 0 {
+                let size = self.bytes.len();
+                return Err(reader.err(DecodeErrorKind::SectionSizeMismatch(size as u32,size - reader.remaining())));
+            }
+            Ok(expr)
+        }).as_ref().map_err(|e|e.clone())
+    }
+}
+
+impl Code {
+    /// 取函数体,延迟解码的在这里解码并缓存
+    pub fn get_expr(&self) -> Result<&instruction::Expr,DecodeError>{
+        match (&self.expr,&self.lazy) {
+            (Some(expr),_) => Ok(expr),
+            (None,Some(lazy)) => lazy.get(),
+            (None,None) => Err(DecodeError{
+                offset: 0,
+                sec_id: Some(SEC_CODE_ID),
+                func_idx: None,
+                kind: DecodeErrorKind::Malformed("code has no body".to_string())
+            }),
         }
     }
+
+    /// 局部变量个数,不含参数;解码时已经检查过总数不超过u32
+    pub fn get_local_count(&self) -> Option<u32>{
+        self.locals.as_ref()?.iter()
+            .try_fold(0u32,|n,l|n.checked_add(l.n.unwrap_or(0)))
+    }
 }
 
 #[derive(Debug,Clone)]
@@ -230,6 +500,7 @@ pub struct Locals {
     pub ty:Option<u8>,
 }
 
+/// 数据段,被动段的mem和offset都是None,只能用memory.init复制到内存
 #[derive(Debug,Clone)]
 pub struct Data{
     pub mem:Option<MemIdx>,
@@ -243,14 +514,59 @@ pub struct CustomSecs{
     pub bytes:Vec<u8>,
 }
 
+/// 名字映射,按索引升序
+pub type NameMap = Vec<(u32,String)>;
+/// 二级名字映射,例如函数索引 -> 局部变量名字映射
+pub type IndirectNameMap = Vec<(u32,NameMap)>;
+
+/// 名字映射按索引升序,用二分查找
+fn lookup_name(map:&[(u32,String)],idx:u32) -> Option<&str>{
+    map.binary_search_by_key(&idx,|(i,_)|*i).ok().map(|i|map[i].1.as_str())
+}
+
+fn lookup_indirect_name(map:&[(u32,NameMap)],outer:u32,idx:u32) -> Option<&str>{
+    let i = map.binary_search_by_key(&outer,|(i,_)|*i).ok()?;
+    lookup_name(&map[i].1,idx)
+}
+
+/// name自定义段
+/// 子段0是模块名,1是函数名,2是局部变量名
+/// 3~9是扩展名字提案里的标签,类型,表,内存,全局变量,元素段,数据段名字,其他子段跳过
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct NameSection{
+    pub module_name:Option<String>,
+    pub func_names:NameMap,
+    pub local_names:IndirectNameMap,
+    pub label_names:IndirectNameMap,
+    pub type_names:NameMap,
+    pub table_names:NameMap,
+    pub mem_names:NameMap,
+    pub global_names:NameMap,
+    pub elem_names:NameMap,
+    pub data_names:NameMap,
+}
+
+pub const NAME_SEC_NAME:&str = "name";
+pub const NAME_SUBSEC_MODULE:u8 = 0;
+pub const NAME_SUBSEC_FUNC:u8 = 1;
+pub const NAME_SUBSEC_LOCAL:u8 = 2;
+pub const NAME_SUBSEC_LABEL:u8 = 3;
+pub const NAME_SUBSEC_TYPE:u8 = 4;
+pub const NAME_SUBSEC_TABLE:u8 = 5;
+pub const NAME_SUBSEC_MEM:u8 = 6;
+pub const NAME_SUBSEC_GLOBAL:u8 = 7;
+pub const NAME_SUBSEC_ELEM:u8 = 8;
+pub const NAME_SUBSEC_DATA:u8 = 9;
+
 /// 索引空间
 /// TypeIdx 类型索引 例如:5个函数即取值0~4
 /// FuncIdx 函数索引 例如:内部2个函数,外部3个函数,即取值0~4
-/// TableIdx 表索引 目前限制只能有一个,取值即0
+/// TableIdx 表索引 外部表+内部表
 /// MemIdx 内存索引 目前限制只能有一个,取值即0
 /// GlobalIdx 全局变量索引 外部全局变量+内部全局变量
 /// LocalIdx 局部变量索引 函数接受的参数+函数内部局部变量
 /// LabelIdx 跳表标签索引 每个函数有自己的跳表标签
+/// TagIdx 异常标签索引 外部标签+内部标签
 pub type TypeIdx = u32;
 pub type FuncIdx = u32;
 pub type TableIdx = u32;
@@ -258,6 +574,7 @@ pub type MemIdx = u32;
 pub type GlobalIdx = u32;
 pub type LocalIdx = u32;
 pub type LabelIdx = u32;
+pub type TagIdx = u32;
 
 
 
@@ -271,14 +588,79 @@ pub struct FuncType {
     pub result_types:Option<Vec<u8>>,
 }
 
+impl FuncType {
+    pub fn params(&self) -> &[u8]{
+        self.param_types.as_deref().unwrap_or(&[])
+    }
+
+    pub fn results(&self) -> &[u8]{
+        self.result_types.as_deref().unwrap_or(&[])
+    }
+}
+
+/// 块类型
+/// Empty 没有参数和返回值
+/// Value 没有参数,一个返回值
+/// TypeIdx 引用类型段中的函数类型,可以有多个参数和多个返回值
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum BlockType {
+    Empty,
+    Value(u8),
+    TypeIdx(TypeIdx),
+}
+
+impl BlockType {
+    /// 块类型的s33编码值
+    pub fn to_s33(self) -> i64{
+        match self {
+            BlockType::Empty => BLOCK_TYPE_EMPTY,
+            BlockType::Value(t) => (t as i64) - 0x80,
+            BlockType::TypeIdx(idx) => idx as i64,
+        }
+    }
+
+    /// 从s33编码值解析块类型
+    pub fn from_s33(n:i64) -> Option<BlockType>{
+        match n {
+            BLOCK_TYPE_EMPTY => Some(BlockType::Empty),
+            // 单字节的负数,加上0x80就是值类型
+            -0x40..=-1 if is_val_type((n + 0x80) as u8) => Some(BlockType::Value((n + 0x80) as u8)),
+            n if n >= 0 && n <= u32::MAX as i64 => Some(BlockType::TypeIdx(n as u32)),
+            _ => None,
+        }
+    }
+}
+
 /// 限制类型
+/// tag 标志位,0x01有上限,0x02共享内存,0x04是memory64
 /// min 下限
 /// max 上限
+/// memory64的上下限是u64,其余的不超过u32
 #[derive(Debug,Clone)]
 pub struct Limits {
     pub tag:Option<u8>,
-    pub min:Option<u32>,
-    pub max:Option<u32>,
+    pub min:Option<u64>,
+    pub max:Option<u64>,
+}
+
+pub const LIMITS_HAS_MAX:u8 = 0x01;
+pub const LIMITS_SHARED:u8 = 0x02;
+pub const LIMITS_MEM64:u8 = 0x04;
+
+impl Limits {
+    pub fn is_shared(&self) -> bool{
+        self.tag.unwrap_or(0) & LIMITS_SHARED != 0
+    }
+
+    /// memory64的地址是i64
+    pub fn is_64(&self) -> bool{
+        self.tag.unwrap_or(0) & LIMITS_MEM64 != 0
+    }
+
+    /// 地址的值类型
+    pub fn addr_type(&self) -> u8{
+        if self.is_64() { VAL_TYPE_I64 } else { VAL_TYPE_I32 }
+    }
 }
 
 /// 内存类型
@@ -286,7 +668,7 @@ pub struct Limits {
 pub type MemType = Limits;
 
 /// 表类型
-/// elem_type目前只能是函数引用,即0x70
+/// elem_type是引用类型,funcref(0x70)或externref(0x6f)
 #[derive(Debug,Clone)]
 pub struct TableType {
     pub elem_type:Option<u8>,
@@ -300,6 +682,20 @@ pub struct GlobalType {
     pub m:Option<u8>,
 }
 
+/// 标签类型
+/// attribute 目前只有0(异常),type_idx 指向的函数类型参数就是异常携带的值,结果必须为空
+#[derive(Debug,Clone)]
+pub struct TagType {
+    pub attribute:Option<u8>,
+    pub type_idx:Option<TypeIdx>,
+}
+
+impl TagType {
+    pub fn new(type_idx:TypeIdx) -> TagType{
+        TagType{ attribute: Some(TAG_ATTRIBUTE_EXCEPTION), type_idx: Some(type_idx) }
+    }
+}
+
 #[cfg(test)]
 mod test{
 
@@ -323,4 +719,117 @@ mod test{
         assert_eq!(r.code_sec.and_then(|v|Some(v.len())),Some(171));
         assert_eq!(r.data_sec.and_then(|v|Some(v.len())),Some(4));
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test2(){
+        use crate::binary::reader;
+        use crate::common::common_error::DecodeErrorKind;
+
+        let data = std::fs::read("./hw_rust.wasm").unwrap();
+        let eager = reader::decode_bytes(&data).unwrap();
+        let lazy = reader::decode_lazy(&data).unwrap();
+        let eager_codes = eager.code_sec.unwrap();
+        let lazy_codes = lazy.code_sec.unwrap();
+        assert_eq!(lazy_codes.len(),171);
+        assert!(lazy_codes.iter().all(|c|c.expr.is_none() && !c.lazy.as_ref().unwrap().is_decoded()));
+
+        // 只解码用到的函数
+        let c = &lazy_codes[3];
+        assert_eq!(format!("{:?}",c.get_expr().unwrap()),format!("{:?}",eager_codes[3].get_expr().unwrap()));
+        assert!(c.lazy.as_ref().unwrap().is_decoded());
+        assert!(!lazy_codes[4].lazy.as_ref().unwrap().is_decoded());
+        assert_eq!(c.locals.as_ref().unwrap().len(),eager_codes[3].locals.as_ref().unwrap().len());
+
+        // 坏的函数体在解码时才报错
+        let m = reader::decode_lazy(&[
+            0x00,0x61,0x73,0x6d,0x01,0x00,0x00,0x00,
+            0x01,0x04,0x01,0x60,0x00,0x00,
+            0x03,0x02,0x01,0x00,
+            0x0a,0x05,0x01,0x03,0x00,0xff,0x0b,
+        ]).unwrap();
+        let code = &m.code_sec.unwrap()[0];
+        let e = code.get_expr().unwrap_err();
+        assert_eq!(e.offset,23);
+        assert_eq!(e.func_idx,Some(0));
+        assert_eq!(e.kind,DecodeErrorKind::UnknownOpcode(0xff));
+        assert_eq!(code.get_expr().unwrap_err(),e);
+    }
+
+    #[test]
+    pub fn test3(){
+        use crate::binary::{reader,module};
+        use crate::common::common_error::DecodeErrorKind;
+
+        let m = reader::decode_file("./hw_rust.wasm".to_string()).unwrap();
+        assert_eq!(m.get_func_name(0),Some("std::rt::lang_start::hec3e200a8398bde9"));
+        assert_eq!(m.display_func(0),"$std::rt::lang_start::hec3e200a8398bde9");
+        assert!((0..171).any(|i|m.get_func_name(i) == Some("main")));
+        assert_eq!(m.get_func_name(1000),None);
+        assert_eq!(m.display_func(1000),"func[1000]");
+
+        fn name(v:&mut Vec<u8>,s:&str){
+            v.push(s.len() as u8);
+            v.extend_from_slice(s.as_bytes());
+        }
+        fn subsec(v:&mut Vec<u8>,id:u8,content:Vec<u8>){
+            v.push(id);
+            v.push(content.len() as u8);
+            v.extend(content);
+        }
+        let mut bytes = vec![];
+        let mut c = vec![];
+        name(&mut c,"m");
+        subsec(&mut bytes,module::NAME_SUBSEC_MODULE,c);
+        let mut c = vec![2,0];
+        name(&mut c,"f");
+        c.push(3);
+        name(&mut c,"g");
+        subsec(&mut bytes,module::NAME_SUBSEC_FUNC,c);
+        let mut c = vec![1,3,1,1];
+        name(&mut c,"x");
+        subsec(&mut bytes,module::NAME_SUBSEC_LOCAL,c);
+        let mut c = vec![1,3,1,0];
+        name(&mut c,"exit");
+        subsec(&mut bytes,module::NAME_SUBSEC_LABEL,c);
+        // 未知子段跳过
+        subsec(&mut bytes,20,vec![1,2,3]);
+        for (id,s) in [(4,"t"),(5,"tab"),(6,"mem"),(7,"sp"),(8,"e"),(9,"d")] {
+            let mut c = vec![1,0];
+            name(&mut c,s);
+            subsec(&mut bytes,id,c);
+        }
+
+        let mut m = module::Module::new();
+        m.add_custom_sec(module::CustomSecs{ name: Some("name".to_string()), bytes: bytes.clone() });
+        assert_eq!(m.get_module_name(),Some("m"));
+        assert_eq!(m.get_func_name(0),Some("f"));
+        assert_eq!(m.get_func_name(3),Some("g"));
+        assert_eq!(m.get_func_name(1),None);
+        assert_eq!(m.get_local_name(3,1),Some("x"));
+        assert_eq!(m.get_local_name(0,1),None);
+        assert_eq!(m.get_label_name(3,0),Some("exit"));
+        assert_eq!(m.get_type_name(0),Some("t"));
+        assert_eq!(m.get_table_name(0),Some("tab"));
+        assert_eq!(m.get_mem_name(0),Some("mem"));
+        assert_eq!(m.get_global_name(0),Some("sp"));
+        assert_eq!(m.get_elem_name(0),Some("e"));
+        assert_eq!(m.get_data_name(0),Some("d"));
+        assert_eq!(m.custom_secs.as_ref().unwrap()[0].bytes,bytes);
+
+        // 索引不是升序
+        let mut c = vec![2,3];
+        name(&mut c,"f");
+        c.push(0);
+        name(&mut c,"g");
+        let mut bytes = vec![];
+        subsec(&mut bytes,module::NAME_SUBSEC_FUNC,c);
+        let e = reader::decode_name_sec(&bytes).unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("name map index 0 out of order".to_string()));
+        assert_eq!(e.offset,6);
+        // 坏的name段不影响模块
+        let mut m = module::Module::new();
+        m.add_custom_sec(module::CustomSecs{ name: Some("name".to_string()), bytes });
+        assert_eq!(m.name_sec,None);
+        assert_eq!(m.custom_secs.map(|v|v.len()),Some(1));
+    }
+}
@@ -1,198 +1,274 @@
 
 use std::collections::HashMap;
 use once_cell::sync::OnceCell;
+use crate::binary::{atomic, simd};
 
-pub static OPCODE_MAP:OnceCell<HashMap<u8,&str>> = OnceCell::new();
+pub static OPCODE_MAP:OnceCell<HashMap<Opcode,&'static str>> = OnceCell::new();
+static NAME_MAP:OnceCell<HashMap<&'static str,Opcode>> = OnceCell::new();
 
 pub fn init(){
-    let mut map:HashMap<u8,&str> = HashMap::new();
-    map.insert(Unreachable,"Unreachable");
-    map.insert(Nop,"nop");
-    map.insert(Block,"block");
-    map.insert(Loop,"loop");
-    map.insert(If,"u");
-    map.insert(Else_,"else");
-    map.insert(End_,"end");
-    map.insert(Br,"br");
-    map.insert(BrIf,"br_if");
-    map.insert(BrTable,"br_table");
-    map.insert(Return, "return");
-    map.insert(Call,"call");
-    map.insert(CallIndirect,"call_indirect");
-    map.insert(Drop,"drop");
-    map.insert(Select,"select");
-    map.insert(LocalGet,"local.get");
-    map.insert(LocalSet,"local.set");
-    map.insert(LocalTee,"local.tee");
-    map.insert(GlobalGet,"global.get");
-    map.insert(GlobalSet,"global.set");
-    map.insert(I32Load,"i32.load");
-    map.insert(I64Load,"i64.load");
-    map.insert(F64Load,"f64.load");
-    map.insert(I32Load8S,"i32.load8_s");
-    map.insert(I32Load8U,"i32.load8_u");
-    map.insert(I32Load16S,"i32.load16_s");
-    map.insert(I32Load16U,"i32.load16_u");
-    map.insert(I64Load8S,"i64.load8_s");
-    map.insert(I64Load8U,"i64.load8_u");
-    map.insert(I64Load16S,"i64.load16_s");
-    map.insert(I64Load16U,"i64.load16_u");
-    map.insert(I64Load32S,"i64.load32_s");
-    map.insert(I64Load32U,"i64.load32_u");
-    map.insert(I32Store,"i32.store");
-    map.insert(I64Store,"i64.store");
-    map.insert(F32Store,"f32.store");
-    map.insert(F64Store,"f64.store");
-    map.insert(I32Store8,"i32.store8");
-    map.insert(I32Store16,"i32.store16");
-    map.insert(I64Store8,"i64.store8");
-    map.insert(I64Store16,"i64.store16");
-    map.insert(I64Store32,"i64.store32");
-    map.insert(MemorySize,"memory.size");
-    map.insert(MemoryGrow,"memory,grow");
-    map.insert(I32Const,"i32.const");
-    map.insert(I64Const,"i64.const");
-    map.insert(F32Const,"f32.const");
-    map.insert(F64Const,"f64.const");
-    map.insert(I32Eqz,"i32.eqz");
-    map.insert(I32Eq,"i32.eq");
-    map.insert(I32Ne,"i32.ne");
-    map.insert(I32LtS,"i32.lts");
-    map.insert(I32LtU,"i32.ltu");
-    map.insert(I32GtS,"i32.gts");
-    map.insert(I32GtU,"i32.gtu");
-    map.insert(I32LeS,"i32.les");
-    map.insert(I32LeU,"i32.leu");
-    map.insert(I32GeS,"i32.ges");
-    map.insert(I32GeU,"i32.geu");
-    map.insert(I64Eqz,"i64.eqz");
-    map.insert(I64Eq,"i64.eq");
-    map.insert(I64Ne,"i64.ne");
-    map.insert(I64LtS,"i64.lts");
-    map.insert(I64LtU,"i64.ltu");
-    map.insert(I64GtS,"i64.gts");
-    map.insert(I64GtU,"i64.gtu");
-    map.insert(I64LeS,"i64.les");
-    map.insert(I64LeU,"i64.leu");
-    map.insert(I64GeS,"i64.ges");
-    map.insert(I64GeU,"i64.geu");
-    map.insert(F32Eq,"f32.eq");
-    map.insert(F32Ne,"f32.ne");
-    map.insert(F32Lt,"f32.lt");
-    map.insert(F32Gt,"f32.gt");
-    map.insert(F32Le,"f32.le");
-    map.insert(F32Ge,"f32.ge");
-    map.insert(F64Eq,"f64.eq");
-    map.insert(F64Ne,"f64.ne");
-    map.insert(F64Lt,"f64.lt");
-    map.insert(F64Gt,"f64.gt");
-    map.insert(F64Le,"f64.le");
-    map.insert(F64Ge,"f64.ge");
-    map.insert(I32Clz,"i32.clz");
-    map.insert(I32Ctz,"i32.ctz");
-    map.insert(I32PopCnt,"i32.popcnt");
-    map.insert(I32Add,"i32.add");
-    map.insert(I32Sub,"i32.sub");
-    map.insert(I32Mul,"i32.mul");
-    map.insert(I32DivS,"i32.div_s");
-    map.insert(I32DivU,"i32.div_u");
-    map.insert(I32RemS,"i32.rem_s");
-    map.insert(I32RemU,"i32.rem_u");
-    map.insert(I32And,"i32.and");
-    map.insert(I32Or,"i32.or");
-    map.insert(I32Xor,"i32.xor");
-    map.insert(I32Shl,"i32.shl");
-    map.insert(I32ShrS,"i32.shr_s");
-    map.insert(I32ShrU,"i32.shr_u");
-    map.insert(I32Rotl,"i32.rotl");
-    map.insert(I32Rotr,"i32.rotr");
-    map.insert(I64Clz,"i64.clz");
-    map.insert(I64Ctz,"i64.ctz");
-    map.insert(I64PopCnt,"i64.popcnt");
-    map.insert(I64Add,"i64.add");
-    map.insert(I64Sub,"i64.sub");
-    map.insert(I64Mul,"i64.mul");
-    map.insert(I64DivS,"i64.div_s");
-    map.insert(I64DivU,"i64.div_u");
-    map.insert(I64RemS,"i64.rem_s");
-    map.insert(I64RemU,"i64.rem_u");
-    map.insert(I64And,"i64.and");
-    map.insert(I64Or,"i64.or");
-    map.insert(I64Xor,"i64.xor");
-    map.insert(I64Shl,"i64.shl");
-    map.insert(I64ShrS,"i64.shr_s");
-    map.insert(I64ShrU,"i64.shr_u");
-    map.insert(I64Rotl,"i64_rotl");
-    map.insert(I64Rotr,"i64.rotr");
-    map.insert(F32Abs,"f32.abs");
-    map.insert(F32Neg,"f32.neg");
-    map.insert(F32Ceil,"f32.ceil");
-    map.insert(F32Floor,"f32.floor");
-    map.insert(F32Trunc,"f32.trunc");
-    map.insert(F32Nearest,"f32.nearest");
-    map.insert(F32Sqrt,"f32.sqrt");
-    map.insert(F32Add,"f32.add");
-    map.insert(F32Sub,"f32.sub");
-    map.insert(F32Mul,"f32.mul");
-    map.insert(F32Div,"f32.div");
-    map.insert(F32Min,"f32.min");
-    map.insert(F32Max,"f32.max");
-    map.insert(F32CopySign,"f32.copysign");
-    map.insert(F64Abs,"f64.abs");
-    map.insert(F64Neg,"f64.neg");
-    map.insert(F64Ceil,"f64.ceil");
-    map.insert(F64Floor,"f64.floor");
-    map.insert(F64Trunc,"f64.trunc");
-    map.insert(F64Nearest,"f64.nearest");
-    map.insert(F64Sqrt,"f64.sqrt");
-    map.insert(F64Add,"f64.add");
-    map.insert(F64Sub,"f64.sub");
-    map.insert(F64Mul,"f64.mul");
-    map.insert(F64Div,"f64.div");
-    map.insert(F64Min,"f64.min");
-    map.insert(F64Max,"f64.max");
-    map.insert(F64CopySign,"f64.copysign");
-    map.insert(I32WrapI64,"i32.wrap_i64");
-    map.insert(I32TruncF32S,"i32.trunc_f32_s");
-    map.insert(I32TruncF32U,"i32_trunc_f32_u");
-    map.insert(I32TruncF64S,"i32_trunc_f64_s");
-    map.insert(I32TruncF64U,"i32_trunc_f64_u");
-    map.insert(I64ExtendI32S,"i64.extend_i32_s");
-    map.insert(I64ExtendI32U,"i64.extend_i32_u");
-    map.insert(I64TruncF32S,"i64.trunc_f32_s");
-    map.insert(I64TruncF32U,"i64.trunc_f32_u");
-    map.insert(I64TruncF64S,"i64.trunc_f64");
-    map.insert(F32ConvertI32S,"f32.convert_i32_s");
-    map.insert(F32ConvertI32U,"f32.convert_i32_u");
-    map.insert(F32ConvertI64S,"f32.convert_i64_s");
-    map.insert(F32ConvertI64U,"f32.convert_i64_u");
-    map.insert(F32DemoteF64,"f32.demote_f64");
-    map.insert(F64ConvertI32S,"f64.convert_i32_s");
-    map.insert(F64ConvertI32U,"f64.convert_i32_u");
-    map.insert(F64ConvertI64S,"f64.convert_i64_s");
-    map.insert(F64ConvertI64U,"f64.convert_i64_u");
-    map.insert(F64PromoteF32,"f64.promote_f32");
-    map.insert(I32ReinterpretF32,"i32.reinterpret_f32");
-    map.insert(I64ReinterpretF64,"i64.reinterpret_i64");
-    map.insert(F32ReinterpretI32,"f32.reinterpret_i32");
-    map.insert(F64ReinterpretI64,"f64.reinterpret_i64");
-    map.insert(I32Extend8S,"i32.extend8_s");
-    map.insert(I32Extend16S,"i32.extend16_s");
-    map.insert(I64Extend8S,"i64.extend8_s");
-    map.insert(I64Extend16S,"i64.extend16_s");
-    map.insert(I64Extend32S,"i64.extend32_s");
-    map.insert(TruncSat,"trunc_sat");
+    let mut map:HashMap<Opcode,&'static str> = HashMap::new();
+    map.insert(Opcode::byte(Unreachable),"unreachable");
+    map.insert(Opcode::byte(Nop),"nop");
+    map.insert(Opcode::byte(Block),"block");
+    map.insert(Opcode::byte(Loop),"loop");
+    map.insert(Opcode::byte(If),"if");
+    map.insert(Opcode::byte(Else_),"else");
+    map.insert(Opcode::byte(Throw),"throw");
+    map.insert(Opcode::byte(ThrowRef),"throw_ref");
+    map.insert(Opcode::byte(End_),"end");
+    map.insert(Opcode::byte(Br),"br");
+    map.insert(Opcode::byte(BrIf),"br_if");
+    map.insert(Opcode::byte(BrTable),"br_table");
+    map.insert(Opcode::byte(Return),"return");
+    map.insert(Opcode::byte(Call),"call");
+    map.insert(Opcode::byte(CallIndirect),"call_indirect");
+    map.insert(Opcode::byte(ReturnCall),"return_call");
+    map.insert(Opcode::byte(ReturnCallIndirect),"return_call_indirect");
+    map.insert(Opcode::byte(Drop),"drop");
+    map.insert(Opcode::byte(Select),"select");
+    map.insert(Opcode::byte(SelectT),"select");
+    map.insert(Opcode::byte(TryTable),"try_table");
+    map.insert(Opcode::byte(LocalGet),"local.get");
+    map.insert(Opcode::byte(LocalSet),"local.set");
+    map.insert(Opcode::byte(LocalTee),"local.tee");
+    map.insert(Opcode::byte(GlobalGet),"global.get");
+    map.insert(Opcode::byte(GlobalSet),"global.set");
+    map.insert(Opcode::byte(TableGet),"table.get");
+    map.insert(Opcode::byte(TableSet),"table.set");
+    map.insert(Opcode::byte(I32Load),"i32.load");
+    map.insert(Opcode::byte(I64Load),"i64.load");
+    map.insert(Opcode::byte(F32Load),"f32.load");
+    map.insert(Opcode::byte(F64Load),"f64.load");
+    map.insert(Opcode::byte(I32Load8S),"i32.load8_s");
+    map.insert(Opcode::byte(I32Load8U),"i32.load8_u");
+    map.insert(Opcode::byte(I32Load16S),"i32.load16_s");
+    map.insert(Opcode::byte(I32Load16U),"i32.load16_u");
+    map.insert(Opcode::byte(I64Load8S),"i64.load8_s");
+    map.insert(Opcode::byte(I64Load8U),"i64.load8_u");
+    map.insert(Opcode::byte(I64Load16S),"i64.load16_s");
+    map.insert(Opcode::byte(I64Load16U),"i64.load16_u");
+    map.insert(Opcode::byte(I64Load32S),"i64.load32_s");
+    map.insert(Opcode::byte(I64Load32U),"i64.load32_u");
+    map.insert(Opcode::byte(I32Store),"i32.store");
+    map.insert(Opcode::byte(I64Store),"i64.store");
+    map.insert(Opcode::byte(F32Store),"f32.store");
+    map.insert(Opcode::byte(F64Store),"f64.store");
+    map.insert(Opcode::byte(I32Store8),"i32.store8");
+    map.insert(Opcode::byte(I32Store16),"i32.store16");
+    map.insert(Opcode::byte(I64Store8),"i64.store8");
+    map.insert(Opcode::byte(I64Store16),"i64.store16");
+    map.insert(Opcode::byte(I64Store32),"i64.store32");
+    map.insert(Opcode::byte(MemorySize),"memory.size");
+    map.insert(Opcode::byte(MemoryGrow),"memory.grow");
+    map.insert(Opcode::byte(I32Const),"i32.const");
+    map.insert(Opcode::byte(I64Const),"i64.const");
+    map.insert(Opcode::byte(F32Const),"f32.const");
+    map.insert(Opcode::byte(F64Const),"f64.const");
+    map.insert(Opcode::byte(I32Eqz),"i32.eqz");
+    map.insert(Opcode::byte(I32Eq),"i32.eq");
+    map.insert(Opcode::byte(I32Ne),"i32.ne");
+    map.insert(Opcode::byte(I32LtS),"i32.lt_s");
+    map.insert(Opcode::byte(I32LtU),"i32.lt_u");
+    map.insert(Opcode::byte(I32GtS),"i32.gt_s");
+    map.insert(Opcode::byte(I32GtU),"i32.gt_u");
+    map.insert(Opcode::byte(I32LeS),"i32.le_s");
+    map.insert(Opcode::byte(I32LeU),"i32.le_u");
+    map.insert(Opcode::byte(I32GeS),"i32.ge_s");
+    map.insert(Opcode::byte(I32GeU),"i32.ge_u");
+    map.insert(Opcode::byte(I64Eqz),"i64.eqz");
+    map.insert(Opcode::byte(I64Eq),"i64.eq");
+    map.insert(Opcode::byte(I64Ne),"i64.ne");
+    map.insert(Opcode::byte(I64LtS),"i64.lt_s");
+    map.insert(Opcode::byte(I64LtU),"i64.lt_u");
+    map.insert(Opcode::byte(I64GtS),"i64.gt_s");
+    map.insert(Opcode::byte(I64GtU),"i64.gt_u");
+    map.insert(Opcode::byte(I64LeS),"i64.le_s");
+    map.insert(Opcode::byte(I64LeU),"i64.le_u");
+    map.insert(Opcode::byte(I64GeS),"i64.ge_s");
+    map.insert(Opcode::byte(I64GeU),"i64.ge_u");
+    map.insert(Opcode::byte(F32Eq),"f32.eq");
+    map.insert(Opcode::byte(F32Ne),"f32.ne");
+    map.insert(Opcode::byte(F32Lt),"f32.lt");
+    map.insert(Opcode::byte(F32Gt),"f32.gt");
+    map.insert(Opcode::byte(F32Le),"f32.le");
+    map.insert(Opcode::byte(F32Ge),"f32.ge");
+    map.insert(Opcode::byte(F64Eq),"f64.eq");
+    map.insert(Opcode::byte(F64Ne),"f64.ne");
+    map.insert(Opcode::byte(F64Lt),"f64.lt");
+    map.insert(Opcode::byte(F64Gt),"f64.gt");
+    map.insert(Opcode::byte(F64Le),"f64.le");
+    map.insert(Opcode::byte(F64Ge),"f64.ge");
+    map.insert(Opcode::byte(I32Clz),"i32.clz");
+    map.insert(Opcode::byte(I32Ctz),"i32.ctz");
+    map.insert(Opcode::byte(I32PopCnt),"i32.popcnt");
+    map.insert(Opcode::byte(I32Add),"i32.add");
+    map.insert(Opcode::byte(I32Sub),"i32.sub");
+    map.insert(Opcode::byte(I32Mul),"i32.mul");
+    map.insert(Opcode::byte(I32DivS),"i32.div_s");
+    map.insert(Opcode::byte(I32DivU),"i32.div_u");
+    map.insert(Opcode::byte(I32RemS),"i32.rem_s");
+    map.insert(Opcode::byte(I32RemU),"i32.rem_u");
+    map.insert(Opcode::byte(I32And),"i32.and");
+    map.insert(Opcode::byte(I32Or),"i32.or");
+    map.insert(Opcode::byte(I32Xor),"i32.xor");
+    map.insert(Opcode::byte(I32Shl),"i32.shl");
+    map.insert(Opcode::byte(I32ShrS),"i32.shr_s");
+    map.insert(Opcode::byte(I32ShrU),"i32.shr_u");
+    map.insert(Opcode::byte(I32Rotl),"i32.rotl");
+    map.insert(Opcode::byte(I32Rotr),"i32.rotr");
+    map.insert(Opcode::byte(I64Clz),"i64.clz");
+    map.insert(Opcode::byte(I64Ctz),"i64.ctz");
+    map.insert(Opcode::byte(I64PopCnt),"i64.popcnt");
+    map.insert(Opcode::byte(I64Add),"i64.add");
+    map.insert(Opcode::byte(I64Sub),"i64.sub");
+    map.insert(Opcode::byte(I64Mul),"i64.mul");
+    map.insert(Opcode::byte(I64DivS),"i64.div_s");
+    map.insert(Opcode::byte(I64DivU),"i64.div_u");
+    map.insert(Opcode::byte(I64RemS),"i64.rem_s");
+    map.insert(Opcode::byte(I64RemU),"i64.rem_u");
+    map.insert(Opcode::byte(I64And),"i64.and");
+    map.insert(Opcode::byte(I64Or),"i64.or");
+    map.insert(Opcode::byte(I64Xor),"i64.xor");
+    map.insert(Opcode::byte(I64Shl),"i64.shl");
+    map.insert(Opcode::byte(I64ShrS),"i64.shr_s");
+    map.insert(Opcode::byte(I64ShrU),"i64.shr_u");
+    map.insert(Opcode::byte(I64Rotl),"i64.rotl");
+    map.insert(Opcode::byte(I64Rotr),"i64.rotr");
+    map.insert(Opcode::byte(F32Abs),"f32.abs");
+    map.insert(Opcode::byte(F32Neg),"f32.neg");
+    map.insert(Opcode::byte(F32Ceil),"f32.ceil");
+    map.insert(Opcode::byte(F32Floor),"f32.floor");
+    map.insert(Opcode::byte(F32Trunc),"f32.trunc");
+    map.insert(Opcode::byte(F32Nearest),"f32.nearest");
+    map.insert(Opcode::byte(F32Sqrt),"f32.sqrt");
+    map.insert(Opcode::byte(F32Add),"f32.add");
+    map.insert(Opcode::byte(F32Sub),"f32.sub");
+    map.insert(Opcode::byte(F32Mul),"f32.mul");
+    map.insert(Opcode::byte(F32Div),"f32.div");
+    map.insert(Opcode::byte(F32Min),"f32.min");
+    map.insert(Opcode::byte(F32Max),"f32.max");
+    map.insert(Opcode::byte(F32CopySign),"f32.copysign");
+    map.insert(Opcode::byte(F64Abs),"f64.abs");
+    map.insert(Opcode::byte(F64Neg),"f64.neg");
+    map.insert(Opcode::byte(F64Ceil),"f64.ceil");
+    map.insert(Opcode::byte(F64Floor),"f64.floor");
+    map.insert(Opcode::byte(F64Trunc),"f64.trunc");
+    map.insert(Opcode::byte(F64Nearest),"f64.nearest");
+    map.insert(Opcode::byte(F64Sqrt),"f64.sqrt");
+    map.insert(Opcode::byte(F64Add),"f64.add");
+    map.insert(Opcode::byte(F64Sub),"f64.sub");
+    map.insert(Opcode::byte(F64Mul),"f64.mul");
+    map.insert(Opcode::byte(F64Div),"f64.div");
+    map.insert(Opcode::byte(F64Min),"f64.min");
+    map.insert(Opcode::byte(F64Max),"f64.max");
+    map.insert(Opcode::byte(F64CopySign),"f64.copysign");
+    map.insert(Opcode::byte(I32WrapI64),"i32.wrap_i64");
+    map.insert(Opcode::byte(I32TruncF32S),"i32.trunc_f32_s");
+    map.insert(Opcode::byte(I32TruncF32U),"i32.trunc_f32_u");
+    map.insert(Opcode::byte(I32TruncF64S),"i32.trunc_f64_s");
+    map.insert(Opcode::byte(I32TruncF64U),"i32.trunc_f64_u");
+    map.insert(Opcode::byte(I64ExtendI32S),"i64.extend_i32_s");
+    map.insert(Opcode::byte(I64ExtendI32U),"i64.extend_i32_u");
+    map.insert(Opcode::byte(I64TruncF32S),"i64.trunc_f32_s");
+    map.insert(Opcode::byte(I64TruncF32U),"i64.trunc_f32_u");
+    map.insert(Opcode::byte(I64TruncF64S),"i64.trunc_f64_s");
+    map.insert(Opcode::byte(I64TruncF64U),"i64.trunc_f64_u");
+    map.insert(Opcode::byte(F32ConvertI32S),"f32.convert_i32_s");
+    map.insert(Opcode::byte(F32ConvertI32U),"f32.convert_i32_u");
+    map.insert(Opcode::byte(F32ConvertI64S),"f32.convert_i64_s");
+    map.insert(Opcode::byte(F32ConvertI64U),"f32.convert_i64_u");
+    map.insert(Opcode::byte(F32DemoteF64),"f32.demote_f64");
+    map.insert(Opcode::byte(F64ConvertI32S),"f64.convert_i32_s");
+    map.insert(Opcode::byte(F64ConvertI32U),"f64.convert_i32_u");
+    map.insert(Opcode::byte(F64ConvertI64S),"f64.convert_i64_s");
+    map.insert(Opcode::byte(F64ConvertI64U),"f64.convert_i64_u");
+    map.insert(Opcode::byte(F64PromoteF32),"f64.promote_f32");
+    map.insert(Opcode::byte(I32ReinterpretF32),"i32.reinterpret_f32");
+    map.insert(Opcode::byte(I64ReinterpretF64),"i64.reinterpret_f64");
+    map.insert(Opcode::byte(F32ReinterpretI32),"f32.reinterpret_i32");
+    map.insert(Opcode::byte(F64ReinterpretI64),"f64.reinterpret_i64");
+    map.insert(Opcode::byte(I32Extend8S),"i32.extend8_s");
+    map.insert(Opcode::byte(I32Extend16S),"i32.extend16_s");
+    map.insert(Opcode::byte(I64Extend8S),"i64.extend8_s");
+    map.insert(Opcode::byte(I64Extend16S),"i64.extend16_s");
+    map.insert(Opcode::byte(I64Extend32S),"i64.extend32_s");
+    map.insert(Opcode::byte(RefNull),"ref.null");
+    map.insert(Opcode::byte(RefIsNull),"ref.is_null");
+    map.insert(Opcode::byte(RefFunc),"ref.func");
+    for (sub,name) in MISC_OPS.iter() {
+        map.insert(Opcode::new(MiscPrefix,*sub),name);
+    }
+    for (sub,name) in simd::SIMD_OPS.iter() {
+        map.insert(Opcode::new(SimdPrefix,*sub),name);
+    }
+    for (sub,name) in atomic::ATOMIC_OPS.iter() {
+        map.insert(Opcode::new(AtomicPrefix,*sub),name);
+    }
 
+    // select和带类型的select同名,按名字查时是不带类型的
+    let mut names:HashMap<&str,Opcode> = map.iter().map(|(op,name)|(*name,*op)).collect();
+    names.insert("select",Opcode::byte(Select));
+    let _ = NAME_MAP.set(names);
     OPCODE_MAP.set(map);
 
 }
 
+/// 指令的完整操作码
+/// 单字节指令的prefix就是操作码,sub是0;0xFC/0xFD/0xFE前缀后面还有u32子操作码,两个一起才确定一条指令
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct Opcode{
+    pub prefix:u8,
+    pub sub:u32,
+}
+
+impl Opcode{
+    pub const fn new(prefix:u8,sub:u32) -> Opcode{
+        Opcode{ prefix, sub }
+    }
+
+    pub const fn byte(opcode:u8) -> Opcode{
+        Opcode{ prefix: opcode, sub: 0 }
+    }
+
+    pub fn is_prefixed(&self) -> bool{
+        is_prefix(self.prefix)
+    }
+}
+
+impl std::fmt::Display for Opcode{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_prefixed() {
+            write!(f,"{:#04x} {}",self.prefix,self.sub)
+        } else {
+            write!(f,"{:#04x}",self.prefix)
+        }
+    }
+}
+
+/// 后面跟子操作码的前缀字节
+pub fn is_prefix(b:u8) -> bool{
+    b == MiscPrefix || b == SimdPrefix || b == AtomicPrefix
+}
+
+/// 指令名,未知的操作码返回None
+pub fn name(op:Opcode) -> Option<&'static str>{
+    OPCODE_MAP.get().and_then(|map|map.get(&op)).copied()
+}
+
+/// 按指令名查操作码
+pub fn by_name(name:&str) -> Option<Opcode>{
+    NAME_MAP.get().and_then(|map|map.get(name)).copied()
+}
+
 pub const Unreachable       :u8= 0x00; // unreachable
 pub const Nop               :u8= 0x01; // nop
 pub const Block             :u8= 0x02; // block rt in* end
 pub const Loop              :u8= 0x03; // loop rt in* end
 pub const If                :u8= 0x04; // if rt in* else in* end
 pub const Else_             :u8= 0x05; // else
+pub const Throw             :u8= 0x08; // throw x
+pub const ThrowRef          :u8= 0x0A; // throw_ref
 pub const End_              :u8= 0x0B; // end
 pub const Br                :u8= 0x0C; // br l
 pub const BrIf              :u8= 0x0D; // br_if l
@@ -200,13 +276,19 @@ pub const BrTable           :u8= 0x0E; // br_table l* lN
 pub const Return            :u8= 0x0F; // return
 pub const Call              :u8= 0x10; // call x
 pub const CallIndirect      :u8= 0x11; // call_indirect x
+pub const ReturnCall        :u8= 0x12; // return_call x
+pub const ReturnCallIndirect:u8= 0x13; // return_call_indirect x
 pub const Drop              :u8= 0x1A; // drop
 pub const Select            :u8= 0x1B; // select
+pub const SelectT           :u8= 0x1C; // select t
+pub const TryTable          :u8= 0x1F; // try_table bt catch* in* end
 pub const LocalGet          :u8= 0x20; // local.get x
 pub const LocalSet          :u8= 0x21; // local.set x
 pub const LocalTee          :u8= 0x22; // local.tee x
 pub const GlobalGet         :u8= 0x23; // global.get x
 pub const GlobalSet         :u8= 0x24; // global.set x
+pub const TableGet          :u8= 0x25; // table.get x
+pub const TableSet          :u8= 0x26; // table.set x
 pub const I32Load           :u8= 0x28; // i32.load m
 pub const I64Load           :u8= 0x29; // i64.load m
 pub const F32Load           :u8= 0x2A; // f32.load m
@@ -364,5 +446,47 @@ pub const I32Extend16S:u8      = 0xC1; // i32.extend16_s
 pub const I64Extend8S:u8       = 0xC2; // i64.extend8_s
 pub const I64Extend16S:u8      = 0xC3; // i64.extend16_s
 pub const I64Extend32S:u8      = 0xC4; // i64.extend32_s
-pub const TruncSat:u8          = 0xFC; // <i32|64>.trunc_sat_<f32|64>_<s|u>
+pub const RefNull:u8           = 0xD0; // ref.null t
+pub const RefIsNull:u8         = 0xD1; // ref.is_null
+pub const RefFunc:u8           = 0xD2; // ref.func x
+pub const MiscPrefix:u8        = 0xFC; // 饱和截断、批量内存和表操作,子操作码见MISC_OPS
+pub const SimdPrefix:u8        = 0xFD; // SIMD指令,子操作码见binary::simd
+pub const AtomicPrefix:u8      = 0xFE; // 原子指令,子操作码见binary::atomic
+
+/// 0xFC前缀的子操作码,0~7是饱和截断,8~11是批量内存操作,15~17是表操作
+/// table.init/elem.drop/table.copy(12~14)还没有支持
+pub const I32TruncSatF32S:u32 = 0x00; // i32.trunc_sat_f32_s
+pub const I32TruncSatF32U:u32 = 0x01; // i32.trunc_sat_f32_u
+pub const I32TruncSatF64S:u32 = 0x02; // i32.trunc_sat_f64_s
+pub const I32TruncSatF64U:u32 = 0x03; // i32.trunc_sat_f64_u
+pub const I64TruncSatF32S:u32 = 0x04; // i64.trunc_sat_f32_s
+pub const I64TruncSatF32U:u32 = 0x05; // i64.trunc_sat_f32_u
+pub const I64TruncSatF64S:u32 = 0x06; // i64.trunc_sat_f64_s
+pub const I64TruncSatF64U:u32 = 0x07; // i64.trunc_sat_f64_u
+pub const MemoryInit:u32 = 0x08; // memory.init
+pub const DataDrop:u32   = 0x09; // data.drop
+pub const MemoryCopy:u32 = 0x0A; // memory.copy
+pub const MemoryFill:u32 = 0x0B; // memory.fill
+pub const TableGrow:u32  = 0x0F; // table.grow x
+pub const TableSize:u32  = 0x10; // table.size x
+pub const TableFill:u32  = 0x11; // table.fill x
+
+/// 0xFC前缀的子操作码和指令名,按子操作码排列
+pub const MISC_OPS:[(u32,&str);15] = [
+    (I32TruncSatF32S,"i32.trunc_sat_f32_s"),
+    (I32TruncSatF32U,"i32.trunc_sat_f32_u"),
+    (I32TruncSatF64S,"i32.trunc_sat_f64_s"),
+    (I32TruncSatF64U,"i32.trunc_sat_f64_u"),
+    (I64TruncSatF32S,"i64.trunc_sat_f32_s"),
+    (I64TruncSatF32U,"i64.trunc_sat_f32_u"),
+    (I64TruncSatF64S,"i64.trunc_sat_f64_s"),
+    (I64TruncSatF64U,"i64.trunc_sat_f64_u"),
+    (MemoryInit,"memory.init"),
+    (DataDrop,"data.drop"),
+    (MemoryCopy,"memory.copy"),
+    (MemoryFill,"memory.fill"),
+    (TableGrow,"table.grow"),
+    (TableSize,"table.size"),
+    (TableFill,"table.fill"),
+];
 
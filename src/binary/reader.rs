@@ -1,7 +1,10 @@
 use std::string::FromUtf8Error;
 use byteorder::{ByteOrder, LittleEndian};
-use crate::binary::{leb128, module, opcodes, instruction};
-use crate::binary::module::{CustomSecs, Module, FuncType, TableType, Limits};
+use crate::binary::{leb128, module, opcodes, instruction, simd, atomic};
+use crate::binary::simd::SimdImm;
+use crate::binary::opcodes::Opcode;
+use crate::binary::module::{CustomSecs, Module, FuncType, TableType, Limits, BlockType};
+use crate::common::common_error::{CommonError, DecodeError, DecodeErrorKind};
 use std::fs::{OpenOptions, read};
 use std::io::Read;
 use anyhow::Context;
@@ -9,1032 +12,1131 @@ use crate::binary::instruction::Instruction;
 use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
 
-
-pub struct WasmReader{
-    data:Vec<u8>,
+/// 默认的块嵌套层数上限
+/// 规范没有限制嵌套层数,但解码、校验和执行都是每层块递归一次,太深会把本地栈撑爆
+/// 调试版本解码每层要3KB左右的本地栈,256层在默认2MB栈的线程上也有足够余量
+/// 大的switch会被编译成上千层嵌套的block,栈更大的嵌入方可以用set_max_block_depth调高
+pub const MAX_BLOCK_DEPTH:u32 = 256;
+
+pub type DecodeResult<T> = Result<T,DecodeError>;
+
+/// 在借来的字节切片上用游标读取,不再复制剩余数据
+/// pos 是当前在data中的位置,base 是data[0]在整个二进制中的绝对偏移
+/// sec_id/func_idx 记录当前正在读的段和函数,报错时带上
+/// lazy 为true时代码段的函数体不解码,见module::LazyExpr
+pub struct WasmReader<'a>{
+    data:&'a [u8],
+    pos:usize,
+    base:usize,
+    sec_id:Option<u8>,
+    func_idx:Option<u32>,
+    depth:u32,
+    max_depth:u32,
+    lazy:bool,
 }
 
 pub fn decode_file(path:String) -> anyhow::Result<module::Module>{
     let v = std::fs::read(path)?;
-    decode(v)
+    Ok(decode_bytes(v.as_slice())?)
+}
+
+pub fn decode(data:Vec<u8>) -> DecodeResult<module::Module>{
+    decode_bytes(data.as_slice())
+}
+
+pub fn decode_bytes(data:&[u8]) -> DecodeResult<module::Module>{
+    if opcodes::OPCODE_MAP.get().is_none() {
+        opcodes::init();
+    }
+    let mut reader = WasmReader::new(data);
+    reader.read_module()
 }
 
-pub fn decode(data:Vec<u8>) -> anyhow::Result<module::Module>{
-    let mut reader = WasmReader{ data };
-    reader.read_module().context("read module none")
+/// 指定块嵌套层数上限解码
+pub fn decode_with_max_block_depth(data:&[u8],max_depth:u32) -> DecodeResult<module::Module>{
+    if opcodes::OPCODE_MAP.get().is_none() {
+        opcodes::init();
+    }
+    let mut reader = WasmReader::new(data);
+    reader.set_max_block_depth(max_depth);
+    reader.read_module()
+}
+
+/// 有数据计数段时,数据段的个数必须和它一致
+pub fn check_data_count(m:&module::Module) -> Result<(),DecodeErrorKind>{
+    if let Some(n) = m.data_count_sec {
+        if n as usize != m.data_sec.as_ref().map(|v|v.len()).unwrap_or(0) {
+            return Err(DecodeErrorKind::Malformed("data count and data section have inconsistent lengths".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// 解析name自定义段的内容,bytes不包括段名
+pub fn decode_name_sec(bytes:&[u8]) -> DecodeResult<module::NameSection>{
+    let mut reader = WasmReader::new(bytes);
+    reader.set_sec_id(Some(module::SEC_CUSTOM_ID));
+    reader.read_name_sec()
+}
+
+/// 延迟解码,代码段的函数体在第一次Code::get_expr时才解码
+pub fn decode_lazy(data:&[u8]) -> DecodeResult<module::Module>{
+    if opcodes::OPCODE_MAP.get().is_none() {
+        opcodes::init();
+    }
+    let mut reader = WasmReader::new(data);
+    reader.set_lazy(true);
+    reader.read_module()
+}
+
+impl<'a> WasmReader<'a> {
+    pub fn new(data:&'a [u8]) -> WasmReader<'a>{
+        WasmReader::with_base(data,0)
+    }
+
+    /// base 是data[0]在整个二进制中的绝对偏移,流式解码时用
+    pub fn with_base(data:&'a [u8],base:usize) -> WasmReader<'a>{
+        WasmReader{ data, pos: 0, base, sec_id: None, func_idx: None, depth: 0, max_depth: MAX_BLOCK_DEPTH, lazy: false }
+    }
+
+    pub fn set_sec_id(&mut self,sec_id:Option<u8>){
+        self.sec_id = sec_id;
+    }
+
+    pub fn set_func_idx(&mut self,func_idx:Option<u32>){
+        self.func_idx = func_idx;
+    }
+
+    pub fn set_lazy(&mut self,lazy:bool){
+        self.lazy = lazy;
+    }
+
+    /// 块最多嵌套的层数,默认是MAX_BLOCK_DEPTH
+    pub fn set_max_block_depth(&mut self,max_depth:u32){
+        self.max_depth = max_depth;
+    }
+
+    /// 从当前位置截取n个字节作为一个新的reader,偏移和段信息继承过去
+    pub fn sub_reader(&mut self,n:usize) -> DecodeResult<WasmReader<'a>>{
+        let base = self.offset();
+        let data = self.read_n(n)?;
+        Ok(WasmReader{ data, pos: 0, base, sec_id: self.sec_id, func_idx: self.func_idx, depth: self.depth, max_depth: self.max_depth, lazy: self.lazy })
+    }
+
+    /// 当前读到的绝对偏移
+    pub fn offset(&self) -> usize{
+        self.base + self.pos
+    }
+
+    pub fn err(&self,kind:DecodeErrorKind) -> DecodeError{
+        self.err_at(self.offset(),kind)
+    }
+
+    pub fn err_at(&self,offset:usize,kind:DecodeErrorKind) -> DecodeError{
+        DecodeError{ offset, sec_id: self.sec_id, func_idx: self.func_idx, kind }
+    }
+
+    fn leb128_err(&self,e:CommonError) -> DecodeError{
+        match e {
+            CommonError::UnexpectedEof => self.err(DecodeErrorKind::UnexpectedEof),
+            e => self.err(DecodeErrorKind::BadLeb128(e.to_string())),
+        }
+    }
+
+    /// 还没读的部分
+    fn rest(&self) -> &'a [u8]{
+        &self.data[self.pos..]
+    }
+
+    /// 读取n个字节,不够就报错
+    fn read_n(&mut self,n:usize) -> DecodeResult<&'a [u8]>{
+        if n > self.remaining() {
+            return Err(self.err(DecodeErrorKind::UnexpectedEof));
+        }
+        let v = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(v)
+    }
 }
 
 /// 读取基础数据结构
-impl WasmReader {
+impl<'a> WasmReader<'a> {
 
     /// 读取定长首字节,
-    /// 读取玩之后除去第一位
-    pub fn read_byte(&mut self) -> Option<u8>{
-
-        let mut val:Option<u8> = Option::default();
-        match self.data.get(0) {
-            None => {val=None}
+    /// 读取完之后游标后移一位
+    pub fn read_byte(&mut self) -> DecodeResult<u8>{
+        match self.data.get(self.pos) {
+            None => {Err(self.err(DecodeErrorKind::UnexpectedEof))}
             Some(b) => {
-                val = Some(*b)
+                self.pos += 1;
+                Ok(*b)
             }
         }
-
-        let v:Vec<u8> = self.data.drain(1..).collect();
-        self.data = v;
-
-        val
     }
 
     /// 读取定长u32
-    /// 读完之后去除前4个
-    pub fn read_u32(&mut self) -> Option<u32>{
-        let val = Some(byteorder::LittleEndian::read_u32(self.data.as_slice()));
-        self.data = self.data.drain(4..).collect();
-        val
+    /// 读完之后游标后移4位
+    pub fn read_u32(&mut self) -> DecodeResult<u32>{
+        let v = self.read_n(4)?;
+        Ok(byteorder::LittleEndian::read_u32(v))
     }
 
     /// 读取定长f32
-    /// 读完之后去除前4个
-    pub fn read_f32(&mut self) -> Option<f32>{
-
-        let val = Some(byteorder::LittleEndian::read_f32(self.data.as_slice()));
-        self.data = self.data.drain(4..).collect();
-        val
+    /// 读完之后游标后移4位
+    pub fn read_f32(&mut self) -> DecodeResult<f32>{
+        let v = self.read_n(4)?;
+        Ok(byteorder::LittleEndian::read_f32(v))
     }
 
     /// 读取定长f64
-    /// 读完之后去除钱8个
-    pub fn read_f64(&mut self) -> Option<f64>{
-
-        let val = Some(byteorder::LittleEndian::read_f64(self.data.as_slice()));
-        self.data = self.data.drain(8..).collect();
-        val
+    /// 读完之后游标后移8位
+    pub fn read_f64(&mut self) -> DecodeResult<f64>{
+        let v = self.read_n(8)?;
+        Ok(byteorder::LittleEndian::read_f64(v))
     }
 
     /// 读取变长u32
     /// 根据leb128中返回的size
-    pub fn read_var_u32(&mut self) -> Option<u32>{
-        let mut val:Option<u32> = Option::default();
-        match leb128::decode_var_uint(self.data.as_slice(),32){
+    pub fn read_var_u32(&mut self) -> DecodeResult<u32>{
+        match leb128::decode_var_uint(self.rest(),32){
+            Ok((num,i)) => {
+                self.pos += i;
+                Ok(num as u32)
+            }
+            Err(e) => {
+                Err(self.leb128_err(e))
+            }
+        }
+    }
+
+    /// 读取变长u64,memory64的限制和memarg偏移
+    pub fn read_var_u64(&mut self) -> DecodeResult<u64>{
+        match leb128::decode_var_uint(self.rest(),64){
             Ok((num,i)) => {
-                val = Some(num as u32);
-                let v:Vec<u8> = self.data.drain(i..).collect();
-                self.data = v;
+                self.pos += i;
+                Ok(num)
             }
             Err(e) => {
-                println!("{:?}",e);
-                val = None
+                Err(self.leb128_err(e))
             }
         }
-        val
     }
 
     /// 读取变长i32
-    /// 根据leb128返回的size截取vec
-    pub fn read_var_s32(&mut self) -> Option<i32>{
-        let mut val:Option<i32> = Option::default();
-        match leb128::decode_var_int(self.data.as_slice(),32){
+    /// 根据leb128返回的size移动游标
+    pub fn read_var_s32(&mut self) -> DecodeResult<i32>{
+        match leb128::decode_var_int(self.rest(),32){
             Ok((num,i)) => {
-                val = Some(num as i32);
-                let v:Vec<u8> = self.data.drain(i..).collect();
-                self.data = v;
+                self.pos += i;
+                Ok(num as i32)
             }
             Err(e) => {
-                println!("{:?}",e);
-                val = None
+                Err(self.leb128_err(e))
             }
         }
-        val
     }
 
-    /// 读取变长f64
-    pub fn read_var_s64(&mut self) -> Option<i64>{
-        let mut val:Option<i64> = Option::default();
-        match leb128::decode_var_int(self.data.as_slice(),64){
+    /// 读取变长s33,只用于块类型
+    pub fn read_var_s33(&mut self) -> DecodeResult<i64>{
+        match leb128::decode_var_int(self.rest(),33){
             Ok((num,i)) => {
-                val = Some(num);
-                let v:Vec<u8> = self.data.drain(i..).collect();
-                self.data = v;
+                self.pos += i;
+                Ok(num)
             }
             Err(e) => {
-                println!("{:?}",e);
-                val = None
+                Err(self.leb128_err(e))
             }
         }
-        val
     }
 
-    /// 读取变长u32
-    /// 返回0..n的vec
-    /// 截取vec n..
-    pub fn read_bytes(&mut self) -> Option<Vec<u8>>{
-        let mut val:Option<Vec<u8>> = Option::default();
-        match self.read_var_u32(){
-            None => {val = None}
-            Some(n) => {
-                let v:Vec<u8> = self.data.drain(0..n as usize).collect();
-                // let v2:Vec<u8> = self.data.drain(n as usize..).collect();
-                val = Some(v);
-                self.data = self.data.clone()
+    /// 读取变长f64
+    pub fn read_var_s64(&mut self) -> DecodeResult<i64>{
+        match leb128::decode_var_int(self.rest(),64){
+            Ok((num,i)) => {
+                self.pos += i;
+                Ok(num)
+            }
+            Err(e) => {
+                Err(self.leb128_err(e))
             }
         }
+    }
 
-        val
+    /// 读取变长u32
+    /// 返回接下来n个字节的切片
+    pub fn read_bytes(&mut self) -> DecodeResult<&'a [u8]>{
+        let n = self.read_var_u32()?;
+        self.read_n(n as usize)
     }
 
     /// 读取变长u32然后转为string
-    pub fn read_name(&mut self) -> Option<String>{
-        return match self.read_bytes(){
-            None => {None}
-            Some(v) => {
-                String::from_utf8(v).ok()
-            }
-        }
+    pub fn read_name(&mut self) -> DecodeResult<String>{
+        let offset = self.offset();
+        let v = self.read_bytes()?;
+        String::from_utf8(v.to_vec()).map_err(|_|self.err_at(offset,DecodeErrorKind::InvalidUtf8))
     }
 
-    /// 查询vec长度
+    /// 剩余未读的字节数
     pub fn remaining(&self) -> usize{
-        self.data.len()
+        self.data.len() - self.pos
     }
 
 }
 
 /// 读取wasm二进制文件
-impl WasmReader{
-    pub fn read_code(&mut self) -> Option<module::Code>{
-        let mut code = module::Code{ locals: None, expr: None };
-        match self.read_bytes() {
-            None => {
-                return None
-            }
-            Some(v) => {
-                let mut code_reader = WasmReader{ data: v };
-                code.locals = code_reader.read_locals_vec();
-            }
-        }
-
-        Some(code)
+impl<'a> WasmReader<'a>{
+    pub fn read_module(&mut self) -> DecodeResult<module::Module>{
+        self.read_sections()
     }
 
-    pub fn read_module(&mut self) -> Option<module::Module>{
-        return match self.read_sections(){
-            Ok(m) => {
-                Some(m)
-            }
-            Err(str) => {
-                println!("{:?}",str);
-                None
-            }
+    pub fn read_sections(&mut self) -> DecodeResult<module::Module>{
+        let mut m = module::Module::new();
+
+        let offset = self.offset();
+        let magic = self.read_u32()?;//读4个
+        if magic != module::MAGIC_NUMBER {
+            return Err(self.err_at(offset,DecodeErrorKind::BadMagic(magic)));
         }
-    }
+        let offset = self.offset();
+        let version = self.read_u32()?;//读4个
+        if version != module::VERSION {
+            return Err(self.err_at(offset,DecodeErrorKind::BadVersion(version)));
+        }
+        m.magic = Some(magic);
+        m.version = Some(version);
 
-    pub fn read_sections(&mut self) -> anyhow::Result<module::Module,&'static str>{
-        let mut m = module::Module{
-            magic: self.read_u32(),//读4个
-            version: self.read_u32(),//读4个
-            custom_secs: None,
-            type_sec: None,
-            import_sec: None,
-            func_sec: None,
-            table_sec: None,
-            mem_sec: None,
-            global_sec: None,
-            export_sec: None,
-            start_sec: None,
-            elem_sec: None,
-            code_sec: None,
-            data_sec: None
-        };
         let mut prev_sec_id = 0u8;
 
         while self.remaining() > 0 {
-            match self.read_byte(){
-                None => {
-                    return anyhow::Result::Err("read byte is none")
-                }
-                Some(b) => {
-                    if b == module::SEC_CUSTOM_ID {
-                        if m.custom_secs.as_ref().is_none() {
-                            let mut v:Vec<module::CustomSecs> = Vec::new();
-                            match self.read_custom_sec(){
-                                None => {
-                                    return anyhow::Result::Err("read custom sec none")
-                                }
-                                Some(c) => {
-                                    v.push(c);
-                                }
-                            }
-                            m.custom_secs = Some(v);
-                        } else {
-                            let mut v = m.custom_secs.unwrap();
-                            match self.read_custom_sec(){
-                                None => {
-                                    return anyhow::Result::Err("read custom sec none")
-                                }
-                                Some(c) => {
-                                    v.push(c);
-                                    m.custom_secs = Some(v);
-                                }
-                            }
-
-                        }
-                        continue
-                    }
-
-                    if b > module::SEC_DATA_ID {
-                        return anyhow::Result::Err("the id large");
-                    }
-
-                    if b <= prev_sec_id {
-                        return anyhow::Result::Err("less than prev sec id");
-                    }
-
-                    prev_sec_id = b;
-
-                    match self.read_var_u32(){
-                        None => {
-                            return anyhow::Result::Err("read var u32 none");
-                        }
-                        Some(n) => {
-                            let l = self.remaining();
-                            self.read_non_custom_sec(b,&mut m);
-                            if self.remaining() + n as usize != l {
-                                panic!("section size mismatch");
-                                // return anyhow::Result::Err("section size mismatch");
-                            }
-                        }
-                    }
+            let sec_offset = self.offset();
+            let b = self.read_byte()?;
+            self.sec_id = Some(b);
+
+            if b == module::SEC_CUSTOM_ID {
+                let c = self.read_custom_sec()?;
+                m.add_custom_sec(c);
+                self.sec_id = None;
+                continue
+            }
 
-                }
+            let order = match module::sec_order(b) {
+                Some(order) => order,
+                None => return Err(self.err_at(sec_offset,DecodeErrorKind::UnknownSection(b))),
+            };
+
+            if order <= module::sec_order(prev_sec_id).unwrap() {
+                return Err(self.err_at(sec_offset,DecodeErrorKind::SectionOutOfOrder(b,prev_sec_id)));
             }
+
+            prev_sec_id = b;
+
+            let n = self.read_var_u32()?;
+            let mut sec_reader = self.sub_reader(n as usize)?;
+            sec_reader.read_non_custom_sec(b,&mut m)?;
+            if sec_reader.remaining() != 0 {
+                return Err(sec_reader.err(DecodeErrorKind::SectionSizeMismatch(n,n as usize - sec_reader.remaining())));
+            }
+            self.sec_id = None;
+        }
+
+        let func_count = m.func_sec.as_ref().map(|v|v.len()).unwrap_or(0);
+        let code_count = m.code_sec.as_ref().map(|v|v.len()).unwrap_or(0);
+        if func_count != code_count {
+            return Err(self.err(DecodeErrorKind::Malformed("function and code section have inconsistent lengths".to_string())));
         }
+        check_data_count(&m).map_err(|kind|self.err(kind))?;
+
         Ok(m)
     }
 
-    pub fn read_non_custom_sec(&mut self, sec_id:u8, m: &mut module::Module) {
+    pub fn read_non_custom_sec(&mut self, sec_id:u8, m: &mut module::Module) -> DecodeResult<()>{
         match sec_id {
-            module::SEC_TYPE_ID => m.type_sec = self.read_type_sec(),
-            module::SEC_IMPORT_ID => m.import_sec = self.read_import_sec(),
-            module::SEC_FUNC_ID => m.func_sec = self.read_indices(),
-            module::SEC_TABLE_ID => m.table_sec = self.read_table_sec(),
-            module::SEC_MEM_ID => m.mem_sec = self.read_mem_sec(),
-            module::SEC_GLOBAL_ID => m.global_sec = self.read_global_sec(),
-            module::SEC_EXPORT_ID => m.export_sec = self.read_export_sec(),
-            module::SEC_START_ID => m.start_sec = self.read_start_sec(),
-            module::SEC_ELEM_ID => m.elem_sec = self.read_elem_sec(),
-            module::SEC_CODE_ID => m.code_sec = self.read_code_sec(),
-            module::SEC_DATA_ID => m.data_sec = self.read_data_sec(),
-            _ => {}
+            module::SEC_TYPE_ID => m.type_sec = Some(self.read_type_sec()?),
+            module::SEC_IMPORT_ID => m.import_sec = Some(self.read_import_sec()?),
+            module::SEC_FUNC_ID => m.func_sec = Some(self.read_indices()?),
+            module::SEC_TABLE_ID => m.table_sec = Some(self.read_table_sec()?),
+            module::SEC_MEM_ID => m.mem_sec = Some(self.read_mem_sec()?),
+            module::SEC_TAG_ID => m.tag_sec = Some(self.read_tag_sec()?),
+            module::SEC_GLOBAL_ID => m.global_sec = Some(self.read_global_sec()?),
+            module::SEC_EXPORT_ID => m.export_sec = Some(self.read_export_sec()?),
+            module::SEC_START_ID => m.start_sec = Some(self.read_start_sec()?),
+            module::SEC_ELEM_ID => m.elem_sec = Some(self.read_elem_sec()?),
+            module::SEC_CODE_ID => {
+                m.code_sec = Some(self.read_code_sec(m.get_import_func_count())?)
+            },
+            module::SEC_DATA_ID => m.data_sec = Some(self.read_data_sec()?),
+            module::SEC_DATA_COUNT_ID => m.data_count_sec = Some(self.read_var_u32()?),
+            _ => return Err(self.err(DecodeErrorKind::UnknownSection(sec_id)))
         }
+        Ok(())
     }
 }
 
 /// 读取段
-impl WasmReader {
+impl<'a> WasmReader<'a> {
 
     /// 根据第一个单字节来判断导入的是什么类型的数据,读取导读描述
-    pub fn read_import_desc(&mut self) -> Option<module::ImportDesc>{
+    pub fn read_import_desc(&mut self) -> DecodeResult<module::ImportDesc>{
+        let offset = self.offset();
+        let tag = self.read_byte()?;
         let mut desc = module::ImportDesc{
-            tag: self.read_byte(),
+            tag: Some(tag),
             fun_type: None,
             table: None,
             mem: None,
-            global: None
+            global: None,
+            tag_type: None
         };
 
-        match desc.tag {
-            None => {
-                panic!("read import desc none")
-            }
-            Some(b) => {
-                match b {
-                    module::IMPORT_TAG_FUNC => {
-                        desc.fun_type = self.read_var_u32();
-                    },
-                    module::IMPORT_TAG_TABLE => {
-                        desc.table = self.read_table_type();
-                    },
-                    module::IMPORT_TAG_MEM => {
-                        desc.mem = self.read_limits();
-                    },
-                    module::IMPORT_TAG_GLOBAL => {
-                        desc.global = self.read_global_type();
-                    },
-                    _ => {
-                        panic!("invalid import desc tag:{:?}",b);
-                    }
-                }
+        match tag {
+            module::IMPORT_TAG_FUNC => {
+                desc.fun_type = Some(self.read_var_u32()?);
+            },
+            module::IMPORT_TAG_TABLE => {
+                desc.table = Some(self.read_table_type()?);
+            },
+            module::IMPORT_TAG_MEM => {
+                desc.mem = Some(self.read_limits()?);
+            },
+            module::IMPORT_TAG_GLOBAL => {
+                desc.global = Some(self.read_global_type()?);
+            },
+            module::IMPORT_TAG_TAG => {
+                desc.tag_type = Some(self.read_tag_type()?);
+            },
+            _ => {
+                return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("invalid import desc tag:{:?}",tag))));
             }
         }
 
-        Some(desc)
+        Ok(desc)
     }
 
-    pub fn read_locals_vec(&mut self) -> Option<Vec<module::Locals>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::Locals> = Vec::new();
-                for _ in 0..n {
-                    let locals = module::Locals{
-                        n: self.read_var_u32(),
-                        ty: self.read_val_type(),
-                    };
-                    v.push(locals);
-                }
-                Some(v)
+    pub fn read_locals_vec(&mut self) -> DecodeResult<Vec<module::Locals>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::Locals> = Vec::new();
+        let mut total = 0u64;
+        for _ in 0..n {
+            let offset = self.offset();
+            let locals = module::Locals{
+                n: Some(self.read_var_u32()?),
+                ty: Some(self.read_val_type()?),
+            };
+            total += locals.n.unwrap() as u64;
+            if total >= u32::MAX as u64 {
+                return Err(self.err_at(offset,DecodeErrorKind::Malformed("too many locals".to_string())));
             }
+            v.push(locals);
         }
+        Ok(v)
     }
 
-    pub fn read_custom_sec(&mut self) -> Option<module::CustomSecs>{
-        match self.read_bytes() {
-            None => {
-                None
-            }
-            Some(v) => {
-                let mut reader = WasmReader{ data: v };
-                Some(module::CustomSecs{ name: reader.read_name(), bytes: reader.data })
-            }
-        }
+    pub fn read_custom_sec(&mut self) -> DecodeResult<module::CustomSecs>{
+        let n = self.read_var_u32()?;
+        let mut reader = self.sub_reader(n as usize)?;
+        let name = reader.read_name()?;
+        Ok(module::CustomSecs{ name: Some(name), bytes: reader.rest().to_vec() })
     }
 
-    pub fn read_type_sec(&mut self) -> Option<Vec<module::FuncType>>{
-        match self.read_var_u32() {
-            None => {
-                None
+    pub fn read_name_sec(&mut self) -> DecodeResult<module::NameSection>{
+        let mut names = module::NameSection::default();
+        while self.remaining() > 0 {
+            let id = self.read_byte()?;
+            let n = self.read_var_u32()?;
+            let mut reader = self.sub_reader(n as usize)?;
+            match id {
+                module::NAME_SUBSEC_MODULE => names.module_name = Some(reader.read_name()?),
+                module::NAME_SUBSEC_FUNC => names.func_names = reader.read_name_map()?,
+                module::NAME_SUBSEC_LOCAL => names.local_names = reader.read_indirect_name_map()?,
+                module::NAME_SUBSEC_LABEL => names.label_names = reader.read_indirect_name_map()?,
+                module::NAME_SUBSEC_TYPE => names.type_names = reader.read_name_map()?,
+                module::NAME_SUBSEC_TABLE => names.table_names = reader.read_name_map()?,
+                module::NAME_SUBSEC_MEM => names.mem_names = reader.read_name_map()?,
+                module::NAME_SUBSEC_GLOBAL => names.global_names = reader.read_name_map()?,
+                module::NAME_SUBSEC_ELEM => names.elem_names = reader.read_name_map()?,
+                module::NAME_SUBSEC_DATA => names.data_names = reader.read_name_map()?,
+                _ => continue,
             }
-            Some(n) => {
-                let mut v:Vec<module::FuncType> = Vec::new();
-                for _ in 0..n {
-                    match self.read_func_type() {
-                        None => {
-                            panic!("read func type none")
-                        }
-                        Some(ft) => {
-                            v.push(ft);
-                        }
-                    }
-                };
-                // println!("type-sec{:?}",v);
-                Some(v)
+            if reader.remaining() != 0 {
+                return Err(reader.err(DecodeErrorKind::SectionSizeMismatch(n,n as usize - reader.remaining())));
             }
         }
+        Ok(names)
     }
 
-    pub fn read_import_sec(&mut self) -> Option<Vec<module::Import>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::Import> = Vec::new();
-                for _ in 0..n {
-                    v.push(self.read_import().unwrap());
-                };
-                // println!("import-sec:{:?}",v);
-                Some(v)
-            }
+    pub fn read_name_map(&mut self) -> DecodeResult<module::NameMap>{
+        let n = self.read_var_u32()?;
+        let mut v:module::NameMap = Vec::new();
+        for _ in 0..n {
+            let offset = self.offset();
+            let idx = self.read_var_u32()?;
+            self.check_name_idx_order(offset,v.last().map(|(i,_)|*i),idx)?;
+            v.push((idx,self.read_name()?));
         }
+        Ok(v)
     }
 
-    pub fn read_import(&mut self) -> Option<module::Import>{
-        Some(module::Import{
-            module: self.read_name(),
-            name: self.read_name(),
-            import_desc: self.read_import_desc(),
-        })
+    pub fn read_indirect_name_map(&mut self) -> DecodeResult<module::IndirectNameMap>{
+        let n = self.read_var_u32()?;
+        let mut v:module::IndirectNameMap = Vec::new();
+        for _ in 0..n {
+            let offset = self.offset();
+            let idx = self.read_var_u32()?;
+            self.check_name_idx_order(offset,v.last().map(|(i,_)|*i),idx)?;
+            v.push((idx,self.read_name_map()?));
+        }
+        Ok(v)
     }
 
-    pub fn read_indices(&mut self) -> Option<Vec<module::TypeIdx>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::TypeIdx> = Vec::new();
-                for _ in 0..n {
-                    match self.read_var_u32() {
-                        None => {
-                            panic!("read indices read var u32 none");
-                        }
-                        Some(num) => {
-                            v.push(num);
-                        }
-                    }
-                };
-                // println!("func-sec:{:?}",v);
-                Some(v)
-            }
+    /// 名字映射里的索引必须严格升序
+    fn check_name_idx_order(&self,offset:usize,prev:Option<u32>,idx:u32) -> DecodeResult<()>{
+        match prev {
+            Some(prev) if idx <= prev => Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("name map index {} out of order",idx)))),
+            _ => Ok(()),
         }
     }
 
-    pub fn read_table_sec(&mut self) -> Option<Vec<module::TableType>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::TableType> = Vec::new();
-                for _ in 0..n {
-                    match self.read_table_type() {
-                        None => {
-                            panic!("read table sec read table type none")
-                        }
-                        Some(tt) => {
-                            v.push(tt);
-                        }
-                    }
-                };
-                // println!("table-sec:{:?}",v);
-                Some(v)
-            }
-        }
+    pub fn read_type_sec(&mut self) -> DecodeResult<Vec<module::FuncType>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::FuncType> = Vec::new();
+        for _ in 0..n {
+            v.push(self.read_func_type()?);
+        };
+        Ok(v)
+    }
+
+    pub fn read_import_sec(&mut self) -> DecodeResult<Vec<module::Import>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::Import> = Vec::new();
+        for _ in 0..n {
+            v.push(self.read_import()?);
+        };
+        Ok(v)
+    }
+
+    pub fn read_import(&mut self) -> DecodeResult<module::Import>{
+        Ok(module::Import{
+            module: Some(self.read_name()?),
+            name: Some(self.read_name()?),
+            import_desc: Some(self.read_import_desc()?),
+        })
+    }
+
+    pub fn read_indices(&mut self) -> DecodeResult<Vec<module::TypeIdx>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::TypeIdx> = Vec::new();
+        for _ in 0..n {
+            v.push(self.read_var_u32()?);
+        };
+        Ok(v)
     }
 
-    pub fn read_limits(&mut self) -> Option<module::Limits>{
+    pub fn read_table_sec(&mut self) -> DecodeResult<Vec<module::TableType>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::TableType> = Vec::new();
+        for _ in 0..n {
+            v.push(self.read_table_type()?);
+        };
+        Ok(v)
+    }
 
+    /// 标志位0x04是memory64,上下限按u64读
+    pub fn read_limits(&mut self) -> DecodeResult<module::Limits>{
+        let offset = self.offset();
+        let tag = self.read_byte()?;
+        if tag > 0x07 {
+            return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("integer too large, limits tag:{:?}",tag))));
+        }
+        let read = |r:&mut Self| if tag & module::LIMITS_MEM64 != 0 {
+            r.read_var_u64()
+        } else {
+            r.read_var_u32().map(|v|v as u64)
+        };
         let mut limits = module::Limits{
-            tag: self.read_byte(),
-            min: self.read_var_u32(),
+            tag: Some(tag),
+            min: Some(read(self)?),
             max: None
         };
+        if tag & module::LIMITS_HAS_MAX != 0 {
+            limits.max = Some(read(self)?);
+        }
+        Ok(limits)
+    }
 
-        match limits.tag {
-            None => {
-                panic!("read limits tag is none");
-            }
-            Some(n) => {
+    pub fn read_mem_sec(&mut self) -> DecodeResult<Vec<module::MemType>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::MemType> = Vec::new();
+        for _ in 0..n {
+            v.push(self.read_limits()?);
+        };
+        Ok(v)
+    }
+
+    pub fn read_tag_sec(&mut self) -> DecodeResult<Vec<module::TagType>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::TagType> = Vec::new();
+        for _ in 0..n {
+            v.push(self.read_tag_type()?);
+        };
+        Ok(v)
+    }
 
-                if n == 1 {
-                    limits.max = self.read_var_u32();
+    pub fn read_global_sec(&mut self) -> DecodeResult<Vec<module::GlobalSec>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::GlobalSec> = Vec::new();
+        for _ in 0..n {
+            let g = module::GlobalSec{ ty: Some(self.read_global_type()?), init: Some(self.read_expr()?) };
+            v.push(g);
+        };
+        Ok(v)
+    }
+
+    pub fn read_export_sec(&mut self) -> DecodeResult<Vec<module::Export>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::Export> = Vec::new();
+        for _ in 0..n {
+            let mut export = module::Export{
+                name: Some(self.read_name()?),
+                desc: None
+            };
+
+            let offset = self.offset();
+            let desc = module::ExportDesc{
+                tag: Some(self.read_byte()?),
+                idx: Some(self.read_var_u32()?),
+            };
+
+            match desc.tag.unwrap() {
+                module::EXPORT_TAG_FUNC|
+                module::EXPORT_TAG_TABLE|
+                module::EXPORT_TAG_MEM|
+                module::EXPORT_TAG_GLOBAL|
+                module::EXPORT_TAG_TAG => {},
+                tag => {
+                    return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("invalid export desc tag:{:?}",tag))));
                 }
-                Some(limits)
             }
-        }
+            export.desc = Some(desc);
+            v.push(export);
+        };
+        Ok(v)
     }
 
-    pub fn read_mem_sec(&mut self) -> Option<Vec<module::MemType>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::MemType> = Vec::new();
-                for _ in 0..n {
-                    match self.read_limits() {
-                        None => {
-                            panic!("read limits none")
-                        }
-                        Some(limits) => {
-                            v.push(limits);
-                        }
-                    }
-                };
-                // println!("mem-sec:{:?}",v);
-                Some(v)
-            }
-        }
+    pub fn read_start_sec(&mut self) -> DecodeResult<module::FuncIdx>{
+        self.read_var_u32()
     }
 
-    pub fn read_global_sec(&mut self) -> Option<Vec<module::GlobalSec>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::GlobalSec> = Vec::new();
-                for _ in 0..n {
-                    let g = module::GlobalSec{ ty: self.read_global_type(), init: self.read_expr() };
-                    v.push(g);
-                };
-                // println!("global-sec:{:?}",v);
-                Some(v)
-            }
-        }
+    pub fn read_elem_sec(&mut self) -> DecodeResult<Vec<module::Elem>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::Elem> = Vec::new();
+        for _ in 0..n {
+            v.push(self.read_elem()?);
+        };
+        Ok(v)
     }
 
-    pub fn read_export_sec(&mut self) -> Option<Vec<module::Export>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::Export> = Vec::new();
-                for _ in 0..n {
-                    let mut export = module::Export{
-                        name: self.read_name(),
-                        desc: None
-                    };
-
-                    let desc = module::ExportDesc{
-                        tag: self.read_byte(),
-                        idx: self.read_var_u32(),
-                    };
-
-                    match desc.tag {
-                        None => {
-                            panic!("tag none")
-                        }
-                        Some(num) => {
-                            match num {
-                                module::EXPORT_TAG_FUNC|
-                                module::EXPORT_TAG_TABLE|
-                                module::EXPORT_TAG_MEM|
-                                module::EXPORT_TAG_GLOBAL => {},
-                                _ => {
-                                    panic!("tag unknown")
-                                }
-                            }
-                        }
-                    }
-                    export.desc = Some(desc);
-                    v.push(export);
-                };
-                // println!("export-sec:{:?}",v);
-                Some(v)
+    /// 元素段按标志的三个位分成8种形式
+    pub fn read_elem(&mut self) -> DecodeResult<module::Elem>{
+        let offset = self.offset();
+        let flag = self.read_var_u32()?;
+        if flag > 7 {
+            return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("invalid elem segment flag:{}",flag))));
+        }
+        let passive = flag & module::ELEM_FLAG_PASSIVE != 0;
+        let explicit = flag & module::ELEM_FLAG_EXPLICIT != 0;
+        let exprs = flag & module::ELEM_FLAG_EXPRS != 0;
+        let mut elem = module::Elem{ table: None, offset: None, init: None, elem_type: None, exprs: None, declarative: passive && explicit };
+        if !passive {
+            elem.table = Some(if explicit { self.read_var_u32()? } else { 0 });
+            elem.offset = Some(self.read_expr()?);
+        }
+        // 表索引为0的主动段省略了元素类型
+        if passive || explicit {
+            elem.elem_type = Some(if exprs {
+                self.read_ref_type()?
+            } else {
+                let offset = self.offset();
+                let kind = self.read_byte()?;
+                if kind != module::ELEM_KIND_FUNC_REF {
+                    return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("invalid elem kind:{}",kind))));
+                }
+                module::FUNC_REF
+            });
+        }
+        if exprs {
+            let n = self.read_var_u32()?;
+            let mut v = vec![];
+            for _ in 0..n {
+                v.push(self.read_expr()?);
             }
+            elem.exprs = Some(v);
+        } else {
+            elem.init = Some(self.read_indices()?);
         }
+        Ok(elem)
     }
 
-    pub fn read_start_sec(&mut self) -> Option<module::FuncIdx>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                // println!("start-sec:{:?}",n);
-                Some(n)
-            }
+    /// func_base 是导入函数的个数,代码段第i个函数的索引是func_base+i
+    pub fn read_code_sec(&mut self,func_base:u32) -> DecodeResult<Vec<module::Code>>{
+        let n = self.read_var_u32()?;
+        let mut vc:Vec<module::Code> = Vec::new();
+        for i in 0..n {
+            self.func_idx = Some(func_base + i);
+            let size = self.read_var_u32()?;
+            let mut reader = self.sub_reader(size as usize)?;
+            vc.push(reader.read_code_body()?);
         }
+        self.func_idx = None;
+        Ok(vc)
     }
 
-    pub fn read_elem_sec(&mut self) -> Option<Vec<module::Elem>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::Elem> = Vec::new();
-                for _ in 0..n {
-                    let elem = module::Elem{
-                        table: self.read_var_u32(),
-                        offset: self.read_expr(),
-                        init: self.read_indices(),
-                    };
-
-                    v.push(elem);
-                };
-                // println!("elem-sec:{:?}",v);
-                Some(v)
-            }
+    /// 读取一个函数体,reader里只能是这个函数体的字节
+    /// 延迟模式下只读局部变量,剩下的指令字节原样保存
+    pub fn read_code_body(&mut self) -> DecodeResult<module::Code>{
+        let size = self.remaining();
+        let locals = self.read_locals_vec()?;
+        if self.lazy {
+            let offset = self.offset();
+            let bytes = self.read_n(self.remaining())?.to_vec();
+            let func_idx = self.func_idx.unwrap_or(0);
+            return Ok(module::Code{ locals: Some(locals), expr: None, lazy: Some(module::LazyExpr::new(bytes,offset,func_idx)) });
         }
+        let code = module::Code{ locals: Some(locals), expr: Some(self.read_expr()?), lazy: None };
+        if self.remaining() != 0 {
+            return Err(self.err(DecodeErrorKind::SectionSizeMismatch(size as u32,size - self.remaining())));
+        }
+        Ok(code)
     }
 
-    pub fn read_code_sec(&mut self) -> Option<Vec<module::Code>>{
-       match self.read_var_u32() {
-           None => {
-               None
-           }
-           Some(n) => {
-               let mut vc:Vec<module::Code> = Vec::new();
-               for _ in 0..n {
-                   match self.read_bytes() {
-                       None => {
-                           return None
-                       }
-                       Some(v) => {
-                           let mut reader = WasmReader{ data: v };
-                           let code = module::Code{ locals: reader.read_locals_vec(), expr: reader.read_expr() };
-                           if code.get_local_count().unwrap() >= u32::MAX {
-                               panic!("too many locals");
-                           }
-                           vc.push(code);
-                       }
-                   }
-               }
-               // println!("code-sec:{:?}",vc);
-               Some(vc)
-           }
-       }
-    }
-
-    pub fn read_data_sec(&mut self) -> Option<Vec<module::Data>>{
-        match self.read_var_u32() {
-            None => {
-                None
-            }
-            Some(n) => {
-                let mut v:Vec<module::Data> = Vec::new();
-                for _ in 0..n {
-                    let data = module::Data{
-                        mem: self.read_var_u32(),
-                        offset: self.read_expr(),
-                        init: self.read_bytes(),
-                    };
-
-                    v.push(data);
+    pub fn read_data_sec(&mut self) -> DecodeResult<Vec<module::Data>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<module::Data> = Vec::new();
+        for _ in 0..n {
+            let offset = self.offset();
+            let (mem,expr) = match self.read_var_u32()? {
+                module::DATA_FLAG_ACTIVE => (Some(0),Some(self.read_expr()?)),
+                module::DATA_FLAG_PASSIVE => (None,None),
+                module::DATA_FLAG_ACTIVE_MEM => {
+                    let mem = self.read_var_u32()?;
+                    (Some(mem),Some(self.read_expr()?))
                 }
-                // println!("data-sec:{:?}",v);
-                Some(v)
-            }
+                flag => return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("invalid data segment flag:{}",flag)))),
+            };
+            let data = module::Data{
+                mem,
+                offset: expr,
+                init: Some(self.read_bytes()?.to_vec()),
+            };
+
+            v.push(data);
         }
+        Ok(v)
     }
 
 }
 
 /// 读取操作指令
-impl WasmReader {
+impl<'a> WasmReader<'a> {
 
-    pub fn read_expr(&mut self) -> Option<instruction::Expr>{
-        match self.read_instructions(){
-            None => {
-                panic!("read expr none")
-            }
-            Some(p) => {
-                if p.1 != opcodes::End_ {
-                    panic!("invalid expr end:{:?}",p.1)
-                }
-                Some(p.0)
-            }
+    pub fn read_expr(&mut self) -> DecodeResult<instruction::Expr>{
+        let offset = self.offset();
+        let (expr,end) = self.read_instructions()?;
+        if end != opcodes::End_ {
+            return Err(self.err_at(offset,DecodeErrorKind::InvalidEnd(end)));
         }
+        Ok(expr)
     }
 
-    pub fn read_instructions(&mut self) -> Option<(instruction::Expr,u8)>{
+    pub fn read_instructions(&mut self) -> DecodeResult<(instruction::Expr,u8)>{
         let mut v:Vec<instruction::Instruction> = Vec::new();
-        let mut end = 0u8;
         loop {
-            match self.read_instruction(){
-                None => {panic!("read instr none")}
-                Some(i) => {
-
-                    if i.opcode.as_ref().is_none() {
-                        panic!("opcode is none")
-                    }
-                    let code = i.opcode.unwrap();
-                    if code == opcodes::Else_ || code == opcodes::End_ {
-                        end = code;
-                        break
-                    }
-                    v.push(i)
-                }
+            let i = self.read_instruction()?;
+            let code = i.opcode.unwrap();
+            if code == opcodes::Else_ || code == opcodes::End_ {
+                return Ok((v,code));
             }
+            v.push(i)
         }
-        Some((v,end))
-    }
-
-    pub fn read_instruction(&mut self) -> Option<instruction::Instruction>{
-
-        let map = opcodes::OPCODE_MAP.get().unwrap();
-        match self.read_byte() {
-            None => {panic!("read instrs none")}
-            Some(n) => {
-                match map.get(&n) {
-                    None => {panic!("map get none:{:?}",n)}
-                    Some(str) => {
-                        if str.eq(&"") {
-                            panic!("get string is empty")
-                        } else {
-                            Some(instruction::Instruction{
-                                opcode: Some(n),
-                                args: self.read_args(n)
-                            })
-                        }
-                    }
-                }
-            }
+    }
+
+    pub fn read_instruction(&mut self) -> DecodeResult<instruction::Instruction>{
+        let offset = self.offset();
+        let n = self.read_byte()?;
+        if opcodes::is_prefix(n) {
+            return self.read_prefixed_instruction(n);
+        }
+        if opcodes::name(Opcode::byte(n)).is_none() {
+            return Err(self.err_at(offset,DecodeErrorKind::UnknownOpcode(n)));
         }
+        // 块直接在这里递归,不经过read_args,每层嵌套占的本地栈少一些
+        let args = match n {
+            opcodes::Block|opcodes::Loop|opcodes::TryTable => Some(self.read_block_args(n)?),
+            opcodes::If => Some(self.read_if_args()?),
+            _ => self.read_args(n)?,
+        };
+        Ok(instruction::Instruction{
+            opcode: Some(n),
+            sub: 0,
+            args
+        })
     }
 
-    pub fn read_args(&mut self,opcode:u8) -> Option<instruction::ArgsEnum>{
-        // println!("123");
-        match opcode {
-            opcodes::Block|opcodes::Loop => {
-                self.read_block_args()
-            },
-            opcodes::If => {
-                self.read_if_args()
-            },
+    pub fn read_args(&mut self,opcode:u8) -> DecodeResult<Option<instruction::ArgsEnum>>{
+        let args = match opcode {
             opcodes::Br|opcodes::BrIf  => {
-                match self.read_var_u32() {
-                    None => {panic!("read u32 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::U32(n))
-                    }
-                }
+                instruction::ArgsEnum::U32(self.read_var_u32()?)
             },
             opcodes::BrTable => {
-                self.read_br_table_args()
+                self.read_br_table_args()?
             },
-            opcodes::Call => {
-                match self.read_var_u32() {
-                    None => {panic!("read u32 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::U32(n))
-                    }
-                }
+            opcodes::Call|opcodes::ReturnCall|opcodes::Throw => {
+                instruction::ArgsEnum::U32(self.read_var_u32()?)
+            },
+            opcodes::CallIndirect|opcodes::ReturnCallIndirect => {
+                self.read_call_indirect_args()?
+            },
+            opcodes::SelectT => {
+                self.read_select_types()?
+            },
+            opcodes::TableGet|opcodes::TableSet|opcodes::RefFunc => {
+                instruction::ArgsEnum::U32(self.read_var_u32()?)
             },
-            opcodes::CallIndirect => {
-                self.read_call_indirect_args().and_then(|n|Some(instruction::ArgsEnum::U32(n)))
+            opcodes::RefNull => {
+                instruction::ArgsEnum::U8(self.read_ref_type()?)
             },
             opcodes::LocalGet|opcodes::LocalSet|opcodes::LocalTee => {
-                match self.read_var_u32() {
-                    None => {panic!("read u32 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::U32(n))
-                    }
-                }
+                instruction::ArgsEnum::U32(self.read_var_u32()?)
             },
             opcodes::GlobalSet|opcodes::GlobalGet => {
-                match self.read_var_u32() {
-                    None => {panic!("read u32 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::U32(n))
-                    }
-                }
+                instruction::ArgsEnum::U32(self.read_var_u32()?)
             },
             opcodes::MemoryGrow|opcodes::MemorySize => {
-                match self.read_zero() {
-                    None => {panic!("read zero none")}
-                    Some(n) => {Some(instruction::ArgsEnum::U8(n))}
-                }
+                instruction::ArgsEnum::U32(self.read_var_u32()?)
             },
             opcodes::I32Const => {
-                match self.read_var_s32() {
-                    None => {panic!("read s32 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::I32(n))
-                    }
-                }
+                instruction::ArgsEnum::I32(self.read_var_s32()?)
             },
             opcodes::I64Const => {
-                match self.read_var_s64() {
-                    None => {panic!("read s64 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::I64(n))
-                    }
-                }
+                instruction::ArgsEnum::I64(self.read_var_s64()?)
             },
             opcodes::F32Const => {
-                match self.read_f32() {
-                    None => {panic!("read f32 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::F32(n))
-                    }
-                }
+                instruction::ArgsEnum::F32(self.read_f32()?)
             },
             opcodes::F64Const => {
-                match self.read_f64() {
-                    None => {panic!("read f64 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::F64(n))
-                    }
-                }
-            },
-            opcodes::TruncSat => {
-                match self.read_byte() {
-                    None => {panic!("read u8 none")}
-                    Some(n) => {
-                        Some(instruction::ArgsEnum::U8(n))
-                    }
-                }
+                instruction::ArgsEnum::F64(self.read_f64()?)
             },
             _=>{
                 if opcode >= opcodes::I32Load && opcode <= opcodes::I64Store32 {
-                    self.read_mem_arg().and_then(|o|Some(instruction::ArgsEnum::MemArg(o)))
+                    instruction::ArgsEnum::MemArg(self.read_mem_arg()?)
                 } else {
-                    None
+                    return Ok(None)
                 }
 
             }
-        }
+        };
+        Ok(Some(args))
     }
 
-    pub fn read_block_args(&mut self) -> Option<instruction::ArgsEnum>{
-
-        match self.read_block_type() {
-            None => {panic!("read block type none")}
-            Some(i) => {
-                match self.read_instructions() {
-                    None => {panic!("read instrs none")}
-                    Some(p) => {
-                        if p.1 != opcodes::End_ {
-                            panic!("invalid block end:{:?}",p.1)
-                        }
-                        Some(instruction::ArgsEnum::BlockArgs(instruction::BlockArgs{
-                            bt: Some(i),
-                            instrs: Some(p.0)
-                        }))
-                    }
-                }
-            }
+    /// 前缀后面是u32子操作码,(前缀,子操作码)一起查指令,立即数按前缀分开读
+    /// 单独一个函数,块嵌套时read_instruction是递归的,分支多了栈帧会变大
+    #[inline(never)]
+    fn read_prefixed_instruction(&mut self,prefix:u8) -> DecodeResult<instruction::Instruction>{
+        let offset = self.offset();
+        let sub = self.read_var_u32()?;
+        if opcodes::name(Opcode::new(prefix,sub)).is_none() {
+            return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("unknown {:#04x} sub opcode:{}",prefix,sub))));
         }
+        let args = match prefix {
+            opcodes::MiscPrefix => self.read_prefix_fc_args(sub)?,
+            opcodes::SimdPrefix => self.read_simd_args(sub)?,
+            _ => self.read_atomic_args(sub)?,
+        };
+        Ok(instruction::Instruction::prefixed(prefix,sub,args))
+    }
 
+    /// 0xFC子操作码后面的立即数
+    /// 饱和截断没有立即数,memory.init和memory.copy带两个索引
+    pub fn read_prefix_fc_args(&mut self,sub:u32) -> DecodeResult<instruction::ArgsEnum>{
+        let args = match sub {
+            opcodes::I32TruncSatF32S..=opcodes::I64TruncSatF64U => {
+                instruction::ArgsEnum::PrefixArgs(instruction::PrefixArgs{ idx: None, idx2: None })
+            }
+            opcodes::MemoryInit|opcodes::MemoryCopy => {
+                let idx = self.read_var_u32()?;
+                let idx2 = self.read_var_u32()?;
+                instruction::ArgsEnum::PrefixArgs(instruction::PrefixArgs{ idx: Some(idx), idx2: Some(idx2) })
+            }
+            opcodes::DataDrop|opcodes::MemoryFill|opcodes::TableGrow|opcodes::TableSize|opcodes::TableFill => {
+                let idx = self.read_var_u32()?;
+                instruction::ArgsEnum::PrefixArgs(instruction::PrefixArgs{ idx: Some(idx), idx2: None })
+            }
+            _ => return Err(self.err(DecodeErrorKind::Malformed(format!("unknown 0xfc sub opcode:{}",sub)))),
+        };
+        Ok(args)
     }
 
-    pub fn read_if_args(&mut self) -> Option<instruction::ArgsEnum>{
-
-        match self.read_block_type() {
-            None => {panic!("read block type is none")}
-            Some(i) => {
-                match self.read_instructions() {
-                    None => {panic!("read instrs is none")}
-                    Some(p) => {
-                        let mut ia = instruction::IfArgs{
-                            bt: Some(i),
-                            instrs1: Some(p.0),
-                            instrs2: None
-                        };
-
-                        if p.1 == opcodes::End_ {
-                            match self.read_instructions() {
-                                None => {panic!("read instrs2 none")}
-                                Some(p2) => {
-                                    if p2.1 != opcodes::End_ {
-                                        panic!("invalid block end:{:?}",p.1)
-                                    }
-
-                                    ia.instrs2 = Some(p2.0);
-                                }
-                            }
-                        }
-                        Some(instruction::ArgsEnum::IfArgs(ia))
-                    }
-                }
+    /// 0xFD子操作码后面的立即数
+    pub fn read_simd_args(&mut self,sub:u32) -> DecodeResult<instruction::ArgsEnum>{
+        let mut args = instruction::SimdArgs{ mem_arg: None, lane: None, v128: None };
+        match simd::imm(sub) {
+            SimdImm::None => {}
+            SimdImm::MemArg => args.mem_arg = Some(self.read_mem_arg()?),
+            SimdImm::MemArgLane => {
+                args.mem_arg = Some(self.read_mem_arg()?);
+                args.lane = Some(self.read_byte()?);
+            }
+            SimdImm::Lane => args.lane = Some(self.read_byte()?),
+            SimdImm::V128|SimdImm::Shuffle => {
+                args.v128 = Some(byteorder::LittleEndian::read_u128(self.read_n(16)?));
             }
         }
+        Ok(instruction::ArgsEnum::SimdArgs(Box::new(args)))
+    }
 
+    /// 0xFE子操作码后面的立即数
+    pub fn read_atomic_args(&mut self,sub:u32) -> DecodeResult<instruction::ArgsEnum>{
+        let mem_arg = if sub == atomic::AtomicFence {
+            self.read_zero()?;
+            None
+        } else {
+            Some(self.read_mem_arg()?)
+        };
+        Ok(instruction::ArgsEnum::AtomicArgs(instruction::AtomicArgs{ mem_arg }))
     }
 
-    pub fn read_br_table_args(&mut self) -> Option<instruction::ArgsEnum>{
-        Some(instruction::ArgsEnum::BrTableArgs(instruction::BrTableArgs{
-            labels: self.read_indices(),
-            default: self.read_var_u32(),
+    /// 进入一层块,超过嵌套上限报错
+    fn enter_block(&mut self) -> DecodeResult<()>{
+        if self.depth >= self.max_depth {
+            return Err(self.err(DecodeErrorKind::Malformed("block nesting too deep".to_string())));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// block/loop/try_table,try_table多了catch子句,单独一个函数,不让block的栈帧变大
+    pub fn read_block_args(&mut self,opcode:u8) -> DecodeResult<instruction::ArgsEnum>{
+        if opcode == opcodes::TryTable {
+            return self.read_try_table_args();
+        }
+        let bt = self.read_block_type()?;
+        self.enter_block()?;
+        let offset = self.offset();
+        let (instrs,end) = self.read_instructions()?;
+        self.depth -= 1;
+        if end != opcodes::End_ {
+            return Err(self.err_at(offset,DecodeErrorKind::InvalidEnd(end)));
+        }
+        Ok(instruction::ArgsEnum::BlockArgs(instruction::BlockArgs{
+            bt: Some(bt),
+            instrs: Some(instrs)
         }))
     }
 
-    pub fn read_call_indirect_args(&mut self) -> Option<u32>{
+    #[inline(never)]
+    pub fn read_try_table_args(&mut self) -> DecodeResult<instruction::ArgsEnum>{
+        let bt = self.read_block_type()?;
+        let catches = self.read_catches()?;
+        self.enter_block()?;
+        let offset = self.offset();
+        let (instrs,end) = self.read_instructions()?;
+        self.depth -= 1;
+        if end != opcodes::End_ {
+            return Err(self.err_at(offset,DecodeErrorKind::InvalidEnd(end)));
+        }
+        Ok(instruction::ArgsEnum::TryTableArgs(Box::new(instruction::TryTableArgs{
+            bt: Some(bt),
+            catches: Some(catches),
+            instrs: Some(instrs)
+        })))
+    }
+
+    /// catch x l / catch_ref x l / catch_all l / catch_all_ref l
+    pub fn read_catches(&mut self) -> DecodeResult<Vec<instruction::Catch>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<instruction::Catch> = Vec::new();
+        for _ in 0..n {
+            let offset = self.offset();
+            let kind = self.read_byte()?;
+            let tag = match kind {
+                instruction::CATCH|instruction::CATCH_REF => Some(self.read_var_u32()?),
+                instruction::CATCH_ALL|instruction::CATCH_ALL_REF => None,
+                _ => return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("invalid catch kind:{:?}",kind)))),
+            };
+            v.push(instruction::Catch{ kind, tag, label: self.read_var_u32()? });
+        }
+        Ok(v)
+    }
+
+    pub fn read_if_args(&mut self) -> DecodeResult<instruction::ArgsEnum>{
+        let bt = self.read_block_type()?;
+        self.enter_block()?;
+        let (instrs1,end) = self.read_instructions()?;
+        let mut ia = instruction::IfArgs{
+            bt: Some(bt),
+            instrs1: Some(instrs1),
+            instrs2: None
+        };
 
-        match self.read_var_u32() {
-            None => {panic!("read var u32 none")}
-            Some(n) => {
-                self.read_zero();
-                Some(n)
+        // 遇到else才有第二段指令
+        if end == opcodes::Else_ {
+            let offset = self.offset();
+            let (instrs2,end2) = self.read_instructions()?;
+            if end2 != opcodes::End_ {
+                return Err(self.err_at(offset,DecodeErrorKind::InvalidEnd(end2)));
             }
+            ia.instrs2 = Some(instrs2);
         }
+        self.depth -= 1;
+        Ok(instruction::ArgsEnum::IfArgs(ia))
     }
 
-    pub fn read_mem_arg(&mut self) -> Option<instruction::MemArg>{
+    pub fn read_br_table_args(&mut self) -> DecodeResult<instruction::ArgsEnum>{
+        Ok(instruction::ArgsEnum::BrTableArgs(instruction::BrTableArgs{
+            labels: Some(self.read_indices()?),
+            default: Some(self.read_var_u32()?),
+        }))
+    }
 
-        Some(instruction::MemArg{
-            align: self.read_var_u32(),
-            offset: self.read_var_u32(),
-        })
+    pub fn read_call_indirect_args(&mut self) -> DecodeResult<instruction::ArgsEnum>{
+        Ok(instruction::ArgsEnum::CallIndirectArgs(instruction::CallIndirectArgs{
+            type_idx: Some(self.read_var_u32()?),
+            table: Some(self.read_var_u32()?),
+        }))
     }
 
-    pub fn read_zero(&mut self) -> Option<u8>{
-        self.read_byte().and_then(|b|{
-            if b != 0 {
-                panic!("zero flag expected, got:{:?}",b)
-            } else {
-                Some(0)
-            }
+    /// 带类型的select,类型只能有一个
+    pub fn read_select_types(&mut self) -> DecodeResult<instruction::ArgsEnum>{
+        let offset = self.offset();
+        let types = self.read_val_types()?;
+        if types.len() != 1 {
+            return Err(self.err_at(offset,DecodeErrorKind::Malformed("invalid result arity".to_string())));
+        }
+        Ok(instruction::ArgsEnum::U8(types[0]))
+    }
+
+    /// 对齐标志的第6位表示后面跟着内存索引
+    pub fn read_mem_arg(&mut self) -> DecodeResult<instruction::MemArg>{
+        let flags = self.read_var_u32()?;
+        let mem = if flags & instruction::MEM_ARG_HAS_MEM != 0 {
+            Some(self.read_var_u32()?)
+        } else {
+            None
+        };
+        Ok(instruction::MemArg{
+            align: Some(flags & !instruction::MEM_ARG_HAS_MEM),
+            offset: Some(self.read_var_u64()?),
+            mem,
         })
     }
+
+    pub fn read_zero(&mut self) -> DecodeResult<u8>{
+        let offset = self.offset();
+        let b = self.read_byte()?;
+        if b != 0 {
+            Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("zero flag expected, got:{:?}",b))))
+        } else {
+            Ok(0)
+        }
+    }
 }
 
 /// 读取类型
-impl WasmReader {
+impl<'a> WasmReader<'a> {
 
-    pub fn read_func_type(&mut self) -> Option<module::FuncType>{
-        let ft = module::FuncType{
-            tag: self.read_byte(),
-            param_types: self.read_val_types(),
-            result_types: self.read_val_types(),
-        };
-
-        match ft.tag {
-            None => {
-                panic!("read func type none")
-            }
-            Some(n) => {
-                if n != module::FT_TAG {
-                    panic!("invalid elem type")
-                }
-
-                Some(ft)
-            }
+    pub fn read_func_type(&mut self) -> DecodeResult<module::FuncType>{
+        let offset = self.offset();
+        let tag = self.read_byte()?;
+        if tag != module::FT_TAG {
+            return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("invalid func type tag:{:?}",tag))));
         }
 
+        Ok(module::FuncType{
+            tag: Some(tag),
+            param_types: Some(self.read_val_types()?),
+            result_types: Some(self.read_val_types()?),
+        })
     }
 
-    pub fn read_table_type(&mut self) -> Option<module::TableType>{
-        let tt = module::TableType{
-            elem_type: self.read_byte(),
-            limits: self.read_limits(),
-        };
-
-        match tt.elem_type {
-            None => {None}
-            Some(n) => {
-                if n != module::FUNC_REF {
-                    panic!("invalid element type:{:?}",n);
-                }
+    pub fn read_table_type(&mut self) -> DecodeResult<module::TableType>{
+        let elem_type = self.read_ref_type()?;
 
-                Some(tt)
-            }
-        }
+        Ok(module::TableType{
+            elem_type: Some(elem_type),
+            limits: Some(self.read_limits()?),
+        })
     }
 
-    pub fn read_global_type(&mut self) -> Option<module::GlobalType>{
+    pub fn read_global_type(&mut self) -> DecodeResult<module::GlobalType>{
+        let val_type = self.read_val_type()?;
+        let offset = self.offset();
+        let m = self.read_byte()?;
 
-        let mut gt = module::GlobalType{
-            val_type: self.read_val_type(),
-            m: self.read_byte()
-        };
+        match m {
+            module::MUT_CONST => {},
+            module::MUT_VAR => {},
+            _ => {
+                return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("malformed mutability:{:?}",m))));
+            },
+        }
 
-        match gt.m {
-            None => {
-                panic!("read global type is none")
-            }
-            Some(n) => {
-                match n {
-                    module::MUT_CONST => {},
-                    module::MUT_VAR => {},
-                    _ => {
-                        panic!("malformed mutability:{:?}",n)
-                    },
-                }
+        Ok(module::GlobalType{
+            val_type: Some(val_type),
+            m: Some(m)
+        })
+    }
 
-                gt.m = Some(n);
+    pub fn read_val_types(&mut self) -> DecodeResult<Vec<u8>>{
+        let n = self.read_var_u32()?;
+        let mut v:Vec<u8> = Vec::new();
+        for _ in 0..n {
+            v.push(self.read_val_type()?);
+        }
+        Ok(v)
+    }
 
-                Some(gt)
+    pub fn read_val_type(&mut self) -> DecodeResult<u8>{
+        let offset = self.offset();
+        let n = self.read_byte()?;
+        match n {
+            module::VAL_TYPE_I32 => {},
+            module::VAL_TYPE_F32 => {},
+            module::VAL_TYPE_F64 => {},
+            module::VAL_TYPE_I64 => {},
+            module::VAL_TYPE_V128 => {},
+            module::FUNC_REF => {},
+            module::EXTERN_REF => {},
+            module::EXN_REF => {},
+            _ => {
+                return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("malformed value type:{:?}",n))));
             }
         }
 
-
+        Ok(n)
     }
 
-    pub fn read_val_types(&mut self) -> Option<Vec<u8>>{
-        match self.read_var_u32() {
-            None => {None}
-            Some(n) => {
-                let mut v:Vec<u8> = Vec::new();
-                for _ in 0..n {
-                    match self.read_val_type() {
-                        None => {
-                            panic!("read val none")
-                        }
-                        Some(num) => {
-                            v.push(num);
-                        }
-                    }
-                }
-                Some(v)
-            }
+    pub fn read_ref_type(&mut self) -> DecodeResult<u8>{
+        let offset = self.offset();
+        let n = self.read_byte()?;
+        if !module::is_ref_type(n) {
+            return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("malformed reference type:{:?}",n))));
         }
+        Ok(n)
     }
 
-    pub fn read_val_type(&mut self) -> Option<u8>{
-        match self.read_byte() {
-            None => {panic!("read val type read byte is none")}
-            Some(mut n) => {
-                match n {
-                    module::VAL_TYPE_I32 => {},
-                    module::VAL_TYPE_F32 => {},
-                    module::VAL_TYPE_F64 => {},
-                    module::VAL_TYPE_I64 => {},
-                    _ => {
-                        panic!("malformed value type:{:?}",n);
-                    }
-                }
-
-                Some(n)
-            }
+    /// 标签类型,属性只能是0
+    pub fn read_tag_type(&mut self) -> DecodeResult<module::TagType>{
+        let offset = self.offset();
+        let attribute = self.read_byte()?;
+        if attribute != module::TAG_ATTRIBUTE_EXCEPTION {
+            return Err(self.err_at(offset,DecodeErrorKind::Malformed(format!("invalid tag attribute:{:?}",attribute))));
         }
+        Ok(module::TagType{ attribute: Some(attribute), type_idx: Some(self.read_var_u32()?) })
     }
 
-    pub fn read_block_type(&mut self) -> Option<i32>{
-
-        match self.read_var_s32() {
-            None => {panic!("read i32 is none")}
-            Some(n) => {
-                if n < 0 {
-                    match n {
-                        module::BLOCK_TYPE_I32|
-                        module::BLOCK_TYPE_I64|
-                        module::BLOCK_TYPE_F32|
-                        module::BLOCK_TYPE_F64|
-                        module::BLOCK_TYPE_EMPTY => {Some(n)}
-                        _ => None
-                    }
-                } else {
-                    Some(n)
-                }
-            }
-        }
+    pub fn read_block_type(&mut self) -> DecodeResult<BlockType>{
+        let offset = self.offset();
+        let n = self.read_var_s33()?;
+        BlockType::from_s33(n).ok_or_else(||self.err_at(offset,DecodeErrorKind::Malformed(format!("malformed block type:{:?}",n))))
     }
 }
 
@@ -1045,9 +1147,7 @@ mod test {
     fn test1(){
         use crate::binary::reader;
         let v = b"hello".to_vec();
-        let mut r = reader::WasmReader{
-            data: v
-        };
+        let mut r = reader::WasmReader::new(&v);
 
         println!("{:?}",r.read_u32());
         println!("{:?}",r.remaining());
@@ -1067,10 +1167,10 @@ mod test {
             0xC0, 0xBB, 0x78,
             0x03, 0x01, 0x02, 0x03,
             0x03, 0x66, 0x6f, 0x6f,
-            0x80, 0x80, 0xc0, 0x0b
+            0xE5, 0x8E, 0xA6, 0x80, 0x00, // 补齐到5字节的624485
         ];
 
-        let mut reader = reader::WasmReader{ data: v };
+        let mut reader = reader::WasmReader::new(&v);
 
 
         assert_eq!(1u8,reader.read_byte().unwrap());
@@ -1084,4 +1184,364 @@ mod test {
         assert_eq!("foo".to_string(),reader.read_name().unwrap());
         assert_eq!(624485u32,reader.read_var_u32().unwrap());
     }
+
+    #[test]
+    fn test3(){
+        use crate::binary::reader;
+        use crate::common::common_error::DecodeErrorKind;
+
+        // 魔数不对
+        let e = reader::decode(vec![0x00,0x61,0x73,0x6e,0x01,0x00,0x00,0x00]).unwrap_err();
+        assert_eq!(e.offset,0);
+        assert_eq!(e.kind,DecodeErrorKind::BadMagic(0x6e736100));
+
+        // 段顺序错误
+        let e = reader::decode(vec![0x00,0x61,0x73,0x6d,0x01,0x00,0x00,0x00, 0x03,0x01,0x00, 0x01,0x01,0x00]).unwrap_err();
+        assert_eq!(e.offset,11);
+        assert_eq!(e.kind,DecodeErrorKind::SectionOutOfOrder(1,3));
+
+        // 段大小不对,类型段声明2个字节,实际只用了1个
+        let e = reader::decode(vec![0x00,0x61,0x73,0x6d,0x01,0x00,0x00,0x00, 0x01,0x02,0x00,0x00]).unwrap_err();
+        assert_eq!(e.sec_id,Some(1));
+        assert_eq!(e.kind,DecodeErrorKind::SectionSizeMismatch(2,1));
+
+        // 段大小超出剩余数据
+        let e = reader::decode(vec![0x00,0x61,0x73,0x6d,0x01,0x00,0x00,0x00, 0x01,0x05,0x00]).unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::UnexpectedEof);
+
+        // leb128太长
+        let e = reader::decode(vec![0x00,0x61,0x73,0x6d,0x01,0x00,0x00,0x00, 0x01,0x80,0x80,0x80,0x80,0x80,0x00]).unwrap_err();
+        assert_eq!(e.offset,9);
+        assert!(matches!(e.kind,DecodeErrorKind::BadLeb128(_)));
+
+        // 代码段里的未知操作码,带上函数索引
+        let e = reader::decode(vec![
+            0x00,0x61,0x73,0x6d,0x01,0x00,0x00,0x00,
+            0x01,0x04,0x01,0x60,0x00,0x00,
+            0x03,0x02,0x01,0x00,
+            0x0a,0x05,0x01,0x03,0x00,0xff,0x0b,
+        ]).unwrap_err();
+        assert_eq!(e.offset,23);
+        assert_eq!(e.sec_id,Some(10));
+        assert_eq!(e.func_idx,Some(0));
+        assert_eq!(e.kind,DecodeErrorKind::UnknownOpcode(0xff));
+    }
+
+    #[test]
+    fn test4(){
+        use crate::binary::reader;
+        use crate::common::common_error::DecodeErrorKind;
+
+        // 截断或篡改过的二进制只能返回错误,不能panic
+        let data = std::fs::read("./hw_rust.wasm").unwrap();
+        for n in (0..data.len()).step_by(251) {
+            assert!(reader::decode(data[..n].to_vec()).is_err());
+        }
+        for i in (8..data.len()).step_by(509) {
+            let mut v = data.clone();
+            v[i] = v[i].wrapping_add(0x5a);
+            let _ = reader::decode(v);
+        }
+
+        // 嵌套太深的块,后面补齐end,不会因为数据不够而出错
+        crate::binary::init();
+        let nested = |n:usize|{
+            let mut body:Vec<u8> = Vec::new();
+            for _ in 0..n {
+                body.extend_from_slice(&[0x02,0x40]);
+            }
+            body.resize(body.len() + n + 1,0x0b);
+            body
+        };
+        let body = nested(9);
+        let mut r = reader::WasmReader::new(&body);
+        r.set_max_block_depth(8);
+        let e = r.read_expr().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("block nesting too deep".to_string()));
+        assert_eq!(e.offset,18);
+        let mut r = reader::WasmReader::new(&body);
+        r.set_max_block_depth(9);
+        assert_eq!(r.read_expr().unwrap().len(),1);
+
+        // 默认上限在测试线程默认大小的栈上就能解码
+        let body = nested(reader::MAX_BLOCK_DEPTH as usize);
+        assert!(reader::WasmReader::new(&body).read_expr().is_ok());
+        let body = nested(reader::MAX_BLOCK_DEPTH as usize + 1);
+        let e = reader::WasmReader::new(&body).read_expr().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("block nesting too deep".to_string()));
+    }
+
+    #[test]
+    fn test5(){
+        use crate::binary::reader;
+        use crate::common::common_error::DecodeErrorKind;
+
+        let header = vec![0x00,0x61,0x73,0x6d,0x01,0x00,0x00,0x00];
+        // 数据计数段和数据段个数不一致
+        let mut data = header.clone();
+        data.extend(&[0x0c,0x01,0x02, 0x0b,0x04,0x01,0x01,0x01,0xaa]);
+        let e = reader::decode_bytes(&data).unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("data count and data section have inconsistent lengths".to_string()));
+        let mut data = header.clone();
+        data.extend(&[0x0c,0x01,0x01, 0x0b,0x04,0x01,0x01,0x01,0xaa]);
+        let m = reader::decode_bytes(&data).unwrap();
+        assert_eq!(m.data_count_sec,Some(1));
+        assert!(m.data_sec.unwrap()[0].offset.is_none());
+
+        // 数据计数段要在代码段前面
+        let mut data = header.clone();
+        data.extend(&[0x0a,0x01,0x00, 0x0c,0x01,0x00]);
+        let e = reader::decode_bytes(&data).unwrap_err();
+        assert_eq!(e.offset,11);
+        assert_eq!(e.kind,DecodeErrorKind::SectionOutOfOrder(12,10));
+
+        // 未知的数据段标志和0xFC子操作码
+        let mut data = header.clone();
+        data.extend(&[0x0b,0x03,0x01,0x03,0x00]);
+        let e = reader::decode_bytes(&data).unwrap_err();
+        assert_eq!(e.offset,11);
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("invalid data segment flag:3".to_string()));
+        let mut data = header;
+        data.extend(&[
+            0x01,0x04,0x01,0x60,0x00,0x00,
+            0x03,0x02,0x01,0x00,
+            0x0a,0x06,0x01,0x04,0x00,0xfc,0x0c,0x0b,
+        ]);
+        let e = reader::decode_bytes(&data).unwrap_err();
+        assert_eq!(e.offset,24);
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("unknown 0xfc sub opcode:12".to_string()));
+    }
+
+    #[test]
+    fn test6(){
+        use crate::binary::reader;
+        use crate::binary::module::{BlockType, VAL_TYPE_F64};
+        use crate::common::common_error::DecodeErrorKind;
+
+        // 块类型是s33,单字节的负数是值类型,非负数是类型索引
+        let v = vec![0x40, 0x7c, 0x00, 0x3f, 0xc0,0x00, 0xff,0xff,0xff,0xff,0x0f];
+        let mut r = reader::WasmReader::new(&v);
+        assert_eq!(r.read_block_type().unwrap(),BlockType::Empty);
+        assert_eq!(r.read_block_type().unwrap(),BlockType::Value(VAL_TYPE_F64));
+        assert_eq!(r.read_block_type().unwrap(),BlockType::TypeIdx(0));
+        assert_eq!(r.read_block_type().unwrap(),BlockType::TypeIdx(63));
+        assert_eq!(r.read_block_type().unwrap(),BlockType::TypeIdx(64));
+        assert_eq!(r.read_block_type().unwrap(),BlockType::TypeIdx(u32::MAX));
+
+        let v = vec![0x7a];
+        let e = reader::WasmReader::new(&v).read_block_type().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("malformed block type:-6".to_string()));
+        // 超过33位
+        let v = vec![0xff,0xff,0xff,0xff,0x1f];
+        assert!(reader::WasmReader::new(&v).read_block_type().is_err());
+    }
+
+    #[test]
+    fn test7(){
+        use crate::binary::reader;
+        use crate::binary::module::{FUNC_REF, EXTERN_REF};
+        use crate::binary::opcodes;
+        use crate::common::common_error::DecodeErrorKind;
+
+        // 0: 表0的主动段,函数索引
+        let v = vec![0x00, 0x41,0x01,0x0b, 0x02,0x03,0x04];
+        let e = reader::WasmReader::new(&v).read_elem().unwrap();
+        assert_eq!((e.table,e.elem_type,e.init),(Some(0),None,Some(vec![3,4])));
+        // 1: 被动段,元素种类
+        let v = vec![0x01, 0x00, 0x01,0x05];
+        let e = reader::WasmReader::new(&v).read_elem().unwrap();
+        assert_eq!((e.is_active(),e.elem_type,e.init),(false,Some(FUNC_REF),Some(vec![5])));
+        // 6: 带表索引的主动段,表达式
+        let v = vec![0x06, 0x01, 0x41,0x00,0x0b, 0x6f, 0x01, 0xd0,0x6f,0x0b];
+        let e = reader::WasmReader::new(&v).read_elem().unwrap();
+        assert_eq!((e.table,e.get_elem_type()),(Some(1),EXTERN_REF));
+        assert_eq!(e.exprs.unwrap()[0][0].opcode,Some(opcodes::RefNull));
+        // 7: 声明段
+        let v = vec![0x07, 0x70, 0x00];
+        let e = reader::WasmReader::new(&v).read_elem().unwrap();
+        assert!(e.declarative && e.exprs.map(|v|v.is_empty()) == Some(true));
+
+        let v = vec![0x08];
+        let e = reader::WasmReader::new(&v).read_elem().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("invalid elem segment flag:8".to_string()));
+        let v = vec![0x05, 0x7f, 0x00];
+        let e = reader::WasmReader::new(&v).read_elem().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("malformed reference type:127".to_string()));
+    }
+
+    #[test]
+    fn test8(){
+        use crate::binary::reader;
+        use crate::binary::simd;
+        use crate::common::common_error::DecodeErrorKind;
+
+        // SIMD的子操作码是u32,可以用多字节的leb128
+        crate::binary::init();
+        let v = vec![0xfd, 0x8b, 0x01, 0xfd, 0x80, 0x00, 0x02, 0x10, 0xfd, 0x15, 0x0f];
+        let mut r = reader::WasmReader::new(&v);
+        let i = r.read_instruction().unwrap();
+        assert_eq!(simd::name(i.sub),Some("i16x8.shl"));
+        let i = r.read_instruction().unwrap();
+        let mem_arg = i.args.unwrap().get_simd_args().mem_arg.unwrap();
+        assert_eq!((i.sub,mem_arg.align,mem_arg.offset),(simd::V128Load,Some(2),Some(16)));
+        let i = r.read_instruction().unwrap();
+        assert_eq!((i.sub,i.args.unwrap().get_simd_args().lane),(simd::I8x16ExtractLaneS,Some(15)));
+
+        // 0x9a是保留的子操作码
+        let v = vec![0xfd, 0x9a, 0x01];
+        let e = reader::WasmReader::new(&v).read_instruction().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("unknown 0xfd sub opcode:154".to_string()));
+        // v128.const后面不够16个字节
+        let v = vec![0xfd, 0x0c, 0x01, 0x02];
+        assert!(reader::WasmReader::new(&v).read_instruction().is_err());
+    }
+
+    #[test]
+    fn test9(){
+        use crate::binary::reader;
+        use crate::binary::atomic;
+        use crate::common::common_error::DecodeErrorKind;
+
+        // 共享内存的限制标志是0x02/0x03
+        let v = vec![0x03, 0x01, 0x02, 0x02, 0x01, 0x08, 0x01];
+        let mut r = reader::WasmReader::new(&v);
+        let l = r.read_limits().unwrap();
+        assert!(l.is_shared() && l.min == Some(1) && l.max == Some(2));
+        let l = r.read_limits().unwrap();
+        assert!(l.is_shared() && l.max.is_none());
+        let e = r.read_limits().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("integer too large, limits tag:8".to_string()));
+
+        // 原子指令,atomic.fence后面是保留的0字节
+        crate::binary::init();
+        let v = vec![0xfe, 0x1e, 0x02, 0x08, 0xfe, 0x03, 0x00, 0xfe, 0xce, 0x00, 0x03, 0x00];
+        let mut r = reader::WasmReader::new(&v);
+        let i = r.read_instruction().unwrap();
+        let mem_arg = i.args.unwrap().get_atomic_args().mem_arg.unwrap();
+        assert_eq!((i.sub,mem_arg.align,mem_arg.offset),(atomic::I32AtomicRmwAdd,Some(2),Some(8)));
+        let i = r.read_instruction().unwrap();
+        assert!(i.sub == atomic::AtomicFence && i.args.unwrap().get_atomic_args().mem_arg.is_none());
+        let i = r.read_instruction().unwrap();
+        assert_eq!(atomic::name(i.sub),Some("i64.atomic.rmw32.cmpxchg_u"));
+
+        let e = reader::WasmReader::new(&[0xfe, 0x03, 0x01]).read_instruction().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("zero flag expected, got:1".to_string()));
+        let e = reader::WasmReader::new(&[0xfe, 0x04, 0x02, 0x00]).read_instruction().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("unknown 0xfe sub opcode:4".to_string()));
+    }
+
+    #[test]
+    fn test10(){
+        use crate::binary::{reader, module, instruction, opcodes};
+        use crate::common::common_error::DecodeErrorKind;
+
+        // 标签段在内存段和全局段之间,导入导出的类型都是4
+        let header = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        let mut v = header.clone();
+        v.extend_from_slice(&[0x01, 0x05, 0x01, 0x60, 0x01, 0x7f, 0x00]);
+        v.extend_from_slice(&[0x02, 0x08, 0x01, 0x01, 0x6d, 0x01, 0x65, 0x04, 0x00, 0x00]);
+        v.extend_from_slice(&[0x05, 0x03, 0x01, 0x00, 0x01]);
+        v.extend_from_slice(&[0x0d, 0x03, 0x01, 0x00, 0x00]);
+        v.extend_from_slice(&[0x06, 0x06, 0x01, 0x7f, 0x00, 0x41, 0x00, 0x0b]);
+        v.extend_from_slice(&[0x07, 0x05, 0x01, 0x01, 0x74, 0x04, 0x01]);
+        let m = reader::decode_bytes(&v).unwrap();
+        assert_eq!(m.tag_sec.as_ref().map(|v|v.len()),Some(1));
+        let desc = m.import_sec.as_ref().unwrap()[0].import_desc.clone().unwrap();
+        assert_eq!((desc.tag,desc.tag_type.and_then(|t|t.type_idx)),(Some(module::IMPORT_TAG_TAG),Some(0)));
+        assert_eq!(m.export_sec.as_ref().unwrap()[0].desc.as_ref().unwrap().tag,Some(module::EXPORT_TAG_TAG));
+        assert_eq!(m.get_tag_func_type(1).map(|ft|ft.params().to_vec()),Some(vec![module::VAL_TYPE_I32]));
+
+        let mut v = header.clone();
+        v.extend_from_slice(&[0x01, 0x04, 0x01, 0x60, 0x00, 0x00]);
+        v.extend_from_slice(&[0x06, 0x01, 0x00]);
+        v.extend_from_slice(&[0x0d, 0x01, 0x00]);
+        let e = reader::decode_bytes(&v).unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::SectionOutOfOrder(module::SEC_TAG_ID,module::SEC_GLOBAL_ID));
+        let e = reader::WasmReader::new(&[0x01, 0x00]).read_tag_type().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("invalid tag attribute:1".to_string()));
+
+        // try_table (catch 0 1) (catch_all_ref 0) throw 0 end, throw_ref
+        crate::binary::init();
+        let v = vec![0x1f, 0x40, 0x02, 0x00, 0x00, 0x01, 0x03, 0x00, 0x08, 0x00, 0x0b, 0x0a];
+        let mut r = reader::WasmReader::new(&v);
+        let args = r.read_instruction().unwrap().args.unwrap().get_try_table_args();
+        assert_eq!(args.catches.unwrap(),vec![
+            instruction::Catch{ kind: instruction::CATCH, tag: Some(0), label: 1 },
+            instruction::Catch{ kind: instruction::CATCH_ALL_REF, tag: None, label: 0 },
+        ]);
+        let body = args.instrs.unwrap();
+        assert_eq!((body[0].opcode,body[0].args.clone()),(Some(opcodes::Throw),Some(instruction::ArgsEnum::U32(0))));
+        assert_eq!(r.read_instruction().unwrap().opcode,Some(opcodes::ThrowRef));
+        let e = reader::WasmReader::new(&[0x1f, 0x40, 0x01, 0x04, 0x00, 0x0b]).read_instruction().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("invalid catch kind:4".to_string()));
+    }
+
+    #[test]
+    fn test11(){
+        use crate::binary::{opcodes, reader};
+
+        // memory64的限制标志是0x04~0x07,上下限是u64
+        let v = vec![0x05, 0x01, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20, 0x04, 0x00, 0x07, 0x01, 0x02];
+        let mut r = reader::WasmReader::new(&v);
+        let l = r.read_limits().unwrap();
+        assert!(l.is_64() && !l.is_shared() && l.min == Some(1) && l.max == Some(1 << 40));
+        let l = r.read_limits().unwrap();
+        assert!(l.is_64() && l.min == Some(0) && l.max.is_none());
+        let l = r.read_limits().unwrap();
+        assert!(l.is_64() && l.is_shared() && l.max == Some(2));
+        // 32位内存的上下限还是u32
+        assert!(reader::WasmReader::new(&[0x00, 0x80, 0x80, 0x80, 0x80, 0x10]).read_limits().is_err());
+
+        // memarg的偏移是u64
+        crate::binary::init();
+        let v = vec![0x29, 0x03, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20];
+        let i = reader::WasmReader::new(&v).read_instruction().unwrap();
+        let mem_arg = i.args.unwrap().get_mem_args();
+        assert_eq!((i.opcode,mem_arg.align,mem_arg.offset),(Some(opcodes::I64Load),Some(3),Some(1 << 40)));
+    }
+
+    #[test]
+    fn test12(){
+        use crate::binary::{opcodes, reader};
+        use crate::binary::instruction::{ArgsEnum, PrefixArgs};
+
+        // 多内存: memarg标志带0x40时对齐后面跟内存索引
+        crate::binary::init();
+        let v = vec![0x28, 0x42, 0x01, 0x04, 0x3f, 0x01, 0xfc, 0x0a, 0x01, 0x00, 0xfc, 0x08, 0x02, 0x01, 0xfc, 0x0b, 0x03];
+        let mut r = reader::WasmReader::new(&v);
+        let i = r.read_instruction().unwrap();
+        let mem_arg = i.args.unwrap().get_mem_args();
+        assert_eq!((i.opcode,mem_arg.align,mem_arg.offset,mem_arg.mem),(Some(opcodes::I32Load),Some(2),Some(4),Some(1)));
+        let i = r.read_instruction().unwrap();
+        assert_eq!((i.opcode,i.args.unwrap().get_u32()),(Some(opcodes::MemorySize),1));
+        let i = r.read_instruction().unwrap();
+        assert_eq!(i.sub,opcodes::MemoryCopy);
+        assert_eq!(i.args,Some(ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(1), idx2: Some(0) })));
+        let i = r.read_instruction().unwrap();
+        assert_eq!(i.sub,opcodes::MemoryInit);
+        assert_eq!(i.args,Some(ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(2), idx2: Some(1) })));
+        let i = r.read_instruction().unwrap();
+        assert_eq!(i.sub,opcodes::MemoryFill);
+        assert_eq!(i.args,Some(ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(3), idx2: None })));
+    }
+
+    #[test]
+    fn test13(){
+        use crate::binary::{opcodes, reader, simd};
+        use crate::binary::opcodes::Opcode;
+        use crate::common::common_error::DecodeErrorKind;
+
+        // 前缀后面的子操作码都是u32的leb128,可以有填充
+        crate::binary::init();
+        let v = vec![0xfc, 0x87, 0x00, 0xfc, 0x8b, 0x80, 0x00, 0x00, 0xfd, 0x8f, 0x00];
+        let mut r = reader::WasmReader::new(&v);
+        assert_eq!(r.read_instruction().unwrap().op(),Opcode::new(opcodes::MiscPrefix,opcodes::I64TruncSatF64U));
+        assert_eq!(r.read_instruction().unwrap().op(),Opcode::new(opcodes::MiscPrefix,opcodes::MemoryFill));
+        assert_eq!(r.read_instruction().unwrap().op(),Opcode::new(opcodes::SimdPrefix,simd::I8x16Splat));
+
+        let e = reader::WasmReader::new(&[0xfc, 0x80, 0x01]).read_instruction().unwrap_err();
+        assert_eq!((e.offset,e.kind),(1,DecodeErrorKind::Malformed("unknown 0xfc sub opcode:128".to_string())));
+        let e = reader::WasmReader::new(&[0xfe, 0x04]).read_instruction().unwrap_err();
+        assert_eq!(e.kind,DecodeErrorKind::Malformed("unknown 0xfe sub opcode:4".to_string()));
+    }
 }
@@ -0,0 +1,478 @@
+//! 流式解码
+//! 字节可以一块一块地push进来,每凑够一段就吐出一个事件,
+//! 代码段不等整段到齐,每个函数体的字节到齐了就吐出来
+//!
+//! 事件顺序:
+//! Header -> Section/CodeSectionStart+CodeBody* ... -> End
+
+use std::io::Read;
+use std::ops::Range;
+use crate::binary::{module, opcodes};
+use crate::binary::reader::{WasmReader, DecodeResult};
+use crate::common::common_error::{DecodeError, DecodeErrorKind};
+
+/// 已经解析好的段内容
+#[derive(Debug,Clone)]
+pub enum Section{
+    Custom(module::CustomSecs),
+    Type(Vec<module::FuncType>),
+    Import(Vec<module::Import>),
+    Func(Vec<module::TypeIdx>),
+    Table(Vec<module::TableType>),
+    Mem(Vec<module::MemType>),
+    Tag(Vec<module::TagType>),
+    Global(Vec<module::GlobalSec>),
+    Export(Vec<module::Export>),
+    Start(module::FuncIdx),
+    Elem(Vec<module::Elem>),
+    Data(Vec<module::Data>),
+    DataCount(u32),
+}
+
+impl Section {
+    /// 把段内容放到模块里
+    pub fn apply(self,m:&mut module::Module){
+        match self {
+            Section::Custom(c) => m.add_custom_sec(c),
+            Section::Type(v) => m.type_sec = Some(v),
+            Section::Import(v) => m.import_sec = Some(v),
+            Section::Func(v) => m.func_sec = Some(v),
+            Section::Table(v) => m.table_sec = Some(v),
+            Section::Mem(v) => m.mem_sec = Some(v),
+            Section::Tag(v) => m.tag_sec = Some(v),
+            Section::Global(v) => m.global_sec = Some(v),
+            Section::Export(v) => m.export_sec = Some(v),
+            Section::Start(idx) => m.start_sec = Some(idx),
+            Section::Elem(v) => m.elem_sec = Some(v),
+            Section::Data(v) => m.data_sec = Some(v),
+            Section::DataCount(n) => m.data_count_sec = Some(n),
+        }
+    }
+}
+
+/// 解码事件
+/// range 都是在整个二进制中的绝对偏移,Section的range是段内容(不含id和大小)
+#[derive(Debug,Clone)]
+pub enum Payload{
+    Header{ magic:u32, version:u32 },
+    Section{ id:u8, range:Range<usize>, content:Section },
+    CodeSectionStart{ count:u32, range:Range<usize> },
+    CodeBody{ func_idx:module::FuncIdx, range:Range<usize>, code:module::Code },
+    End,
+}
+
+impl Payload {
+    /// 把事件的内容放到模块里
+    pub fn apply(self,m:&mut module::Module){
+        match self {
+            Payload::Header { magic, version } => {
+                m.magic = Some(magic);
+                m.version = Some(version);
+            }
+            Payload::Section { content, .. } => content.apply(m),
+            Payload::CodeSectionStart { count, .. } => {
+                m.code_sec = Some(Vec::with_capacity(count as usize));
+            }
+            Payload::CodeBody { code, .. } => {
+                m.code_sec.get_or_insert_with(Vec::new).push(code);
+            }
+            Payload::End => {}
+        }
+    }
+}
+
+#[derive(Debug,Clone)]
+enum State{
+    Header,
+    Sections,
+    /// 正在读代码段,left 剩下的函数体个数,end 代码段结束的绝对偏移
+    CodeBodies{ left:u32, next_idx:u32, size:u32, start:usize, end:usize },
+    Done,
+}
+
+pub struct StreamDecoder{
+    buf:Vec<u8>,
+    /// buf[0]在整个二进制中的绝对偏移
+    buf_base:usize,
+    /// buf中已经处理过的位置
+    pos:usize,
+    eof:bool,
+    state:State,
+    prev_sec_id:u8,
+    import_func_count:u32,
+    func_count:usize,
+    code_count:usize,
+    data_count:Option<u32>,
+    data_len:usize,
+    lazy:bool,
+}
+
+impl Default for StreamDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamDecoder {
+    pub fn new() -> StreamDecoder{
+        if opcodes::OPCODE_MAP.get().is_none() {
+            opcodes::init();
+        }
+        StreamDecoder{
+            buf: Vec::new(),
+            buf_base: 0,
+            pos: 0,
+            eof: false,
+            state: State::Header,
+            prev_sec_id: 0,
+            import_func_count: 0,
+            func_count: 0,
+            code_count: 0,
+            data_count: None,
+            data_len: 0,
+            lazy: false
+        }
+    }
+
+    /// 函数体延迟解码,见module::LazyExpr
+    pub fn set_lazy(&mut self,lazy:bool){
+        self.lazy = lazy;
+    }
+
+    /// 追加收到的字节
+    pub fn push(&mut self,chunk:&[u8]){
+        // 已经处理过的部分超过一半时再挪动,保证整体是线性的
+        if self.pos > 0 && self.pos * 2 >= self.buf.len() {
+            self.buf.drain(..self.pos);
+            self.buf_base += self.pos;
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// 不会再有数据了
+    pub fn finish(&mut self){
+        self.eof = true;
+    }
+
+    /// 当前处理到的绝对偏移
+    pub fn offset(&self) -> usize{
+        self.buf_base + self.pos
+    }
+
+    fn available(&self) -> usize{
+        self.buf.len() - self.pos
+    }
+
+    fn reader(&self) -> WasmReader<'_>{
+        WasmReader::with_base(&self.buf[self.pos..],self.offset())
+    }
+
+    fn err(&self,kind:DecodeErrorKind) -> DecodeError{
+        DecodeError{ offset: self.offset(), sec_id: None, func_idx: None, kind }
+    }
+
+    /// 数据不够时,没结束就等下一块,结束了就报错
+    fn need_more<T>(&self,e:DecodeError) -> DecodeResult<Option<T>>{
+        if e.kind == DecodeErrorKind::UnexpectedEof && !self.eof {
+            Ok(None)
+        } else {
+            Err(e)
+        }
+    }
+
+    /// 读一个变长u32,返回值和占的字节数
+    fn peek_var_u32(&self,skip:usize) -> DecodeResult<Option<(u32,usize)>>{
+        let mut reader = WasmReader::with_base(&self.buf[self.pos + skip..],self.offset() + skip);
+        match reader.read_var_u32() {
+            Ok(n) => Ok(Some((n,self.available() - skip - reader.remaining()))),
+            Err(e) => self.need_more(e),
+        }
+    }
+
+    /// 取下一个事件,数据不够时返回None
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> DecodeResult<Option<Payload>>{
+        match self.state.clone() {
+            State::Header => self.next_header(),
+            State::Sections => self.next_section(),
+            State::CodeBodies { left, next_idx, size, start, end } => self.next_code_body(left,next_idx,size,start,end),
+            State::Done => Ok(None),
+        }
+    }
+
+    fn next_header(&mut self) -> DecodeResult<Option<Payload>>{
+        if self.available() < 8 {
+            return if self.eof {
+                Err(self.err(DecodeErrorKind::UnexpectedEof))
+            } else {
+                Ok(None)
+            }
+        }
+        let mut reader = self.reader();
+        let magic = reader.read_u32()?;
+        if magic != module::MAGIC_NUMBER {
+            return Err(self.err(DecodeErrorKind::BadMagic(magic)));
+        }
+        let version = reader.read_u32()?;
+        if version != module::VERSION {
+            return Err(reader.err_at(self.offset() + 4,DecodeErrorKind::BadVersion(version)));
+        }
+        self.pos += 8;
+        self.state = State::Sections;
+        Ok(Some(Payload::Header{ magic, version }))
+    }
+
+    fn next_section(&mut self) -> DecodeResult<Option<Payload>>{
+        if self.available() == 0 {
+            return if self.eof {
+                if self.func_count != self.code_count {
+                    return Err(self.err(DecodeErrorKind::Malformed("function and code section have inconsistent lengths".to_string())));
+                }
+                if self.data_count.map(|n|n as usize != self.data_len).unwrap_or(false) {
+                    return Err(self.err(DecodeErrorKind::Malformed("data count and data section have inconsistent lengths".to_string())));
+                }
+                self.state = State::Done;
+                Ok(Some(Payload::End))
+            } else {
+                Ok(None)
+            }
+        }
+
+        let id = self.buf[self.pos];
+        if id != module::SEC_CUSTOM_ID {
+            let order = match module::sec_order(id) {
+                Some(order) => order,
+                None => return Err(self.err(DecodeErrorKind::UnknownSection(id))),
+            };
+            if order <= module::sec_order(self.prev_sec_id).unwrap() {
+                return Err(self.err(DecodeErrorKind::SectionOutOfOrder(id,self.prev_sec_id)));
+            }
+        }
+
+        let (size,leb_len) = match self.peek_var_u32(1) {
+            Ok(Some(p)) => p,
+            Ok(None) => return Ok(None),
+            Err(mut e) => {
+                e.sec_id = Some(id);
+                return Err(e);
+            }
+        };
+        let header_len = 1 + leb_len;
+        let start = self.offset() + header_len;
+        let end = start + size as usize;
+
+        if id == module::SEC_CODE_ID {
+            // 代码段只要等到函数个数就可以开始了
+            let (count,count_len) = match self.peek_var_u32(header_len) {
+                Ok(Some(p)) => p,
+                Ok(None) => {
+                    if self.eof {
+                        return Err(self.err(DecodeErrorKind::UnexpectedEof));
+                    }
+                    return Ok(None)
+                },
+                Err(mut e) => {
+                    e.sec_id = Some(id);
+                    return Err(e);
+                }
+            };
+            if count_len > size as usize {
+                return Err(DecodeError{ offset: start, sec_id: Some(id), func_idx: None, kind: DecodeErrorKind::UnexpectedEof });
+            }
+            self.prev_sec_id = id;
+            self.code_count = count as usize;
+            self.pos += header_len + count_len;
+            self.state = State::CodeBodies{ left: count, next_idx: self.import_func_count, size, start, end };
+            return Ok(Some(Payload::CodeSectionStart{ count, range: start..end }));
+        }
+
+        if self.available() < header_len + size as usize {
+            return if self.eof {
+                Err(DecodeError{ offset: start, sec_id: Some(id), func_idx: None, kind: DecodeErrorKind::UnexpectedEof })
+            } else {
+                Ok(None)
+            }
+        }
+
+        let mut reader = WasmReader::with_base(&self.buf[self.pos + 1..self.pos + header_len + size as usize],self.offset() + 1);
+        reader.set_sec_id(Some(id));
+        let content = if id == module::SEC_CUSTOM_ID {
+            Section::Custom(reader.read_custom_sec()?)
+        } else {
+            reader.read_var_u32()?;
+            let mut sec_reader = reader.sub_reader(size as usize)?;
+            let content = match id {
+                module::SEC_TYPE_ID => Section::Type(sec_reader.read_type_sec()?),
+                module::SEC_IMPORT_ID => {
+                    let v = sec_reader.read_import_sec()?;
+                    self.import_func_count = v.iter().filter(|i|i.import_desc.as_ref().and_then(|d|d.tag) == Some(module::IMPORT_TAG_FUNC)).count() as u32;
+                    Section::Import(v)
+                },
+                module::SEC_FUNC_ID => {
+                    let v = sec_reader.read_indices()?;
+                    self.func_count = v.len();
+                    Section::Func(v)
+                },
+                module::SEC_TABLE_ID => Section::Table(sec_reader.read_table_sec()?),
+                module::SEC_MEM_ID => Section::Mem(sec_reader.read_mem_sec()?),
+                module::SEC_TAG_ID => Section::Tag(sec_reader.read_tag_sec()?),
+                module::SEC_GLOBAL_ID => Section::Global(sec_reader.read_global_sec()?),
+                module::SEC_EXPORT_ID => Section::Export(sec_reader.read_export_sec()?),
+                module::SEC_START_ID => Section::Start(sec_reader.read_start_sec()?),
+                module::SEC_ELEM_ID => Section::Elem(sec_reader.read_elem_sec()?),
+                module::SEC_DATA_ID => {
+                    let v = sec_reader.read_data_sec()?;
+                    self.data_len = v.len();
+                    Section::Data(v)
+                },
+                module::SEC_DATA_COUNT_ID => {
+                    let n = sec_reader.read_var_u32()?;
+                    self.data_count = Some(n);
+                    Section::DataCount(n)
+                },
+                _ => return Err(sec_reader.err(DecodeErrorKind::UnknownSection(id))),
+            };
+            if sec_reader.remaining() != 0 {
+                return Err(sec_reader.err(DecodeErrorKind::SectionSizeMismatch(size,size as usize - sec_reader.remaining())));
+            }
+            self.prev_sec_id = id;
+            content
+        };
+
+        self.pos += header_len + size as usize;
+        Ok(Some(Payload::Section{ id, range: start..end, content }))
+    }
+
+    fn next_code_body(&mut self,left:u32,next_idx:u32,size:u32,start:usize,end:usize) -> DecodeResult<Option<Payload>>{
+        let err = |offset:usize,kind:DecodeErrorKind|{
+            DecodeError{ offset, sec_id: Some(module::SEC_CODE_ID), func_idx: Some(next_idx), kind }
+        };
+
+        if left == 0 {
+            if self.offset() != end {
+                return Err(err(self.offset(),DecodeErrorKind::SectionSizeMismatch(size,self.offset() - start)));
+            }
+            self.state = State::Sections;
+            return self.next_section();
+        }
+
+        let (body_size,leb_len) = match self.peek_var_u32(0) {
+            Ok(Some(p)) => p,
+            Ok(None) => return Ok(None),
+            Err(mut e) => {
+                e.sec_id = Some(module::SEC_CODE_ID);
+                e.func_idx = Some(next_idx);
+                return Err(e);
+            }
+        };
+        let body_start = self.offset() + leb_len;
+        let body_end = body_start + body_size as usize;
+        if body_end > end {
+            return Err(err(self.offset(),DecodeErrorKind::SectionSizeMismatch(size,body_end - start)));
+        }
+        if self.available() < leb_len + body_size as usize {
+            return if self.eof {
+                Err(err(body_start,DecodeErrorKind::UnexpectedEof))
+            } else {
+                Ok(None)
+            }
+        }
+
+        let mut reader = WasmReader::with_base(&self.buf[self.pos + leb_len..self.pos + leb_len + body_size as usize],body_start);
+        reader.set_sec_id(Some(module::SEC_CODE_ID));
+        reader.set_func_idx(Some(next_idx));
+        reader.set_lazy(self.lazy);
+        let code = reader.read_code_body()?;
+
+        self.pos += leb_len + body_size as usize;
+        self.state = State::CodeBodies{ left: left - 1, next_idx: next_idx + 1, size, start, end };
+        Ok(Some(Payload::CodeBody{ func_idx: next_idx, range: body_start..body_end, code }))
+    }
+}
+
+/// 从io::Read里一块一块地读,每个事件先交给f,f返回错误就立即停止
+/// 全部读完返回组装好的模块
+pub fn decode_stream<R,F>(mut r:R,mut f:F) -> anyhow::Result<module::Module>
+    where R:Read, F:FnMut(&Payload) -> anyhow::Result<()>{
+    let mut decoder = StreamDecoder::new();
+    let mut m = module::Module::new();
+    let mut chunk = vec![0u8;64 * 1024];
+    loop {
+        while let Some(p) = decoder.next()? {
+            f(&p)?;
+            let end = matches!(p,Payload::End);
+            p.apply(&mut m);
+            if end {
+                return Ok(m)
+            }
+        }
+        let n = r.read(&mut chunk)?;
+        if n == 0 {
+            decoder.finish();
+        } else {
+            decoder.push(&chunk[..n]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::binary::{reader, stream, module};
+    use crate::binary::stream::Payload;
+
+    #[test]
+    fn test1(){
+        // 按各种块大小喂进去,结果要和一次性解码一样
+        let data = std::fs::read("./hw_rust.wasm").unwrap();
+        let whole = reader::decode_bytes(&data).unwrap();
+        for chunk_size in [1usize,7,100,4096,100000] {
+            let mut decoder = stream::StreamDecoder::new();
+            let mut m = module::Module::new();
+            let mut bodies = 0;
+            let mut done = false;
+            for chunk in data.chunks(chunk_size) {
+                decoder.push(chunk);
+                while let Some(p) = decoder.next().unwrap() {
+                    if let Payload::CodeBody { func_idx, range, .. } = &p {
+                        assert_eq!(*func_idx,bodies);
+                        assert!(range.end <= decoder.offset());
+                        bodies += 1;
+                    }
+                    p.apply(&mut m);
+                }
+            }
+            decoder.finish();
+            while let Some(p) = decoder.next().unwrap() {
+                done = matches!(p,Payload::End);
+                p.apply(&mut m);
+            }
+            assert!(done);
+            assert_eq!(bodies,171);
+            assert_eq!(format!("{:?}",m),format!("{:?}",whole));
+        }
+    }
+
+    #[test]
+    fn test2(){
+        // 从io::Read里读,可以在中途拒绝
+        let data = std::fs::read("./hw_rust.wasm").unwrap();
+        let m = stream::decode_stream(data.as_slice(),|_|Ok(())).unwrap();
+        assert_eq!(m.code_sec.map(|v|v.len()),Some(171));
+
+        let mut seen = 0;
+        let r = stream::decode_stream(data.as_slice(),|p|{
+            seen += 1;
+            match p {
+                Payload::CodeBody { .. } => Err(anyhow::anyhow!("too big")),
+                _ => Ok(()),
+            }
+        });
+        assert!(r.is_err());
+        assert!(seen < 20);
+
+        // 截断的数据
+        let r = stream::decode_stream(&data[..data.len() / 2],|_|Ok(()));
+        assert!(r.is_err());
+    }
+}
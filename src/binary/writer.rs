@@ -0,0 +1,801 @@
+use byteorder::{ByteOrder, LittleEndian};
+use crate::binary::{leb128, module, opcodes, instruction};
+use crate::binary::module::{Module, BlockType};
+use crate::binary::instruction::{ArgsEnum, AtomicArgs, Instruction, MemArg, SimdArgs};
+
+/// 把Module写回二进制,和WasmReader一一对应
+/// 自定义段的位置在解码时没有保留,统一写在最后
+/// 延迟解码且还没解码的函数体直接写回原始字节
+pub struct WasmWriter{
+    buf:Vec<u8>,
+}
+
+pub fn encode(m:&Module) -> Vec<u8>{
+    let mut writer = WasmWriter::new();
+    writer.write_module(m);
+    writer.into_bytes()
+}
+
+pub fn encode_file(path:String,m:&Module) -> anyhow::Result<()>{
+    std::fs::write(path,encode(m))?;
+    Ok(())
+}
+
+impl Default for WasmWriter{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WasmWriter{
+    pub fn new() -> WasmWriter{
+        WasmWriter{ buf: Vec::new() }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8>{
+        self.buf
+    }
+
+    pub fn write_byte(&mut self,b:u8){
+        self.buf.push(b);
+    }
+
+    pub fn write_u32(&mut self,n:u32){
+        let mut b = [0u8;4];
+        LittleEndian::write_u32(&mut b,n);
+        self.buf.extend_from_slice(&b);
+    }
+
+    pub fn write_f32(&mut self,n:f32){
+        let mut b = [0u8;4];
+        LittleEndian::write_f32(&mut b,n);
+        self.buf.extend_from_slice(&b);
+    }
+
+    pub fn write_f64(&mut self,n:f64){
+        let mut b = [0u8;8];
+        LittleEndian::write_f64(&mut b,n);
+        self.buf.extend_from_slice(&b);
+    }
+
+    pub fn write_v128(&mut self,n:u128){
+        self.buf.extend_from_slice(&n.to_le_bytes());
+    }
+
+    pub fn write_var_u32(&mut self,n:u32){
+        self.buf.extend(leb128::encode_var_uint(n as u64));
+    }
+
+    pub fn write_var_u64(&mut self,n:u64){
+        self.buf.extend(leb128::encode_var_uint(n));
+    }
+
+    pub fn write_var_s32(&mut self,n:i32){
+        self.buf.extend(leb128::encode_var_int(n as i64));
+    }
+
+    pub fn write_var_s64(&mut self,n:i64){
+        self.buf.extend(leb128::encode_var_int(n));
+    }
+
+    /// 长度前缀的字节
+    pub fn write_bytes(&mut self,bytes:&[u8]){
+        self.write_var_u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn write_name(&mut self,name:&str){
+        self.write_bytes(name.as_bytes());
+    }
+
+    /// 先写到单独的writer里,再带上长度前缀写进来,段和函数体都这样写
+    fn write_sized<F:FnOnce(&mut WasmWriter)>(&mut self,f:F){
+        let mut w = WasmWriter::new();
+        f(&mut w);
+        self.write_bytes(&w.buf);
+    }
+
+    fn write_vec<T,F:Fn(&mut WasmWriter,&T)>(&mut self,v:&[T],f:F){
+        self.write_var_u32(v.len() as u32);
+        for item in v {
+            f(self,item);
+        }
+    }
+
+    pub fn write_module(&mut self,m:&Module){
+        self.write_u32(m.magic.unwrap_or(module::MAGIC_NUMBER));
+        self.write_u32(m.version.unwrap_or(module::VERSION));
+
+        if let Some(v) = &m.type_sec {
+            self.write_sec(module::SEC_TYPE_ID,|w|w.write_vec(v,|w,t|w.write_func_type(t)));
+        }
+        if let Some(v) = &m.import_sec {
+            self.write_sec(module::SEC_IMPORT_ID,|w|w.write_vec(v,|w,i|w.write_import(i)));
+        }
+        if let Some(v) = &m.func_sec {
+            self.write_sec(module::SEC_FUNC_ID,|w|w.write_vec(v,|w,idx|w.write_var_u32(*idx)));
+        }
+        if let Some(v) = &m.table_sec {
+            self.write_sec(module::SEC_TABLE_ID,|w|w.write_vec(v,|w,t|w.write_table_type(t)));
+        }
+        if let Some(v) = &m.mem_sec {
+            self.write_sec(module::SEC_MEM_ID,|w|w.write_vec(v,|w,l|w.write_limits(l)));
+        }
+        if let Some(v) = &m.tag_sec {
+            self.write_sec(module::SEC_TAG_ID,|w|w.write_vec(v,|w,t|w.write_tag_type(t)));
+        }
+        if let Some(v) = &m.global_sec {
+            self.write_sec(module::SEC_GLOBAL_ID,|w|w.write_vec(v,|w,g|{
+                w.write_global_type(g.ty.as_ref().unwrap());
+                w.write_expr(g.init.as_deref().unwrap_or(&[]));
+            }));
+        }
+        if let Some(v) = &m.export_sec {
+            self.write_sec(module::SEC_EXPORT_ID,|w|w.write_vec(v,|w,e|w.write_export(e)));
+        }
+        if let Some(idx) = m.start_sec {
+            self.write_sec(module::SEC_START_ID,|w|w.write_var_u32(idx));
+        }
+        if let Some(v) = &m.elem_sec {
+            self.write_sec(module::SEC_ELEM_ID,|w|w.write_vec(v,|w,e|w.write_elem(e)));
+        }
+        if let Some(n) = m.data_count_sec {
+            self.write_sec(module::SEC_DATA_COUNT_ID,|w|w.write_var_u32(n));
+        }
+        if let Some(v) = &m.code_sec {
+            self.write_sec(module::SEC_CODE_ID,|w|w.write_vec(v,|w,c|w.write_code(c)));
+        }
+        if let Some(v) = &m.data_sec {
+            self.write_sec(module::SEC_DATA_ID,|w|w.write_vec(v,|w,d|{
+                match (&d.offset,d.mem.unwrap_or(0)) {
+                    (None,_) => w.write_var_u32(module::DATA_FLAG_PASSIVE),
+                    (Some(offset),0) => {
+                        w.write_var_u32(module::DATA_FLAG_ACTIVE);
+                        w.write_expr(offset);
+                    }
+                    (Some(offset),mem) => {
+                        w.write_var_u32(module::DATA_FLAG_ACTIVE_MEM);
+                        w.write_var_u32(mem);
+                        w.write_expr(offset);
+                    }
+                }
+                w.write_bytes(d.init.as_deref().unwrap_or(&[]));
+            }));
+        }
+        for c in m.custom_secs.iter().flatten() {
+            self.write_sec(module::SEC_CUSTOM_ID,|w|{
+                w.write_name(c.name.as_deref().unwrap_or(""));
+                w.buf.extend_from_slice(&c.bytes);
+            });
+        }
+    }
+
+    pub fn write_sec<F:FnOnce(&mut WasmWriter)>(&mut self,sec_id:u8,f:F){
+        self.write_byte(sec_id);
+        self.write_sized(f);
+    }
+}
+
+/// 写段的内容
+impl WasmWriter{
+
+    pub fn write_import(&mut self,i:&module::Import){
+        self.write_name(i.module.as_deref().unwrap_or(""));
+        self.write_name(i.name.as_deref().unwrap_or(""));
+        let desc = i.import_desc.as_ref().unwrap();
+        let tag = desc.tag.unwrap();
+        self.write_byte(tag);
+        match tag {
+            module::IMPORT_TAG_FUNC => self.write_var_u32(desc.fun_type.unwrap()),
+            module::IMPORT_TAG_TABLE => self.write_table_type(desc.table.as_ref().unwrap()),
+            module::IMPORT_TAG_MEM => self.write_limits(desc.mem.as_ref().unwrap()),
+            module::IMPORT_TAG_GLOBAL => self.write_global_type(desc.global.as_ref().unwrap()),
+            module::IMPORT_TAG_TAG => self.write_tag_type(desc.tag_type.as_ref().unwrap()),
+            _ => panic!("invalid import desc tag:{:?}",tag),
+        }
+    }
+
+    pub fn write_export(&mut self,e:&module::Export){
+        self.write_name(e.name.as_deref().unwrap_or(""));
+        let desc = e.desc.as_ref().unwrap();
+        self.write_byte(desc.tag.unwrap());
+        self.write_var_u32(desc.idx.unwrap());
+    }
+
+    /// 函数体: 长度前缀 + 局部变量 + 指令
+    pub fn write_code(&mut self,c:&module::Code){
+        self.write_sized(|w|{
+            w.write_vec(c.locals.as_deref().unwrap_or(&[]),|w,l|{
+                w.write_var_u32(l.n.unwrap_or(0));
+                w.write_byte(l.ty.unwrap());
+            });
+            match (&c.expr,&c.lazy) {
+                (Some(expr),_) => w.write_expr(expr),
+                (None,Some(lazy)) if !lazy.is_decoded() => w.buf.extend_from_slice(&lazy.bytes),
+                (None,Some(lazy)) => w.write_expr(lazy.get().unwrap()),
+                (None,None) => w.write_expr(&[]),
+            }
+        });
+    }
+}
+
+/// 写操作指令
+impl WasmWriter{
+
+    pub fn write_expr(&mut self,expr:&[Instruction]){
+        self.write_instructions(expr);
+        self.write_byte(opcodes::End_);
+    }
+
+    pub fn write_instructions(&mut self,instrs:&[Instruction]){
+        for i in instrs {
+            self.write_instruction(i);
+        }
+    }
+
+    /// 按元素段的形式选标志,MVP形式的段仍然写成标志0
+    pub fn write_elem(&mut self,e:&module::Elem){
+        let mut flag = 0;
+        if e.exprs.is_some() {
+            flag |= module::ELEM_FLAG_EXPRS;
+        }
+        if !e.is_active() {
+            flag |= module::ELEM_FLAG_PASSIVE;
+            if e.declarative {
+                flag |= module::ELEM_FLAG_EXPLICIT;
+            }
+        } else if e.elem_type.is_some() || e.table.unwrap_or(0) != 0 {
+            flag |= module::ELEM_FLAG_EXPLICIT;
+        }
+        self.write_var_u32(flag);
+        if let Some(offset) = &e.offset {
+            if flag & module::ELEM_FLAG_EXPLICIT != 0 {
+                self.write_var_u32(e.table.unwrap_or(0));
+            }
+            self.write_expr(offset);
+        }
+        if flag & (module::ELEM_FLAG_PASSIVE|module::ELEM_FLAG_EXPLICIT) != 0 {
+            match &e.exprs {
+                Some(_) => self.write_byte(e.get_elem_type()),
+                None => self.write_byte(module::ELEM_KIND_FUNC_REF),
+            }
+        }
+        match &e.exprs {
+            Some(exprs) => self.write_vec(exprs,|w,expr|w.write_expr(expr)),
+            None => self.write_vec(e.init.as_deref().unwrap_or(&[]),|w,idx|w.write_var_u32(*idx)),
+        }
+    }
+
+    /// 前缀指令在前缀后面写u32子操作码
+    pub fn write_instruction(&mut self,i:&Instruction){
+        let op = i.op();
+        self.write_byte(op.prefix);
+        if op.is_prefixed() {
+            self.write_var_u32(op.sub);
+        }
+        match (op.prefix,&i.args) {
+            // 0xFC指令的立即数取决于子操作码
+            (opcodes::MiscPrefix,Some(args)) => self.write_prefix_fc_args(op.sub,args),
+            (_,Some(args)) => self.write_args(op.prefix,args),
+            (_,None) => {}
+        }
+    }
+
+    pub fn write_args(&mut self,opcode:u8,args:&ArgsEnum){
+        match opcode {
+            opcodes::Block|opcodes::Loop => {
+                let args = args.get_block_args();
+                self.write_block_type(args.bt.unwrap_or(BlockType::Empty));
+                self.write_expr(args.instrs.as_deref().unwrap_or(&[]));
+            },
+            opcodes::TryTable => {
+                let args = args.get_try_table_args();
+                self.write_block_type(args.bt.unwrap_or(BlockType::Empty));
+                self.write_vec(args.catches.as_deref().unwrap_or(&[]),|w,c|{
+                    w.write_byte(c.kind);
+                    if let Some(tag) = c.tag {
+                        w.write_var_u32(tag);
+                    }
+                    w.write_var_u32(c.label);
+                });
+                self.write_expr(args.instrs.as_deref().unwrap_or(&[]));
+            },
+            opcodes::If => {
+                let args = args.get_if_args();
+                self.write_block_type(args.bt.unwrap_or(BlockType::Empty));
+                self.write_instructions(args.instrs1.as_deref().unwrap_or(&[]));
+                if let Some(instrs2) = &args.instrs2 {
+                    self.write_byte(opcodes::Else_);
+                    self.write_instructions(instrs2);
+                }
+                self.write_byte(opcodes::End_);
+            },
+            opcodes::BrTable => {
+                let args = args.get_br_table_args();
+                self.write_vec(args.labels.as_deref().unwrap_or(&[]),|w,l|w.write_var_u32(*l));
+                self.write_var_u32(args.default.unwrap_or(0));
+            },
+            opcodes::CallIndirect|opcodes::ReturnCallIndirect => {
+                let args = args.get_call_indirect_args();
+                self.write_var_u32(args.type_idx.unwrap_or(0));
+                self.write_var_u32(args.table.unwrap_or(0));
+            },
+            opcodes::SelectT => {
+                self.write_val_types(&[args.get_u8()]);
+            },
+            opcodes::RefNull => {
+                self.write_byte(args.get_u8());
+            },
+            opcodes::MemoryGrow|opcodes::MemorySize => {
+                self.write_var_u32(args.get_u32());
+            },
+            opcodes::SimdPrefix => self.write_simd_args(&args.get_simd_args()),
+            opcodes::AtomicPrefix => self.write_atomic_args(&args.get_atomic_args()),
+            opcodes::I32Const => self.write_var_s32(args.get_i32()),
+            opcodes::I64Const => self.write_var_s64(args.get_i64()),
+            opcodes::F32Const => self.write_f32(args.get_f32()),
+            opcodes::F64Const => self.write_f64(args.get_f64()),
+            _ => {
+                match args {
+                    ArgsEnum::MemArg(arg) => self.write_mem_arg(arg),
+                    ArgsEnum::U32(n) => self.write_var_u32(*n),
+                    ArgsEnum::NONE => {},
+                    v => panic!("unexpected args for opcode {:#04x}:{:?}",opcode,v),
+                }
+            }
+        }
+    }
+}
+
+/// 写类型
+impl WasmWriter{
+
+    pub fn write_func_type(&mut self,t:&module::FuncType){
+        self.write_byte(t.tag.unwrap_or(module::FT_TAG));
+        self.write_val_types(t.param_types.as_deref().unwrap_or(&[]));
+        self.write_val_types(t.result_types.as_deref().unwrap_or(&[]));
+    }
+
+    pub fn write_tag_type(&mut self,t:&module::TagType){
+        self.write_byte(t.attribute.unwrap_or(module::TAG_ATTRIBUTE_EXCEPTION));
+        self.write_var_u32(t.type_idx.unwrap());
+    }
+
+    pub fn write_val_types(&mut self,v:&[u8]){
+        self.write_bytes(v);
+    }
+
+    /// 和WasmReader::read_limits对应,u32和u64的leb128编码一样,所以直接按u64写
+    pub fn write_limits(&mut self,l:&module::Limits){
+        let tag = l.tag.unwrap_or(0) & (module::LIMITS_SHARED | module::LIMITS_MEM64);
+        match l.max {
+            None => {
+                self.write_byte(tag);
+                self.write_var_u64(l.min.unwrap_or(0));
+            },
+            Some(max) => {
+                self.write_byte(module::LIMITS_HAS_MAX | tag);
+                self.write_var_u64(l.min.unwrap_or(0));
+                self.write_var_u64(max);
+            }
+        }
+    }
+
+    /// 内存索引是0时省略,和单内存的编码一样
+    pub fn write_mem_arg(&mut self,arg:&MemArg){
+        let align = arg.align.unwrap_or(0);
+        match arg.mem {
+            Some(mem) if mem != 0 => {
+                self.write_var_u32(align | instruction::MEM_ARG_HAS_MEM);
+                self.write_var_u32(mem);
+            }
+            _ => self.write_var_u32(align),
+        }
+        self.write_var_u64(arg.offset.unwrap_or(0));
+    }
+
+    pub fn write_table_type(&mut self,t:&module::TableType){
+        self.write_byte(t.elem_type.unwrap_or(module::FUNC_REF));
+        self.write_limits(t.limits.as_ref().unwrap());
+    }
+
+    pub fn write_global_type(&mut self,t:&module::GlobalType){
+        self.write_byte(t.val_type.unwrap());
+        self.write_byte(t.m.unwrap_or(module::MUT_CONST));
+    }
+
+    /// 块类型按有符号leb128写,和类型索引共用编码
+    /// 和WasmReader::read_prefix_fc_args对应,子操作码已经在write_instruction里写过
+    pub fn write_prefix_fc_args(&mut self,sub:u32,args:&ArgsEnum){
+        let args = match args {
+            ArgsEnum::PrefixArgs(args) => args,
+            v => panic!("unexpected args for opcode 0xfc:{:?}",v),
+        };
+        match sub {
+            opcodes::I32TruncSatF32S..=opcodes::I64TruncSatF64U => {}
+            opcodes::MemoryInit|opcodes::MemoryCopy => {
+                self.write_var_u32(args.idx.unwrap_or(0));
+                self.write_var_u32(args.idx2.unwrap_or(0));
+            }
+            _ => self.write_var_u32(args.idx.unwrap_or(0)),
+        }
+    }
+
+    /// 和WasmReader::read_simd_args对应
+    pub fn write_simd_args(&mut self,args:&SimdArgs){
+        if let Some(arg) = &args.mem_arg {
+            self.write_mem_arg(arg);
+        }
+        if let Some(lane) = args.lane {
+            self.write_byte(lane);
+        }
+        if let Some(v) = args.v128 {
+            self.write_v128(v);
+        }
+    }
+
+    /// atomic.fence没有memarg,写一个保留的0字节
+    pub fn write_atomic_args(&mut self,args:&AtomicArgs){
+        match &args.mem_arg {
+            Some(arg) => self.write_mem_arg(arg),
+            None => self.write_byte(0),
+        }
+    }
+
+    pub fn write_block_type(&mut self,bt:BlockType){
+        self.write_var_s64(bt.to_s33());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::binary::{reader, writer};
+
+    #[test]
+    fn test1(){
+        // 原文件里有填充过的leb128,所以按解码结果比较,再写一遍要和第一次写的字节完全一致
+        let data = std::fs::read("./hw_rust.wasm").unwrap();
+        let m = reader::decode_bytes(&data).unwrap();
+        let bytes = writer::encode(&m);
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}",m),format!("{:?}",m2));
+        assert_eq!(writer::encode(&m2),bytes);
+
+        // 延迟解码的函数体原样写回
+        let lazy = reader::decode_lazy(&data).unwrap();
+        let lazy_bytes = writer::encode(&lazy);
+        assert_eq!(format!("{:?}",reader::decode_bytes(&lazy_bytes).unwrap()),format!("{:?}",m));
+    }
+
+    #[test]
+    fn test2(){
+        use crate::binary::instruction::{ArgsEnum, BlockArgs, BrTableArgs, IfArgs, Instruction, MemArg};
+        use crate::binary::module::{self, BlockType, Code, Locals, Module, FuncType};
+        use crate::binary::opcodes;
+
+        let i = |opcode:u8,args:Option<ArgsEnum>|Instruction{ opcode: Some(opcode), sub: 0, args };
+        let mut m = Module::new();
+        m.magic = Some(module::MAGIC_NUMBER);
+        m.version = Some(module::VERSION);
+        m.type_sec = Some(vec![FuncType{ tag: Some(module::FT_TAG), param_types: Some(vec![module::VAL_TYPE_I32]), result_types: Some(vec![module::VAL_TYPE_I32]) }]);
+        m.func_sec = Some(vec![0]);
+        m.code_sec = Some(vec![Code{
+            locals: Some(vec![Locals{ n: Some(2), ty: Some(module::VAL_TYPE_I64) }]),
+            expr: Some(vec![
+                i(opcodes::Block,Some(ArgsEnum::BlockArgs(BlockArgs{ bt: Some(BlockType::Empty), instrs: Some(vec![
+                    i(opcodes::LocalGet,Some(ArgsEnum::U32(0))),
+                    i(opcodes::BrTable,Some(ArgsEnum::BrTableArgs(BrTableArgs{ labels: Some(vec![0,1]), default: Some(0) }))),
+                ]) }))),
+                i(opcodes::I32Const,Some(ArgsEnum::I32(-64))),
+                i(opcodes::If,Some(ArgsEnum::IfArgs(IfArgs{ bt: Some(BlockType::Value(module::VAL_TYPE_I32)), instrs1: Some(vec![
+                    i(opcodes::I32Const,Some(ArgsEnum::I32(0))),
+                    i(opcodes::I32Load,Some(ArgsEnum::MemArg(MemArg{ align: Some(2), offset: Some(300), mem: None }))),
+                ]), instrs2: Some(vec![i(opcodes::I32Const,Some(ArgsEnum::I32(i32::MIN)))]) }))),
+                i(opcodes::I64Const,Some(ArgsEnum::I64(i64::MAX))),
+                i(opcodes::Drop,None),
+                i(opcodes::F64Const,Some(ArgsEnum::F64(-0.5))),
+                Instruction::prefixed(opcodes::MiscPrefix,opcodes::I32TruncSatF64S,ArgsEnum::PrefixArgs(crate::binary::instruction::PrefixArgs{ idx: None, idx2: None })),
+                i(opcodes::I32Add,None),
+            ]),
+            lazy: None
+        }]);
+        let bytes = writer::encode(&m);
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}",m),format!("{:?}",m2));
+    }
+
+    #[test]
+    fn test3(){
+        use crate::binary::module;
+        use crate::text;
+        use crate::validator;
+
+        // 批量内存: 数据计数段,被动数据段,0xFC 8~11
+        let m = text::parse(r#"(module
+  (memory 1)
+  (data $p "hello")
+  (data (i32.const 8) "world")
+  (func (param i32)
+    (memory.init $p (local.get 0) (i32.const 1) (i32.const 3))
+    (data.drop $p)
+    (memory.copy (i32.const 0) (i32.const 8) (i32.const 5))
+    (memory.fill (i32.const 0) (i32.const 0xff) (i32.const 2))))"#).unwrap();
+        assert_eq!(m.data_count_sec,Some(2));
+        let datas = m.data_sec.as_ref().unwrap();
+        assert!(datas[0].offset.is_none() && datas[0].mem.is_none());
+        assert_eq!(datas[1].mem,Some(0));
+        validator::validate(&m).unwrap();
+
+        let bytes = writer::encode(&m);
+        // 数据计数段在代码段前面
+        let count_sec = [module::SEC_DATA_COUNT_ID,1,2,module::SEC_CODE_ID];
+        assert!(bytes.windows(4).any(|w|w == count_sec));
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}",m),format!("{:?}",m2));
+        assert_eq!(format!("{:?}",text::parse(&text::print(&m2)).unwrap()),format!("{:?}",m));
+    }
+
+    #[test]
+    fn test4(){
+        use crate::binary::instruction::ArgsEnum;
+        use crate::binary::module::BlockType;
+        use crate::text;
+        use crate::validator;
+
+        // 多值: 带参数和多个结果的块用类型索引编码
+        let m = text::parse(r#"(module
+  (func (param i32 i32) (result i32 i32)
+    local.get 0 local.get 1
+    block (param i32 i32) (result i32 i32) end
+    loop (param i32 i32) (result i32) i32.add end
+    local.get 0
+    if (param i32) (result i32 i32) i32.const 1 else i32.const 2 end))"#).unwrap();
+        validator::validate(&m).unwrap();
+        assert_eq!(m.type_sec.as_ref().unwrap().len(),3);
+        let expr = m.code_sec.as_ref().unwrap()[0].expr.as_ref().unwrap();
+        match &expr[2].args {
+            Some(ArgsEnum::BlockArgs(args)) => assert_eq!(args.bt,Some(BlockType::TypeIdx(0))),
+            v => panic!("{:?}",v),
+        }
+
+        let bytes = writer::encode(&m);
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}",m),format!("{:?}",m2));
+        let printed = text::print(&m2);
+        assert!(printed.contains("block (type 0) (param i32 i32) (result i32 i32)"));
+        assert_eq!(format!("{:?}",text::parse(&printed).unwrap()),format!("{:?}",m));
+    }
+
+    #[test]
+    fn test5(){
+        use crate::binary::module;
+        use crate::text;
+        use crate::validator;
+
+        // 引用类型: 多个表、各种形式的元素段、带类型的select和表指令
+        let m = text::parse(r#"(module
+  (type $t (func (param i32) (result i32)))
+  (table $funcs 2 funcref)
+  (table $objs 1 10 externref)
+  (func $id (type $t) local.get 0)
+  (func $f (param externref i32) (result externref)
+    local.get 0 ref.null extern local.get 1 select (result externref))
+  (func $g (param i32) (result i32)
+    local.get 0 i32.const 0 call_indirect $funcs (type $t)
+    i32.const 0 local.get 0 table.get $objs table.set $objs
+    ref.null extern i32.const 1 table.grow $objs
+    table.size $objs i32.add i32.add
+    i32.const 0 ref.func $id i32.const 2 table.fill $funcs
+    ref.func $id ref.is_null i32.add)
+  (elem (i32.const 0) $id)
+  (elem $p func $id $g)
+  (elem declare func $f)
+  (elem (table $objs) (i32.const 0) externref (ref.null extern))
+  (elem funcref (item ref.func $id) (ref.null func)))"#).unwrap();
+        validator::validate(&m).unwrap();
+        let elems = m.elem_sec.as_ref().unwrap();
+        assert_eq!(elems[0].elem_type,None);
+        assert_eq!((elems[1].is_active(),elems[2].declarative),(false,true));
+        assert_eq!((elems[3].table,elems[3].get_elem_type()),(Some(1),module::EXTERN_REF));
+
+        let bytes = writer::encode(&m);
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}",m),format!("{:?}",m2));
+        let printed = text::print(&m2);
+        assert!(printed.contains("(table (;1;) 1 10 externref)"));
+        assert!(printed.contains("call_indirect (type 0)") && printed.contains("table.get 1"));
+        assert!(printed.contains("select (result externref)"));
+        assert_eq!(format!("{:?}",text::parse(&printed).unwrap()),format!("{:?}",m));
+    }
+
+    #[test]
+    fn test6(){
+        use crate::binary::instruction::{ArgsEnum, Instruction, MemArg, SimdArgs};
+        use crate::binary::module::{self, Code, FuncType, Module};
+        use crate::binary::opcodes;
+        use crate::binary::simd::{self, SimdImm};
+        use crate::text;
+
+        // 所有SIMD指令编码、解码、打印、解析一圈
+        let instrs:Vec<Instruction> = simd::SIMD_OPS.iter().map(|(sub,_)|{
+            let imm = simd::imm(*sub);
+            let mem = imm == SimdImm::MemArg || imm == SimdImm::MemArgLane;
+            let args = SimdArgs{
+                mem_arg: if mem { Some(MemArg{ align: Some(0), offset: Some(*sub as u64 * 3), mem: None }) } else { None },
+                lane: if imm == SimdImm::Lane || imm == SimdImm::MemArgLane { Some(1) } else { None },
+                v128: match imm {
+                    SimdImm::V128 => Some(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10_u128.wrapping_neg()),
+                    SimdImm::Shuffle => Some(u128::from_le_bytes([31,0,30,1,29,2,28,3,27,4,26,5,25,6,24,7])),
+                    _ => None,
+                },
+            };
+            Instruction::prefixed(opcodes::SimdPrefix,*sub,ArgsEnum::SimdArgs(Box::new(args)))
+        }).collect();
+        let mut m = Module::new();
+        m.magic = Some(module::MAGIC_NUMBER);
+        m.version = Some(module::VERSION);
+        m.type_sec = Some(vec![FuncType{ tag: Some(module::FT_TAG), param_types: Some(vec![]), result_types: Some(vec![]) }]);
+        m.func_sec = Some(vec![0]);
+        m.code_sec = Some(vec![Code{ locals: Some(vec![]), expr: Some(instrs), lazy: None }]);
+
+        let bytes = writer::encode(&m);
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}",m),format!("{:?}",m2));
+        let printed = text::print(&m2);
+        assert!(printed.contains("v128.load8_lane offset=252 1"));
+        assert!(printed.contains("i8x16.shuffle 31 0 30 1"));
+        assert!(printed.contains("v128.const i32x4 0xf2f1f0f0 0xf6f5f4f3 0xfaf9f8f7 0xfefdfcfb"));
+        assert_eq!(format!("{:?}",text::parse(&printed).unwrap()),format!("{:?}",m));
+
+        // 其他形状的v128.const
+        let m = text::parse(r#"(module (func (result v128) v128.const f32x4 1.0 -0x1p+1 nan inf drop v128.const i8x16 -1 0 1 2 3 4 5 6 7 8 9 10 11 12 13 255))"#).unwrap();
+        let expr = m.code_sec.as_ref().unwrap()[0].expr.as_ref().unwrap();
+        assert_eq!(expr[0].args.as_ref().unwrap().get_simd_args().v128,
+            Some(1.0f32.to_bits() as u128 | ((-2.0f32).to_bits() as u128) << 32 | (f32::NAN.to_bits() as u128) << 64 | (f32::INFINITY.to_bits() as u128) << 96));
+        assert_eq!(expr[2].args.as_ref().unwrap().get_simd_args().v128,
+            Some(u128::from_le_bytes([255,0,1,2,3,4,5,6,7,8,9,10,11,12,13,255])));
+    }
+
+    #[test]
+    fn test7(){
+        use crate::binary::atomic;
+        use crate::binary::instruction::{ArgsEnum, AtomicArgs, Instruction, MemArg};
+        use crate::binary::module::{self, Code, FuncType, Limits, Module};
+        use crate::binary::opcodes;
+        use crate::text;
+
+        // 共享内存和所有原子指令编码、解码、打印、解析一圈
+        let instrs:Vec<Instruction> = atomic::ATOMIC_OPS.iter().map(|(sub,_)|{
+            let mem_arg = match *sub {
+                atomic::AtomicFence => None,
+                _ => Some(MemArg{ align: Some(atomic::natural_align(*sub)), offset: Some(*sub as u64 * 4), mem: None }),
+            };
+            Instruction::prefixed(opcodes::AtomicPrefix,*sub,ArgsEnum::AtomicArgs(AtomicArgs{ mem_arg }))
+        }).collect();
+        let mut m = Module::new();
+        m.magic = Some(module::MAGIC_NUMBER);
+        m.version = Some(module::VERSION);
+        m.type_sec = Some(vec![FuncType{ tag: Some(module::FT_TAG), param_types: Some(vec![]), result_types: Some(vec![]) }]);
+        m.func_sec = Some(vec![0]);
+        m.mem_sec = Some(vec![Limits{ tag: Some(module::LIMITS_HAS_MAX | module::LIMITS_SHARED), min: Some(1), max: Some(2) }]);
+        m.code_sec = Some(vec![Code{ locals: Some(vec![]), expr: Some(instrs), lazy: None }]);
+
+        let bytes = writer::encode(&m);
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}",m),format!("{:?}",m2));
+        let printed = text::print(&m2);
+        assert!(printed.contains("(memory (;0;) 1 2 shared)"));
+        assert!(printed.contains("atomic.fence\n"));
+        assert!(printed.contains("i64.atomic.rmw32.cmpxchg_u offset=312\n"));
+        assert_eq!(format!("{:?}",text::parse(&printed).unwrap()),format!("{:?}",m));
+    }
+
+    #[test]
+    fn test8(){
+        use crate::text;
+
+        // 尾调用指令编码、解码、打印一圈
+        let m = text::parse(r#"(module (type (func)) (table 1 funcref)
+            (func $f return_call $f)
+            (func i32.const 0 return_call_indirect (type 0)))"#).unwrap();
+        let bytes = writer::encode(&m);
+        assert!(bytes.windows(2).any(|w|w == [0x12,0x00]));
+        assert!(bytes.windows(4).any(|w|w == [0x41,0x00,0x13,0x00]));
+        let printed = text::print(&reader::decode_bytes(&bytes).unwrap());
+        assert!(printed.contains("return_call 0\n"));
+        assert!(printed.contains("return_call_indirect (type 0)\n"));
+    }
+
+    #[test]
+    fn test9(){
+        use crate::text;
+
+        // 异常处理: 标签段、标签的导入导出、try_table和throw/throw_ref
+        let m = text::parse(r#"(module
+            (import "env" "e" (tag $e (param i32)))
+            (memory 1)
+            (tag $f (export "f") (param i64 i32))
+            (global i32 (i32.const 0))
+            (func (result exnref)
+                (block $h (result exnref)
+                    (block $c (result i64 i32)
+                        (try_table (catch $f $c) (catch_all_ref $h)
+                            (throw $e (i32.const 1))))
+                    unreachable)
+                (try_table $t (catch_ref $e 0) (catch_all 0))
+                (ref.null exn)
+                throw_ref))"#).unwrap();
+        assert_eq!(m.tag_sec.as_ref().map(|v|v.len()),Some(1));
+        let bytes = writer::encode(&m);
+        let mem = bytes.windows(5).position(|w|w == [0x05,0x03,0x01,0x00,0x01]).unwrap();
+        let tag = bytes.windows(5).position(|w|w == [0x0d,0x03,0x01,0x00,0x01]).unwrap();
+        let global = bytes.windows(2).position(|w|w == [0x06,0x06]).unwrap();
+        assert!(mem < tag && tag < global);
+        assert!(bytes.windows(8).any(|w|w == [0x1f,0x40,0x02,0x00,0x01,0x00,0x03,0x01]));
+        assert!(bytes.windows(4).any(|w|w == [0x41,0x01,0x08,0x00]));
+        assert!(bytes.windows(3).any(|w|w == [0xd0,0x69,0x0a]));
+
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        let printed = text::print(&m2);
+        assert!(printed.contains("(import \"env\" \"e\" (tag (;0;) (type 0) (param i32)))"));
+        assert!(printed.contains("(tag (;1;) (type 1) (param i64 i32))"));
+        assert!(printed.contains("(export \"f\" (tag 1))"));
+        assert!(printed.contains("try_table (catch 1 0) (catch_all_ref 1)\n"));
+        assert!(printed.contains("try_table (catch_ref 0 0) (catch_all 0)\n"));
+        assert!(printed.contains("ref.null exn\n"));
+        assert_eq!(writer::encode(&text::parse(&printed).unwrap()),bytes);
+    }
+
+    #[test]
+    fn test10(){
+        use crate::text;
+
+        // memory64: 限制标志带0x04,memarg偏移按u64写
+        let m = text::parse(r#"(module
+            (import "env" "m" (memory i64 1))
+            (func (param i64) (result i64) (i64.load offset=4294967296 (local.get 0))))"#).unwrap();
+        let bytes = writer::encode(&m);
+        assert!(bytes.windows(5).any(|w|w == [b'm',0x02,0x04,0x01,0x03]));
+        assert!(bytes.windows(8).any(|w|w == [0x29,0x03,0x80,0x80,0x80,0x80,0x10,0x0b]));
+
+        let m2 = reader::decode_bytes(&bytes).unwrap();
+        let printed = text::print(&m2);
+        assert!(printed.contains("(import \"env\" \"m\" (memory (;0;) i64 1))"));
+        assert!(printed.contains("i64.load offset=4294967296\n"));
+        assert_eq!(writer::encode(&text::parse(&printed).unwrap()),bytes);
+
+        let m = text::parse(r#"(module (memory i64 (data "abc")) (memory $m i64 2 3 shared))"#).unwrap();
+        let printed = text::print(&reader::decode_bytes(&writer::encode(&m)).unwrap());
+        assert!(printed.contains("(memory (;0;) i64 1 1)"));
+        assert!(printed.contains("(memory (;1;) i64 2 3 shared)"));
+        assert!(printed.contains("(i64.const 0)"));
+    }
+
+    #[test]
+    fn test11(){
+        use crate::text;
+
+        // 多内存: 非0内存索引写进memarg和批量内存指令
+        let m = text::parse(r#"(module (memory 1) (memory $b 2) (data "x")
+            (func (result i32)
+                (memory.init $b 0 (i32.const 0) (i32.const 0) (i32.const 1))
+                (memory.copy 0 $b (i32.const 8) (i32.const 0) (i32.const 1))
+                (memory.fill $b (i32.const 0) (i32.const 0) (i32.const 1))
+                (i32.store8 $b offset=3 (i32.const 0) (memory.size $b))
+                (i32.load $b offset=3 (i32.const 0))))"#).unwrap();
+        let bytes = writer::encode(&m);
+        assert!(bytes.windows(4).any(|w|w == [0xfc,0x08,0x00,0x01]));
+        assert!(bytes.windows(4).any(|w|w == [0xfc,0x0a,0x00,0x01]));
+        assert!(bytes.windows(3).any(|w|w == [0xfc,0x0b,0x01]));
+        assert!(bytes.windows(4).any(|w|w == [0x28,0x42,0x01,0x03]));
+        assert!(bytes.windows(2).any(|w|w == [0x3f,0x01]));
+
+        let printed = text::print(&reader::decode_bytes(&bytes).unwrap());
+        assert!(printed.contains("memory.init 1 0\n"));
+        assert!(printed.contains("memory.copy 0 1\n"));
+        assert!(printed.contains("memory.fill 1\n"));
+        assert!(printed.contains("memory.size 1\n"));
+        assert!(printed.contains("i32.load 1 offset=3\n"));
+        assert_eq!(writer::encode(&text::parse(&printed).unwrap()),bytes);
+    }
+}
@@ -1,23 +1,296 @@
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug)]
+#[derive(Debug,Clone,PartialEq)]
 pub enum CommonError{
     Leb128Error(String),
+    /// 数据不够读了
+    UnexpectedEof,
+    DecodeError(DecodeError),
+    ValidationError(ValidationError),
+    InstantiationError(InstantiationError),
 }
 
 impl Display for CommonError{
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             CommonError::Leb128Error(str) => {f.write_str(str)}
+            CommonError::UnexpectedEof => {f.write_str("unexpected end")}
+            CommonError::DecodeError(e) => {e.fmt(f)}
+            CommonError::ValidationError(e) => {e.fmt(f)}
+            CommonError::InstantiationError(e) => {e.fmt(f)}
         }
     }
 }
 
-// impl std::error::Error for CommonError {
-//     fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
-//         match self {
-//             CommonError::Leb128Error(str) => {Some()}
-//         }
-//     }
-// }
-//
+impl std::error::Error for CommonError {}
+
+impl From<DecodeError> for CommonError {
+    fn from(e: DecodeError) -> Self {
+        CommonError::DecodeError(e)
+    }
+}
+
+impl From<ValidationError> for CommonError {
+    fn from(e: ValidationError) -> Self {
+        CommonError::ValidationError(e)
+    }
+}
+
+impl From<InstantiationError> for CommonError {
+    fn from(e: InstantiationError) -> Self {
+        CommonError::InstantiationError(e)
+    }
+}
+
+/// 解码错误的具体类型
+#[derive(Debug,Clone,PartialEq)]
+pub enum DecodeErrorKind{
+    /// 数据提前结束
+    UnexpectedEof,
+    /// leb128编码有误
+    BadLeb128(String),
+    /// 魔数不对
+    BadMagic(u32),
+    /// 版本号不对
+    BadVersion(u32),
+    /// 未知的段id
+    UnknownSection(u8),
+    /// 段顺序错误或重复出现,(当前段id,上一个段id)
+    SectionOutOfOrder(u8,u8),
+    /// 段大小与实际读取的字节数不一致,(声明的大小,实际读取的大小)
+    SectionSizeMismatch(u32,usize),
+    /// 未知的操作码
+    UnknownOpcode(u8),
+    /// 表达式或块结尾不对
+    InvalidEnd(u8),
+    /// 名字不是合法的utf8
+    InvalidUtf8,
+    /// 其他格式错误,例如非法的类型/tag值
+    Malformed(String),
+}
+
+impl Display for DecodeErrorKind{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeErrorKind::UnexpectedEof => {write!(f,"unexpected end")}
+            DecodeErrorKind::BadLeb128(s) => {write!(f,"bad leb128: {}",s)}
+            DecodeErrorKind::BadMagic(m) => {write!(f,"magic header not detected: {:#x}",m)}
+            DecodeErrorKind::BadVersion(v) => {write!(f,"unknown binary version: {:#x}",v)}
+            DecodeErrorKind::UnknownSection(id) => {write!(f,"malformed section id: {}",id)}
+            DecodeErrorKind::SectionOutOfOrder(id,prev) => {write!(f,"section {} out of order after section {}",id,prev)}
+            DecodeErrorKind::SectionSizeMismatch(n,read) => {write!(f,"section size mismatch: declared {} read {}",n,read)}
+            DecodeErrorKind::UnknownOpcode(op) => {write!(f,"unknown opcode: {:#04x}",op)}
+            DecodeErrorKind::InvalidEnd(op) => {write!(f,"invalid expr end: {:#04x}",op)}
+            DecodeErrorKind::InvalidUtf8 => {write!(f,"malformed UTF-8 encoding")}
+            DecodeErrorKind::Malformed(s) => {f.write_str(s)}
+        }
+    }
+}
+
+/// 解码错误
+/// offset 出错位置在整个二进制中的绝对偏移
+/// sec_id 出错时正在读取的段
+/// func_idx 出错时正在读取的代码段函数下标
+#[derive(Debug,Clone,PartialEq)]
+pub struct DecodeError{
+    pub offset:usize,
+    pub sec_id:Option<u8>,
+    pub func_idx:Option<u32>,
+    pub kind:DecodeErrorKind,
+}
+
+impl Display for DecodeError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"{} at offset {:#x}",self.kind,self.offset)?;
+        if let Some(id) = self.sec_id {
+            write!(f,", section {}",id)?;
+        }
+        if let Some(idx) = self.func_idx {
+            write!(f,", func {}",idx)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// 值类型的名字,用于错误信息
+pub(crate) fn val_type_name(t:Option<u8>) -> String{
+    match t {
+        None => "nothing".to_string(),
+        Some(0x7f) => "i32".to_string(),
+        Some(0x7e) => "i64".to_string(),
+        Some(0x7d) => "f32".to_string(),
+        Some(0x7c) => "f64".to_string(),
+        Some(0x7b) => "v128".to_string(),
+        Some(0x70) => "funcref".to_string(),
+        Some(0x6f) => "externref".to_string(),
+        Some(0x69) => "exnref".to_string(),
+        Some(t) => format!("{:#04x}",t),
+    }
+}
+
+/// 校验错误的具体类型
+#[derive(Debug,Clone,PartialEq)]
+pub enum ValidationErrorKind{
+    /// 操作数类型不对,expected/actual为None表示没有值
+    TypeMismatch{ expected:Option<u8>, actual:Option<u8> },
+    /// 块结束时栈上多出了值
+    ExtraValues(usize),
+    UnknownType(u32),
+    UnknownFunc(u32),
+    UnknownTable(u32),
+    UnknownMemory(u32),
+    UnknownGlobal(u32),
+    UnknownLocal(u32),
+    UnknownLabel(u32),
+    UnknownData(u32),
+    UnknownTag(u32),
+    /// memory.init/data.drop需要数据计数段
+    DataCountRequired,
+    /// 给不可变全局变量赋值
+    ImmutableGlobal(u32),
+    /// 需要常量表达式
+    ConstantExprRequired,
+    InvalidLimits(String),
+    InvalidAlignment(u32),
+    /// 原子指令的对齐必须等于自然对齐
+    InvalidAtomicAlignment(u32),
+    /// SIMD指令的车道下标超过了车道数
+    InvalidLaneIndex(u8),
+    DuplicateExport(String),
+    /// 代码段里的函数体延迟解码失败
+    Decode(DecodeError),
+    /// 其他不满足规范的情况
+    Invalid(String),
+}
+
+impl Display for ValidationErrorKind{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationErrorKind::TypeMismatch { expected, actual } => {
+                write!(f,"type mismatch: expected {} got {}",val_type_name(*expected),val_type_name(*actual))
+            }
+            ValidationErrorKind::ExtraValues(n) => {write!(f,"type mismatch: {} extra values on stack",n)}
+            ValidationErrorKind::UnknownType(i) => {write!(f,"unknown type {}",i)}
+            ValidationErrorKind::UnknownFunc(i) => {write!(f,"unknown function {}",i)}
+            ValidationErrorKind::UnknownTable(i) => {write!(f,"unknown table {}",i)}
+            ValidationErrorKind::UnknownMemory(i) => {write!(f,"unknown memory {}",i)}
+            ValidationErrorKind::UnknownGlobal(i) => {write!(f,"unknown global {}",i)}
+            ValidationErrorKind::UnknownLocal(i) => {write!(f,"unknown local {}",i)}
+            ValidationErrorKind::UnknownLabel(i) => {write!(f,"unknown label {}",i)}
+            ValidationErrorKind::UnknownData(i) => {write!(f,"unknown data segment {}",i)}
+            ValidationErrorKind::UnknownTag(i) => {write!(f,"unknown tag {}",i)}
+            ValidationErrorKind::DataCountRequired => {write!(f,"data count section required")}
+            ValidationErrorKind::ImmutableGlobal(i) => {write!(f,"global {} is immutable",i)}
+            ValidationErrorKind::ConstantExprRequired => {write!(f,"constant expression required")}
+            ValidationErrorKind::InvalidLimits(s) => {write!(f,"invalid limits: {}",s)}
+            ValidationErrorKind::InvalidAlignment(a) => {write!(f,"alignment must not be larger than natural: {}",a)}
+            ValidationErrorKind::InvalidAtomicAlignment(a) => {write!(f,"alignment must be equal to natural: {}",a)}
+            ValidationErrorKind::InvalidLaneIndex(i) => {write!(f,"invalid lane index {}",i)}
+            ValidationErrorKind::DuplicateExport(name) => {write!(f,"duplicate export name {:?}",name)}
+            ValidationErrorKind::Decode(e) => {e.fmt(f)}
+            ValidationErrorKind::Invalid(s) => {f.write_str(s)}
+        }
+    }
+}
+
+/// 校验错误
+/// func_idx 出错的函数索引(包括导入函数)
+/// instr_idx 出错的指令在函数体中按先序遍历的下标,嵌套块里的指令也算在内
+#[derive(Debug,Clone,PartialEq)]
+pub struct ValidationError{
+    pub func_idx:Option<u32>,
+    pub instr_idx:Option<usize>,
+    pub kind:ValidationErrorKind,
+}
+
+impl ValidationError{
+    pub fn new(kind:ValidationErrorKind) -> ValidationError{
+        ValidationError{ func_idx: None, instr_idx: None, kind }
+    }
+}
+
+impl Display for ValidationError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"{}",self.kind)?;
+        if let Some(idx) = self.func_idx {
+            write!(f,", func {}",idx)?;
+        }
+        if let Some(idx) = self.instr_idx {
+            write!(f,", instr {}",idx)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// 文本格式解析错误,line/col从1开始
+#[derive(Debug,Clone,PartialEq)]
+pub struct TextError{
+    pub line:usize,
+    pub col:usize,
+    pub message:String,
+}
+
+impl Display for TextError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"{} at {}:{}",self.message,self.line,self.col)
+    }
+}
+
+impl std::error::Error for TextError {}
+
+/// 实例化错误,出错时不会留下实例
+#[derive(Debug,Clone,PartialEq)]
+pub enum InstantiationError{
+    /// 没有提供这个导入项
+    UnknownImport{ module:String, name:String },
+    /// 导入项的种类或类型和导入声明不一致
+    IncompatibleImportType{ module:String, name:String },
+    /// 内存的初始大小分配不出来,参数是内存索引
+    OutOfMemory(u32),
+    /// 主动数据段超出内存,参数是数据段索引
+    DataOutOfBounds(u32),
+    /// 主动元素段超出表,参数是元素段索引
+    ElemOutOfBounds(u32),
+    /// 常量表达式求值失败
+    ConstExpr(ValidationError),
+    /// start函数陷入
+    Trap(String),
+}
+
+impl Display for InstantiationError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstantiationError::UnknownImport { module, name } => {write!(f,"unknown import {}.{}",module,name)}
+            InstantiationError::IncompatibleImportType { module, name } => {write!(f,"incompatible import type {}.{}",module,name)}
+            InstantiationError::OutOfMemory(i) => {write!(f,"memory {} is too large to allocate",i)}
+            InstantiationError::DataOutOfBounds(i) => {write!(f,"data segment {} does not fit",i)}
+            InstantiationError::ElemOutOfBounds(i) => {write!(f,"elements segment {} does not fit",i)}
+            InstantiationError::ConstExpr(e) => {e.fmt(f)}
+            InstantiationError::Trap(s) => {write!(f,"start function trapped: {}",s)}
+        }
+    }
+}
+
+impl std::error::Error for InstantiationError {}
+
+impl From<ValidationError> for InstantiationError {
+    fn from(e: ValidationError) -> Self {
+        InstantiationError::ConstExpr(e)
+    }
+}
+
+/// 调用时陷入,参数是陷入的原因,比如errUnreachable
+#[derive(Debug,Clone,PartialEq)]
+pub struct Trap(pub String);
+
+impl Display for Trap{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"trap: {}",self.0)
+    }
+}
+
+impl std::error::Error for Trap {}
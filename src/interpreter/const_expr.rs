@@ -0,0 +1,94 @@
+use crate::binary::instruction::{ArgsEnum, Expr};
+use crate::binary::{opcodes, simd};
+use crate::common::common_error::{ValidationError, ValidationErrorKind};
+
+/// 常量表达式求值
+/// 全局变量初始值、数据段和元素段的偏移都是常量表达式,支持扩展常量提案的i32/i64 add/sub/mul
+/// globals 导入的和前面定义的全局变量的值,global.get只能读这些
+/// func_ref 把函数索引换成当前实例的函数引用
+pub fn eval(expr:&Expr,globals:&[ArgsEnum],func_ref:&dyn Fn(u32) -> ArgsEnum) -> Result<ArgsEnum,ValidationError>{
+    let mut stack:Vec<ArgsEnum> = vec![];
+    for instr in expr {
+        let v = match (instr.opcode,&instr.args) {
+            (Some(opcodes::I32Const),Some(v@ArgsEnum::I32(_)))
+            |(Some(opcodes::I64Const),Some(v@ArgsEnum::I64(_)))
+            |(Some(opcodes::F32Const),Some(v@ArgsEnum::F32(_)))
+            |(Some(opcodes::F64Const),Some(v@ArgsEnum::F64(_))) => v.clone(),
+            (Some(opcodes::SimdPrefix),Some(ArgsEnum::SimdArgs(args))) if instr.sub == simd::V128Const => {
+                ArgsEnum::V128(args.v128.unwrap_or(0))
+            }
+            (Some(opcodes::RefNull),Some(ArgsEnum::U8(t))) => crate::interpreter::vm_table::null_ref(*t),
+            (Some(opcodes::RefFunc),Some(ArgsEnum::U32(idx))) => func_ref(*idx),
+            (Some(opcodes::GlobalGet),Some(ArgsEnum::U32(idx))) => globals.get(*idx as usize).cloned()
+                .ok_or_else(||ValidationError::new(ValidationErrorKind::UnknownGlobal(*idx)))?,
+            (Some(op@(opcodes::I32Add|opcodes::I32Sub|opcodes::I32Mul|opcodes::I64Add|opcodes::I64Sub|opcodes::I64Mul)),_) => {
+                let b = stack.pop();
+                let a = stack.pop();
+                binary_op(op,a,b)?
+            }
+            _ => return Err(ValidationError::new(ValidationErrorKind::ConstantExprRequired)),
+        };
+        stack.push(v);
+    }
+    match (stack.pop(),stack.is_empty()) {
+        (Some(v),true) => Ok(v),
+        _ => Err(ValidationError::new(ValidationErrorKind::Invalid("constant expression must leave exactly one value".to_string()))),
+    }
+}
+
+/// 扩展常量的整数运算,溢出时回绕
+fn binary_op(op:u8,a:Option<ArgsEnum>,b:Option<ArgsEnum>) -> Result<ArgsEnum,ValidationError>{
+    let v = match (op,a,b) {
+        (opcodes::I32Add,Some(ArgsEnum::I32(a)),Some(ArgsEnum::I32(b))) => ArgsEnum::I32(a.wrapping_add(b)),
+        (opcodes::I32Sub,Some(ArgsEnum::I32(a)),Some(ArgsEnum::I32(b))) => ArgsEnum::I32(a.wrapping_sub(b)),
+        (opcodes::I32Mul,Some(ArgsEnum::I32(a)),Some(ArgsEnum::I32(b))) => ArgsEnum::I32(a.wrapping_mul(b)),
+        (opcodes::I64Add,Some(ArgsEnum::I64(a)),Some(ArgsEnum::I64(b))) => ArgsEnum::I64(a.wrapping_add(b)),
+        (opcodes::I64Sub,Some(ArgsEnum::I64(a)),Some(ArgsEnum::I64(b))) => ArgsEnum::I64(a.wrapping_sub(b)),
+        (opcodes::I64Mul,Some(ArgsEnum::I64(a)),Some(ArgsEnum::I64(b))) => ArgsEnum::I64(a.wrapping_mul(b)),
+        (op,a,b) => return Err(ValidationError::new(ValidationErrorKind::Invalid(
+            format!("type mismatch in constant expression {:#04x}: {:?} {:?}",op,a,b)))),
+    };
+    Ok(v)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::binary::instruction::ArgsEnum::{self, *};
+    use crate::common::common_error::ValidationErrorKind;
+    use crate::interpreter::const_expr::eval;
+
+    /// 取第一个全局变量的初始化表达式求值,函数引用换成对应的i64
+    fn eval_wat(wat:&str,globals:&[ArgsEnum]) -> Result<ArgsEnum,ValidationErrorKind>{
+        let m = crate::text::parse(wat).unwrap();
+        eval(m.global_sec.as_ref().unwrap()[0].init.as_ref().unwrap(),globals,&|idx|I64(idx as i64 + 100)).map_err(|e|e.kind)
+    }
+
+    #[test]
+    fn test1(){
+        assert_eq!(eval_wat("(module (global i32 (i32.const -7)))",&[]),Ok(I32(-7)));
+        assert_eq!(eval_wat("(module (global f64 (f64.const 1.5)))",&[]),Ok(F64(1.5)));
+        assert_eq!(eval_wat("(module (global v128 (v128.const i64x2 1 2)))",&[]),Ok(V128(1 | 2 << 64)));
+        assert_eq!(eval_wat("(module (global funcref (ref.null func)))",&[]),Ok(FuncRef(None)));
+        assert_eq!(eval_wat("(module (func) (func) (global funcref (ref.func 1)))",&[]),Ok(I64(101)));
+
+        // 扩展常量: 位置无关代码用导入的基址算出偏移
+        let wat = r#"(module (import "env" "base" (global i32))
+            (global i32 (i32.add (global.get 0) (i32.mul (i32.const 4) (i32.sub (i32.const 10) (i32.const 2))))))"#;
+        assert_eq!(eval_wat(wat,&[I32(1024)]),Ok(I32(1056)));
+        assert_eq!(eval_wat(wat,&[I32(i32::MAX)]),Ok(I32(i32::MIN + 31)));
+        let bytes = crate::binary::writer::encode(&crate::text::parse(wat).unwrap());
+        let printed = crate::text::print(&crate::binary::reader::decode_bytes(&bytes).unwrap());
+        assert_eq!(eval_wat(&printed,&[I32(1024)]),Ok(I32(1056)));
+        assert_eq!(eval_wat("(module (global i64 (i64.mul (i64.const 0x100000000) (i64.const 0x100000001))))",&[]),
+            Ok(I64(0x100000000)));
+
+        assert_eq!(eval_wat("(module (global i32 (global.get 1)))",&[I32(0)]),Err(ValidationErrorKind::UnknownGlobal(1)));
+        assert_eq!(eval_wat("(module (global i32 (i32.div_s (i32.const 1) (i32.const 1))))",&[]),
+            Err(ValidationErrorKind::ConstantExprRequired));
+        assert_eq!(eval_wat("(module (global f32 (f32.add (f32.const 1) (f32.const 1))))",&[]),
+            Err(ValidationErrorKind::ConstantExprRequired));
+        assert!(eval_wat("(module (global i32 (i32.add (i32.const 1) (i64.const 1))))",&[]).is_err());
+        assert!(eval_wat("(module (global i32 i32.const 1 i32.const 2))",&[]).is_err());
+        assert!(eval_wat("(module (global i32 i32.add))",&[]).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::binary::instruction::{ArgsEnum, Tag};
+use crate::binary::module::{self, Limits, Module};
+use crate::common::common_error::{InstantiationError, Trap};
+use crate::interpreter::const_expr;
+use crate::interpreter::vm::{HostFunc, Vm};
+use crate::interpreter::vm_global::Global;
+use crate::interpreter::vm_memory::Memory;
+use crate::interpreter::vm_table::Table;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// 导入项,都是句柄,导入的实例和提供的一方用的是同一个
+#[derive(Debug,Clone)]
+pub enum Extern{
+    Func(HostFunc),
+    Table(Table),
+    Memory(Memory),
+    Global(Global),
+    Tag(Tag),
+}
+
+/// 导入对象,按(模块名,字段名)提供导入项
+#[derive(Debug,Clone,Default)]
+pub struct Imports{
+    items:HashMap<(String,String),Extern>,
+}
+
+impl Imports{
+    pub fn new() -> Imports{
+        Imports::default()
+    }
+
+    pub fn define(&mut self,module:&str,name:&str,item:Extern) -> &mut Imports{
+        self.items.insert((module.to_string(),name.to_string()),item);
+        self
+    }
+
+    pub fn get(&self,module:&str,name:&str) -> Option<&Extern>{
+        self.items.get(&(module.to_string(),name.to_string()))
+    }
+}
+
+/// 模块实例
+#[derive(Debug,Clone)]
+pub struct Instance{
+    vm:Vm,
+}
+
+/// 导入的内存和表按当前大小比较,min不能比声明的小,声明了max时实际的max不能更大
+fn limits_match(min:u64,max:Option<u64>,required:&Limits) -> bool{
+    let max_ok = match (max,required.max) {
+        (_,None) => true,
+        (Some(max),Some(required)) => max <= required,
+        (None,Some(_)) => false,
+    };
+    min >= required.min.unwrap_or(0) && max_ok
+}
+
+/// 偏移表达式的值,memory64的是i64
+fn offset_value(v:ArgsEnum) -> u64{
+    match v {
+        ArgsEnum::I32(v) => v as u32 as u64,
+        ArgsEnum::I64(v) => v as u64,
+        v => panic!("offset is not an integer:{:?}",v),
+    }
+}
+
+impl Instance{
+    /// 实例化
+    /// 按规范的顺序: 解析导入,分配内存和表,求全局变量的初值,检查所有主动段的边界,
+    /// 复制数据段和元素段,最后执行start函数
+    /// 任何一步出错都返回错误,不会留下初始化了一半的实例
+    pub fn new(m:&Module,imports:&Imports) -> Result<Instance,InstantiationError>{
+        let mut host_funcs = vec![];
+        let mut memories = vec![];
+        let mut tables = vec![];
+        let mut globals = vec![];
+        let mut tags = vec![];
+        for import in m.import_sec.iter().flatten() {
+            let module_name = import.module.clone().unwrap_or_default();
+            let name = import.name.clone().unwrap_or_default();
+            let incompatible = ||InstantiationError::IncompatibleImportType{ module: module_name.clone(), name: name.clone() };
+            let desc = import.import_desc.as_ref().expect("import has no desc");
+            let item = imports.get(&module_name,&name)
+                .ok_or_else(||InstantiationError::UnknownImport{ module: module_name.clone(), name: name.clone() })?;
+            match (desc.tag,item) {
+                (Some(module::IMPORT_TAG_FUNC),Extern::Func(f)) => {
+                    let ft = m.get_func_type(host_funcs.len() as u32).expect("errUnknownType");
+                    if !f.matches(ft) {
+                        return Err(incompatible());
+                    }
+                    host_funcs.push(Some(f.clone()));
+                }
+                (Some(module::IMPORT_TAG_TABLE),Extern::Table(t)) => {
+                    let required = desc.table.as_ref().expect("import has no table type");
+                    let limits = required.limits.as_ref().expect("table has no limits");
+                    let max = t._type.limits.as_ref().and_then(|l|l.max);
+                    if t.elem_type() != required.elem_type.unwrap_or(module::FUNC_REF) || !limits_match(t.size() as u64,max,limits) {
+                        return Err(incompatible());
+                    }
+                    tables.push(t.clone());
+                }
+                (Some(module::IMPORT_TAG_MEM),Extern::Memory(mem)) => {
+                    let required = desc.mem.as_ref().expect("import has no memory type");
+                    if mem._type.is_shared() != required.is_shared() || mem._type.is_64() != required.is_64()
+                        || !limits_match(mem.size() as u64,mem._type.max,required) {
+                        return Err(incompatible());
+                    }
+                    memories.push(mem.clone());
+                }
+                (Some(module::IMPORT_TAG_GLOBAL),Extern::Global(g)) => {
+                    let required = desc.global.as_ref().expect("import has no global type");
+                    if g._type.val_type != required.val_type || g._type.m != required.m {
+                        return Err(incompatible());
+                    }
+                    globals.push(g.clone());
+                }
+                (Some(module::IMPORT_TAG_TAG),Extern::Tag(tag)) => {
+                    let ft = m.get_tag_func_type(tags.len() as u32).expect("errUnknownType");
+                    if tag.func_type().params() != ft.params() || tag.func_type().results() != ft.results() {
+                        return Err(incompatible());
+                    }
+                    tags.push(tag.clone());
+                }
+                _ => return Err(incompatible()),
+            }
+        }
+        for mt in m.mem_sec.iter().flatten() {
+            let mem = Memory::try_new(mt.clone()).ok_or(InstantiationError::OutOfMemory(memories.len() as u32))?;
+            memories.push(mem);
+        }
+        tables.extend(m.table_sec.iter().flatten().cloned().map(Table::new));
+        for i in tags.len()..m.get_tag_types().len() {
+            tags.push(Tag::new(m.get_tag_func_type(i as u32).cloned().expect("errUnknownType")));
+        }
+
+        let mut vm = Vm::with_store(Arc::new(m.clone()),memories,tables,globals,tags,host_funcs)?;
+        let global_vals:Vec<ArgsEnum> = (0..m.get_global_types().len() as u32).map(|i|vm.global(i).get()).collect();
+
+        // 先算出所有主动段的位置并检查边界,都没问题再复制
+        let mut datas = vec![];
+        for (i,d) in m.data_sec.iter().flatten().enumerate() {
+            let offset = match &d.offset {
+                Some(expr) => offset_value(const_expr::eval(expr,&global_vals,&|idx|vm.func_ref(idx))?),
+                None => continue,
+            };
+            let mem = d.mem.unwrap_or(0);
+            let bytes = d.init.as_deref().unwrap_or(&[]);
+            let size = vm.memory(mem).data().len() as u64;
+            match offset.checked_add(bytes.len() as u64) {
+                Some(end) if end <= size => datas.push((i as u32,mem,offset as usize)),
+                _ => return Err(InstantiationError::DataOutOfBounds(i as u32)),
+            }
+        }
+        let mut elems = vec![];
+        for (i,e) in m.elem_sec.iter().flatten().enumerate() {
+            let offset = match &e.offset {
+                Some(expr) => offset_value(const_expr::eval(expr,&global_vals,&|idx|vm.func_ref(idx))?),
+                None => continue,
+            };
+            let table = e.table.unwrap_or(0);
+            let vals = match &e.exprs {
+                Some(exprs) => exprs.iter().map(|expr|const_expr::eval(expr,&global_vals,&|idx|vm.func_ref(idx))).collect::<Result<Vec<_>,_>>()?,
+                None => e.init.iter().flatten().map(|idx|vm.func_ref(*idx)).collect(),
+            };
+            match offset.checked_add(vals.len() as u64) {
+                Some(end) if end <= vm.table(table).size() as u64 => elems.push((table,offset as u32,vals)),
+                _ => return Err(InstantiationError::ElemOutOfBounds(i as u32)),
+            }
+        }
+
+        // 主动数据段复制完就丢掉了,之后memory.init只能用长度0
+        for (idx,mem,offset) in datas {
+            let bytes = &m.data_sec.as_ref().unwrap()[idx as usize].init;
+            vm.memory(mem).write(offset,bytes.as_deref().unwrap_or(&[]));
+            vm.data_drop(idx);
+        }
+        for (table,offset,vals) in elems {
+            for (i,v) in vals.into_iter().enumerate() {
+                vm.table(table).set(offset + i as u32,v);
+            }
+        }
+
+        if let Some(start) = m.start_sec {
+            vm.invoke(start,vec![]).map_err(|t|InstantiationError::Trap(t.0))?;
+        }
+        Ok(Instance{ vm })
+    }
+
+    pub fn vm(&mut self) -> &mut Vm{
+        &mut self.vm
+    }
+
+    /// 调用导出函数
+    pub fn invoke(&mut self,name:&str,args:Vec<ArgsEnum>) -> Result<Vec<ArgsEnum>,Trap>{
+        let idx = self.vm.module().get_export(module::EXPORT_TAG_FUNC,name)
+            .ok_or_else(||Trap("errUnknownExport".to_string()))?;
+        self.vm.invoke(idx,args)
+    }
+
+    pub fn export_global(&self,name:&str) -> Option<Global>{
+        self.vm.export_global(name)
+    }
+
+    pub fn export_memory(&self,name:&str) -> Option<&Memory>{
+        let idx = self.vm.module().get_export(module::EXPORT_TAG_MEM,name)?;
+        Some(self.vm.memory(idx))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::binary::instruction::ArgsEnum::*;
+    use crate::binary::instruction::ExternRef;
+    use crate::binary::module::{self, FuncType, GlobalType, Limits, TableType};
+    use crate::common::common_error::{InstantiationError, Trap};
+    use crate::interpreter::instance::{Extern, Imports, Instance};
+    use crate::interpreter::operand;
+    use crate::interpreter::vm::{HostFunc, Vm};
+    use crate::interpreter::vm_global::Global;
+    use crate::interpreter::vm_memory::Memory;
+    use crate::interpreter::vm_table::Table;
+
+    fn instantiate(wat:&str,imports:&Imports) -> Result<Instance,InstantiationError>{
+        let m = crate::text::parse(wat).unwrap();
+        crate::validator::validate(&m).unwrap();
+        Instance::new(&m,imports)
+    }
+
+    #[test]
+    fn test1(){
+        let wat = r#"(module
+            (import "env" "log" (func $log (param i32) (result i32)))
+            (import "env" "base" (global $base i32))
+            (memory (export "mem") 1)
+            (table 4 funcref)
+            (global $sp (export "sp") (mut i32) (global.get $base))
+            (global $started (mut i32) (i32.const 0))
+            (data (i32.const 16) "hello")
+            (data (global.get $base) "\01\02")
+            (elem (i32.const 1) $get $log)
+            (func $get (param i32) (result i32) (i32.load8_u (local.get 0)))
+            (func $elem (export "elem") (param i32) (result funcref) (table.get (local.get 0)))
+            (func $started (export "started") (result i32) (global.get $started))
+            (func $init (global.set $started (call $log (i32.const 7))))
+            (start $init))"#;
+        let unop = FuncType{ tag: Some(module::FT_TAG), param_types: Some(vec![module::VAL_TYPE_I32]), result_types: Some(vec![module::VAL_TYPE_I32]) };
+        let mut imports = Imports::new();
+        imports.define("env","log",Extern::Func(HostFunc::new(unop,|_,args|Ok(vec![match args[0] { I32(v) => I32(v * 2), _ => unreachable!() }]))))
+            .define("env","base",Extern::Global(Global::new(GlobalType{ val_type: Some(module::VAL_TYPE_I32), m: Some(module::MUT_CONST) },I32(100))));
+        let mut inst = instantiate(wat,&imports).unwrap();
+        // start函数在实例化时执行过了
+        assert_eq!(inst.invoke("started",vec![]).unwrap(),vec![I32(14)]);
+        assert_eq!(inst.export_global("sp").unwrap().get(),I32(100));
+        let mem = inst.export_memory("mem").unwrap();
+        assert_eq!(&mem.data()[16..21],b"hello");
+        assert_eq!(&mem.data()[100..102],&[1,2]);
+        assert_eq!(inst.invoke("elem",vec![I32(0)]).unwrap(),vec![FuncRef(None)]);
+        assert_eq!(inst.invoke("elem",vec![I32(1)]).unwrap(),vec![inst.vm().func_ref(1)]);
+        assert_eq!(inst.invoke("elem",vec![I32(2)]).unwrap(),vec![inst.vm().func_ref(0)]);
+        assert_eq!(inst.invoke("missing",vec![]).unwrap_err(),Trap("errUnknownExport".to_string()));
+
+        // 缺少导入项、导入项类型不对
+        let mut missing = Imports::new();
+        missing.define("env","log",imports.get("env","log").cloned().unwrap());
+        assert_eq!(instantiate(wat,&missing).unwrap_err(),
+            InstantiationError::UnknownImport{ module: "env".to_string(), name: "base".to_string() });
+        let mut wrong = imports.clone();
+        wrong.define("env","base",Extern::Global(Global::new(GlobalType{ val_type: Some(module::VAL_TYPE_I32), m: Some(module::MUT_VAR) },I32(100))));
+        assert_eq!(instantiate(wat,&wrong).unwrap_err(),
+            InstantiationError::IncompatibleImportType{ module: "env".to_string(), name: "base".to_string() });
+        wrong.define("env","base",imports.get("env","log").cloned().unwrap());
+        assert!(matches!(instantiate(wat,&wrong),Err(InstantiationError::IncompatibleImportType{..})));
+        // 宿主函数的类型要和导入声明的一样
+        let mut wrong = imports.clone();
+        let nullary = FuncType{ tag: Some(module::FT_TAG), param_types: Some(vec![]), result_types: Some(vec![module::VAL_TYPE_I32]) };
+        wrong.define("env","log",Extern::Func(HostFunc::new(nullary,|_,_|Ok(vec![I32(0)]))));
+        assert_eq!(instantiate(wat,&wrong).unwrap_err(),
+            InstantiationError::IncompatibleImportType{ module: "env".to_string(), name: "log".to_string() });
+
+        // 数据段越界
+        let mut far = imports.clone();
+        far.define("env","base",Extern::Global(Global::new(GlobalType{ val_type: Some(module::VAL_TYPE_I32), m: Some(module::MUT_CONST) },I32(65535))));
+        assert_eq!(instantiate(wat,&far).unwrap_err(),InstantiationError::DataOutOfBounds(1));
+    }
+
+    #[test]
+    fn test2(){
+        // 共享内存导入的是同一块,检查边界失败时前面的数据段也不会写进去
+        let limits = Limits{ tag: Some(module::LIMITS_HAS_MAX | module::LIMITS_SHARED), min: Some(1), max: Some(2) };
+        let shared = Memory::new(limits);
+        let mut imports = Imports::new();
+        imports.define("env","mem",Extern::Memory(shared.clone()));
+        let e = instantiate(r#"(module (import "env" "mem" (memory 1 2 shared))
+            (data (i32.const 0) "abc")
+            (data (i32.const 65535) "de"))"#,&imports).unwrap_err();
+        assert_eq!(e,InstantiationError::DataOutOfBounds(1));
+        assert_eq!(&shared.data()[..3],&[0,0,0]);
+        let e = instantiate(r#"(module (import "env" "mem" (memory 1 2 shared))
+            (table 1 funcref) (func)
+            (data (i32.const 0) "abc")
+            (elem (i32.const 1) 0))"#,&imports).unwrap_err();
+        assert_eq!(e,InstantiationError::ElemOutOfBounds(0));
+        assert_eq!(&shared.data()[..3],&[0,0,0]);
+
+        let inst = instantiate(r#"(module (import "env" "mem" (memory 1 2 shared))
+            (data (i32.const 0) "abc"))"#,&imports).unwrap();
+        assert_eq!(&shared.data()[..3],b"abc");
+        drop(inst);
+
+        // 内存的限制要兼容: 声明的max比实际的小就不行
+        let e = instantiate(r#"(module (import "env" "mem" (memory 1 1 shared)))"#,&imports).unwrap_err();
+        assert!(matches!(e,InstantiationError::IncompatibleImportType{..}));
+        let e = instantiate(r#"(module (import "env" "mem" (memory 2 4 shared)))"#,&imports).unwrap_err();
+        assert!(matches!(e,InstantiationError::IncompatibleImportType{..}));
+
+        // start函数陷入时实例化失败
+        let e = instantiate(r#"(module (func $s unreachable) (start $s))"#,&Imports::new()).unwrap_err();
+        assert_eq!(e,InstantiationError::Trap("errUnreachable".to_string()));
+    }
+
+    #[test]
+    fn test3(){
+        // 非共享的内存和表导入的也是同一个: 宿主写入guest能读到,guest写入宿主也能看到
+        let mem = Memory::new(Limits{ tag: Some(module::LIMITS_HAS_MAX), min: Some(1), max: Some(2) });
+        let table = Table::new(TableType{ elem_type: Some(module::EXTERN_REF), limits: Some(Limits{ tag: Some(0), min: Some(2), max: None }) });
+        let (a,b) = (ExternRef::new(7),ExternRef::new(9));
+        mem.write(8,&[0x2a]);
+        table.set(0,ExternRef(Some(a.clone())));
+        let mut imports = Imports::new();
+        imports.define("env","mem",Extern::Memory(mem.clone()))
+            .define("env","tab",Extern::Table(table.clone()));
+        let mut inst = instantiate(r#"(module
+            (import "env" "mem" (memory 1 2))
+            (import "env" "tab" (table 2 externref))
+            (func (export "load") (param i32) (result i32) (i32.load8_u (local.get 0)))
+            (func (export "store") (param i32 i32) (i32.store8 (local.get 0) (local.get 1)))
+            (func (export "get") (param i32) (result externref) (table.get (local.get 0)))
+            (func (export "set") (param i32 externref) (table.set (local.get 0) (local.get 1)))
+            (func (export "grow") (result i32) (memory.grow (i32.const 1))))"#,&imports).unwrap();
+        assert_eq!(inst.invoke("load",vec![I32(8)]).unwrap(),vec![I32(0x2a)]);
+        assert_eq!(inst.invoke("get",vec![I32(0)]).unwrap(),vec![ExternRef(Some(a))]);
+        inst.invoke("store",vec![I32(9),I32(0x63)]).unwrap();
+        inst.invoke("set",vec![I32(1),ExternRef(Some(b.clone()))]).unwrap();
+        assert_eq!(&mem.data()[8..10],&[0x2a,0x63]);
+        assert_eq!(table.get(1),ExternRef(Some(b)));
+        // guest里增长内存,宿主的句柄也跟着变大
+        assert_eq!(inst.invoke("grow",vec![]).unwrap(),vec![I32(1)]);
+        assert_eq!(mem.data().len(),2 * 65536);
+    }
+
+    #[test]
+    fn test4(){
+        // 初始大小分配不出来时实例化失败,而不是panic
+        let e = instantiate(r#"(module (memory 1) (memory i64 0x800000000000))"#,&Imports::new()).unwrap_err();
+        assert_eq!(e,InstantiationError::OutOfMemory(1));
+
+        // Vm::new不绑定导入,但导入的表也占表索引,定义的表排在后面
+        let m = crate::text::parse(r#"(module
+            (import "env" "tab" (table 1 externref))
+            (table 2 funcref)
+            (func (export "size") (result i32) (table.size 1)))"#).unwrap();
+        crate::validator::validate(&m).unwrap();
+        let mut vm = Vm::new(operand::new(),m,Memory::new(Limits{ tag: Some(0), min: Some(0), max: None }));
+        assert_eq!(vm.invoke(0,vec![]).unwrap(),vec![I32(2)]);
+    }
+}
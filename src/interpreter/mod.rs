@@ -3,3 +3,8 @@ pub mod operand;
 pub mod instructions;
 pub mod vm_memory;
 
+pub mod vm_table;
+pub mod vm_global;
+pub mod simd;
+pub mod const_expr;
+pub mod instance;
@@ -47,7 +47,10 @@ impl OperandStack {
     }
 
     pub fn pop_u64(&mut self) -> Option<u64>{
-        self.slots.pop().and_then(|e|Some(e.get_u64()))
+        self.slots.pop().map(|e|match e {
+            ArgsEnum::I64(v) => v as u64,
+            e => e.get_u64(),
+        })
     }
 
     pub fn push_s64(&mut self, val:i64){
@@ -55,7 +58,10 @@ impl OperandStack {
     }
 
     pub fn pop_s64(&mut self)->Option<i64>{
-        self.slots.pop().and_then(|e| Some(e.get_i64()))
+        self.slots.pop().map(|e|match e {
+            ArgsEnum::U64(v) => v as i64,
+            e => e.get_i64(),
+        })
     }
 
     pub fn push_u32(&mut self,val:u32){
@@ -63,7 +69,11 @@ impl OperandStack {
     }
 
     pub fn pop_u32(&mut self) -> Option<u32>{
-        self.slots.pop().and_then(|e|Some(e.get_u32()))
+        self.slots.pop().map(|e|match e {
+            ArgsEnum::I32(v) => v as u32,
+            ArgsEnum::Bool(v) => v as u32,
+            e => e.get_u32(),
+        })
     }
 
     pub fn push_s32(&mut self ,val:i32){
@@ -71,7 +81,11 @@ impl OperandStack {
     }
 
     pub fn pop_s32(&mut self) -> Option<i32>{
-        self.slots.pop().and_then(|e|Some(e.get_i32()))
+        self.slots.pop().map(|e|match e {
+            ArgsEnum::U32(v) => v as i32,
+            ArgsEnum::Bool(v) => v as i32,
+            e => e.get_i32(),
+        })
     }
 
     pub fn push_f64(&mut self,val:f64){
@@ -99,12 +113,43 @@ impl OperandStack {
     }
 
     pub fn pop_bool(&mut self)->Option<bool>{
-        self.slots.pop().and_then(|e|Some(e.get_bool()))
+        self.slots.pop().map(|e|match e {
+            ArgsEnum::I32(v) => v != 0,
+            ArgsEnum::U32(v) => v != 0,
+            e => e.get_bool(),
+        })
     }
 
     pub fn pop(&mut self) -> Option<ArgsEnum>{
         self.slots.pop()
     }
+
+    pub fn len(&self) -> usize{
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.slots.is_empty()
+    }
+
+    /// 弹出栈顶的n个值,保持原来的顺序
+    pub fn pop_n(&mut self,n:usize) -> Vec<ArgsEnum>{
+        if n > self.slots.len() {
+            panic!("errOperandStackUnderflow")
+        }
+        self.slots.split_off(self.slots.len() - n)
+    }
+
+    pub fn push_n(&mut self,vals:Vec<ArgsEnum>){
+        self.slots.extend(vals);
+    }
+
+    /// 跳转时栈顶的n个值保留,它们下面直到height的值全部丢弃
+    pub fn unwind(&mut self,height:usize,n:usize){
+        let vals = self.pop_n(n);
+        self.slots.truncate(height);
+        self.slots.extend(vals);
+    }
 }
 
 #[cfg(test)]
@@ -136,4 +181,28 @@ mod test{
         stack.push_bool(1);
         assert_eq!(stack.pop_bool(),Some(true));
     }
+
+    #[test]
+    fn test2(){
+        use crate::interpreter::operand;
+        use crate::binary::instruction::ArgsEnum;
+        let mut stack = operand::new();
+
+        // i32的值不管是有符号、无符号还是比较结果都能按需要的方式取出
+        stack.push_s32(-1);
+        assert_eq!(stack.pop_u32(),Some(u32::MAX));
+        stack.push_bool(1);
+        assert_eq!(stack.pop_s32(),Some(1));
+        stack.push_u32(2);
+        assert_eq!(stack.pop_bool(),Some(true));
+        stack.push_s64(-2);
+        assert_eq!(stack.pop_u64(),Some(u64::MAX - 1));
+
+        for i in 0..5 {
+            stack.push_s32(i);
+        }
+        stack.unwind(1,2);
+        assert_eq!(stack.pop_n(3),vec![ArgsEnum::I32(0),ArgsEnum::I32(3),ArgsEnum::I32(4)]);
+        assert!(stack.is_empty());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,420 @@
+//! SIMD指令的运算
+//! v128按小端存成u128,车道0在最低的字节
+
+use crate::binary::instruction::ArgsEnum;
+use crate::binary::simd;
+use crate::binary::module::VAL_TYPE_I32;
+use std::convert::TryInto;
+
+/// 车道的元素类型
+pub trait Lane: Copy {
+    const SIZE:usize;
+    fn read(b:&[u8]) -> Self;
+    fn write(self,b:&mut [u8]);
+}
+
+macro_rules! impl_lane {
+    ($($t:ty),*) => {$(
+        impl Lane for $t {
+            const SIZE:usize = std::mem::size_of::<$t>();
+            fn read(b:&[u8]) -> Self{
+                <$t>::from_le_bytes(b.try_into().unwrap())
+            }
+            fn write(self,b:&mut [u8]){
+                b.copy_from_slice(&self.to_le_bytes())
+            }
+        }
+    )*}
+}
+impl_lane!(i8,u8,i16,u16,i32,u32,i64,u64,f32,f64);
+
+pub fn lanes<T:Lane>(v:u128) -> Vec<T>{
+    v.to_le_bytes().chunks(T::SIZE).map(T::read).collect()
+}
+
+/// 车道不够16个字节时高位补0
+pub fn from_lanes<T:Lane>(l:&[T]) -> u128{
+    let mut bytes = [0u8;16];
+    for (x,b) in l.iter().zip(bytes.chunks_mut(T::SIZE)) {
+        x.write(b);
+    }
+    u128::from_le_bytes(bytes)
+}
+
+fn map<T:Lane>(v:u128,f:impl Fn(T) -> T) -> u128{
+    from_lanes(&lanes::<T>(v).into_iter().map(f).collect::<Vec<T>>())
+}
+
+fn zip<T:Lane>(a:u128,b:u128,f:impl Fn(T,T) -> T) -> u128{
+    let v:Vec<T> = lanes::<T>(a).into_iter().zip(lanes::<T>(b)).map(|(x,y)|f(x,y)).collect();
+    from_lanes(&v)
+}
+
+/// 比较结果的车道全1或全0
+fn cmp<T:Lane>(a:u128,b:u128,f:impl Fn(T,T) -> bool) -> u128{
+    let mut bytes = [0u8;16];
+    for ((x,y),c) in lanes::<T>(a).into_iter().zip(lanes::<T>(b)).zip(bytes.chunks_mut(T::SIZE)) {
+        if f(x,y) {
+            c.fill(0xFF);
+        }
+    }
+    u128::from_le_bytes(bytes)
+}
+
+/// 取低半部分或高半部分的车道,每个扩展成两倍宽
+fn widen<T:Lane,U:Lane>(v:u128,high:bool,f:impl Fn(T) -> U) -> Vec<U>{
+    let l = lanes::<T>(v);
+    let half = l.len() / 2;
+    let part = if high {&l[half..]} else {&l[..half]};
+    part.iter().map(|x|f(*x)).collect()
+}
+
+/// 相邻两个车道相加,结果是两倍宽
+fn pairwise<T:Lane,U:Lane>(v:u128,f:impl Fn(T) -> U,add:impl Fn(U,U) -> U) -> u128{
+    let l:Vec<U> = lanes::<T>(v).into_iter().map(f).collect();
+    from_lanes(&l.chunks(2).map(|p|add(p[0],p[1])).collect::<Vec<U>>())
+}
+
+/// a的车道在前,b的车道在后,每个饱和成一半宽
+fn narrow<T:Lane,U:Lane>(a:u128,b:u128,f:impl Fn(T) -> U) -> u128{
+    let l:Vec<U> = lanes::<T>(a).into_iter().chain(lanes::<T>(b)).map(f).collect();
+    from_lanes(&l)
+}
+
+macro_rules! int_ops {
+    ($binary:ident,$unary:ident,$shift:ident,$s:ty,$u:ty) => {
+        fn $binary(op:&str) -> Option<fn(u128,u128) -> u128>{
+            let f:fn(u128,u128) -> u128 = match op {
+                "add" => |a,b|zip::<$s>(a,b,|x,y|x.wrapping_add(y)),
+                "sub" => |a,b|zip::<$s>(a,b,|x,y|x.wrapping_sub(y)),
+                "mul" => |a,b|zip::<$s>(a,b,|x,y|x.wrapping_mul(y)),
+                "add_sat_s" => |a,b|zip::<$s>(a,b,|x,y|x.saturating_add(y)),
+                "add_sat_u" => |a,b|zip::<$u>(a,b,|x,y|x.saturating_add(y)),
+                "sub_sat_s" => |a,b|zip::<$s>(a,b,|x,y|x.saturating_sub(y)),
+                "sub_sat_u" => |a,b|zip::<$u>(a,b,|x,y|x.saturating_sub(y)),
+                "min_s" => |a,b|zip::<$s>(a,b,|x,y|x.min(y)),
+                "min_u" => |a,b|zip::<$u>(a,b,|x,y|x.min(y)),
+                "max_s" => |a,b|zip::<$s>(a,b,|x,y|x.max(y)),
+                "max_u" => |a,b|zip::<$u>(a,b,|x,y|x.max(y)),
+                "avgr_u" => |a,b|zip::<$u>(a,b,|x,y|(x as u64 + y as u64).div_ceil(2) as $u),
+                "eq" => |a,b|cmp::<$s>(a,b,|x,y|x == y),
+                "ne" => |a,b|cmp::<$s>(a,b,|x,y|x != y),
+                "lt_s" => |a,b|cmp::<$s>(a,b,|x,y|x < y),
+                "lt_u" => |a,b|cmp::<$u>(a,b,|x,y|x < y),
+                "gt_s" => |a,b|cmp::<$s>(a,b,|x,y|x > y),
+                "gt_u" => |a,b|cmp::<$u>(a,b,|x,y|x > y),
+                "le_s" => |a,b|cmp::<$s>(a,b,|x,y|x <= y),
+                "le_u" => |a,b|cmp::<$u>(a,b,|x,y|x <= y),
+                "ge_s" => |a,b|cmp::<$s>(a,b,|x,y|x >= y),
+                "ge_u" => |a,b|cmp::<$u>(a,b,|x,y|x >= y),
+                _ => return None,
+            };
+            Some(f)
+        }
+
+        fn $unary(op:&str) -> Option<fn(u128) -> ArgsEnum>{
+            let f:fn(u128) -> ArgsEnum = match op {
+                "abs" => |v|ArgsEnum::V128(map::<$s>(v,|x|x.wrapping_abs())),
+                "neg" => |v|ArgsEnum::V128(map::<$s>(v,|x|x.wrapping_neg())),
+                "popcnt" => |v|ArgsEnum::V128(map::<$u>(v,|x|x.count_ones() as $u)),
+                "all_true" => |v|ArgsEnum::I32(lanes::<$u>(v).iter().all(|x|*x != 0) as i32),
+                // 每个车道的最高位
+                "bitmask" => |v|ArgsEnum::I32(lanes::<$u>(v).iter().enumerate()
+                    .fold(0,|mask,(i,x)|mask | (((*x >> (<$u>::BITS - 1)) as i32) << i))),
+                _ => return None,
+            };
+            Some(f)
+        }
+
+        /// 移位数按车道位数取模
+        fn $shift(op:&str) -> Option<fn(u128,u32) -> u128>{
+            let f:fn(u128,u32) -> u128 = match op {
+                "shl" => |v,n|map::<$s>(v,|x|x.wrapping_shl(n % <$u>::BITS)),
+                "shr_s" => |v,n|map::<$s>(v,|x|x.wrapping_shr(n % <$u>::BITS)),
+                "shr_u" => |v,n|map::<$u>(v,|x|x.wrapping_shr(n % <$u>::BITS)),
+                _ => return None,
+            };
+            Some(f)
+        }
+    }
+}
+int_ops!(i8x16_binary,i8x16_unary,i8x16_shift,i8,u8);
+int_ops!(i16x8_binary,i16x8_unary,i16x8_shift,i16,u16);
+int_ops!(i32x4_binary,i32x4_unary,i32x4_shift,i32,u32);
+int_ops!(i64x2_binary,i64x2_unary,i64x2_shift,i64,u64);
+
+macro_rules! float_ops {
+    ($binary:ident,$unary:ident,$t:ty) => {
+        fn $binary(op:&str) -> Option<fn(u128,u128) -> u128>{
+            let f:fn(u128,u128) -> u128 = match op {
+                "add" => |a,b|zip::<$t>(a,b,|x,y|x + y),
+                "sub" => |a,b|zip::<$t>(a,b,|x,y|x - y),
+                "mul" => |a,b|zip::<$t>(a,b,|x,y|x * y),
+                "div" => |a,b|zip::<$t>(a,b,|x,y|x / y),
+                // 有nan结果就是nan,-0比+0小
+                "min" => |a,b|zip::<$t>(a,b,|x,y|{
+                    if x.is_nan() || y.is_nan() { <$t>::NAN }
+                    else if x == y { if x.is_sign_negative() {x} else {y} }
+                    else { x.min(y) }
+                }),
+                "max" => |a,b|zip::<$t>(a,b,|x,y|{
+                    if x.is_nan() || y.is_nan() { <$t>::NAN }
+                    else if x == y { if x.is_sign_positive() {x} else {y} }
+                    else { x.max(y) }
+                }),
+                "pmin" => |a,b|zip::<$t>(a,b,|x,y|if y < x {y} else {x}),
+                "pmax" => |a,b|zip::<$t>(a,b,|x,y|if x < y {y} else {x}),
+                "eq" => |a,b|cmp::<$t>(a,b,|x,y|x == y),
+                "ne" => |a,b|cmp::<$t>(a,b,|x,y|x != y),
+                "lt" => |a,b|cmp::<$t>(a,b,|x,y|x < y),
+                "gt" => |a,b|cmp::<$t>(a,b,|x,y|x > y),
+                "le" => |a,b|cmp::<$t>(a,b,|x,y|x <= y),
+                "ge" => |a,b|cmp::<$t>(a,b,|x,y|x >= y),
+                _ => return None,
+            };
+            Some(f)
+        }
+
+        fn $unary(op:&str) -> Option<fn(u128) -> ArgsEnum>{
+            let f:fn(u128) -> ArgsEnum = match op {
+                "abs" => |v|ArgsEnum::V128(map::<$t>(v,|x|x.abs())),
+                "neg" => |v|ArgsEnum::V128(map::<$t>(v,|x|-x)),
+                "sqrt" => |v|ArgsEnum::V128(map::<$t>(v,|x|x.sqrt())),
+                "ceil" => |v|ArgsEnum::V128(map::<$t>(v,|x|x.ceil())),
+                "floor" => |v|ArgsEnum::V128(map::<$t>(v,|x|x.floor())),
+                "trunc" => |v|ArgsEnum::V128(map::<$t>(v,|x|x.trunc())),
+                "nearest" => |v|ArgsEnum::V128(map::<$t>(v,|x|x.round_ties_even())),
+                _ => return None,
+            };
+            Some(f)
+        }
+    }
+}
+float_ops!(f32x4_binary,f32x4_unary,f32);
+float_ops!(f64x2_binary,f64x2_unary,f64);
+
+/// 栈上的标量统一取出位模式,8位和16位车道只用低位
+fn scalar_bits(v:&ArgsEnum) -> u64{
+    match v {
+        ArgsEnum::I32(v) => *v as u32 as u64,
+        ArgsEnum::U32(v) => *v as u64,
+        ArgsEnum::Bool(v) => *v as u64,
+        ArgsEnum::I64(v) => *v as u64,
+        ArgsEnum::U64(v) => *v,
+        ArgsEnum::F32(v) => v.to_bits() as u64,
+        ArgsEnum::F64(v) => v.to_bits(),
+        v => panic!("not a scalar:{:?}",v),
+    }
+}
+
+/// 车道的字节宽度
+fn lane_size(sub:u32) -> usize{
+    16 / simd::lane_count(sub) as usize
+}
+
+/// 把下标为lane的车道换成bytes
+pub fn replace_bytes(v:u128,lane:u8,bytes:&[u8]) -> u128{
+    let mut b = v.to_le_bytes();
+    let start = lane as usize * bytes.len();
+    b[start..start + bytes.len()].copy_from_slice(bytes);
+    u128::from_le_bytes(b)
+}
+
+pub fn lane_bytes(v:u128,lane:u8,size:usize) -> Vec<u8>{
+    let start = lane as usize * size;
+    v.to_le_bytes()[start..start + size].to_vec()
+}
+
+fn splat(size:usize,x:&ArgsEnum) -> u128{
+    let bits = scalar_bits(x).to_le_bytes();
+    let v:Vec<u8> = (0..16).map(|i|bits[i % size]).collect();
+    u128::from_le_bytes(v.as_slice().try_into().unwrap())
+}
+
+/// 按指令名选出车道的读法
+fn lane_reader(name:&str) -> fn(&[u8]) -> ArgsEnum{
+    match name {
+        "i8x16.extract_lane_s" => |b|ArgsEnum::I32(i8::read(b) as i32),
+        "i8x16.extract_lane_u" => |b|ArgsEnum::I32(u8::read(b) as i32),
+        "i16x8.extract_lane_s" => |b|ArgsEnum::I32(i16::read(b) as i32),
+        "i16x8.extract_lane_u" => |b|ArgsEnum::I32(u16::read(b) as i32),
+        "i32x4.extract_lane" => |b|ArgsEnum::I32(i32::read(b)),
+        "i64x2.extract_lane" => |b|ArgsEnum::I64(i64::read(b)),
+        "f32x4.extract_lane" => |b|ArgsEnum::F32(f32::read(b)),
+        _ => |b|ArgsEnum::F64(f64::read(b)),
+    }
+}
+
+/// 下标小于16的从a取,否则从b取
+fn shuffle(a:u128,b:u128,idx:u128) -> u128{
+    let src:Vec<u8> = a.to_le_bytes().iter().chain(b.to_le_bytes().iter()).copied().collect();
+    let v:Vec<u8> = idx.to_le_bytes().iter().map(|i|src[*i as usize]).collect();
+    u128::from_le_bytes(v.as_slice().try_into().unwrap())
+}
+
+/// 下标超出范围的结果是0
+fn swizzle(a:u128,idx:u128) -> u128{
+    let src = a.to_le_bytes();
+    let v:Vec<u8> = idx.to_le_bytes().iter().map(|i|src.get(*i as usize).copied().unwrap_or(0)).collect();
+    u128::from_le_bytes(v.as_slice().try_into().unwrap())
+}
+
+fn splat_bytes(bytes:&[u8]) -> u128{
+    let x:Vec<u8> = (0..16).map(|i|bytes[i % bytes.len()]).collect();
+    u128::from_le_bytes(x.as_slice().try_into().unwrap())
+}
+
+/// 不够16个字节时高位补0
+fn padded(bytes:&[u8]) -> u128{
+    let mut v = [0u8;16];
+    v[..bytes.len()].copy_from_slice(bytes);
+    u128::from_le_bytes(v)
+}
+
+/// v128.load*的读法,参数是从内存读出来的mem_size个字节
+pub fn loader(sub:u32) -> fn(&[u8]) -> u128{
+    match simd::name(sub).unwrap_or("") {
+        "v128.load8x8_s" => |b|from_lanes(&lanes::<i8>(padded(b))[..8].iter().map(|x|*x as i16).collect::<Vec<_>>()),
+        "v128.load8x8_u" => |b|from_lanes(&lanes::<u8>(padded(b))[..8].iter().map(|x|*x as u16).collect::<Vec<_>>()),
+        "v128.load16x4_s" => |b|from_lanes(&lanes::<i16>(padded(b))[..4].iter().map(|x|*x as i32).collect::<Vec<_>>()),
+        "v128.load16x4_u" => |b|from_lanes(&lanes::<u16>(padded(b))[..4].iter().map(|x|*x as u32).collect::<Vec<_>>()),
+        "v128.load32x2_s" => |b|from_lanes(&lanes::<i32>(padded(b))[..2].iter().map(|x|*x as i64).collect::<Vec<_>>()),
+        "v128.load32x2_u" => |b|from_lanes(&lanes::<u32>(padded(b))[..2].iter().map(|x|*x as u64).collect::<Vec<_>>()),
+        "v128.load8_splat"|"v128.load16_splat"|"v128.load32_splat"|"v128.load64_splat" => splat_bytes,
+        // v128.load和load32_zero/load64_zero
+        _ => padded,
+    }
+}
+
+/// 运算的入口,参数是车道下标、立即数和按栈上顺序排列的操作数
+pub type SimdFn = Box<dyn Fn(u8,u128,&[ArgsEnum]) -> ArgsEnum + Send + Sync>;
+
+fn v128_unary(f:impl Fn(u128) -> u128 + Send + Sync + 'static) -> SimdFn{
+    Box::new(move |_,_,o|ArgsEnum::V128(f(o[0].get_v128())))
+}
+
+fn v128_binary(f:impl Fn(u128,u128) -> u128 + Send + Sync + 'static) -> SimdFn{
+    Box::new(move |_,_,o|ArgsEnum::V128(f(o[0].get_v128(),o[1].get_v128())))
+}
+
+fn v128_test(f:fn(u128) -> ArgsEnum) -> SimdFn{
+    Box::new(move |_,_,o|f(o[0].get_v128()))
+}
+
+fn v128_shift(f:fn(u128,u32) -> u128) -> SimdFn{
+    Box::new(move |_,_,o|ArgsEnum::V128(f(o[0].get_v128(),scalar_bits(&o[1]) as u32)))
+}
+
+/// 内存和v128.const以外的SIMD指令,按子操作码选好运算,执行时直接调用
+pub fn resolve(sub:u32) -> SimdFn{
+    let name = simd::name(sub).unwrap_or_else(||panic!("unknown 0xfd sub opcode:{}",sub));
+    let (shape,op) = name.split_once('.').unwrap();
+    let high = op.contains("high");
+    match name {
+        "i8x16.shuffle" => Box::new(|_,imm,o|ArgsEnum::V128(shuffle(o[0].get_v128(),o[1].get_v128(),imm))),
+        "i8x16.swizzle" => v128_binary(swizzle),
+        _ if op == "splat" => {
+            let size = lane_size(sub);
+            Box::new(move |_,_,o|ArgsEnum::V128(splat(size,&o[0])))
+        }
+        _ if op.starts_with("extract_lane") => {
+            let (size,read) = (lane_size(sub),lane_reader(name));
+            Box::new(move |lane,_,o|read(&lane_bytes(o[0].get_v128(),lane,size)))
+        }
+        _ if op == "replace_lane" => {
+            let size = lane_size(sub);
+            Box::new(move |lane,_,o|ArgsEnum::V128(replace_bytes(o[0].get_v128(),lane,&scalar_bits(&o[1]).to_le_bytes()[..size])))
+        }
+        "v128.not" => v128_unary(|a|!a),
+        "v128.and" => v128_binary(|a,b|a & b),
+        "v128.andnot" => v128_binary(|a,b|a & !b),
+        "v128.or" => v128_binary(|a,b|a | b),
+        "v128.xor" => v128_binary(|a,b|a ^ b),
+        "v128.bitselect" => Box::new(|_,_,o|{
+            let (a,b,c) = (o[0].get_v128(),o[1].get_v128(),o[2].get_v128());
+            ArgsEnum::V128((a & c) | (b & !c))
+        }),
+        "v128.any_true" => v128_test(|a|ArgsEnum::I32((a != 0) as i32)),
+        "i8x16.narrow_i16x8_s" => v128_binary(|a,b|narrow::<i16,i8>(a,b,|x|x.clamp(i8::MIN as i16,i8::MAX as i16) as i8)),
+        "i8x16.narrow_i16x8_u" => v128_binary(|a,b|narrow::<i16,u8>(a,b,|x|x.clamp(0,u8::MAX as i16) as u8)),
+        "i16x8.narrow_i32x4_s" => v128_binary(|a,b|narrow::<i32,i16>(a,b,|x|x.clamp(i16::MIN as i32,i16::MAX as i32) as i16)),
+        "i16x8.narrow_i32x4_u" => v128_binary(|a,b|narrow::<i32,u16>(a,b,|x|x.clamp(0,u16::MAX as i32) as u16)),
+        "i16x8.extend_low_i8x16_s"|"i16x8.extend_high_i8x16_s" => v128_unary(move |a|from_lanes(&widen::<i8,i16>(a,high,|x|x as i16))),
+        "i16x8.extend_low_i8x16_u"|"i16x8.extend_high_i8x16_u" => v128_unary(move |a|from_lanes(&widen::<u8,u16>(a,high,|x|x as u16))),
+        "i32x4.extend_low_i16x8_s"|"i32x4.extend_high_i16x8_s" => v128_unary(move |a|from_lanes(&widen::<i16,i32>(a,high,|x|x as i32))),
+        "i32x4.extend_low_i16x8_u"|"i32x4.extend_high_i16x8_u" => v128_unary(move |a|from_lanes(&widen::<u16,u32>(a,high,|x|x as u32))),
+        "i64x2.extend_low_i32x4_s"|"i64x2.extend_high_i32x4_s" => v128_unary(move |a|from_lanes(&widen::<i32,i64>(a,high,|x|x as i64))),
+        "i64x2.extend_low_i32x4_u"|"i64x2.extend_high_i32x4_u" => v128_unary(move |a|from_lanes(&widen::<u32,u64>(a,high,|x|x as u64))),
+        "i16x8.extmul_low_i8x16_s"|"i16x8.extmul_high_i8x16_s" => v128_binary(move |a,b|{
+            let (a,b) = (widen::<i8,i16>(a,high,|x|x as i16),widen::<i8,i16>(b,high,|x|x as i16));
+            zip::<i16>(from_lanes(&a),from_lanes(&b),|x,y|x.wrapping_mul(y))
+        }),
+        "i16x8.extmul_low_i8x16_u"|"i16x8.extmul_high_i8x16_u" => v128_binary(move |a,b|{
+            let (a,b) = (widen::<u8,u16>(a,high,|x|x as u16),widen::<u8,u16>(b,high,|x|x as u16));
+            zip::<u16>(from_lanes(&a),from_lanes(&b),|x,y|x.wrapping_mul(y))
+        }),
+        "i32x4.extmul_low_i16x8_s"|"i32x4.extmul_high_i16x8_s" => v128_binary(move |a,b|{
+            let (a,b) = (widen::<i16,i32>(a,high,|x|x as i32),widen::<i16,i32>(b,high,|x|x as i32));
+            zip::<i32>(from_lanes(&a),from_lanes(&b),|x,y|x.wrapping_mul(y))
+        }),
+        "i32x4.extmul_low_i16x8_u"|"i32x4.extmul_high_i16x8_u" => v128_binary(move |a,b|{
+            let (a,b) = (widen::<u16,u32>(a,high,|x|x as u32),widen::<u16,u32>(b,high,|x|x as u32));
+            zip::<u32>(from_lanes(&a),from_lanes(&b),|x,y|x.wrapping_mul(y))
+        }),
+        "i64x2.extmul_low_i32x4_s"|"i64x2.extmul_high_i32x4_s" => v128_binary(move |a,b|{
+            let (a,b) = (widen::<i32,i64>(a,high,|x|x as i64),widen::<i32,i64>(b,high,|x|x as i64));
+            zip::<i64>(from_lanes(&a),from_lanes(&b),|x,y|x.wrapping_mul(y))
+        }),
+        "i64x2.extmul_low_i32x4_u"|"i64x2.extmul_high_i32x4_u" => v128_binary(move |a,b|{
+            let (a,b) = (widen::<u32,u64>(a,high,|x|x as u64),widen::<u32,u64>(b,high,|x|x as u64));
+            zip::<u64>(from_lanes(&a),from_lanes(&b),|x,y|x.wrapping_mul(y))
+        }),
+        "i16x8.extadd_pairwise_i8x16_s" => v128_unary(|a|pairwise::<i8,i16>(a,|x|x as i16,|x,y|x + y)),
+        "i16x8.extadd_pairwise_i8x16_u" => v128_unary(|a|pairwise::<u8,u16>(a,|x|x as u16,|x,y|x + y)),
+        "i32x4.extadd_pairwise_i16x8_s" => v128_unary(|a|pairwise::<i16,i32>(a,|x|x as i32,|x,y|x + y)),
+        "i32x4.extadd_pairwise_i16x8_u" => v128_unary(|a|pairwise::<u16,u32>(a,|x|x as u32,|x,y|x + y)),
+        "i32x4.dot_i16x8_s" => v128_binary(|a,b|{
+            let products:Vec<i32> = lanes::<i16>(a).into_iter().zip(lanes::<i16>(b)).map(|(x,y)|x as i32 * y as i32).collect();
+            from_lanes(&products.chunks(2).map(|c|c[0].wrapping_add(c[1])).collect::<Vec<i32>>())
+        }),
+        "i16x8.q15mulr_sat_s" => v128_binary(|a,b|zip::<i16>(a,b,|x,y|{
+            (((x as i32 * y as i32) + 0x4000) >> 15).clamp(i16::MIN as i32,i16::MAX as i32) as i16
+        })),
+        "i32x4.trunc_sat_f32x4_s" => v128_unary(|a|from_lanes(&lanes::<f32>(a).iter().map(|x|*x as i32).collect::<Vec<_>>())),
+        "i32x4.trunc_sat_f32x4_u" => v128_unary(|a|from_lanes(&lanes::<f32>(a).iter().map(|x|*x as u32).collect::<Vec<_>>())),
+        "i32x4.trunc_sat_f64x2_s_zero" => v128_unary(|a|from_lanes(&lanes::<f64>(a).iter().map(|x|*x as i32).collect::<Vec<_>>())),
+        "i32x4.trunc_sat_f64x2_u_zero" => v128_unary(|a|from_lanes(&lanes::<f64>(a).iter().map(|x|*x as u32).collect::<Vec<_>>())),
+        "f32x4.convert_i32x4_s" => v128_unary(|a|from_lanes(&lanes::<i32>(a).iter().map(|x|*x as f32).collect::<Vec<_>>())),
+        "f32x4.convert_i32x4_u" => v128_unary(|a|from_lanes(&lanes::<u32>(a).iter().map(|x|*x as f32).collect::<Vec<_>>())),
+        "f64x2.convert_low_i32x4_s" => v128_unary(|a|from_lanes(&lanes::<i32>(a)[..2].iter().map(|x|*x as f64).collect::<Vec<_>>())),
+        "f64x2.convert_low_i32x4_u" => v128_unary(|a|from_lanes(&lanes::<u32>(a)[..2].iter().map(|x|*x as f64).collect::<Vec<_>>())),
+        "f32x4.demote_f64x2_zero" => v128_unary(|a|from_lanes(&lanes::<f64>(a).iter().map(|x|*x as f32).collect::<Vec<_>>())),
+        "f64x2.promote_low_f32x4" => v128_unary(|a|from_lanes(&lanes::<f32>(a)[..2].iter().map(|x|*x as f64).collect::<Vec<_>>())),
+        _ => {
+            // 其余的按参数个数分成一元、移位和二元运算
+            let (params,_) = simd::signature(sub);
+            let unary = params.len() == 1;
+            let shift = params.last() == Some(&VAL_TYPE_I32);
+            let f = match shape {
+                "i8x16" if unary => i8x16_unary(op).map(v128_test),
+                "i16x8" if unary => i16x8_unary(op).map(v128_test),
+                "i32x4" if unary => i32x4_unary(op).map(v128_test),
+                "i64x2" if unary => i64x2_unary(op).map(v128_test),
+                "f32x4" if unary => f32x4_unary(op).map(v128_test),
+                "f64x2" if unary => f64x2_unary(op).map(v128_test),
+                "i8x16" if shift => i8x16_shift(op).map(v128_shift),
+                "i16x8" if shift => i16x8_shift(op).map(v128_shift),
+                "i32x4" if shift => i32x4_shift(op).map(v128_shift),
+                "i64x2" if shift => i64x2_shift(op).map(v128_shift),
+                "i8x16" => i8x16_binary(op).map(v128_binary),
+                "i16x8" => i16x8_binary(op).map(v128_binary),
+                "i32x4" => i32x4_binary(op).map(v128_binary),
+                "i64x2" => i64x2_binary(op).map(v128_binary),
+                "f32x4" => f32x4_binary(op).map(v128_binary),
+                "f64x2" => f64x2_binary(op).map(v128_binary),
+                _ => None,
+            };
+            f.unwrap_or_else(||panic!("unsupported simd instruction:{}",name))
+        }
+    }
+}
@@ -1,11 +1,24 @@
 use crate::{interpreter::operand,
             binary,
             binary::instruction::MemArg,
-            interpreter::vm_memory::Memory};
+            interpreter::vm_memory::Memory,
+            interpreter::vm_table::{self, Table},
+            interpreter::vm_global::Global,
+            interpreter::const_expr,
+            interpreter::simd};
 
 
 use crate::{binary::instruction::ArgsEnum,binary::opcodes,utils};
 use once_cell::sync::OnceCell;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use crate::binary::opcodes::Opcode;
+use crate::binary::module::{self, BlockType, FuncIdx, LabelIdx};
+use crate::binary::instruction::{AtomicArgs, CallIndirectArgs, ExnRef, FuncRef, IfArgs, Instruction, SimdArgs, Tag, TryTableArgs};
+use crate::binary::atomic::{self, RmwOp};
+use crate::binary::simd::SimdImm;
+use crate::common::common_error::{Trap, ValidationError};
 use std::os::unix::raw::uid_t;
 use std::any::type_name;
 use bitintr::{Lzcnt, Tzcnt, Popcnt};
@@ -13,197 +26,824 @@ use std::ops::Neg;
 use byteorder::ByteOrder;
 
 type InstrFn = fn(vm:&mut Vm,args:ArgsEnum);
-pub static OPCODE_MAP:OnceCell<Vec<Option<InstrFn>>> = OnceCell::new();
+/// 前缀指令的入口,建表时按子操作码选好运算,执行时不再按子操作码分派
+type PrefixedFn = Box<dyn Fn(&mut Vm,&ArgsEnum) + Send + Sync>;
+pub static OPCODE_MAP:OnceCell<InstrTable> = OnceCell::new();
+
+/// 默认的执行深度上限,按控制栈的长度算,函数调用和嵌套的块各占一层
+/// 每层在本地栈上都要递归一次,不限制的话无限递归会把本地栈撑爆,而不是陷入
+/// 调试版本每层最多占1KB左右的本地栈,默认值在2MB栈的线程上也有余量
+/// 栈更大的嵌入方可以用set_max_stack_depth调高
+pub const MAX_STACK_DEPTH:usize = 1024;
 
 pub fn init(){
+    OPCODE_MAP.get_or_init(opcode_map);
+}
+
+/// 指令表,单字节指令按操作码下标查,前缀指令先按前缀选表再按子操作码下标查
+/// 支持新的前缀指令只要往prefixed里登记
+pub struct InstrTable{
+    single:Vec<Option<InstrFn>>,
+    prefixed:[Vec<Option<PrefixedFn>>;3],
+}
+
+/// 0xFC/0xFD/0xFE各自一张表
+fn prefix_slot(prefix:u8) -> Option<usize>{
+    match prefix {
+        opcodes::MiscPrefix => Some(0),
+        opcodes::SimdPrefix => Some(1),
+        opcodes::AtomicPrefix => Some(2),
+        _ => None,
+    }
+}
+
+impl InstrTable{
+    /// 单字节指令
+    pub fn get(&self,op:Opcode) -> Option<InstrFn>{
+        if op.is_prefixed() {
+            return None;
+        }
+        self.single[op.prefix as usize]
+    }
+
+    pub fn get_prefixed(&self,op:Opcode) -> Option<&PrefixedFn>{
+        self.prefixed[prefix_slot(op.prefix)?].get(op.sub as usize)?.as_ref()
+    }
+
+    fn insert(&mut self,op:Opcode,f:PrefixedFn){
+        let table = &mut self.prefixed[prefix_slot(op.prefix).expect("not a prefix opcode")];
+        let sub = op.sub as usize;
+        if table.len() <= sub {
+            table.resize_with(sub + 1,||None);
+        }
+        table[sub] = Some(f);
+    }
+}
+
+/// 控制指令、调用和局部变量指令在exec_instr里处理,不在这张表里
+fn opcode_map() -> InstrTable{
     let mut v:Vec<Option<InstrFn>> = Vec::new();
     v.resize(256,None);
-    v.insert(opcodes::Call as usize, Some(|vm:&mut Vm, args:ArgsEnum|{}));
-    v.insert(opcodes::Drop as usize, Some(|vm: &mut Vm, args:ArgsEnum|{vm.drop()}));
-    v.insert(opcodes::Select as usize, Some(|vm:&mut Vm, args:ArgsEnum|{vm.select()}));
-    v.insert(opcodes::I32Const as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_const(args.get_i32())}));
-    v.insert(opcodes::I64Const as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_const(args.get_i64())}));
-    v.insert(opcodes::F32Const as usize, Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_const(args.get_f32())}));
-    v.insert(opcodes::F64Const as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_const(args.get_f64())}));
-    v.insert(opcodes::I32Eqz as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_eqz()}));
-    v.insert(opcodes::I32Eq as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_eq()}));
-    v.insert(opcodes::I32Ne as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_ne()}));
-    v.insert(opcodes::I32LtS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_lts()}));
-    v.insert(opcodes::I32LtU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_ltu()}));
-    v.insert(opcodes::I32GtS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_gts()}));
-    v.insert(opcodes::I32GtU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_gtu()}));
-    v.insert(opcodes::I32LeS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_les()}));
-    v.insert(opcodes::I32LeU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_leu()}));
-    v.insert(opcodes::I32GeS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_ges()}));
-    v.insert(opcodes::I32GeU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_geu()}));
-    v.insert(opcodes::I64Eqz as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_eqz()}));
-    v.insert(opcodes::I64Eq as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_eq()}));
-    v.insert(opcodes::I64Ne as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_ne()}));
-    v.insert(opcodes::I64LtS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_lts()}));
-    v.insert(opcodes::I64LtU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_ltu()}));
-    v.insert(opcodes::I64GtS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_gts()}));
-    v.insert(opcodes::I64GtU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_gtu()}));
-    v.insert(opcodes::I64LeS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_les()}));
-    v.insert(opcodes::I64LeU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_leu()}));
-    v.insert(opcodes::I64GeS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_ges()}));
-    v.insert(opcodes::I64GeU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_geu()}));
-    v.insert(opcodes::F32Eq as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_eq()}));
-    v.insert(opcodes::F32Ne as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_ne()}));
-    v.insert(opcodes::F32Lt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_lt()}));
-    v.insert(opcodes::F32Gt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_gt()}));
-    v.insert(opcodes::F32Le as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_le()}));
-    v.insert(opcodes::F32Ge as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_ge()}));
-    v.insert(opcodes::F64Eq as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_eq()}));
-    v.insert(opcodes::F64Ne as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_ne()}));
-    v.insert(opcodes::F64Lt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_lt()}));
-    v.insert(opcodes::F64Gt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_gt()}));
-    v.insert(opcodes::F64Le as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_le()}));
-    v.insert(opcodes::F64Ge as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_ge()}));
-    v.insert(opcodes::I32Clz as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_clz()}));
-    v.insert(opcodes::I32Ctz as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_ctz()}));
-    v.insert(opcodes::I32PopCnt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_popcnt()}));
-    v.insert(opcodes::I32Add as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_add()}));
-    v.insert(opcodes::I32Sub as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_sub()}));
-    v.insert(opcodes::I32Mul as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_mul()}));
-    v.insert(opcodes::I32DivS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_divs()}));
-    v.insert(opcodes::I32DivU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_divu()}));
-    v.insert(opcodes::I32RemS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_rems()}));
-    v.insert(opcodes::I32RemU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_remu()}));
-    v.insert(opcodes::I32And as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_and()}));
-    v.insert(opcodes::I32Or as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_or()}));
-    v.insert(opcodes::I32Xor as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_xor()}));
-    v.insert(opcodes::I32Shl as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_shl()}));
-    v.insert(opcodes::I32ShrS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_shrs()}));
-    v.insert(opcodes::I32ShrU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_shru()}));
-    v.insert(opcodes::I32Rotl as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_rotl()}));
-    v.insert(opcodes::I32Rotr as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_rotr()}));
-    v.insert(opcodes::I64Clz as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_clz()}));
-    v.insert(opcodes::I64Ctz as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_ctz()}));
-    v.insert(opcodes::I64PopCnt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_popcnt()}));
-    v.insert(opcodes::I64Add as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_add()}));
-    v.insert(opcodes::I64Sub as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_sub()}));
-    v.insert(opcodes::I64Mul as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_mul()}));
-    v.insert(opcodes::I64DivS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_divs()}));
-    v.insert(opcodes::I64DivU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_divu()}));
-    v.insert(opcodes::I64RemS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_rems()}));
-    v.insert(opcodes::I64RemU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_remu()}));
-    v.insert(opcodes::I64And as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_and()}));
-    v.insert(opcodes::I64Or as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_or()}));
-    v.insert(opcodes::I64Xor as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_xor()}));
-    v.insert(opcodes::I64Shl as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_shl()}));
-    v.insert(opcodes::I64ShrS as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_shrs()}));
-    v.insert(opcodes::I64ShrU as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_shru()}));
-    v.insert(opcodes::I64Rotl as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_rotl()}));
-    v.insert(opcodes::I64Rotr as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_rotr()}));
-    v.insert(opcodes::F32Abs as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_abs()}));
-    v.insert(opcodes::F32Neg as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_neg()}));
-    v.insert(opcodes::F32Ceil as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_ceil()}));
-    v.insert(opcodes::F32Floor as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_floor()}));
-    v.insert(opcodes::F32Trunc as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_trunc()}));
-    v.insert(opcodes::F32Nearest as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_nearest()}));
-    v.insert(opcodes::F32Sqrt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_sqrt()}));
-    v.insert(opcodes::F32Add as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_add()}));
-    v.insert(opcodes::F32Sub as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_sub()}));
-    v.insert(opcodes::F32Mul as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_mul()}));
-    v.insert(opcodes::F32Div as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_div()}));
-    v.insert(opcodes::F32Min as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_min()}));
-    v.insert(opcodes::F32Max as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_max()}));
-    v.insert(opcodes::F32CopySign as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_copy_sign()}));
-    v.insert(opcodes::F32Abs as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_abs()}));
-    v.insert(opcodes::F32Neg as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_neg()}));
-    v.insert(opcodes::F32Ceil as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_ceil()}));
-    v.insert(opcodes::F32Floor as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_floor()}));
-    v.insert(opcodes::F32Trunc as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_trunc()}));
-    v.insert(opcodes::F32Nearest as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_nearest()}));
-    v.insert(opcodes::F32Sqrt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_sqrt()}));
-    v.insert(opcodes::F32Add as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_add()}));
-    v.insert(opcodes::F32Sub as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_sub()}));
-    v.insert(opcodes::F32Mul as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_mul()}));
-    v.insert(opcodes::F32Div as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_div()}));
-    v.insert(opcodes::F32Min as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_min()}));
-    v.insert(opcodes::F32Max as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_max()}));
-    v.insert(opcodes::F32CopySign as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_copy_sign()}));
-    v.insert(opcodes::F64Abs as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_abs()}));
-    v.insert(opcodes::F64Neg as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_neg()}));
-    v.insert(opcodes::F64Ceil as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_ceil()}));
-    v.insert(opcodes::F64Floor as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_floor()}));
-    v.insert(opcodes::F64Trunc as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_trunc()}));
-    v.insert(opcodes::F64Nearest as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_nearest()}));
-    v.insert(opcodes::F64Sqrt as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_sqrt()}));
-    v.insert(opcodes::F64Add as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_add()}));
-    v.insert(opcodes::F64Sub as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_sub()}));
-    v.insert(opcodes::F64Mul as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_mul()}));
-    v.insert(opcodes::F64Div as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_div()}));
-    v.insert(opcodes::F64Min as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_min()}));
-    v.insert(opcodes::F64Max as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_max()}));
-    v.insert(opcodes::F64CopySign as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_copy_sign()}));
-    v.insert(opcodes::I32WrapI64 as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_warp_i64()}));
-    v.insert(opcodes::I32TruncF32S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_trunc_f32_s()}));
-    v.insert(opcodes::I32TruncF32U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_trunc_f32_u()}));
-    v.insert(opcodes::I32TruncF64S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_trunc_f64_s()}));
-    v.insert(opcodes::I32TruncF64U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_trunc_f64_u()}));
-    v.insert(opcodes::I64ExtendI32S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_extend_i32_s()}));
-    v.insert(opcodes::I64ExtendI32U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_extend_i32_u()}));
-    v.insert(opcodes::I64TruncF32S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_trunc_f32_s()}));
-    v.insert(opcodes::I64TruncF32U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_trunc_f32_u()}));
-    v.insert(opcodes::I64TruncF64S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_trunc_f64_s()}));
-    v.insert(opcodes::I64TruncF64U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_trunc_f64_u()}));
-    v.insert(opcodes::F32ConvertI32S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_convert_i32_s()}));
-    v.insert(opcodes::F32ConvertI32U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_convert_i32_u()}));
-    v.insert(opcodes::F32ConvertI64S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_convert_i64_s()}));
-    v.insert(opcodes::F32ConvertI64U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_convert_i64_u()}));
-    v.insert(opcodes::F32DemoteF64 as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_demote_f64()}));
-    v.insert(opcodes::F64ConvertI32S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_convert_i32_s()}));
-    v.insert(opcodes::F64ConvertI32U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_convert_i32_u()}));
-    v.insert(opcodes::F64ConvertI64S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_convert_i64_s()}));
-    v.insert(opcodes::F64ConvertI64U as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_convert_i64_u()}));
-    v.insert(opcodes::F64PromoteF32 as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_promote_f32()}));
-    v.insert(opcodes::I32ReinterpretF32 as usize,Some(|vm:&mut Vm, args:ArgsEnum|{}));
-    v.insert(opcodes::I64ReinterpretF64 as usize,Some(|vm:&mut Vm, args:ArgsEnum|{}));
-    v.insert(opcodes::F32ReinterpretI32 as usize,Some(|vm:&mut Vm, args:ArgsEnum|{}));
-    v.insert(opcodes::F64ReinterpretI64 as usize,Some(|vm:&mut Vm, args:ArgsEnum|{}));
-    v.insert(opcodes::I32Extend8S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_extend_8_s()}));
-    v.insert(opcodes::I32Extend16S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_extend_16_s()}));
-    v.insert(opcodes::I64Extend8S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_extend_8_s()}));
-    v.insert(opcodes::I64Extend16S as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_extend_16_s()}));
-    v.insert(opcodes::TruncSat as usize,Some(|vm:&mut Vm, args:ArgsEnum|{vm.trunc_sat(args.get_u8())}));
-    v.insert(opcodes::MemorySize as usize, Some(|vm:&mut Vm,args:ArgsEnum|{vm.memory_size()}));
-    v.insert(opcodes::MemoryGrow as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.memory_grow()}));
-
-    // 这里有点神奇,如果先insert store,再insert load,会让store的变成none
-    v.insert(opcodes::I32Load as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load(args.get_mem_args())}));
-    v.insert(opcodes::I64Load as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load(args.get_mem_args())}));
-    v.insert(opcodes::F32Load as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.f32_load(args.get_mem_args())}));
-    v.insert(opcodes::F64Load as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.f64_load(args.get_mem_args())}));
-    v.insert(opcodes::I32Load8S as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load_8s(args.get_mem_args())}));
-    v.insert(opcodes::I32Load8U as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load_8u(args.get_mem_args())}));
-    v.insert(opcodes::I32Load16S as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load_16s(args.get_mem_args())}));
-    v.insert(opcodes::I32Load16U as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load_16u(args.get_mem_args())}));
-    v.insert(opcodes::I64Load8S as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_8s(args.get_mem_args())}));
-    v.insert(opcodes::I64Load8U as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_8u(args.get_mem_args())}));
-    v.insert(opcodes::I64Load16S as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_16s(args.get_mem_args())}));
-    v.insert(opcodes::I64Load16U as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_16u(args.get_mem_args())}));
-    v.insert(opcodes::I64Load32S as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_32s(args.get_mem_args())}));
-    v.insert(opcodes::I64Load32U as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_32u(args.get_mem_args())}));
-
-    v.insert(opcodes::I32Store as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_store(args.get_mem_args())}));
-    v.insert(opcodes::I64Store as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_store(args.get_mem_args())}));
-    v.insert(opcodes::F32Store as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.f32_store(args.get_mem_args())}));
-    v.insert(opcodes::F64Store as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.f64_store(args.get_mem_args())}));
-    v.insert(opcodes::I32Store8 as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_store_8(args.get_mem_args())}));
-    v.insert(opcodes::I32Store16 as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_store_16(args.get_mem_args())}));
-    v.insert(opcodes::I64Store8 as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_store_8(args.get_mem_args())}));
-    v.insert(opcodes::I64Store16 as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_store_16(args.get_mem_args())}));
-    v.insert(opcodes::I64Store32 as usize,Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_store_32(args.get_mem_args())}));
-
-    OPCODE_MAP.set(v);
+    v[opcodes::Drop as usize] = Some(|vm: &mut Vm, args:ArgsEnum|{vm.drop()});
+    v[opcodes::Select as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.select()});
+    v[opcodes::SelectT as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.select()});
+    v[opcodes::TableGet as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.table_get(args.get_u32())});
+    v[opcodes::TableSet as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.table_set(args.get_u32())});
+    v[opcodes::RefNull as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.ref_null(args.get_u8())});
+    v[opcodes::RefIsNull as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.ref_is_null()});
+    v[opcodes::RefFunc as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.ref_func(args.get_u32())});
+    v[opcodes::GlobalGet as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.global_get(args.get_u32())});
+    v[opcodes::GlobalSet as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.global_set(args.get_u32())});
+    v[opcodes::I32Const as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_const(args.get_i32())});
+    v[opcodes::I64Const as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_const(args.get_i64())});
+    v[opcodes::F32Const as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_const(args.get_f32())});
+    v[opcodes::F64Const as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_const(args.get_f64())});
+    v[opcodes::I32Eqz as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_eqz()});
+    v[opcodes::I32Eq as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_eq()});
+    v[opcodes::I32Ne as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_ne()});
+    v[opcodes::I32LtS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_lts()});
+    v[opcodes::I32LtU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_ltu()});
+    v[opcodes::I32GtS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_gts()});
+    v[opcodes::I32GtU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_gtu()});
+    v[opcodes::I32LeS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_les()});
+    v[opcodes::I32LeU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_leu()});
+    v[opcodes::I32GeS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_ges()});
+    v[opcodes::I32GeU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_geu()});
+    v[opcodes::I64Eqz as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_eqz()});
+    v[opcodes::I64Eq as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_eq()});
+    v[opcodes::I64Ne as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_ne()});
+    v[opcodes::I64LtS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_lts()});
+    v[opcodes::I64LtU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_ltu()});
+    v[opcodes::I64GtS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_gts()});
+    v[opcodes::I64GtU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_gtu()});
+    v[opcodes::I64LeS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_les()});
+    v[opcodes::I64LeU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_leu()});
+    v[opcodes::I64GeS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_ges()});
+    v[opcodes::I64GeU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_geu()});
+    v[opcodes::F32Eq as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_eq()});
+    v[opcodes::F32Ne as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_ne()});
+    v[opcodes::F32Lt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_lt()});
+    v[opcodes::F32Gt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_gt()});
+    v[opcodes::F32Le as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_le()});
+    v[opcodes::F32Ge as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_ge()});
+    v[opcodes::F64Eq as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_eq()});
+    v[opcodes::F64Ne as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_ne()});
+    v[opcodes::F64Lt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_lt()});
+    v[opcodes::F64Gt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_gt()});
+    v[opcodes::F64Le as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_le()});
+    v[opcodes::F64Ge as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_ge()});
+    v[opcodes::I32Clz as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_clz()});
+    v[opcodes::I32Ctz as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_ctz()});
+    v[opcodes::I32PopCnt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_popcnt()});
+    v[opcodes::I32Add as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_add()});
+    v[opcodes::I32Sub as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_sub()});
+    v[opcodes::I32Mul as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_mul()});
+    v[opcodes::I32DivS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_divs()});
+    v[opcodes::I32DivU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_divu()});
+    v[opcodes::I32RemS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_rems()});
+    v[opcodes::I32RemU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_remu()});
+    v[opcodes::I32And as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_and()});
+    v[opcodes::I32Or as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_or()});
+    v[opcodes::I32Xor as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_xor()});
+    v[opcodes::I32Shl as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_shl()});
+    v[opcodes::I32ShrS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_shrs()});
+    v[opcodes::I32ShrU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_shru()});
+    v[opcodes::I32Rotl as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_rotl()});
+    v[opcodes::I32Rotr as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_rotr()});
+    v[opcodes::I64Clz as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_clz()});
+    v[opcodes::I64Ctz as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_ctz()});
+    v[opcodes::I64PopCnt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_popcnt()});
+    v[opcodes::I64Add as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_add()});
+    v[opcodes::I64Sub as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_sub()});
+    v[opcodes::I64Mul as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_mul()});
+    v[opcodes::I64DivS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_divs()});
+    v[opcodes::I64DivU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_divu()});
+    v[opcodes::I64RemS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_rems()});
+    v[opcodes::I64RemU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_remu()});
+    v[opcodes::I64And as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_and()});
+    v[opcodes::I64Or as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_or()});
+    v[opcodes::I64Xor as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_xor()});
+    v[opcodes::I64Shl as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_shl()});
+    v[opcodes::I64ShrS as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_shrs()});
+    v[opcodes::I64ShrU as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_shru()});
+    v[opcodes::I64Rotl as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_rotl()});
+    v[opcodes::I64Rotr as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_rotr()});
+    v[opcodes::F32Abs as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_abs()});
+    v[opcodes::F32Neg as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_neg()});
+    v[opcodes::F32Ceil as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_ceil()});
+    v[opcodes::F32Floor as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_floor()});
+    v[opcodes::F32Trunc as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_trunc()});
+    v[opcodes::F32Nearest as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_nearest()});
+    v[opcodes::F32Sqrt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_sqrt()});
+    v[opcodes::F32Add as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_add()});
+    v[opcodes::F32Sub as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_sub()});
+    v[opcodes::F32Mul as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_mul()});
+    v[opcodes::F32Div as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_div()});
+    v[opcodes::F32Min as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_min()});
+    v[opcodes::F32Max as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_max()});
+    v[opcodes::F32CopySign as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_copy_sign()});
+    v[opcodes::F32Abs as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_abs()});
+    v[opcodes::F32Neg as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_neg()});
+    v[opcodes::F32Ceil as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_ceil()});
+    v[opcodes::F32Floor as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_floor()});
+    v[opcodes::F32Trunc as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_trunc()});
+    v[opcodes::F32Nearest as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_nearest()});
+    v[opcodes::F32Sqrt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_sqrt()});
+    v[opcodes::F32Add as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_add()});
+    v[opcodes::F32Sub as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_sub()});
+    v[opcodes::F32Mul as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_mul()});
+    v[opcodes::F32Div as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_div()});
+    v[opcodes::F32Min as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_min()});
+    v[opcodes::F32Max as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_max()});
+    v[opcodes::F32CopySign as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_copy_sign()});
+    v[opcodes::F64Abs as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_abs()});
+    v[opcodes::F64Neg as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_neg()});
+    v[opcodes::F64Ceil as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_ceil()});
+    v[opcodes::F64Floor as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_floor()});
+    v[opcodes::F64Trunc as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_trunc()});
+    v[opcodes::F64Nearest as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_nearest()});
+    v[opcodes::F64Sqrt as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_sqrt()});
+    v[opcodes::F64Add as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_add()});
+    v[opcodes::F64Sub as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_sub()});
+    v[opcodes::F64Mul as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_mul()});
+    v[opcodes::F64Div as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_div()});
+    v[opcodes::F64Min as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_min()});
+    v[opcodes::F64Max as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_max()});
+    v[opcodes::F64CopySign as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_copy_sign()});
+    v[opcodes::I32WrapI64 as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_warp_i64()});
+    v[opcodes::I32TruncF32S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_trunc_f32_s()});
+    v[opcodes::I32TruncF32U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_trunc_f32_u()});
+    v[opcodes::I32TruncF64S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_trunc_f64_s()});
+    v[opcodes::I32TruncF64U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_trunc_f64_u()});
+    v[opcodes::I64ExtendI32S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_extend_i32_s()});
+    v[opcodes::I64ExtendI32U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_extend_i32_u()});
+    v[opcodes::I64TruncF32S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_trunc_f32_s()});
+    v[opcodes::I64TruncF32U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_trunc_f32_u()});
+    v[opcodes::I64TruncF64S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_trunc_f64_s()});
+    v[opcodes::I64TruncF64U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_trunc_f64_u()});
+    v[opcodes::F32ConvertI32S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_convert_i32_s()});
+    v[opcodes::F32ConvertI32U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_convert_i32_u()});
+    v[opcodes::F32ConvertI64S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_convert_i64_s()});
+    v[opcodes::F32ConvertI64U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_convert_i64_u()});
+    v[opcodes::F32DemoteF64 as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f32_demote_f64()});
+    v[opcodes::F64ConvertI32S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_convert_i32_s()});
+    v[opcodes::F64ConvertI32U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_convert_i32_u()});
+    v[opcodes::F64ConvertI64S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_convert_i64_s()});
+    v[opcodes::F64ConvertI64U as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_convert_i64_u()});
+    v[opcodes::F64PromoteF32 as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.f64_promote_f32()});
+    v[opcodes::I32ReinterpretF32 as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{});
+    v[opcodes::I64ReinterpretF64 as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{});
+    v[opcodes::F32ReinterpretI32 as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{});
+    v[opcodes::F64ReinterpretI64 as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{});
+    v[opcodes::I32Extend8S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_extend_8_s()});
+    v[opcodes::I32Extend16S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i32_extend_16_s()});
+    v[opcodes::I64Extend8S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_extend_8_s()});
+    v[opcodes::I64Extend16S as usize] = Some(|vm:&mut Vm, args:ArgsEnum|{vm.i64_extend_16_s()});
+    v[opcodes::MemorySize as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.memory_size(args.get_u32())});
+    v[opcodes::MemoryGrow as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.memory_grow(args.get_u32())});
+
+    v[opcodes::I32Load as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load(args.get_mem_args())});
+    v[opcodes::I64Load as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load(args.get_mem_args())});
+    v[opcodes::F32Load as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.f32_load(args.get_mem_args())});
+    v[opcodes::F64Load as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.f64_load(args.get_mem_args())});
+    v[opcodes::I32Load8S as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load_8s(args.get_mem_args())});
+    v[opcodes::I32Load8U as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load_8u(args.get_mem_args())});
+    v[opcodes::I32Load16S as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load_16s(args.get_mem_args())});
+    v[opcodes::I32Load16U as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_load_16u(args.get_mem_args())});
+    v[opcodes::I64Load8S as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_8s(args.get_mem_args())});
+    v[opcodes::I64Load8U as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_8u(args.get_mem_args())});
+    v[opcodes::I64Load16S as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_16s(args.get_mem_args())});
+    v[opcodes::I64Load16U as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_16u(args.get_mem_args())});
+    v[opcodes::I64Load32S as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_32s(args.get_mem_args())});
+    v[opcodes::I64Load32U as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_load_32u(args.get_mem_args())});
+
+    v[opcodes::I32Store as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_store(args.get_mem_args())});
+    v[opcodes::I64Store as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_store(args.get_mem_args())});
+    v[opcodes::F32Store as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.f32_store(args.get_mem_args())});
+    v[opcodes::F64Store as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.f64_store(args.get_mem_args())});
+    v[opcodes::I32Store8 as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_store_8(args.get_mem_args())});
+    v[opcodes::I32Store16 as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i32_store_16(args.get_mem_args())});
+    v[opcodes::I64Store8 as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_store_8(args.get_mem_args())});
+    v[opcodes::I64Store16 as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_store_16(args.get_mem_args())});
+    v[opcodes::I64Store32 as usize] = Some(|vm:&mut Vm,args:ArgsEnum|{vm.i64_store_32(args.get_mem_args())});
+
+
+    let mut t = InstrTable{ single: v, prefixed: [Vec::new(),Vec::new(),Vec::new()] };
+    let misc = |sub:u32|Opcode::new(opcodes::MiscPrefix,sub);
+    t.insert(misc(opcodes::I32TruncSatF32S),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.trunc_sat(opcodes::I32TruncSatF32S)}));
+    t.insert(misc(opcodes::I32TruncSatF32U),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.trunc_sat(opcodes::I32TruncSatF32U)}));
+    t.insert(misc(opcodes::I32TruncSatF64S),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.trunc_sat(opcodes::I32TruncSatF64S)}));
+    t.insert(misc(opcodes::I32TruncSatF64U),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.trunc_sat(opcodes::I32TruncSatF64U)}));
+    t.insert(misc(opcodes::I64TruncSatF32S),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.trunc_sat(opcodes::I64TruncSatF32S)}));
+    t.insert(misc(opcodes::I64TruncSatF32U),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.trunc_sat(opcodes::I64TruncSatF32U)}));
+    t.insert(misc(opcodes::I64TruncSatF64S),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.trunc_sat(opcodes::I64TruncSatF64S)}));
+    t.insert(misc(opcodes::I64TruncSatF64U),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.trunc_sat(opcodes::I64TruncSatF64U)}));
+    t.insert(misc(opcodes::MemoryInit),Box::new(|vm:&mut Vm,args:&ArgsEnum|{
+        let args = args.get_prefix_args();
+        vm.memory_init(args.idx.unwrap(),args.idx2.unwrap_or(0))
+    }));
+    t.insert(misc(opcodes::DataDrop),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.data_drop(args.get_prefix_args().idx.unwrap())}));
+    t.insert(misc(opcodes::MemoryCopy),Box::new(|vm:&mut Vm,args:&ArgsEnum|{
+        let args = args.get_prefix_args();
+        vm.memory_copy(args.idx.unwrap_or(0),args.idx2.unwrap_or(0))
+    }));
+    t.insert(misc(opcodes::MemoryFill),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.memory_fill(args.get_prefix_args().idx.unwrap_or(0))}));
+    t.insert(misc(opcodes::TableGrow),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.table_grow(args.get_prefix_args().idx.unwrap_or(0))}));
+    t.insert(misc(opcodes::TableSize),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.table_size(args.get_prefix_args().idx.unwrap_or(0))}));
+    t.insert(misc(opcodes::TableFill),Box::new(|vm:&mut Vm,args:&ArgsEnum|{vm.table_fill(args.get_prefix_args().idx.unwrap_or(0))}));
+
+    // SIMD和原子指令每个子操作码一个入口
+    for (sub,_) in binary::simd::SIMD_OPS.iter() {
+        t.insert(Opcode::new(opcodes::SimdPrefix,*sub),Vm::simd_handler(*sub));
+    }
+    for (sub,_) in atomic::ATOMIC_OPS.iter() {
+        t.insert(Opcode::new(opcodes::AtomicPrefix,*sub),Vm::atomic_handler(*sub));
+    }
+
+    t
+}
+
+/// 模块实例,各个索引空间里都是句柄
+/// Vm执行时用它,函数引用也指向它,别的实例通过函数引用调用时换成它来执行
+#[derive(Debug)]
+pub struct ModuleInst {
+    module:Arc<binary::module::Module>,
+    /// 线性内存,按MemIdx排列
+    memories:Vec<Memory>,
+    /// 表,按TableIdx排列
+    tables:Vec<Table>,
+    /// 全局变量,按GlobalIdx排列,导入的在前
+    globals:Vec<Global>,
+    /// 异常标签,按TagIdx排列
+    tags:RwLock<Vec<Tag>>,
+    /// 宿主函数,按导入函数的FuncIdx排列
+    host_funcs:RwLock<Vec<Option<HostFunc>>>,
+    /// data.drop过的数据段
+    dropped_datas:Mutex<HashSet<u32>>,
+}
+
+impl ModuleInst {
+    /// 各个索引空间都已经按导入在前、定义在后排好
+    /// globals只有导入的全局变量,定义的在这里按初始化表达式求初值,初值里的函数引用指向新建的实例
+    fn new(module:Arc<binary::module::Module>,memories:Vec<Memory>,tables:Vec<Table>,mut globals:Vec<Global>,
+           tags:Vec<Tag>,host_funcs:Vec<Option<HostFunc>>) -> Result<Arc<ModuleInst>,ValidationError>{
+        let mut result = Ok(());
+        let inst = Arc::new_cyclic(|owner|{
+            result = init_globals(&module,&mut globals,owner);
+            ModuleInst{
+                module,
+                memories,
+                tables,
+                globals,
+                tags: RwLock::new(tags),
+                host_funcs: RwLock::new(host_funcs),
+                dropped_datas: Mutex::default(),
+            }
+        });
+        result.map(|_|inst)
+    }
+}
+
+/// 实例里函数的引用
+fn func_ref(owner:&Weak<ModuleInst>,idx:FuncIdx) -> ArgsEnum{
+    let owner:Weak<dyn std::any::Any + Send + Sync> = owner.clone();
+    ArgsEnum::FuncRef(Some(FuncRef::new(idx,owner)))
+}
+
+/// 按初始化表达式给定义的全局变量赋初值,表达式可以读导入的和前面定义的全局变量
+/// 只在实例化时求值一次,之后全局变量的值只通过global.set和句柄改变
+fn init_globals(module:&binary::module::Module,globals:&mut Vec<Global>,owner:&Weak<ModuleInst>) -> Result<(),ValidationError>{
+    let mut vals:Vec<ArgsEnum> = globals.iter().map(Global::get).collect();
+    for g in module.global_sec.iter().flatten() {
+        let init = g.init.as_ref().expect("global has no init expression");
+        let v = const_expr::eval(init,&vals,&|idx|func_ref(owner,idx))?;
+        globals.push(Global::new(g.ty.clone().expect("global has no type"),v.clone()));
+        vals.push(v);
+    }
+    Ok(())
 }
 
 #[derive(Debug,Clone)]
 pub struct Vm {
     operand_stack:operand::OperandStack,
-    module:binary::module::Module,
-    memory:Memory,
+    /// 正在执行的实例,调用别的实例的函数时临时换成那个实例
+    inst:Arc<ModuleInst>,
+    /// 控制栈,每个block/loop/if和函数体对应一个控制帧
+    ctrl_stack:Vec<ControlFrame>,
+    /// 调用栈
+    frames:Vec<Frame>,
+    /// 控制栈最多的层数,超过时陷入errCallStackExhausted
+    max_stack_depth:usize,
+    /// 正在向外传播的异常,被catch或者交给宿主后清空
+    exception:Option<ExnRef>,
+}
+
+/// 陷入时的panic信息
+pub(crate) fn trap_message(e:Box<dyn std::any::Any + Send>) -> String{
+    match e.downcast::<&str>() {
+        Ok(s) => s.to_string(),
+        Err(e) => e.downcast::<String>().map(|s|*s).unwrap_or_else(|_|"unknown trap".to_string()),
+    }
+}
+
+/// 宿主函数,拿到参数返回结果,返回Err就是向模块里抛出这个异常
+/// 宿主函数里可以用try_invoke回调模块,捕获模块抛出的异常
+pub type HostFn = dyn Fn(&mut Vm,Vec<ArgsEnum>) -> Result<Vec<ArgsEnum>,ExnRef> + Send + Sync;
+
+/// 宿主函数带着自己的函数类型,绑定导入时和导入声明的类型比较
+#[derive(Clone)]
+pub struct HostFunc{
+    ft:module::FuncType,
+    f:Arc<HostFn>,
+}
+
+impl HostFunc {
+    pub fn new<F>(ft:module::FuncType,f:F) -> HostFunc
+        where F:Fn(&mut Vm,Vec<ArgsEnum>) -> Result<Vec<ArgsEnum>,ExnRef> + Send + Sync + 'static{
+        HostFunc{ ft, f: Arc::new(f) }
+    }
+
+    pub fn func_type(&self) -> &module::FuncType{
+        &self.ft
+    }
+
+    /// 参数和返回值类型都和ft一样
+    pub fn matches(&self,ft:&module::FuncType) -> bool{
+        self.ft.params() == ft.params() && self.ft.results() == ft.results()
+    }
+}
+
+impl std::fmt::Debug for HostFunc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f,"HostFunc({:?},{:p})",self.ft,Arc::as_ptr(&self.f))
+    }
+}
+
+/// 一条指令执行完之后的去向
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Control {
+    /// 顺序执行下一条
+    Next,
+    /// 跳转到由内向外第n层标签
+    Br(LabelIdx),
+    /// 从当前函数返回
+    Return,
+    /// 尾调用,当前函数已经退出,参数留在栈上,由call接着调用这个函数
+    ReturnCall(FuncIdx),
+    /// 抛出了异常,异常在Vm::exception里,一直向外传播到匹配的try_table
+    Throw,
+}
+
+/// 控制帧
+/// arity 跳转到这个标签时带走的值的个数,loop是参数个数,其他是结果个数
+/// height 进入时操作数栈的高度,不包括块的参数
+#[derive(Debug,Clone)]
+pub struct ControlFrame {
+    pub arity:usize,
+    pub height:usize,
+}
+
+/// 调用帧
+/// locals 参数和局部变量
+/// ctrl_base 函数体的控制帧在控制栈中的位置
+#[derive(Debug,Clone)]
+pub struct Frame {
+    pub func_idx:FuncIdx,
+    pub locals:Vec<ArgsEnum>,
+    pub ctrl_base:usize,
+}
+
+/// 值类型的零值,局部变量用它初始化
+pub fn zero_value(t:u8) -> ArgsEnum{
+    match t {
+        module::VAL_TYPE_I32 => ArgsEnum::I32(0),
+        module::VAL_TYPE_I64 => ArgsEnum::I64(0),
+        module::VAL_TYPE_F32 => ArgsEnum::F32(0.0),
+        module::VAL_TYPE_F64 => ArgsEnum::F64(0.0),
+        module::VAL_TYPE_V128 => ArgsEnum::V128(0),
+        module::FUNC_REF|module::EXTERN_REF|module::EXN_REF => vm_table::null_ref(t),
+        t => panic!("unknown value type:{:#04x}",t),
+    }
+}
+
+/// 栈上的整数可能是有符号、无符号或者比较结果,交给外部时统一成值类型对应的形式
+pub fn to_val_type(t:u8,v:ArgsEnum) -> ArgsEnum{
+    match (t,v) {
+        (module::VAL_TYPE_I32,ArgsEnum::U32(v)) => ArgsEnum::I32(v as i32),
+        (module::VAL_TYPE_I32,ArgsEnum::Bool(v)) => ArgsEnum::I32(v as i32),
+        (module::VAL_TYPE_I64,ArgsEnum::U64(v)) => ArgsEnum::I64(v as i64),
+        (_,v) => v,
+    }
+}
+
+/// 控制流和函数调用
+impl Vm {
+    /// 块类型展开成(参数个数,结果个数)
+    fn block_arity(&self,bt:BlockType) -> (usize,usize){
+        match bt {
+            BlockType::Empty => (0,0),
+            BlockType::Value(_) => (0,1),
+            BlockType::TypeIdx(idx) => {
+                let ft = self.inst.module.type_sec.as_ref()
+                    .and_then(|v|v.get(idx as usize))
+                    .expect("errUnknownType");
+                (ft.params().len(),ft.results().len())
+            }
+        }
+    }
+
+    pub fn exec_instrs(&mut self,instrs:&[Instruction]) -> Control{
+        for instr in instrs {
+            // 块和调用直接在这里递归,不经过exec_instr,每层嵌套占的本地栈少一些
+            let c = match (instr.opcode,&instr.args) {
+                (Some(opcodes::Block),Some(ArgsEnum::BlockArgs(args)))|(Some(opcodes::Loop),Some(ArgsEnum::BlockArgs(args))) => {
+                    self.exec_block(instr.opcode.unwrap(),args.bt.unwrap_or(BlockType::Empty),args.instrs.as_deref().unwrap_or(&[]))
+                }
+                (Some(opcodes::If),Some(ArgsEnum::IfArgs(args))) => self.exec_if(args),
+                (Some(opcodes::Call),Some(args)) => self.call(args.get_u32()),
+                (Some(opcodes::CallIndirect),Some(ArgsEnum::CallIndirectArgs(args))) => self.call_indirect(args),
+                (Some(opcodes::TryTable),Some(ArgsEnum::TryTableArgs(args))) => self.exec_try_table(args),
+                _ => self.exec_instr(instr),
+            };
+            if c != Control::Next {
+                return c;
+            }
+        }
+        Control::Next
+    }
+
+    pub fn exec_instr(&mut self,instr:&Instruction) -> Control{
+        let opcode = instr.opcode.expect("instruction has no opcode");
+        let args = instr.args.as_ref().unwrap_or(&ArgsEnum::NONE);
+        match (opcode,args) {
+            (opcodes::Unreachable,_) => panic!("errUnreachable"),
+            (opcodes::Nop,_) => Control::Next,
+            (opcodes::Block,ArgsEnum::BlockArgs(args))|(opcodes::Loop,ArgsEnum::BlockArgs(args)) => {
+                self.exec_block(opcode,args.bt.unwrap_or(BlockType::Empty),args.instrs.as_deref().unwrap_or(&[]))
+            }
+            (opcodes::If,ArgsEnum::IfArgs(args)) => self.exec_if(args),
+            (opcodes::Br,_) => self.br(args.get_u32()),
+            (opcodes::BrIf,_) => {
+                if self.operand_stack.pop_bool().unwrap() {
+                    self.br(args.get_u32())
+                } else {
+                    Control::Next
+                }
+            }
+            (opcodes::BrTable,ArgsEnum::BrTableArgs(args)) => {
+                let idx = self.operand_stack.pop_u32().unwrap();
+                let labels = args.labels.as_deref().unwrap_or(&[]);
+                let l = labels.get(idx as usize).copied().unwrap_or_else(||args.default.unwrap());
+                self.br(l)
+            }
+            (opcodes::Return,_) => self.ret(),
+            (opcodes::Call,_) => self.call(args.get_u32()),
+            (opcodes::CallIndirect,ArgsEnum::CallIndirectArgs(args)) => self.call_indirect(args),
+            (opcodes::ReturnCall,_) => self.return_call(args.get_u32()),
+            (opcodes::ReturnCallIndirect,ArgsEnum::CallIndirectArgs(args)) => self.return_call_indirect(args),
+            (opcodes::TryTable,ArgsEnum::TryTableArgs(args)) => self.exec_try_table(args),
+            (opcodes::Throw,_) => self.throw(args.get_u32()),
+            (opcodes::ThrowRef,_) => {
+                match self.operand_stack.pop().unwrap().get_exn_ref() {
+                    Some(exn) => self.throw_exn(exn),
+                    None => panic!("errNullExnRef"),
+                }
+            }
+            (opcodes::LocalGet,_) => {
+                let v = self.frames.last().unwrap().locals[args.get_u32() as usize].clone();
+                self.operand_stack.push(v);
+                Control::Next
+            }
+            (opcodes::LocalSet,_) => {
+                let v = self.operand_stack.pop().unwrap();
+                self.frames.last_mut().unwrap().locals[args.get_u32() as usize] = v;
+                Control::Next
+            }
+            (opcodes::LocalTee,_) => {
+                let v = self.operand_stack.pop().unwrap();
+                self.operand_stack.push(v.clone());
+                self.frames.last_mut().unwrap().locals[args.get_u32() as usize] = v;
+                Control::Next
+            }
+            _ if opcodes::is_prefix(opcode) => {
+                let f = OPCODE_MAP.get_or_init(opcode_map).get_prefixed(instr.op())
+                    .unwrap_or_else(||panic!("unsupported opcode:{}",instr.op()));
+                f(self,args);
+                Control::Next
+            }
+            _ => {
+                let op = instr.op();
+                let f = OPCODE_MAP.get_or_init(opcode_map).get(op)
+                    .unwrap_or_else(||panic!("unsupported opcode:{}",op));
+                f(self,args.clone());
+                Control::Next
+            }
+        }
+    }
+
+    /// 执行block/loop/if的主体,参数留在栈上作为主体的初始操作数
+    fn exec_block(&mut self,opcode:u8,bt:BlockType,instrs:&[Instruction]) -> Control{
+        let (params,results) = self.block_arity(bt);
+        let height = self.operand_stack.len() - params;
+        let arity = if opcode == opcodes::Loop {params} else {results};
+        loop {
+            self.push_ctrl(ControlFrame{ arity, height });
+            let c = self.exec_instrs(instrs);
+            self.ctrl_stack.pop();
+            match c {
+                // 跳到loop的标签是回到开头,跳到其他块的标签是跳到结尾
+                Control::Br(0) if opcode == opcodes::Loop => continue,
+                Control::Br(0) => return Control::Next,
+                Control::Br(l) => return Control::Br(l - 1),
+                c => return c,
+            }
+        }
+    }
+
+    /// 进入块或者函数体,嵌套的块和调用一起算深度
+    fn push_ctrl(&mut self,frame:ControlFrame){
+        if self.ctrl_stack.len() >= self.max_stack_depth {
+            panic!("errCallStackExhausted")
+        }
+        self.ctrl_stack.push(frame);
+    }
+
+    fn exec_if(&mut self,args:&IfArgs) -> Control{
+        let instrs = if self.operand_stack.pop_bool().unwrap() {&args.instrs1} else {&args.instrs2};
+        self.exec_block(opcodes::If,args.bt.unwrap_or(BlockType::Empty),instrs.as_deref().unwrap_or(&[]))
+    }
+
+    /// 跳转前先把操作数栈恢复到目标标签的高度,只保留标签需要的值
+    fn br(&mut self,l:LabelIdx) -> Control{
+        let frame = &self.ctrl_stack[self.ctrl_stack.len() - 1 - l as usize];
+        self.operand_stack.unwind(frame.height,frame.arity);
+        Control::Br(l)
+    }
+
+    fn ret(&mut self) -> Control{
+        let frame = &self.ctrl_stack[self.frames.last().unwrap().ctrl_base];
+        self.operand_stack.unwind(frame.height,frame.arity);
+        Control::Return
+    }
+
+    /// return_call 只保留被调函数的参数,退出当前函数后再调用,调用栈不会变深
+    fn return_call(&mut self,idx:FuncIdx) -> Control{
+        let n = self.inst.module.get_func_type(idx).expect("errUnknownFunc").params().len();
+        let height = self.ctrl_stack[self.frames.last().unwrap().ctrl_base].height;
+        self.operand_stack.unwind(height,n);
+        Control::ReturnCall(idx)
+    }
+
+    /// 按表里的元素找到要间接调用的函数和它所属的实例,签名按结构比较
+    fn resolve_indirect(&mut self,args:&CallIndirectArgs) -> (Arc<ModuleInst>,FuncIdx){
+        let i = self.operand_stack.pop_u32().unwrap();
+        let table = &self.inst.tables[args.table.unwrap_or(0) as usize];
+        if i >= table.size() {
+            panic!("errUndefinedElement")
+        }
+        let f = match table.get(i) {
+            ArgsEnum::FuncRef(Some(f)) => f,
+            _ => panic!("errUninitializedElement"),
+        };
+        let inst = f.owner::<ModuleInst>().expect("errDroppedInstance");
+        let expected = self.inst.module.type_sec.as_ref()
+            .and_then(|v|v.get(args.type_idx.unwrap_or(0) as usize))
+            .expect("errUnknownType");
+        let actual = inst.module.get_func_type(f.idx()).expect("errUnknownFunc");
+        if expected.params() != actual.params() || expected.results() != actual.results() {
+            panic!("errIndirectCallTypeMismatch")
+        }
+        (inst,f.idx())
+    }
+
+    fn call_indirect(&mut self,args:&CallIndirectArgs) -> Control{
+        match self.resolve_indirect(args) {
+            (inst,idx) if Arc::ptr_eq(&inst,&self.inst) => self.call(idx),
+            (inst,idx) => self.call_in(inst,idx),
+        }
+    }
+
+    /// 别的实例的函数不能在当前函数退出后接着调用,调用完再返回
+    fn return_call_indirect(&mut self,args:&CallIndirectArgs) -> Control{
+        match self.resolve_indirect(args) {
+            (inst,idx) if Arc::ptr_eq(&inst,&self.inst) => self.return_call(idx),
+            (inst,idx) => match self.call_in(inst,idx) {
+                Control::Throw => Control::Throw,
+                _ => self.ret(),
+            },
+        }
+    }
+
+    /// 调用别的实例的函数,换成那个实例执行,调用栈、控制栈和深度上限还是同一套
+    /// 陷入时由invoke换回来
+    fn call_in(&mut self,inst:Arc<ModuleInst>,idx:FuncIdx) -> Control{
+        let caller = std::mem::replace(&mut self.inst,inst);
+        let c = self.call(idx);
+        self.inst = caller;
+        c
+    }
+
+    /// 调用函数,参数已经在操作数栈上,返回后结果留在栈上
+    /// 导入函数占用前面的FuncIdx,交给宿主执行
+    /// 函数体以尾调用结束时在这里循环调用下一个函数,不占用本地栈
+    /// 没有被捕获的异常返回Control::Throw,其他情况返回Control::Next
+    pub fn call(&mut self,mut idx:FuncIdx) -> Control{
+        let module = self.inst.module.clone();
+        let import_count = module.get_import_func_count();
+        loop {
+            if idx < import_count {
+                return self.call_host(idx);
+            }
+            let ft = module.get_func_type(idx).expect("errUnknownFunc");
+            let code = module.code_sec.as_ref()
+                .and_then(|v|v.get((idx - import_count) as usize))
+                .expect("errUnknownFunc");
+            let expr = code.get_expr().unwrap_or_else(|e|panic!("{}",e));
+
+            // 参数在前,后面是初始化为零值的局部变量
+            let n = ft.params().len() + code.get_local_count().unwrap_or(0) as usize;
+            let mut locals = Vec::with_capacity(n);
+            locals.extend(self.operand_stack.pop_n(ft.params().len()));
+            for l in code.locals.iter().flatten() {
+                let v = zero_value(l.ty.unwrap());
+                locals.extend((0..l.n.unwrap_or(0)).map(|_|v.clone()));
+            }
+            self.frames.push(Frame{ func_idx: idx, locals, ctrl_base: self.ctrl_stack.len() });
+            // 函数体相当于一个块,跳出最外层标签和return一样
+            self.push_ctrl(ControlFrame{ arity: ft.results().len(), height: self.operand_stack.len() });
+            let c = self.exec_instrs(expr);
+            self.ctrl_stack.pop();
+            self.frames.pop();
+            match c {
+                Control::ReturnCall(next) => idx = next,
+                Control::Throw => return Control::Throw,
+                _ => return Control::Next,
+            }
+        }
+    }
+
+    /// 调用导入函数,宿主返回的异常在模块里接着传播
+    fn call_host(&mut self,idx:FuncIdx) -> Control{
+        let f = match self.inst.host_funcs.read().unwrap().get(idx as usize) {
+            Some(Some(f)) => f.clone(),
+            _ => panic!("errUnresolvedImport"),
+        };
+        let n = self.inst.module.get_func_type(idx).expect("errUnknownFunc").params().len();
+        let args = self.operand_stack.pop_n(n);
+        match (f.f)(self,args) {
+            Ok(results) => {
+                self.operand_stack.push_n(results);
+                Control::Next
+            }
+            Err(exn) => self.throw_exn(exn),
+        }
+    }
+
+    /// 从外部调用函数,返回值按函数类型排列,陷入和没有被捕获的异常都返回Trap
+    pub fn invoke(&mut self,idx:FuncIdx,args:Vec<ArgsEnum>) -> Result<Vec<ArgsEnum>,Trap>{
+        // 陷入时栈上还留着半截的调用,恢复到调用前的高度,Vm还能接着用
+        let (frames,ctrls,height) = (self.frames.len(),self.ctrl_stack.len(),self.operand_stack.len());
+        let inst = self.inst.clone();
+        match catch_unwind(AssertUnwindSafe(||self.try_invoke(idx,args))) {
+            Ok(Ok(results)) => Ok(results),
+            Ok(Err(_)) => Err(Trap("errUncaughtException".to_string())),
+            Err(e) => {
+                self.inst = inst;
+                self.frames.truncate(frames);
+                self.ctrl_stack.truncate(ctrls);
+                self.operand_stack.unwind(height,0);
+                self.exception = None;
+                Err(Trap(trap_message(e)))
+            }
+        }
+    }
+
+    /// 和invoke一样,但是把没有被捕获的异常交给调用者
+    /// 陷入不在这里处理,一直传到最外层的invoke
+    pub fn try_invoke(&mut self,idx:FuncIdx,args:Vec<ArgsEnum>) -> Result<Vec<ArgsEnum>,ExnRef>{
+        let module = self.inst.module.clone();
+        let ft = module.get_func_type(idx).expect("errUnknownFunc");
+        if args.len() != ft.params().len() {
+            panic!("errArgumentCount")
+        }
+        let height = self.operand_stack.len();
+        self.operand_stack.push_n(args);
+        if self.call(idx) == Control::Throw {
+            self.operand_stack.unwind(height,0);
+            return Err(self.exception.take().unwrap());
+        }
+        let results = self.operand_stack.pop_n(ft.results().len());
+        Ok(ft.results().iter().zip(results).map(|(t,v)|to_val_type(*t,v)).collect())
+    }
+
+    /// 绑定导入函数,类型要和导入声明的一样
+    pub fn set_host_func(&mut self,idx:FuncIdx,f:HostFunc){
+        if idx >= self.inst.module.get_import_func_count() {
+            panic!("errNotImportedFunc")
+        }
+        if !f.matches(self.inst.module.get_func_type(idx).expect("errUnknownFunc")) {
+            panic!("errIncompatibleImportType")
+        }
+        let mut host_funcs = self.inst.host_funcs.write().unwrap();
+        if host_funcs.len() <= idx as usize {
+            host_funcs.resize(idx as usize + 1,None);
+        }
+        host_funcs[idx as usize] = Some(f);
+    }
+}
+
+/// 异常处理
+impl Vm {
+    /// 标签,宿主用它构造要抛进模块的异常,或者辨认模块抛出的异常
+    pub fn tag(&self,idx:u32) -> Tag{
+        self.inst.tags.read().unwrap().get(idx as usize).cloned().expect("errUnknownTag")
+    }
+
+    /// 导入的标签默认是新建的,换成别的实例的标签后两边抛出的异常才能互相捕获
+    pub fn set_tag(&mut self,idx:u32,tag:Tag){
+        *self.inst.tags.write().unwrap().get_mut(idx as usize).expect("errUnknownTag") = tag;
+    }
+
+    /// throw 栈上是标签参数类型对应的值
+    fn throw(&mut self,idx:u32) -> Control{
+        let tag = self.tag(idx);
+        let values = self.operand_stack.pop_n(tag.func_type().params().len());
+        self.throw_exn(ExnRef::new(tag,values))
+    }
+
+    fn throw_exn(&mut self,exn:ExnRef) -> Control{
+        self.exception = Some(exn);
+        Control::Throw
+    }
+
+    /// 主体抛出异常时按顺序找第一个匹配的catch,恢复操作数栈后跳到它的标签
+    /// catch带上异常的值,catch_ref再多带一个异常引用,catch_all什么都不带
+    fn exec_try_table(&mut self,args:&TryTableArgs) -> Control{
+        let bt = args.bt.unwrap_or(BlockType::Empty);
+        let height = self.operand_stack.len() - self.block_arity(bt).0;
+        let c = self.exec_block(opcodes::TryTable,bt,args.instrs.as_deref().unwrap_or(&[]));
+        if c != Control::Throw {
+            return c;
+        }
+        let exn = self.exception.clone().unwrap();
+        for catch in args.catches.iter().flatten() {
+            if let Some(idx) = catch.tag {
+                if self.tag(idx) != *exn.tag() {
+                    continue;
+                }
+            }
+            self.exception = None;
+            self.operand_stack.unwind(height,0);
+            if catch.tag.is_some() {
+                self.operand_stack.push_n(exn.values().to_vec());
+            }
+            if catch.is_ref() {
+                self.operand_stack.push(ArgsEnum::ExnRef(Some(exn)));
+            }
+            return self.br(catch.label);
+        }
+        Control::Throw
+    }
 }
 
 /// i32
@@ -1093,26 +1733,50 @@ impl Vm {
 impl Vm {
 
     pub fn new(var1:operand::OperandStack,var2:binary::module::Module,var3:Memory) -> Vm{
-        Vm{ operand_stack: var1, module: var2 , memory: var3 }
-    }
-
-    // pub fn exec_code(&mut self, idx:usize){
-    //     self.module.code_sec.clone()
-    //         .and_then(|v|v.get(idx).cloned()).or_else(||{println!("vec code none");None})
-    //         .and_then(|code|code.expr).or_else(||{println!("code expr none");None})
-    //         .and_then(|expr|{
-    //             for x in expr {
-    //                 self.exec_instr(x);
-    //             }
-    //             Some(())
-    //         }).expect("exec code none");
-    // }
-
-    // pub fn exec_instr(&mut self, instr:binary::instruction::Instruction){
-    //     OPCODE_MAP.get()
-    //         .and_then(|v| v.get(instr.opcode.unwrap() as usize))
-    //         .and_then(|f| {f(self,instr.args.unwrap());Some(())});
-    // }
+        // 这里不绑定导入,导入的表按表类型新建,导入的全局变量是零值,要绑定导入用Instance::new
+        let tables = var2.get_table_types().into_iter().cloned().map(Table::new).collect();
+        // 传进来的是0号内存,其余的按内存类型新建
+        let mut memories = vec![var3];
+        memories.extend(var2.get_mem_types().into_iter().skip(1).cloned().map(Memory::new));
+        let globals = var2.get_global_types().into_iter().take(var2.get_import_global_count() as usize)
+            .map(|t|Global::new(t.clone(),zero_value(t.val_type.unwrap())))
+            .collect();
+        let tags = (0..var2.get_tag_types().len() as u32)
+            .map(|i|Tag::new(var2.get_tag_func_type(i).cloned().expect("errUnknownType")))
+            .collect();
+        let inst = ModuleInst::new(Arc::new(var2),memories,tables,globals,tags,vec![]).unwrap_or_else(|e|panic!("{}",e));
+        Vm{
+            operand_stack: var1,
+            inst,
+            ctrl_stack: vec![],
+            frames: vec![],
+            max_stack_depth: MAX_STACK_DEPTH,
+            exception: None,
+        }
+    }
+
+    /// 实例化时用,各个索引空间都已经按导入在前、定义在后排好
+    /// globals只有导入的全局变量,定义的在这里求初值
+    pub(crate) fn with_store(module:Arc<binary::module::Module>,memories:Vec<Memory>,tables:Vec<Table>,
+                             globals:Vec<Global>,tags:Vec<Tag>,host_funcs:Vec<Option<HostFunc>>) -> Result<Vm,ValidationError>{
+        Ok(Vm{
+            operand_stack: operand::new(),
+            inst: ModuleInst::new(module,memories,tables,globals,tags,host_funcs)?,
+            ctrl_stack: vec![],
+            frames: vec![],
+            max_stack_depth: MAX_STACK_DEPTH,
+            exception: None,
+        })
+    }
+
+    pub fn module(&self) -> &binary::module::Module{
+        &self.inst.module
+    }
+
+    /// 控制栈最多的层数,默认是MAX_STACK_DEPTH
+    pub fn set_max_stack_depth(&mut self,max_depth:usize){
+        self.max_stack_depth = max_depth;
+    }
 
     //0x1A
     pub fn drop(&mut self){
@@ -1121,22 +1785,10 @@ impl Vm {
 
     //0x1B
     pub fn select(&mut self){
-        let v3 = self.operand_stack.pop_bool();
+        let v3 = self.operand_stack.pop_bool().unwrap();
         let v2 = self.operand_stack.pop().unwrap();
         let v1 = self.operand_stack.pop().unwrap();
-
-        if v1.eq(&v2) {
-            v3.and_then(|b|{
-                if b {
-                    self.operand_stack.push(v1);
-                } else {
-                    self.operand_stack.push(v2);
-                };
-               Some(())
-            });
-        } else {
-            panic!("Data format does not match")
-        }
+        self.operand_stack.push(if v3 {v1} else {v2});
     }
 
     //0x41
@@ -1337,44 +1989,45 @@ impl Vm{
         self.operand_stack.push_s32(v);
     }
 
-    pub fn trunc_sat(&mut self,val:u8){
-        match val {
-            0 => {
+    /// 0xFC前缀的饱和截断,sub是子操作码
+    pub fn trunc_sat(&mut self,sub:u32){
+        match sub {
+            opcodes::I32TruncSatF32S => {
                 let v = self.operand_stack.pop_f32().unwrap();
                 let result = trunc_sat_s(v as f64,32);
                 self.operand_stack.push_s32(result as i32);
             }
-            1 => {
+            opcodes::I32TruncSatF32U => {
                 let v = self.operand_stack.pop_f32().unwrap();
                 let result = trunc_sat_u(v as f64, 32);
                 self.operand_stack.push_u32(result as u32);
             }
-            2 => {
+            opcodes::I32TruncSatF64S => {
                 let v = self.operand_stack.pop_f64().unwrap();
                 let result = trunc_sat_s(v,32);
                 self.operand_stack.push_s32(result as i32);
             }
-            3 => {
+            opcodes::I32TruncSatF64U => {
                 let v = self.operand_stack.pop_f64().unwrap();
                 let result = trunc_sat_u(v,32);
                 self.operand_stack.push_u32(result as u32);
             }
-            4 => {
+            opcodes::I64TruncSatF32S => {
                 let v = self.operand_stack.pop_f32().unwrap();
                 let result = trunc_sat_s(v as f64,64);
                 self.operand_stack.push_s64(result);
             }
-            5 => {
+            opcodes::I64TruncSatF32U => {
                 let v = self.operand_stack.pop_f32().unwrap();
                 let result = trunc_sat_u(v as f64,64);
                 self.operand_stack.push_u64(result)
             }
-            6 => {
+            opcodes::I64TruncSatF64S => {
                 let v = self.operand_stack.pop_f64().unwrap();
                 let result = trunc_sat_s(v,64);
                 self.operand_stack.push_s64(result);
             }
-            7 => {
+            opcodes::I64TruncSatF64U => {
                 let v = self.operand_stack.pop_f64().unwrap();
                 let result = trunc_sat_u(v,64);
                 self.operand_stack.push_u64(result);
@@ -1388,43 +2041,359 @@ impl Vm{
 
 }
 
+/// 引用和表
+impl Vm{
+    pub fn ref_null(&mut self,t:u8){
+        self.operand_stack.push(vm_table::null_ref(t));
+    }
+
+    pub fn ref_is_null(&mut self){
+        let v = self.operand_stack.pop().unwrap();
+        let is_null = v.is_null_ref().unwrap_or_else(||panic!("not a reference:{:?}",v));
+        self.operand_stack.push_bool(is_null as i32);
+    }
+
+    /// 这个实例里函数的引用,放进共享的表里别的实例也能调用
+    pub fn func_ref(&self,idx:FuncIdx) -> ArgsEnum{
+        func_ref(&Arc::downgrade(&self.inst),idx)
+    }
+
+    pub fn ref_func(&mut self,idx:FuncIdx){
+        let v = self.func_ref(idx);
+        self.operand_stack.push(v);
+    }
+
+    pub(crate) fn table(&self,idx:u32) -> &Table{
+        self.inst.tables.get(idx as usize).expect("errUnknownTable")
+    }
+
+    /// table.get 栈上是 下标
+    pub fn table_get(&mut self,idx:u32){
+        let i = self.operand_stack.pop_u32().unwrap();
+        let v = self.table(idx).get(i);
+        self.operand_stack.push(v);
+    }
+
+    /// table.set 栈上是 下标 值
+    pub fn table_set(&mut self,idx:u32){
+        let v = self.operand_stack.pop().unwrap();
+        let i = self.operand_stack.pop_u32().unwrap();
+        self.table(idx).set(i,v);
+    }
+
+    pub fn table_size(&mut self,idx:u32){
+        let size = self.table(idx).size();
+        self.operand_stack.push_u32(size);
+    }
+
+    /// table.grow 栈上是 初始值 增长个数,失败时结果是-1
+    pub fn table_grow(&mut self,idx:u32){
+        let n = self.operand_stack.pop_u32().unwrap();
+        let v = self.operand_stack.pop().unwrap();
+        let old_size = self.table(idx).grow(n,v);
+        self.operand_stack.push_u32(old_size);
+    }
+
+    /// table.fill 栈上是 起始下标 值 个数
+    pub fn table_fill(&mut self,idx:u32){
+        let n = self.operand_stack.pop_u32().unwrap();
+        let v = self.operand_stack.pop().unwrap();
+        let i = self.operand_stack.pop_u32().unwrap();
+        self.table(idx).fill(i,v,n);
+    }
+}
+
+/// 原子指令
+impl Vm{
+    /// 访问宽度、值类型和读改写的运算按子操作码定好
+    fn atomic_handler(sub:u32) -> PrefixedFn{
+        let size = atomic::mem_size(sub) as usize;
+        let is_i64 = atomic::val_type(sub) == binary::module::VAL_TYPE_I64;
+        match sub {
+            atomic::AtomicFence => Box::new(|_,_|std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst)),
+            atomic::MemoryAtomicNotify => Box::new(|vm,args|{
+                let count = vm.operand_stack.pop_u32().unwrap();
+                let (mem,addr) = vm.atomic_addr(args);
+                let n = vm.memory(mem).notify(addr,count);
+                vm.operand_stack.push_s32(n as i32);
+            }),
+            atomic::MemoryAtomicWait32|atomic::MemoryAtomicWait64 => Box::new(move |vm,args|{
+                let timeout = vm.operand_stack.pop_s64().unwrap();
+                let expected = vm.pop_atomic_val(size == 8);
+                let (mem,addr) = vm.atomic_addr(args);
+                let r = vm.memory(mem).wait(addr,size,expected,timeout);
+                vm.operand_stack.push_s32(r as i32);
+            }),
+            atomic::I32AtomicLoad..=atomic::I64AtomicLoad32U => Box::new(move |vm,args|{
+                let (mem,addr) = vm.atomic_addr(args);
+                let v = vm.memory(mem).atomic_load(addr,size);
+                vm.push_atomic_val(is_i64,v);
+            }),
+            atomic::I32AtomicStore..=atomic::I64AtomicStore32 => Box::new(move |vm,args|{
+                let v = vm.pop_atomic_val(is_i64);
+                let (mem,addr) = vm.atomic_addr(args);
+                vm.memory(mem).atomic_store(addr,size,v);
+            }),
+            _ => {
+                let op = atomic::rmw_op(sub).unwrap_or_else(||panic!("unknown 0xfe sub opcode:{}",sub));
+                let f:fn(u64,u64,u64) -> u64 = match op {
+                    RmwOp::Add => |old,v,_|old.wrapping_add(v),
+                    RmwOp::Sub => |old,v,_|old.wrapping_sub(v),
+                    RmwOp::And => |old,v,_|old & v,
+                    RmwOp::Or => |old,v,_|old | v,
+                    RmwOp::Xor => |old,v,_|old ^ v,
+                    RmwOp::Xchg => |_,v,_|v,
+                    RmwOp::Cmpxchg => |old,v,expected|if old == expected {v} else {old},
+                };
+                // cmpxchg的期望值要先截成访问宽度再比较
+                let mask = u64::MAX >> (64 - size * 8);
+                Box::new(move |vm,args|{
+                    let v = vm.pop_atomic_val(is_i64);
+                    let expected = match op {
+                        RmwOp::Cmpxchg => vm.pop_atomic_val(is_i64) & mask,
+                        _ => 0,
+                    };
+                    let (mem,addr) = vm.atomic_addr(args);
+                    let old = vm.memory(mem).atomic_rmw(addr,size,|old|f(old,v,expected));
+                    vm.push_atomic_val(is_i64,old);
+                })
+            }
+        }
+    }
+
+    /// 弹出地址加上memarg的偏移
+    fn atomic_addr(&mut self,args:&ArgsEnum) -> (u32,usize){
+        let mem_arg = match args {
+            ArgsEnum::AtomicArgs(args) => args.mem_arg.as_ref(),
+            v => panic!("unexpected atomic args:{:?}",v),
+        };
+        let mem = mem_arg.and_then(|m|m.mem).unwrap_or(0);
+        (mem,self.effective_addr(mem,mem_arg.and_then(|m|m.offset).unwrap_or(0)))
+    }
+
+    fn pop_atomic_val(&mut self,is_i64:bool) -> u64{
+        if is_i64 {
+            self.operand_stack.pop_u64().unwrap()
+        } else {
+            self.operand_stack.pop_u32().unwrap() as u64
+        }
+    }
+
+    fn push_atomic_val(&mut self,is_i64:bool,v:u64){
+        if is_i64 {
+            self.operand_stack.push_s64(v as i64);
+        } else {
+            self.operand_stack.push_s32(v as i32);
+        }
+    }
+}
+
+/// simd
+impl Vm{
+    /// 内存指令按访问方式、其余指令按simd::resolve选好运算
+    fn simd_handler(sub:u32) -> PrefixedFn{
+        let size = binary::simd::mem_size(sub) as usize;
+        match (binary::simd::imm(sub),sub) {
+            (SimdImm::V128,_) => Box::new(|vm,args|{
+                let v = simd_args(args).v128.unwrap();
+                vm.operand_stack.push(ArgsEnum::V128(v));
+            }),
+            (SimdImm::MemArg,binary::simd::V128Store) => Box::new(|vm,args|{
+                let v = vm.operand_stack.pop().unwrap().get_v128();
+                let (mem,addr) = vm.simd_addr(simd_args(args));
+                vm.memory(mem).write(addr,&v.to_le_bytes());
+            }),
+            (SimdImm::MemArg,_) => {
+                let load = simd::loader(sub);
+                Box::new(move |vm,args|{
+                    let (mem,addr) = vm.simd_addr(simd_args(args));
+                    let mut buf = vec![0u8;size];
+                    vm.memory(mem).read(addr,&mut buf);
+                    vm.operand_stack.push(ArgsEnum::V128(load(&buf)));
+                })
+            }
+            (SimdImm::MemArgLane,binary::simd::V128Store8Lane..=binary::simd::V128Store64Lane) => Box::new(move |vm,args|{
+                let args = simd_args(args);
+                let v = vm.operand_stack.pop().unwrap().get_v128();
+                let (mem,addr) = vm.simd_addr(args);
+                vm.memory(mem).write(addr,&simd::lane_bytes(v,args.lane.unwrap_or(0),size));
+            }),
+            (SimdImm::MemArgLane,_) => Box::new(move |vm,args|{
+                let args = simd_args(args);
+                let v = vm.operand_stack.pop().unwrap().get_v128();
+                let (mem,addr) = vm.simd_addr(args);
+                let mut buf = vec![0u8;size];
+                vm.memory(mem).read(addr,&mut buf);
+                vm.operand_stack.push(ArgsEnum::V128(simd::replace_bytes(v,args.lane.unwrap_or(0),&buf)));
+            }),
+            _ => {
+                let n = binary::simd::signature(sub).0.len();
+                let f = simd::resolve(sub);
+                Box::new(move |vm,args|{
+                    let args = simd_args(args);
+                    let operands = vm.operand_stack.pop_n(n);
+                    vm.operand_stack.push(f(args.lane.unwrap_or(0),args.v128.unwrap_or(0),&operands));
+                })
+            }
+        }
+    }
+
+    fn simd_addr(&mut self,args:&SimdArgs) -> (u32,usize){
+        let mem = args.mem_arg.as_ref().and_then(|m|m.mem).unwrap_or(0);
+        (mem,self.effective_addr(mem,args.mem_arg.as_ref().and_then(|m|m.offset).unwrap_or(0)))
+    }
+}
+
+fn simd_args(args:&ArgsEnum) -> &SimdArgs{
+    match args {
+        ArgsEnum::SimdArgs(args) => args,
+        v => panic!("unexpected simd args:{:?}",v),
+    }
+}
+
+/// global
+impl Vm{
+    /// 全局变量的句柄,宿主通过它读写
+    pub fn global(&self,idx:u32) -> Global{
+        self.inst.globals.get(idx as usize).cloned().expect("errUnknownGlobal")
+    }
+
+    /// 按导出名取全局变量的句柄
+    pub fn export_global(&self,name:&str) -> Option<Global>{
+        let idx = self.inst.module.get_export(module::EXPORT_TAG_GLOBAL,name)?;
+        self.inst.globals.get(idx as usize).cloned()
+    }
+
+    pub fn global_get(&mut self,idx:u32){
+        let v = self.global(idx).get();
+        self.operand_stack.push(v);
+    }
+
+    /// 不可变的全局变量写入时陷入
+    pub fn global_set(&mut self,idx:u32){
+        let g = self.global(idx);
+        let v = self.operand_stack.pop().unwrap();
+        g.set(to_val_type(g.val_type(),v)).unwrap_or_else(|t|panic!("{}",t.0));
+    }
+}
+
+/// 地址超出宿主的地址空间时一定越界
+fn to_addr(v:u64) -> usize{
+    if v > usize::MAX as u64 {
+        panic!("errMemOutOfBounds")
+    }
+    v as usize
+}
+
 /// memory
 impl Vm{
-    pub fn memory_size(&mut self){
-        self.operand_stack.push_u32(self.memory.size() as u32);
+    pub fn memory(&self,idx:u32) -> &Memory{
+        &self.inst.memories[idx as usize]
     }
 
-    pub fn memory_grow(&mut self){
+    /// 弹出地址,memory64的地址是i64,否则是i32
+    fn pop_addr(&mut self,mem:u32) -> u64{
+        if self.memory(mem)._type.is_64() {
+            self.operand_stack.pop_u64().unwrap()
+        } else {
+            self.operand_stack.pop_u32().unwrap() as u64
+        }
+    }
 
-        self.operand_stack.pop_u32().and_then(|v|{
-            let old_size = self.memory.grow(v as usize);
-            self.operand_stack.push_u32(old_size as u32);
-            Some(())
-        }).or_else(||{
-            println!("pop u32 is none");
-            None
-        });
+    fn push_addr(&mut self,mem:u32,v:u64){
+        if self.memory(mem)._type.is_64() {
+            self.operand_stack.push_u64(v);
+        } else {
+            self.operand_stack.push_u32(v as u32);
+        }
+    }
+
+    /// 基址+偏移按u64算,溢出了一定越界,不会绕回
+    fn effective_addr(&mut self,mem:u32,offset:u64) -> usize{
+        let base = self.pop_addr(mem);
+        match base.checked_add(offset) {
+            Some(v) => to_addr(v),
+            None => panic!("errMemOutOfBounds"),
+        }
+    }
+
+    pub fn memory_size(&mut self,mem:u32){
+        self.push_addr(mem,self.memory(mem).size() as u64);
+    }
+
+    /// 失败压入-1,i32是0xFFFFFFFF
+    pub fn memory_grow(&mut self,mem:u32){
+        let n = self.pop_addr(mem);
+        let old_size = self.memory(mem).grow(n).unwrap_or(u64::MAX);
+        self.push_addr(mem,old_size);
+    }
+
+    /// 数据段的内容,被动段drop之后就是空的
+    fn data_bytes(&self,idx:u32) -> &[u8]{
+        if self.inst.dropped_datas.lock().unwrap().contains(&idx) {
+            return &[];
+        }
+        self.inst.module.data_sec.as_ref()
+            .and_then(|v|v.get(idx as usize))
+            .and_then(|d|d.init.as_deref())
+            .unwrap_or(&[])
+    }
+
+    /// memory.init 栈上是 目标地址 数据段偏移 长度,只有目标地址跟着内存的地址类型
+    pub fn memory_init(&mut self,idx:u32,mem:u32){
+        let n = self.operand_stack.pop_u32().unwrap() as usize;
+        let src = self.operand_stack.pop_u32().unwrap() as usize;
+        let dst = to_addr(self.pop_addr(mem));
+        let bytes = self.data_bytes(idx);
+        if src + n > bytes.len() {
+            panic!("errMemOutOfBounds")
+        }
+        let bytes = bytes[src..src + n].to_vec();
+        self.memory(mem).write(dst,&bytes);
+    }
 
+    pub fn data_drop(&mut self,idx:u32){
+        self.inst.dropped_datas.lock().unwrap().insert(idx);
+    }
+
+    /// memory.copy 栈上是 目标地址 源地址 长度
+    /// 两块内存的地址类型不同时长度是i32
+    pub fn memory_copy(&mut self,dst_mem:u32,src_mem:u32){
+        let n = if self.memory(dst_mem)._type.is_64() && self.memory(src_mem)._type.is_64() {
+            self.operand_stack.pop_u64().unwrap()
+        } else {
+            self.operand_stack.pop_u32().unwrap() as u64
+        };
+        let n = to_addr(n);
+        let src = to_addr(self.pop_addr(src_mem));
+        let dst = to_addr(self.pop_addr(dst_mem));
+        if dst_mem == src_mem {
+            self.memory(dst_mem).copy(dst,src,n);
+        } else {
+            let mut buf = vec![0u8;n];
+            self.memory(src_mem).read(src,&mut buf);
+            self.memory(dst_mem).write(dst,&buf);
+        }
+    }
+
+    /// memory.fill 栈上是 目标地址 值 长度
+    pub fn memory_fill(&mut self,mem:u32){
+        let n = to_addr(self.pop_addr(mem));
+        let val = self.operand_stack.pop_u32().unwrap() as u8;
+        let dst = to_addr(self.pop_addr(mem));
+        self.memory(mem).fill(dst,val,n);
     }
 
     // 获取基址+偏移量=值所在位置
     pub fn get_offset(&mut self,mem_arg:MemArg) -> Option<u64>{
-        self.operand_stack.pop_u32().and_then(|v|{
-            mem_arg.offset.and_then(|offset|Some((offset + v) as u64))
-                .or_else(||{
-                    println!("memarg offset is none");
-                    None
-                })
-        }).or_else(||{
-                println!("pop u32 none");
-                None
-        })
+        Some(self.effective_addr(mem_arg.mem.unwrap_or(0),mem_arg.offset.unwrap_or(0)) as u64)
     }
 
     pub fn read_u8(&mut self,mem_arg:MemArg) -> Option<u8>{
+        let mem = mem_arg.mem.unwrap_or(0);
         self.get_offset(mem_arg).and_then(|offset|{
             let mut buf:[u8;1] = [0;1];
-            self.memory.read(offset as usize,&mut buf);
+            self.memory(mem).read(offset as usize,&mut buf);
             Some(buf[0])
         }).or_else(||{
             println!("read u8 none");
@@ -1433,9 +2402,10 @@ impl Vm{
     }
 
     pub fn read_u16(&mut self,mem_arg:MemArg) -> Option<u16>{
+        let mem = mem_arg.mem.unwrap_or(0);
         self.get_offset(mem_arg).and_then(|offset|{
             let mut buf:[u8;2] = [0;2];
-            self.memory.read(offset as usize,&mut buf);
+            self.memory(mem).read(offset as usize,&mut buf);
             let result = byteorder::LittleEndian::read_u16(buf.as_slice());
             Some(result)
         }).or_else(||{
@@ -1445,9 +2415,10 @@ impl Vm{
     }
 
     pub fn read_u32(&mut self,mem_arg:MemArg) -> Option<u32>{
+        let mem = mem_arg.mem.unwrap_or(0);
         self.get_offset(mem_arg).and_then(|offset|{
             let mut buf:[u8;4] = [0;4];
-            self.memory.read(offset as usize,&mut buf);
+            self.memory(mem).read(offset as usize,&mut buf);
             let result = byteorder::LittleEndian::read_u32(buf.as_slice());
             Some(result)
         }).or_else(||{
@@ -1457,9 +2428,10 @@ impl Vm{
     }
 
     pub fn read_u64(&mut self,mem_arg:MemArg) -> Option<u64>{
+        let mem = mem_arg.mem.unwrap_or(0);
         self.get_offset(mem_arg).and_then(|offset|{
             let mut buf:[u8;8] = [0;8];
-            self.memory.read(offset as usize,&mut buf);
+            self.memory(mem).read(offset as usize,&mut buf);
             let result = byteorder::LittleEndian::read_u64(buf.as_slice());
             Some(result)
         }).or_else(||{
@@ -1469,8 +2441,9 @@ impl Vm{
     }
 
     pub fn write_u8(&mut self,mem_arg:MemArg,n:u8){
+        let mem = mem_arg.mem.unwrap_or(0);
         self.get_offset(mem_arg).and_then(|offset|{
-            self.memory.write(offset as usize,vec![n].as_slice());
+            self.memory(mem).write(offset as usize,vec![n].as_slice());
             Some(())
         }).or_else(||{
             println!("get offset none");
@@ -1479,10 +2452,11 @@ impl Vm{
     }
 
     pub fn write_u16(&mut self,mem_arg:MemArg,n:u16){
+        let mem = mem_arg.mem.unwrap_or(0);
         self.get_offset(mem_arg).and_then(|offset|{
             let mut v:[u8;2] = [0;2];
             byteorder::LittleEndian::write_u16(&mut v,n);
-            self.memory.write(offset as usize,&v);
+            self.memory(mem).write(offset as usize,&v);
             Some(())
         }).or_else(||{
             println!("get offset none");
@@ -1491,10 +2465,11 @@ impl Vm{
     }
 
     pub fn write_u32(&mut self,mem_arg:MemArg,n:u32){
+        let mem = mem_arg.mem.unwrap_or(0);
         self.get_offset(mem_arg).and_then(|offset|{
             let mut v:[u8;4] = [0;4];
             byteorder::LittleEndian::write_u32(&mut v,n);
-            self.memory.write(offset as usize,&v);
+            self.memory(mem).write(offset as usize,&v);
             Some(())
         }).or_else(||{
             println!("get offset none");
@@ -1503,10 +2478,11 @@ impl Vm{
     }
 
     pub fn write_u64(&mut self,mem_arg:MemArg,n:u64){
+        let mem = mem_arg.mem.unwrap_or(0);
         self.get_offset(mem_arg).and_then(|offset|{
             let mut v:[u8;8] = [0;8];
             byteorder::LittleEndian::write_u64(&mut v,n);
-            self.memory.write(offset as usize,&v);
+            self.memory(mem).write(offset as usize,&v);
             Some(())
         }).or_else(||{
             println!("get offset none");
@@ -1825,7 +2801,7 @@ pub fn trunc_sat_u(z:f64,n:usize) -> u64{
     if z.is_nan() {
         return 0;
     }
-    let max = (1_u64 << n) -1;
+    let max = u64::MAX >> (64 - n);
     if z.is_infinite() {
         return if z > 0.0 {
             max
@@ -1877,6 +2853,14 @@ mod test{
     use std::sync::atomic::Ordering::AcqRel;
     use crate::binary::instruction::ArgsEnum::{F32, F64, I64, I32, U32, U64};
     use crate::interpreter::vm::OPCODE_MAP;
+    use crate::common::common_error::Trap;
+    use crate::binary::instruction::Instruction;
+
+    /// 执行模块用的Vm,0号内存是空的,没有绑定导入
+    fn vm_for(m:binary::module::Module) -> interpreter::vm::Vm{
+        let limit = binary::module::Limits{ tag: Some(0), min: Some(0), max: None };
+        interpreter::vm::Vm::new(interpreter::operand::new(),m,interpreter::vm_memory::Memory::new(limit))
+    }
 
     #[test]
     pub fn test1(){
@@ -1893,11 +2877,7 @@ mod test{
             max: None
         };
         let memory = interpreter::vm_memory::Memory::new(limit);
-        let mut vm = interpreter::vm::Vm{
-            operand_stack: stack,
-            module: m,
-            memory
-        };
+        let mut vm = interpreter::vm::Vm::new(stack,m,memory);
 
         vm.i32_const(100_i32);
         vm.i64_const(200_i64);
@@ -1916,10 +2896,10 @@ mod test{
         vm.operand_stack.push(var1);
         vm.operand_stack.push(var2);
         interpreter::vm::OPCODE_MAP.get()
-            .and_then(|v|v.get(op_code as usize).clone())
+            .and_then(|v|v.get(crate::binary::opcodes::Opcode::byte(op_code)))
             .or_else(||{println!("get op none");None})
             .and_then(|o|{
-                o.unwrap()(vm,ArgsEnum::NONE);
+                o(vm,ArgsEnum::NONE);
                 let r = vm.operand_stack.pop().unwrap();
                 Some(r)
             }).or_else(||{println!("exec none");None})
@@ -1932,10 +2912,10 @@ mod test{
         }
 
         interpreter::vm::OPCODE_MAP.get()
-            .and_then(|v|v.get(op_code as usize).clone())
+            .and_then(|v|v.get(crate::binary::opcodes::Opcode::byte(op_code)))
             .or_else(||{println!("get op none");None})
             .and_then(|o|{
-                o.unwrap()(vm,ArgsEnum::NONE);
+                o(vm,ArgsEnum::NONE);
                 let r = vm.operand_stack.pop().unwrap();
                 Some(r)
             }).or_else(||{println!("exec none");None})
@@ -1953,11 +2933,7 @@ mod test{
             max: Some(20)
         };
         let memory = interpreter::vm_memory::Memory::new(limit);
-        let mut vm = interpreter::vm::Vm{
-            operand_stack: stack,
-            module: m,
-            memory
-        };
+        let mut vm = interpreter::vm::Vm::new(stack,m,memory);
 
         //初始话操作数组
         interpreter::vm::init();
@@ -2171,10 +3147,11 @@ mod test{
     }
 
 
-    pub fn mem(vm: &mut interpreter::vm::Vm, store_op:u8, load_op:u8, offset:u32, base:ArgsEnum, var1:ArgsEnum){
+    pub fn mem(vm: &mut interpreter::vm::Vm, store_op:u8, load_op:u8, offset:u64, base:ArgsEnum, var1:ArgsEnum){
         let mem_arg = binary::instruction::MemArg{
             align: None,
             offset: Some(offset),
+            mem: None,
         };
 
         // push 基址
@@ -2184,10 +3161,10 @@ mod test{
         vm.operand_stack.push(var1.clone());
 
         interpreter::vm::OPCODE_MAP.get()
-            .and_then(|v|v.get(store_op as usize).clone())
+            .and_then(|v|v.get(crate::binary::opcodes::Opcode::byte(store_op)))
             .or_else(||{println!("get op none");None})
             .and_then(|o|{
-                o.unwrap()(vm,ArgsEnum::MemArg(mem_arg.clone()));
+                o(vm,ArgsEnum::MemArg(mem_arg.clone()));
                 // let r = vm.operand_stack.pop().unwrap();
                 Some(())
             }).or_else(||{println!("exec none");None})
@@ -2197,10 +3174,10 @@ mod test{
         vm.operand_stack.push(base);
 
         interpreter::vm::OPCODE_MAP.get()
-            .and_then(|v|v.get(load_op as usize).clone())
+            .and_then(|v|v.get(crate::binary::opcodes::Opcode::byte(load_op)))
             .or_else(||{println!("get op none");None})
             .and_then(|o|{
-                o.unwrap()(vm,ArgsEnum::MemArg(mem_arg));
+                o(vm,ArgsEnum::MemArg(mem_arg));
                 // let r = vm.operand_stack.pop().unwrap();
                 Some(())
             }).or_else(||{println!("exec none");None})
@@ -2221,11 +3198,7 @@ mod test{
             max: None
         };
         let memory = interpreter::vm_memory::Memory::new(limit);
-        let mut vm = interpreter::vm::Vm{
-            operand_stack: stack,
-            module: m,
-            memory
-        };
+        let mut vm = interpreter::vm::Vm::new(stack,m,memory);
 
         //初始话操作数组
         interpreter::vm::init();
@@ -2250,4 +3223,970 @@ mod test{
         mem(&mut vm,opcodes::I64Store32,opcodes::I64Load32S,0xD0,U32(0x0D),I64(-1000000));
         mem(&mut vm,opcodes::I64Store32,opcodes::I64Load32U,0xE0,U32(0x0E),U64(1000000));
     }
+
+    /// 执行一条指令,前缀指令按(前缀,子操作码)查表
+    pub fn exec(vm:&mut interpreter::vm::Vm,instr:&Instruction){
+        interpreter::vm::init();
+        let table = OPCODE_MAP.get().unwrap();
+        let op = instr.op();
+        let args = instr.args.clone().unwrap_or(ArgsEnum::NONE);
+        match table.get_prefixed(op) {
+            Some(f) => f(vm,&args),
+            None => table.get(op).unwrap_or_else(||panic!("unsupported opcode:{}",op))(vm,args),
+        }
+    }
+
+    #[test]
+    pub fn test5(){
+        use crate::binary::instruction::PrefixArgs;
+        use crate::binary::opcodes;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let m = crate::text::parse(r#"(module (memory 1) (data "hello") (data (i32.const 0) "x"))"#).unwrap();
+        let limit = binary::module::Limits{ tag: Some(0), min: Some(1), max: None };
+        let mut vm = interpreter::vm::Vm::new(interpreter::operand::new(),m,interpreter::vm_memory::Memory::new(limit));
+        let data_op = |sub:u32,idx:u32|Instruction::prefixed(opcodes::MiscPrefix,sub,ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(idx), idx2: None }));
+        let push3 = |vm:&mut interpreter::vm::Vm,a:u32,b:u32,c:u32|{
+            vm.operand_stack.push_u32(a);
+            vm.operand_stack.push_u32(b);
+            vm.operand_stack.push_u32(c);
+        };
+
+        // memory.init 把 "ell" 复制到10
+        push3(&mut vm,10,1,3);
+        exec(&mut vm,&data_op(opcodes::MemoryInit,0));
+        assert_eq!(&vm.memory(0).data()[9..14],b"\0ell\0");
+
+        // memory.copy 重叠的区域
+        push3(&mut vm,11,10,3);
+        exec(&mut vm,&data_op(opcodes::MemoryCopy,0));
+        assert_eq!(&vm.memory(0).data()[10..14],b"eell");
+
+        // memory.fill
+        push3(&mut vm,65530,0xab,6);
+        exec(&mut vm,&data_op(opcodes::MemoryFill,0));
+        assert_eq!(&vm.memory(0).data()[65529..],&[0,0xab,0xab,0xab,0xab,0xab,0xab]);
+
+        let trap = |vm:&mut interpreter::vm::Vm,instr:Instruction|{
+            let r = catch_unwind(AssertUnwindSafe(||exec(vm,&instr)));
+            r.unwrap_err().downcast_ref::<&str>().map(|s|s.to_string())
+        };
+        let oob = Some("errMemOutOfBounds".to_string());
+        // 越界时什么都不写
+        push3(&mut vm,65530,0,7);
+        assert_eq!(trap(&mut vm,data_op(opcodes::MemoryFill,0)),oob);
+        assert_eq!(vm.memory(0).data()[65530],0xab);
+        push3(&mut vm,0,65535,2);
+        assert_eq!(trap(&mut vm,data_op(opcodes::MemoryCopy,0)),oob);
+        push3(&mut vm,65536,0,0);
+        exec(&mut vm,&data_op(opcodes::MemoryCopy,0));
+        push3(&mut vm,65537,0,0);
+        assert_eq!(trap(&mut vm,data_op(opcodes::MemoryCopy,0)),oob);
+        push3(&mut vm,0,3,3);
+        assert_eq!(trap(&mut vm,data_op(opcodes::MemoryInit,0)),oob);
+
+        // drop之后长度是0,只有长度0偏移0的init不会trap
+        exec(&mut vm,&data_op(opcodes::DataDrop,0));
+        exec(&mut vm,&data_op(opcodes::DataDrop,0));
+        push3(&mut vm,0,0,0);
+        exec(&mut vm,&data_op(opcodes::MemoryInit,0));
+        push3(&mut vm,0,0,1);
+        assert_eq!(trap(&mut vm,data_op(opcodes::MemoryInit,0)),oob);
+    }
+
+    #[test]
+    pub fn test6(){
+        use crate::validator::validate;
+
+        // 多值函数、带参数的块和循环
+        let m = crate::text::parse(r#"(module
+            (func $swap (param i32 i32) (result i32 i32) local.get 1 local.get 0)
+            (func $sub_swapped (result i32) i32.const 1 i32.const 5 call $swap i32.sub)
+            (func $add3 (result i32)
+                i32.const 1 i32.const 2 i32.const 3
+                block (param i32 i32) (result i32) i32.add end
+                i32.add)
+            (func $br_multi (result i32 i32)
+                block (result i32 i32) i32.const 1 i32.const 2 i32.const 3 br 0 end)
+            (func $sum (param $n i32) (result i32 i32) (local $acc i32)
+                local.get $n
+                loop $l (param i32) (result i32)
+                    local.get $acc i32.add local.set $acc
+                    local.get $n i32.const 1 i32.sub local.tee $n
+                    local.get $n
+                    br_if $l
+                end
+                local.get $acc)
+            (func $pick (param i32) (result i32 i32)
+                i32.const 10 i32.const 20 local.get 0
+                if (param i32 i32) (result i32 i32)
+                else drop drop i32.const 1 i32.const 2
+                end)
+            (func $early (param i32) (result i32 i32)
+                i32.const 7 i32.const 8
+                local.get 0
+                if (param i32 i32) (result i32 i32) return end
+                drop i32.const 9))"#).unwrap();
+        validate(&m).unwrap();
+        let mut vm = vm_for(m);
+
+        assert_eq!(vm.invoke(0,vec![I32(1),I32(2)]).unwrap(),vec![I32(2),I32(1)]);
+        assert_eq!(vm.invoke(1,vec![]).unwrap(),vec![I32(4)]);
+        assert_eq!(vm.invoke(2,vec![]).unwrap(),vec![I32(6)]);
+        assert_eq!(vm.invoke(3,vec![]).unwrap(),vec![I32(2),I32(3)]);
+        assert_eq!(vm.invoke(4,vec![I32(10)]).unwrap(),vec![I32(0),I32(55)]);
+        assert_eq!(vm.invoke(5,vec![I32(1)]).unwrap(),vec![I32(10),I32(20)]);
+        assert_eq!(vm.invoke(5,vec![I32(0)]).unwrap(),vec![I32(1),I32(2)]);
+        assert_eq!(vm.invoke(6,vec![I32(1)]).unwrap(),vec![I32(7),I32(8)]);
+        assert_eq!(vm.invoke(6,vec![I32(0)]).unwrap(),vec![I32(7),I32(9)]);
+        assert!(vm.operand_stack.is_empty());
+        assert!(vm.ctrl_stack.is_empty() && vm.frames.is_empty());
+    }
+
+    /// 执行一个只有顺序指令的函数,local.get直接取参数,返回函数留在栈上的值
+    pub fn run(vm: &mut interpreter::vm::Vm, idx:usize, args:Vec<ArgsEnum>) -> Vec<ArgsEnum>{
+        interpreter::vm::init();
+        let expr = vm.module().code_sec.as_ref().unwrap()[idx].get_expr().unwrap().clone();
+        for instr in expr.iter() {
+            if instr.opcode == Some(binary::opcodes::LocalGet) {
+                let idx = instr.args.as_ref().unwrap().get_u32();
+                vm.operand_stack.push(args[idx as usize].clone());
+                continue;
+            }
+            exec(vm,instr);
+        }
+        let mut results = vec![];
+        while let Some(v) = vm.operand_stack.pop() {
+            // 无符号数和比较结果按i32/i64返回
+            results.push(match v {
+                U32(v) => I32(v as i32),
+                ArgsEnum::Bool(v) => I32(v as i32),
+                U64(v) => I64(v as i64),
+                v => v,
+            });
+        }
+        results.reverse();
+        results
+    }
+
+    #[test]
+    pub fn test7(){
+        use crate::validator::validate;
+        use crate::binary::instruction::ExternRef;
+
+        // 引用类型和表指令,宿主对象作为externref传进来再取出去
+        let m = crate::text::parse(r#"(module
+            (table $objs 2 externref)
+            (table $funcs 1 funcref)
+            (func $put (param i32 externref) local.get 0 local.get 1 table.set $objs)
+            (func $get (param i32) (result externref) local.get 0 table.get $objs)
+            (func $grow (param externref i32) (result i32) local.get 0 local.get 1 table.grow $objs)
+            (func $size (result i32) table.size $objs)
+            (func $fill (param i32 externref i32) local.get 0 local.get 1 local.get 2 table.fill $objs)
+            (func $is_null (param externref) (result i32) local.get 0 ref.is_null)
+            (func $func_ref (result i32 i32)
+                i32.const 0 ref.func $put table.set $funcs
+                i32.const 0 table.get $funcs ref.is_null
+                ref.null func ref.is_null)
+            (func $pick (param externref externref i32) (result externref)
+                local.get 0 local.get 1 local.get 2 select (result externref))
+            (elem declare func $put))"#).unwrap();
+        validate(&m).unwrap();
+        let mut vm = vm_for(m);
+
+        let obj = ExternRef::new(String::from("host object"));
+        let null = ArgsEnum::ExternRef(None);
+        run(&mut vm,0,vec![I32(1),ArgsEnum::ExternRef(Some(obj.clone()))]);
+        assert_eq!(run(&mut vm,1,vec![I32(0)]),vec![null.clone()]);
+        match &run(&mut vm,1,vec![I32(1)])[0] {
+            ArgsEnum::ExternRef(Some(r)) => {
+                assert_eq!(r,&obj);
+                assert_eq!(r.downcast_ref::<String>().map(|s|s.as_str()),Some("host object"));
+            }
+            v => panic!("{:?}",v),
+        }
+        assert_eq!(run(&mut vm,5,vec![null.clone()]),vec![I32(1)]);
+        assert_eq!(run(&mut vm,5,vec![ArgsEnum::ExternRef(Some(obj.clone()))]),vec![I32(0)]);
+
+        assert_eq!(run(&mut vm,2,vec![ArgsEnum::ExternRef(Some(obj.clone())),I32(3)]),vec![I32(2)]);
+        assert_eq!(run(&mut vm,3,vec![]),vec![I32(5)]);
+        assert_eq!(run(&mut vm,2,vec![null.clone(),I32(u32::MAX as i32)]),vec![I32(-1)]);
+        assert_eq!(run(&mut vm,1,vec![I32(4)]),vec![ArgsEnum::ExternRef(Some(obj.clone()))]);
+        run(&mut vm,4,vec![I32(1),null.clone(),I32(4)]);
+        assert_eq!(run(&mut vm,1,vec![I32(4)]),vec![null.clone()]);
+
+        assert_eq!(run(&mut vm,6,vec![]),vec![I32(0),I32(1)]);
+        assert_eq!(run(&mut vm,7,vec![null.clone(),ArgsEnum::ExternRef(Some(obj.clone())),I32(0)]),
+            vec![ArgsEnum::ExternRef(Some(obj.clone()))]);
+
+        // 越界
+        let e = std::panic::catch_unwind(std::panic::AssertUnwindSafe(||run(&mut vm,1,vec![I32(5)]))).unwrap_err();
+        assert_eq!(e.downcast_ref::<&str>(),Some(&"errTableOutOfBounds"));
+        let e = std::panic::catch_unwind(std::panic::AssertUnwindSafe(||run(&mut vm,4,vec![I32(4),null.clone(),I32(2)]))).unwrap_err();
+        assert_eq!(e.downcast_ref::<&str>(),Some(&"errTableOutOfBounds"));
+    }
+
+    #[test]
+    pub fn test8(){
+        use crate::validator::validate;
+        use crate::interpreter::simd::{from_lanes, lanes};
+
+        // SIMD,用例取自规范测试simd_*.wast
+        let exec = |sig:&str,body:&str,args:Vec<ArgsEnum>| -> ArgsEnum{
+            let m = crate::text::parse(&format!(r#"(module (memory 1)
+                (data (i32.const 0) "\01\02\03\04\05\06\07\08\80\90\a0\b0\c0\d0\e0\f0")
+                (func {} {}))"#,sig,body)).unwrap();
+            validate(&m).unwrap();
+            let limit = binary::module::Limits{ tag: Some(0), min: Some(1), max: None };
+            let memory = interpreter::vm_memory::Memory::new(limit);
+            memory.write(0,&[1,2,3,4,5,6,7,8,0x80,0x90,0xa0,0xb0,0xc0,0xd0,0xe0,0xf0]);
+            let mut vm = interpreter::vm::Vm::new(interpreter::operand::new(),m,memory);
+            run(&mut vm,0,args).pop().unwrap()
+        };
+        let v1 = "(param v128) (result v128)";
+        let v2 = "(param v128 v128) (result v128)";
+        let bin = |op:&str,a:u128,b:u128|exec(v2,&format!("local.get 0 local.get 1 {}",op),vec![ArgsEnum::V128(a),ArgsEnum::V128(b)]).get_v128();
+        let un = |op:&str,a:u128|exec(v1,&format!("local.get 0 {}",op),vec![ArgsEnum::V128(a)]).get_v128();
+        let i8s = |v:&[i8]|from_lanes(v);
+        let i16s = |v:&[i16]|from_lanes(v);
+        let i32s = |v:&[i32]|from_lanes(v);
+        let f32s = |v:&[f32]|from_lanes(v);
+        let f64s = |v:&[f64]|from_lanes(v);
+
+        // 整数运算
+        assert_eq!(bin("i8x16.add_sat_s",i8s(&[127,-128,1,0]),i8s(&[1,-1,1,0])),i8s(&[127,-128,2,0]));
+        assert_eq!(bin("i16x8.sub_sat_u",i16s(&[0,-1,5]),i16s(&[1,1,3])),i16s(&[0,-2,2]));
+        assert_eq!(bin("i32x4.mul",i32s(&[0x40000000,-1,3,0]),i32s(&[4,-1,-3,9])),i32s(&[0,1,-9,0]));
+        assert_eq!(bin("i8x16.avgr_u",i8s(&[-1,0,3]),i8s(&[-1,1,4])),i8s(&[-1,1,4]));
+        assert_eq!(bin("i16x8.q15mulr_sat_s",i16s(&[-32768,0x4000]),i16s(&[-32768,0x4000])),i16s(&[32767,0x2000]));
+        assert_eq!(bin("i32x4.dot_i16x8_s",i16s(&[1,2,3,4,5,6,-32768,-32768]),i16s(&[1,1,1,1,1,1,-32768,-32768])),i32s(&[3,7,11,i32::MIN]));
+        assert_eq!(bin("i8x16.narrow_i16x8_s",i16s(&[300,-300,5,0,0,0,0,0]),i16s(&[-1;8])),
+            i8s(&[127,-128,5,0,0,0,0,0,-1,-1,-1,-1,-1,-1,-1,-1]));
+        assert_eq!(un("i16x8.extend_high_i8x16_u",i8s(&[0,0,0,0,0,0,0,0,-1,2])),i16s(&[255,2]));
+        assert_eq!(un("i8x16.popcnt",i8s(&[-1,0x55,1])),i8s(&[8,4,1]));
+        assert_eq!(un("i32x4.abs",i32s(&[i32::MIN,-5,5])),i32s(&[i32::MIN,5,5]));
+        assert_eq!(bin("i8x16.lt_s",i8s(&[-1,1]),i8s(&[0,0])),i8s(&[-1,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]));
+        assert_eq!(bin("i8x16.lt_u",i8s(&[-1,1]),i8s(&[0,2])),i8s(&[0,-1]));
+        assert_eq!(exec("(param v128 i32) (result v128)","local.get 0 local.get 1 i64x2.shr_s",
+            vec![ArgsEnum::V128(from_lanes(&[-4i64,8])),I32(65)]),ArgsEnum::V128(from_lanes(&[-2i64,4])));
+        assert_eq!(exec("(param v128 i32) (result v128)","local.get 0 local.get 1 i8x16.shl",
+            vec![ArgsEnum::V128(i8s(&[1,-1])),I32(9)]),ArgsEnum::V128(i8s(&[2,-2])));
+
+        // 浮点运算
+        let r = lanes::<f32>(bin("f32x4.min",f32s(&[-0.0,f32::NAN,1.0,2.0]),f32s(&[0.0,1.0,f32::NAN,-3.0])));
+        assert!(r[0] == 0.0 && r[0].is_sign_negative() && r[1].is_nan() && r[2].is_nan() && r[3] == -3.0);
+        let r = lanes::<f32>(bin("f32x4.pmin",f32s(&[f32::NAN,1.0,-0.0,2.0]),f32s(&[1.0,f32::NAN,0.0,1.0])));
+        assert!(r[0].is_nan() && r[1] == 1.0 && r[2].is_sign_negative() && r[3] == 1.0);
+        let r = lanes::<f32>(un("f32x4.nearest",f32s(&[2.5,-2.5,0.5,-0.5])));
+        assert!(r == vec![2.0,-2.0,0.0,-0.0] && r[3].is_sign_negative());
+        assert_eq!(bin("f64x2.div",f64s(&[1.0,-1.0]),f64s(&[0.0,4.0])),f64s(&[f64::INFINITY,-0.25]));
+        assert_eq!(un("i32x4.trunc_sat_f32x4_s",f32s(&[f32::NAN,3e9,-3e9,-1.5])),i32s(&[0,i32::MAX,i32::MIN,-1]));
+        assert_eq!(un("i32x4.trunc_sat_f64x2_u_zero",f64s(&[-1.0,5e9])),i32s(&[0,-1,0,0]));
+        assert_eq!(un("f32x4.convert_i32x4_u",i32s(&[-1,1])),f32s(&[4294967296.0,1.0,0.0,0.0]));
+        assert_eq!(un("f64x2.promote_low_f32x4",f32s(&[1.5,-2.0,9.0,9.0])),f64s(&[1.5,-2.0]));
+        assert_eq!(un("f32x4.demote_f64x2_zero",f64s(&[1.5,1e300])),f32s(&[1.5,f32::INFINITY,0.0,0.0]));
+
+        // 车道操作
+        let reversed = "i8x16.shuffle 31 30 29 28 27 26 25 24 23 22 21 20 19 18 17 16";
+        let a = from_lanes(&(0..16).collect::<Vec<u8>>());
+        let b = from_lanes(&(16..32).collect::<Vec<u8>>());
+        assert_eq!(bin(reversed,a,b),from_lanes(&(16..32).rev().collect::<Vec<u8>>()));
+        assert_eq!(bin("i8x16.swizzle",a,from_lanes(&[15u8,0,16,255,3])),from_lanes(&[15u8,0,0,0,3,0,0,0,0,0,0,0,0,0,0,0]));
+        assert_eq!(exec("(param i32) (result i32)","local.get 0 i8x16.splat i8x16.extract_lane_s 3",vec![I32(0xff)]),I32(-1));
+        assert_eq!(exec("(param i32) (result i32)","local.get 0 i8x16.splat i8x16.extract_lane_u 3",vec![I32(0xff)]),I32(255));
+        assert_eq!(exec("(param v128 f64) (result v128)","local.get 0 local.get 1 f64x2.replace_lane 1",
+            vec![ArgsEnum::V128(f64s(&[1.0,2.0])),F64(-0.5)]),ArgsEnum::V128(f64s(&[1.0,-0.5])));
+        assert_eq!(exec("(param v128) (result i64)","local.get 0 i64x2.extract_lane 1",
+            vec![ArgsEnum::V128(from_lanes(&[1i64,-7]))]),I64(-7));
+        assert_eq!(exec("(param v128) (result i32)","local.get 0 i8x16.bitmask",vec![ArgsEnum::V128(i8s(&[-1,0,-128,1]))]),I32(5));
+        assert_eq!(exec("(param v128) (result i32)","local.get 0 i32x4.all_true",vec![ArgsEnum::V128(i32s(&[1,2,3,0]))]),I32(0));
+        assert_eq!(exec("(param v128) (result i32)","local.get 0 v128.any_true",vec![ArgsEnum::V128(i32s(&[0,0,0,4]))]),I32(1));
+        assert_eq!(exec("(result v128)","v128.const i32x4 0xff00ff00 0 -1 1 v128.const i32x4 -1 -1 0 0 v128.const i32x4 0x0ff00ff0 -1 -1 -1 v128.bitselect",vec![]),
+            ArgsEnum::V128(i32s(&[0xff0fff0fu32 as i32,0,-1,1])));
+
+        // 内存
+        assert_eq!(exec("(result v128)","i32.const 6 v128.load8x8_s",vec![]),ArgsEnum::V128(i16s(&[7,8,-128,-112,-96,-80,-64,-48])));
+        assert_eq!(exec("(result v128)","i32.const 0 v128.load16_splat offset=2",vec![]),ArgsEnum::V128(i16s(&[0x0403;8])));
+        assert_eq!(exec("(result v128)","i32.const 4 v128.load32_zero",vec![]),ArgsEnum::V128(i32s(&[0x08070605,0,0,0])));
+        assert_eq!(exec("(param v128) (result v128)","i32.const 1 local.get 0 v128.load16_lane 7",vec![ArgsEnum::V128(0)]),
+            ArgsEnum::V128(i16s(&[0,0,0,0,0,0,0,0x0302])));
+        assert_eq!(exec("(result i32)","i32.const 100 v128.const i32x4 1 2 3 4 v128.store32_lane 2 i32.const 100 i32.load",vec![]),I32(3));
+        assert_eq!(exec("(result v128)","i32.const 32 v128.const i64x2 5 6 v128.store offset=16 i32.const 48 v128.load",vec![]),
+            ArgsEnum::V128(from_lanes(&[5i64,6])));
+        let e = std::panic::catch_unwind(||exec("(result v128)","i32.const 65535 v128.load",vec![])).unwrap_err();
+        assert_eq!(e.downcast_ref::<&str>(),Some(&"errMemOutOfBounds"));
+        let e = std::panic::catch_unwind(||exec("(result v128)","i32.const -1 v128.load offset=16",vec![])).unwrap_err();
+        assert_eq!(e.downcast_ref::<&str>(),Some(&"errMemOutOfBounds"));
+    }
+
+    #[test]
+    pub fn test9(){
+        use crate::validator::validate;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::sync::mpsc;
+        use std::thread;
+
+        // 共享内存上的原子指令,多个线程各自一个Vm
+        let m = crate::text::parse(r#"(module (memory 1 1 shared)
+            (func i32.const 0 i32.const 1 i32.atomic.rmw.add drop)
+            (func (param i64) (result i32) i32.const 8 i32.const 0 local.get 0 memory.atomic.wait32)
+            (func (param i32) (result i32) i32.const 8 local.get 0 memory.atomic.notify)
+            (func (param i32) (result i32) local.get 0 i32.atomic.load)
+            (func (param i32 i64 i64) (result i64) local.get 0 local.get 1 local.get 2 i64.atomic.rmw32.cmpxchg_u)
+            (func (param i32 i32) (result i32) local.get 0 local.get 1 i32.atomic.rmw8.add_u)
+            (func (param i32) (result i64) local.get 0 i64.atomic.load)
+            (func (param i32) i32.const 16 local.get 0 i32.atomic.store16 atomic.fence))"#).unwrap();
+        validate(&m).unwrap();
+        let memory = interpreter::vm_memory::Memory::new(m.mem_sec.as_ref().unwrap()[0].clone());
+        let new_vm = |memory:&interpreter::vm_memory::Memory|interpreter::vm::Vm::new(interpreter::operand::new(),m.clone(),memory.clone());
+
+        // 4个线程各加1000次
+        let handles:Vec<_> = (0..4).map(|_|{
+            let mut vm = new_vm(&memory);
+            thread::spawn(move||{
+                for _ in 0..1000 {
+                    run(&mut vm,0,vec![]);
+                }
+            })
+        }).collect();
+        handles.into_iter().for_each(|h|h.join().unwrap());
+        let mut vm = new_vm(&memory);
+        assert_eq!(run(&mut vm,3,vec![I32(0)]),vec![I32(4000)]);
+
+        // 值不相等和超时
+        run(&mut vm,7,vec![I32(1)]);
+        assert_eq!(run(&mut vm,3,vec![I32(16)]),vec![I32(1)]);
+        assert_eq!(run(&mut vm,2,vec![I32(1)]),vec![I32(0)]);
+        assert_eq!(run(&mut vm,1,vec![I64(1000)]),vec![I32(2)]);
+
+        // 另一个线程等待,直到被唤醒
+        let (tx,rx) = mpsc::channel();
+        let mut waiter = new_vm(&memory);
+        let h = thread::spawn(move||tx.send(run(&mut waiter,1,vec![I64(-1)])).unwrap());
+        while run(&mut vm,2,vec![I32(1)]) == vec![I32(0)] {
+            thread::yield_now();
+        }
+        assert_eq!(rx.recv().unwrap(),vec![I32(0)]);
+        h.join().unwrap();
+
+        // cmpxchg的期望值按访问宽度截断,rmw8只改一个字节
+        assert_eq!(run(&mut vm,4,vec![I32(0),I64(0x1_0000_0fa0),I64(-1)]),vec![I64(4000)]);
+        assert_eq!(run(&mut vm,6,vec![I32(0)]),vec![I64(0xffff_ffff)]);
+        assert_eq!(run(&mut vm,5,vec![I32(0),I32(0x102)]),vec![I32(0xff)]);
+        assert_eq!(run(&mut vm,6,vec![I32(0)]),vec![I64(0xffff_ff01)]);
+
+        // 不对齐和越界
+        let trap = |idx:usize,args:Vec<ArgsEnum>|{
+            let mut vm = new_vm(&memory);
+            let e = catch_unwind(AssertUnwindSafe(move||{run(&mut vm,idx,args);})).unwrap_err();
+            e.downcast_ref::<&str>().copied()
+        };
+        assert_eq!(trap(3,vec![I32(2)]),Some("errUnalignedAtomic"));
+        assert_eq!(trap(6,vec![I32(4)]),Some("errUnalignedAtomic"));
+        assert_eq!(trap(3,vec![I32(65536)]),Some("errMemOutOfBounds"));
+
+        // 非共享内存上等待会陷入,notify返回0
+        let m = crate::text::parse(r#"(module (memory 1)
+            (func (result i32) i32.const 0 i32.const 0 i64.const 0 memory.atomic.wait32)
+            (func (result i32) i32.const 0 i32.const 1 memory.atomic.notify))"#).unwrap();
+        let memory = interpreter::vm_memory::Memory::new(m.mem_sec.as_ref().unwrap()[0].clone());
+        let mut vm = interpreter::vm::Vm::new(interpreter::operand::new(),m,memory);
+        assert_eq!(run(&mut vm,1,vec![]),vec![I32(0)]);
+        let e = catch_unwind(AssertUnwindSafe(move||{run(&mut vm,0,vec![]);})).unwrap_err();
+        assert_eq!(e.downcast_ref::<&str>(),Some(&"errExpectedSharedMemory"));
+    }
+
+    #[test]
+    pub fn test10(){
+        use crate::validator::validate;
+
+        // 尾调用,一百万次递归不会把本地栈和调用栈撑大
+        let m = crate::text::parse(r#"(module
+            (type $t (func (param i64) (result i32)))
+            (table 2 funcref)
+            (func $count (param i64 i64) (result i64)
+                (if (result i64) (i64.eqz (local.get 0))
+                    (then (local.get 1))
+                    (else (return_call $count (i64.sub (local.get 0) (i64.const 1)) (i64.add (local.get 1) (i64.const 2))))))
+            (func $even (type $t)
+                (if (result i32) (i64.eqz (local.get 0))
+                    (then (i32.const 1))
+                    (else (return_call_indirect (type $t) (i64.sub (local.get 0) (i64.const 1)) (i32.const 1)))))
+            (func $odd (type $t)
+                (if (result i32) (i64.eqz (local.get 0))
+                    (then (i32.const 0))
+                    (else (return_call_indirect (type $t) (i64.sub (local.get 0) (i64.const 1)) (i32.const 0)))))
+            (func (param i32) (result i32) (return_call_indirect (param i32) (result i32) (local.get 0) (i32.const 0)))
+            (func (param i64) (result i32) (call_indirect (type $t) (local.get 0) (i32.const 1))))"#).unwrap();
+        validate(&m).unwrap();
+        let mut vm = vm_for(m);
+        let (f1,f2) = (vm.func_ref(1),vm.func_ref(2));
+        vm.table(0).set(0,f1);
+        vm.table(0).set(1,f2);
+        assert_eq!(vm.invoke(0,vec![I64(1_000_000),I64(0)]).unwrap(),vec![I64(2_000_000)]);
+        assert!(vm.frames.is_empty() && vm.ctrl_stack.is_empty() && vm.operand_stack.is_empty());
+        assert_eq!(vm.invoke(1,vec![I64(100_001)]).unwrap(),vec![I32(0)]);
+        assert_eq!(vm.invoke(2,vec![I64(100_001)]).unwrap(),vec![I32(1)]);
+        assert_eq!(vm.invoke(4,vec![I64(7)]).unwrap(),vec![I32(1)]);
+
+        // 间接尾调用的签名不匹配
+        assert_eq!(vm.invoke(3,vec![I32(5)]).unwrap_err(),Trap("errIndirectCallTypeMismatch".to_string()));
+    }
+
+    #[test]
+    pub fn test11(){
+        use crate::validator::validate;
+        use crate::binary::instruction::ExnRef;
+        use crate::interpreter::vm::HostFunc;
+
+        // 每个标签各是一个对象,类型一样的两个标签也不相同
+        let m = crate::text::parse(r#"(module
+            (import "env" "e" (tag $imported (param i32)))
+            (tag $e (param i32))
+            (tag $f)
+            (func (result exnref) ref.null exn)
+            (func (param exnref) (result i32) local.get 0 ref.is_null))"#).unwrap();
+        validate(&m).unwrap();
+        let limit = binary::module::Limits{ tag: Some(0), min: Some(0), max: None };
+        let mut vm = interpreter::vm::Vm::new(interpreter::operand::new(),m,interpreter::vm_memory::Memory::new(limit));
+        assert!(vm.tag(1) == vm.tag(1) && vm.tag(0) != vm.tag(1) && vm.tag(1) != vm.tag(2));
+        assert_eq!(vm.tag(1).func_type().params(),&[binary::module::VAL_TYPE_I32][..]);
+        assert!(vm.tag(2).func_type().params().is_empty());
+
+        // 导入的标签换成别的实例的标签
+        let other = vm.tag(1);
+        vm.set_tag(0,other.clone());
+        assert!(vm.tag(0) == other);
+
+        assert_eq!(run(&mut vm,0,vec![]),vec![ArgsEnum::ExnRef(None)]);
+        assert_eq!(run(&mut vm,1,vec![ArgsEnum::ExnRef(None)]),vec![I32(1)]);
+        let e = std::panic::catch_unwind(std::panic::AssertUnwindSafe(||vm.tag(3))).unwrap_err();
+        assert_eq!(e.downcast_ref::<String>().map(|s|s.as_str()),Some("errUnknownTag"));
+
+        // 异常跨调用帧传播、catch_ref/throw_ref 重抛、catch_all、宿主与客户互相抛出/捕获
+        let m = crate::text::parse(r#"(module
+            (import "env" "host" (func $host (param i32) (result i32)))
+            (tag $e (param i32))
+            (tag $f)
+            (func $thrower (param i32) (throw $e (local.get 0)))
+            (func $mid (param i32) (result i32) (call $thrower (local.get 0)) (i32.const 100))
+            (func $catch (param i32) (result i32)
+                (block $c (result i32)
+                    (try_table (catch $e $c) (drop (call $mid (local.get 0))))
+                    (return (i32.const -1)))
+                (i32.add (i32.const 1)))
+            (func $rethrow (param i32) (result i32)
+                (block $c (result i32)
+                    (try_table (catch $e $c)
+                        (block $r (result i32 exnref)
+                            (try_table (catch_ref $e $r) (call $thrower (local.get 0)))
+                            (return (i32.const -1)))
+                        throw_ref)
+                    (return (i32.const -2)))
+                (i32.mul (i32.const 2)))
+            (func $all (result i32)
+                (block $a
+                    (block $x (result i32)
+                        (try_table (catch $e $x) (catch_all $a) (throw $f))
+                        (return (i32.const 0)))
+                    (return (i32.const 2)))
+                (i32.const 1))
+            (func $uncaught (param i32) (call $thrower (local.get 0)))
+            (func $via_host (param i32) (result i32)
+                (block $c (result i32)
+                    (try_table (result i32) (catch $e $c) (call $host (local.get 0)))
+                    return)
+                (i32.add (i32.const 1000)))
+            (func (throw_ref (ref.null exn))))"#).unwrap();
+        validate(&m).unwrap();
+        let mut vm = vm_for(m);
+        let ft = vm.module().get_func_type(0).cloned().unwrap();
+        vm.set_host_func(0,HostFunc::new(ft,|vm,args|{
+            match args[0] {
+                I32(0) => Err(ExnRef::new(vm.tag(0),vec![I32(55)])),
+                _ => {
+                    let exn = vm.try_invoke(1,args).unwrap_err();
+                    assert!(*exn.tag() == vm.tag(0));
+                    match exn.values()[0] {
+                        I32(v) => Ok(vec![I32(v + 1)]),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        }));
+        assert_eq!(vm.invoke(3,vec![I32(5)]).unwrap(),vec![I32(6)]);
+        assert!(vm.frames.is_empty() && vm.ctrl_stack.is_empty() && vm.operand_stack.is_empty());
+        assert_eq!(vm.invoke(4,vec![I32(5)]).unwrap(),vec![I32(10)]);
+        assert_eq!(vm.invoke(5,vec![]).unwrap(),vec![I32(1)]);
+        assert!(vm.frames.is_empty() && vm.ctrl_stack.is_empty() && vm.operand_stack.is_empty());
+
+        // 未捕获的异常交给宿主
+        let exn = vm.try_invoke(6,vec![I32(7)]).unwrap_err();
+        assert!(*exn.tag() == vm.tag(0) && *exn.tag() != vm.tag(1));
+        assert_eq!(exn.values(),&[I32(7)][..]);
+        assert!(vm.frames.is_empty() && vm.ctrl_stack.is_empty() && vm.operand_stack.is_empty());
+
+        // 宿主抛给客户,宿主捕获客户
+        assert_eq!(vm.invoke(7,vec![I32(0)]).unwrap(),vec![I32(55 + 1000)]);
+        assert_eq!(vm.invoke(7,vec![I32(9)]).unwrap(),vec![I32(10)]);
+        assert!(vm.frames.is_empty() && vm.ctrl_stack.is_empty() && vm.operand_stack.is_empty());
+
+        assert_eq!(vm.invoke(6,vec![I32(7)]).unwrap_err(),Trap("errUncaughtException".to_string()));
+        assert_eq!(vm.invoke(8,vec![]).unwrap_err(),Trap("errNullExnRef".to_string()));
+    }
+
+    #[test]
+    pub fn test12(){
+        use crate::validator::validate;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // memory64,地址和页数都是i64,基址+偏移溢出不会绕回
+        let m = crate::text::parse(r#"(module (memory i64 1 3)
+            (func (param i64 i64) (i64.store (local.get 0) (local.get 1)))
+            (func (param i64) (result i64) (i64.load offset=8 (local.get 0)))
+            (func (param i64) (result i64) (memory.grow (local.get 0)))
+            (func (result i64) memory.size)
+            (func (param i64) (result i32) (i32.load offset=18446744073709551615 (local.get 0)))
+            (func (param i64 i32 i64) (memory.fill (local.get 0) (local.get 1) (local.get 2))))"#).unwrap();
+        validate(&m).unwrap();
+        let memory = interpreter::vm_memory::Memory::new(m.mem_sec.as_ref().unwrap()[0].clone());
+        let mut vm = interpreter::vm::Vm::new(interpreter::operand::new(),m,memory);
+        run(&mut vm,0,vec![I64(0x10),I64(0x1122334455667788)]);
+        assert_eq!(run(&mut vm,1,vec![I64(0x8)]),vec![I64(0x1122334455667788)]);
+        assert_eq!(run(&mut vm,3,vec![]),vec![I64(1)]);
+        assert_eq!(run(&mut vm,2,vec![I64(2)]),vec![I64(1)]);
+        assert_eq!(run(&mut vm,3,vec![]),vec![I64(3)]);
+        assert_eq!(run(&mut vm,2,vec![I64(1)]),vec![I64(-1)]);
+        assert_eq!(run(&mut vm,2,vec![I64(1 << 60)]),vec![I64(-1)]);
+        run(&mut vm,5,vec![I64(3 * 65536 - 4),I32(0xab),I64(4)]);
+        assert_eq!(vm.memory(0).data()[3 * 65536 - 4..],[0xab;4]);
+
+        let trap = |vm:&mut interpreter::vm::Vm,idx:usize,args:Vec<ArgsEnum>|{
+            let e = catch_unwind(AssertUnwindSafe(||run(vm,idx,args))).unwrap_err();
+            assert_eq!(e.downcast_ref::<&str>(),Some(&"errMemOutOfBounds"));
+        };
+        trap(&mut vm,1,vec![I64(3 * 65536 - 8)]);
+        trap(&mut vm,1,vec![I64(-8)]);
+        trap(&mut vm,4,vec![I64(1)]);
+        trap(&mut vm,5,vec![I64(0),I32(0),I64(-1)]);
+
+        // 32位内存的基址+偏移也按u64算
+        let m = crate::text::parse(r#"(module (memory 1)
+            (func (param i32) (result i32) (i32.load offset=4294967295 (local.get 0)))
+            (func (result i32) (memory.grow (i32.const 65536))))"#).unwrap();
+        validate(&m).unwrap();
+        let memory = interpreter::vm_memory::Memory::new(m.mem_sec.as_ref().unwrap()[0].clone());
+        let mut vm = interpreter::vm::Vm::new(interpreter::operand::new(),m,memory);
+        trap(&mut vm,0,vec![I32(1)]);
+        assert_eq!(run(&mut vm,1,vec![]),vec![I32(-1)]);
+    }
+
+    #[test]
+    pub fn test13(){
+        use crate::validator::validate;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // 多内存: 内存0由宿主共享给两个实例,内存1是各自私有的草稿区
+        let m = crate::text::parse(r#"(module
+            (import "env" "shared" (memory 1 1 shared))
+            (memory $scratch 1 2)
+            (func (param i32 i32) (i32.store $scratch (local.get 0) (local.get 1)))
+            (func (param i32) (result i32) (i32.load $scratch (local.get 0)))
+            (func (param i32 i32 i32) (memory.copy 0 $scratch (local.get 0) (local.get 1) (local.get 2)))
+            (func (param i32) (result i32) (i32.load (local.get 0)))
+            (func (result i32) (drop (memory.grow $scratch (i32.const 1))) (memory.size $scratch))
+            (func (param i32 i32) (memory.fill $scratch (local.get 0) (local.get 1) (i32.const 4))))"#).unwrap();
+        validate(&m).unwrap();
+        let shared = interpreter::vm_memory::Memory::new(m.get_mem_types()[0].clone());
+        let mut vm1 = interpreter::vm::Vm::new(interpreter::operand::new(),m.clone(),shared.clone());
+        let mut vm2 = interpreter::vm::Vm::new(interpreter::operand::new(),m,shared);
+
+        run(&mut vm1,0,vec![I32(16),I32(0x11223344)]);
+        assert_eq!(run(&mut vm1,1,vec![I32(16)]),vec![I32(0x11223344)]);
+        assert_eq!(run(&mut vm2,1,vec![I32(16)]),vec![I32(0)]);
+        assert_eq!(run(&mut vm1,3,vec![I32(16)]),vec![I32(0)]);
+
+        // 从私有内存拷到共享内存,另一个实例能看到
+        run(&mut vm1,2,vec![I32(100),I32(16),I32(4)]);
+        assert_eq!(run(&mut vm2,3,vec![I32(100)]),vec![I32(0x11223344)]);
+        assert_eq!(vm2.memory(0).data()[100..104],[0x44,0x33,0x22,0x11]);
+
+        assert_eq!(run(&mut vm1,4,vec![]),vec![I32(2)]);
+        assert_eq!(vm2.memory(1).size(),1);
+        run(&mut vm1,5,vec![I32(65536),I32(0x7f)]);
+        assert_eq!(vm1.memory(1).data()[65536..65540],[0x7f;4]);
+
+        let e = catch_unwind(AssertUnwindSafe(||run(&mut vm2,5,vec![I32(65536),I32(0)]))).unwrap_err();
+        assert_eq!(e.downcast_ref::<&str>(),Some(&"errMemOutOfBounds"));
+        let e = catch_unwind(AssertUnwindSafe(||run(&mut vm2,2,vec![I32(65534),I32(0),I32(4)]))).unwrap_err();
+        assert_eq!(e.downcast_ref::<&str>(),Some(&"errMemOutOfBounds"));
+    }
+
+    #[test]
+    pub fn test14(){
+        use crate::binary::{atomic, opcodes, simd};
+        use crate::binary::opcodes::Opcode;
+        use crate::validator::validate;
+
+        // 指令表按(前缀,子操作码)分派,每条前缀指令都有入口
+        interpreter::vm::init();
+        let table = interpreter::vm::OPCODE_MAP.get().unwrap();
+        for (sub,_) in opcodes::MISC_OPS.iter() {
+            assert!(table.get_prefixed(Opcode::new(opcodes::MiscPrefix,*sub)).is_some());
+        }
+        for (sub,_) in simd::SIMD_OPS.iter() {
+            assert!(table.get_prefixed(Opcode::new(opcodes::SimdPrefix,*sub)).is_some());
+        }
+        for (sub,_) in atomic::ATOMIC_OPS.iter() {
+            assert!(table.get_prefixed(Opcode::new(opcodes::AtomicPrefix,*sub)).is_some());
+        }
+        assert!(table.get_prefixed(Opcode::new(opcodes::MiscPrefix,0x0C)).is_none());
+        assert!(table.get_prefixed(Opcode::new(opcodes::SimdPrefix,0x9A)).is_none());
+        assert!(table.get_prefixed(Opcode::byte(opcodes::I32Add)).is_none());
+        assert!(table.get(Opcode::byte(opcodes::I32Add)).is_some());
+        assert!(table.get(Opcode::byte(opcodes::Call)).is_none());
+
+        let m = crate::text::parse(r#"(module
+            (func (param f32) (result i32) (i32.trunc_sat_f32_s (local.get 0)))
+            (func (param f64) (result i64) (i64.trunc_sat_f64_u (local.get 0))))"#).unwrap();
+        validate(&m).unwrap();
+        let mut vm = vm_for(m);
+        assert_eq!(vm.invoke(0,vec![F32(f32::NAN)]).unwrap(),vec![I32(0)]);
+        assert_eq!(vm.invoke(0,vec![F32(-3e9)]).unwrap(),vec![I32(i32::MIN)]);
+        assert_eq!(vm.invoke(1,vec![F64(-1.5)]).unwrap(),vec![I64(0)]);
+        assert_eq!(vm.invoke(1,vec![F64(1e20)]).unwrap(),vec![I64(-1)]);
+    }
+
+    #[test]
+    pub fn test15(){
+        use crate::validator::validate;
+
+        // 结构化控制流: 跳转时丢掉块里多出来的操作数,只带走标签需要的值
+        // 函数没有参数,每组输入写成一个函数,需要改写的值放在内存里
+        let classify = |i:i32|format!(r#"(func (result i32)
+                block $d block $c block $b block $a
+                    i32.const {} br_table $a $b $c $d
+                end i32.const 100 return
+                end i32.const 101 return
+                end i32.const 102 return
+                end i32.const 103)"#,i);
+        let fact = |n:i64|format!(r#"(func (result i64)
+                i32.const 0 i64.const {} i64.store
+                i32.const 8 i64.const 1 i64.store
+                block $done
+                    loop $next
+                        i32.const 0 i64.load i64.eqz br_if $done
+                        i32.const 8 i32.const 8 i64.load i32.const 0 i64.load i64.mul i64.store
+                        i32.const 0 i32.const 0 i64.load i64.const 1 i64.sub i64.store
+                        br $next
+                    end
+                end
+                i32.const 8 i64.load)"#,n);
+        let abs = |x:i32|format!(r#"(func (result i32)
+                i32.const {0} i32.const 0 i32.lt_s
+                if (result i32) i32.const {0} i32.const -1 i32.xor i32.const 1 i32.add else i32.const {0} end)"#,x);
+        let trap = |c:i32|format!(r#"(func (result i32)
+                block i32.const {} br_if 0 unreachable end
+                i32.const 1)"#,c);
+        let mut funcs:Vec<String> = [0,1,2,3,4,-1].iter().map(|i|classify(*i)).collect();
+        funcs.push(r#"(func (result i32)
+                i32.const 1
+                block (result i32)
+                    i32.const 2 i32.const 3 i32.const 4
+                    block i32.const 5 i32.const 6 br 1 end
+                    unreachable
+                end
+                i32.add)"#.to_string());
+        funcs.extend(vec![fact(20),fact(0),abs(-5),abs(6)]);
+        funcs.push(r#"(func (result i32)
+                i32.const 9
+                block block loop
+                    i32.const 1 i32.const 42 return
+                end end end
+                drop i32.const 0)"#.to_string());
+        // 跳到loop的标签带走的是loop的参数
+        funcs.push(r#"(func (result i32)
+                i32.const 0
+                loop $l (param i32) (result i32)
+                    i32.const 1 i32.add
+                    i32.const 16 i32.const 16 i32.load i32.const 1 i32.add i32.store
+                    i32.const 16 i32.load i32.const 5 i32.lt_u br_if $l
+                end)"#.to_string());
+        funcs.push(r#"(func (result i32)
+                i32.const 1 i32.const 2
+                block (param i32 i32) (result i32) i32.add end)"#.to_string());
+        funcs.extend(vec![trap(1),trap(0)]);
+        let m = crate::text::parse(&format!("(module (memory 1) {})",funcs.join("\n"))).unwrap();
+        validate(&m).unwrap();
+        let limit = binary::module::Limits{ tag: Some(0), min: Some(1), max: None };
+        let mut vm = interpreter::vm::Vm::new(interpreter::operand::new(),m,interpreter::vm_memory::Memory::new(limit));
+
+        // br_table越界时走默认标签
+        let classify:Vec<_> = (0..6).map(|i|vm.invoke(i,vec![]).unwrap()).collect();
+        assert_eq!(classify,vec![vec![I32(100)],vec![I32(101)],vec![I32(102)],vec![I32(103)],vec![I32(103)],vec![I32(103)]]);
+        assert_eq!(vm.invoke(6,vec![]).unwrap(),vec![I32(7)]);
+        assert_eq!(vm.invoke(7,vec![]).unwrap(),vec![I64(2432902008176640000)]);
+        assert_eq!(vm.invoke(8,vec![]).unwrap(),vec![I64(1)]);
+        assert_eq!(vm.invoke(9,vec![]).unwrap(),vec![I32(5)]);
+        assert_eq!(vm.invoke(10,vec![]).unwrap(),vec![I32(6)]);
+        assert_eq!(vm.invoke(11,vec![]).unwrap(),vec![I32(42)]);
+        assert_eq!(vm.invoke(12,vec![]).unwrap(),vec![I32(5)]);
+        assert_eq!(vm.invoke(13,vec![]).unwrap(),vec![I32(3)]);
+        assert_eq!(vm.invoke(14,vec![]).unwrap(),vec![I32(1)]);
+        assert!(vm.operand_stack.is_empty());
+        assert!(vm.ctrl_stack.is_empty() && vm.frames.is_empty());
+
+        assert_eq!(vm.invoke(15,vec![]).unwrap_err(),Trap("errUnreachable".to_string()));
+    }
+
+    #[test]
+    pub fn test16(){
+        use crate::validator::validate;
+        use crate::interpreter::vm::HostFunc;
+
+        // 导入函数排在前面,定义的函数从导入函数个数开始编号
+        let m = crate::text::parse(r#"(module
+            (import "env" "scale" (func $scale (param i32) (result i32)))
+            (func $locals (param i32 i64) (result i32 i64 f32 f64)
+                (local i32 i64) (local f32) (local f64 v128)
+                local.get 2 local.get 0 i32.add
+                local.get 3 local.get 1 i64.add
+                local.get 4
+                local.get 5)
+            (func $sum (param i32 i32 i32) (result i32)
+                (local $t i32)
+                (local.set $t (i32.add (local.get 0) (local.get 1)))
+                (i32.add (local.get $t) (local.get 2)))
+            (func $fib (param i64) (result i64)
+                (if (result i64) (i64.lt_u (local.get 0) (i64.const 2))
+                    (then (local.get 0))
+                    (else (i64.add
+                        (call $fib (i64.sub (local.get 0) (i64.const 1)))
+                        (call $fib (i64.sub (local.get 0) (i64.const 2)))))))
+            (func $caller (result i32)
+                (i32.const 100)
+                (call $sum (i32.const 1) (call $scale (i32.const 2)) (i32.const 3))
+                i32.add)
+            (func $tee (param i32) (result i32) (local i32)
+                (i32.add (local.tee 1 (i32.const 5)) (local.get 0))))"#).unwrap();
+        validate(&m).unwrap();
+        let code = &m.code_sec.as_ref().unwrap()[0];
+        assert_eq!(code.get_local_count(),Some(5));
+        let mut vm = vm_for(m);
+
+        // 没有绑定导入函数时调用会陷入
+        assert_eq!(vm.invoke(4,vec![]).unwrap_err(),Trap("errUnresolvedImport".to_string()));
+        // 陷入后栈恢复到调用前,Vm可以接着用
+        assert!(vm.frames.is_empty() && vm.ctrl_stack.is_empty() && vm.operand_stack.is_empty());
+
+        let ft = vm.module().get_func_type(0).cloned().unwrap();
+        vm.set_host_func(0,HostFunc::new(ft,|_,args|match args[0] {
+            I32(v) => Ok(vec![I32(v * 10)]),
+            _ => unreachable!(),
+        }));
+        assert_eq!(vm.invoke(0,vec![I32(4)]).unwrap(),vec![I32(40)]);
+        // 局部变量初始化为零值,每次调用都重新初始化
+        for _ in 0..2 {
+            assert_eq!(vm.invoke(1,vec![I32(7),I64(-8)]).unwrap(),vec![I32(7),I64(-8),F32(0.0),F64(0.0)]);
+        }
+        assert_eq!(vm.invoke(2,vec![I32(1),I32(2),I32(3)]).unwrap(),vec![I32(6)]);
+        assert_eq!(vm.invoke(3,vec![I64(20)]).unwrap(),vec![I64(6765)]);
+        // 参数从栈上弹出,结果压回调用者的栈
+        assert_eq!(vm.invoke(4,vec![]).unwrap(),vec![I32(124)]);
+        assert_eq!(vm.invoke(5,vec![I32(1)]).unwrap(),vec![I32(6)]);
+        assert!(vm.operand_stack.is_empty());
+        assert!(vm.ctrl_stack.is_empty() && vm.frames.is_empty());
+
+        assert_eq!(vm.invoke(2,vec![I32(1)]).unwrap_err(),Trap("errArgumentCount".to_string()));
+
+        // 调用和嵌套的块一起算深度,无限递归陷入而不是撑爆本地栈
+        let m = crate::text::parse(r#"(module
+            (func $down (param i32) (result i32)
+                (if (result i32) (i32.eqz (local.get 0))
+                    (then (i32.const 0))
+                    (else (call $down (i32.sub (local.get 0) (i32.const 1))))))
+            (func $forever (call $forever))
+            (func $nested (block (block (block (block (block (block (block (block (call $nested))))))))))
+            (func $tries (try_table (try_table (try_table (try_table (call $tries)))))))"#).unwrap();
+        validate(&m).unwrap();
+        let mut vm = vm_for(m);
+        // 每层递归是函数体加上if两层
+        vm.set_max_stack_depth(20);
+        assert_eq!(vm.invoke(0,vec![I32(9)]).unwrap(),vec![I32(0)]);
+        assert_eq!(vm.invoke(0,vec![I32(10)]).unwrap_err(),Trap("errCallStackExhausted".to_string()));
+        // 默认上限在测试线程默认大小的栈上就会陷入
+        vm.set_max_stack_depth(interpreter::vm::MAX_STACK_DEPTH);
+        for idx in 1..4 {
+            assert_eq!(vm.invoke(idx,vec![]).unwrap_err(),Trap("errCallStackExhausted".to_string()));
+            assert!(vm.frames.is_empty() && vm.ctrl_stack.is_empty() && vm.operand_stack.is_empty());
+        }
+    }
+
+    #[test]
+    pub fn test17(){
+        use crate::validator::validate;
+        use crate::common::common_error::InstantiationError;
+        use crate::interpreter::instance::{Extern, Imports, Instance};
+        use crate::interpreter::vm_global::Global;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // 全局变量: 初值按常量表达式求值,可以读导入的全局变量
+        let m = crate::text::parse(r#"(module
+            (import "env" "base" (global $base i32))
+            (import "env" "counter" (global $counter (mut i64)))
+            (global $sp (export "sp") (mut i32) (i32.add (global.get $base) (i32.const 1024)))
+            (global $pi (export "pi") f64 (f64.const 3.5))
+            (global $f (mut funcref) (ref.func $push))
+            (func $push (param i32) (result i32)
+                (global.set $sp (i32.sub (global.get $sp) (local.get 0)))
+                (global.get $sp))
+            (func $tick (result i64)
+                (global.set $counter (i64.add (global.get $counter) (i64.const 1)))
+                (global.get $counter))
+            (func $pi (result f64) (global.get $pi)))"#).unwrap();
+        validate(&m).unwrap();
+        let vm = vm_for(m);
+        // 导入没有绑定时是零值
+        assert_eq!(vm.global(2).get(),I32(1024));
+        assert_eq!(vm.global(4).get(),vm.func_ref(0));
+
+        // 导入在实例化时绑定,定义的全局变量按绑定的值求初值
+        let global_type = |t:u8,m:u8|binary::module::GlobalType{ val_type: Some(t), m: Some(m) };
+        let counter = Global::new(global_type(binary::module::VAL_TYPE_I64,binary::module::MUT_VAR),I64(41));
+        let mut imports = Imports::new();
+        imports.define("env","base",Extern::Global(Global::new(global_type(binary::module::VAL_TYPE_I32,binary::module::MUT_CONST),I32(4096))))
+            .define("env","counter",Extern::Global(counter.clone()));
+        let mut inst = Instance::new(vm.module(),&imports).unwrap();
+        let vm = inst.vm();
+        let sp = vm.export_global("sp").unwrap();
+        assert_eq!(sp.get(),I32(5120));
+        assert!(sp.same(&vm.global(2)) && counter.same(&vm.global(1)));
+
+        assert_eq!(vm.invoke(0,vec![I32(16)]).unwrap(),vec![I32(5104)]);
+        assert_eq!(sp.get(),I32(5104));
+        // 宿主写入后模块马上能读到
+        sp.set(I32(100)).unwrap();
+        assert_eq!(vm.invoke(0,vec![I32(4)]).unwrap(),vec![I32(96)]);
+        assert_eq!(vm.invoke(1,vec![]).unwrap(),vec![I64(42)]);
+        assert_eq!(counter.get(),I64(42));
+        counter.set(I64(99)).unwrap();
+        assert_eq!(vm.invoke(1,vec![]).unwrap(),vec![I64(100)]);
+        assert_eq!(vm.invoke(2,vec![]).unwrap(),vec![F64(3.5)]);
+        assert!(vm.export_global("missing").is_none());
+
+        // 不可变的全局变量不能写,类型不符也不能写
+        let pi = vm.export_global("pi").unwrap();
+        assert_eq!(pi.set(F64(3.0)).unwrap_err(),Trap("errImmutableGlobal".to_string()));
+        assert_eq!(sp.set(I64(1)).unwrap_err(),Trap("errGlobalTypeMismatch".to_string()));
+        // 模块里的global.set写不可变的全局变量会陷入
+        vm.f64_const(3.0);
+        let e = catch_unwind(AssertUnwindSafe(||vm.global_set(3))).unwrap_err();
+        assert_eq!(interpreter::vm::trap_message(e),"errImmutableGlobal");
+        assert_eq!(pi.get(),F64(3.5));
+        assert_eq!(sp.get(),I32(96));
+
+        // 绑定导入要求类型和可变性一致
+        imports.define("env","counter",Extern::Global(sp.clone()));
+        assert_eq!(Instance::new(vm.module(),&imports).unwrap_err(),
+            InstantiationError::IncompatibleImportType{ module: "env".to_string(), name: "counter".to_string() });
+    }
+
+    #[test]
+    pub fn test18(){
+        use crate::interpreter::instance::{Extern, Imports, Instance};
+        use crate::interpreter::vm::HostFunc;
+        use crate::interpreter::vm_table::Table;
+
+        // call_indirect: 表由元素段填充,签名按结构比较,不看类型索引
+        let m = crate::text::parse(r#"(module
+            (type $binop (func (param i32 i32) (result i32)))
+            (type $binop2 (func (param i32 i32) (result i32)))
+            (type $unop (func (param i32) (result i32)))
+            (import "env" "max" (func $max (type $binop)))
+            (table $ops 8 funcref)
+            (table $unops 2 funcref)
+            (func $add (type $binop2) (i32.add (local.get 0) (local.get 1)))
+            (func $mul (type $binop) (i32.mul (local.get 0) (local.get 1)))
+            (func $double (type $unop) (i32.add (local.get 0) (local.get 0)))
+            (func $neg (type $unop) (i32.sub (i32.const 0) (local.get 0)))
+            (elem (table $ops) (i32.const 0) func $add $mul $max $double)
+            (elem (table $ops) (i32.const 5) funcref (ref.func $mul) (ref.null func))
+            (elem (table $unops) (i32.const 0) func $double)
+            (elem declare func $neg)
+            (func (export "apply") (param i32 i32 i32) (result i32)
+                (call_indirect $ops (type $binop) (local.get 1) (local.get 2) (local.get 0)))
+            (func (export "unary") (param i32 i32) (result i32)
+                (call_indirect $unops (type $unop) (local.get 1) (local.get 0)))
+            (func (export "patch") (param i32)
+                (table.set $unops (local.get 0) (ref.func $neg)))
+            (func (export "fold") (param i32 i32) (result i32) (local $i i32) (local $acc i32)
+                (local.set $acc (i32.const 1))
+                (block $done
+                    (loop $next
+                        (br_if $done (i32.ge_u (local.get $i) (local.get 1)))
+                        (local.set $acc (call_indirect (type $binop) (local.get $acc) (i32.const 2) (local.get 0)))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br $next)))
+                (local.get $acc)))"#).unwrap();
+        crate::validator::validate(&m).unwrap();
+        let mut imports = Imports::new();
+        imports.define("env","max",Extern::Func(HostFunc::new(m.get_func_type(0).cloned().unwrap(),|_,args|match (&args[0],&args[1]) {
+            (I32(a),I32(b)) => Ok(vec![I32(*a.max(b))]),
+            _ => unreachable!(),
+        })));
+        let mut inst = Instance::new(&m,&imports).unwrap();
+
+        assert_eq!(inst.invoke("apply",vec![I32(0),I32(3),I32(4)]).unwrap(),vec![I32(7)]);
+        assert_eq!(inst.invoke("apply",vec![I32(1),I32(3),I32(4)]).unwrap(),vec![I32(12)]);
+        assert_eq!(inst.invoke("apply",vec![I32(2),I32(3),I32(4)]).unwrap(),vec![I32(4)]);
+        assert_eq!(inst.invoke("apply",vec![I32(5),I32(5),I32(6)]).unwrap(),vec![I32(30)]);
+        assert_eq!(inst.invoke("unary",vec![I32(0),I32(21)]).unwrap(),vec![I32(42)]);
+        assert_eq!(inst.invoke("fold",vec![I32(1),I32(10)]).unwrap(),vec![I32(1024)]);
+        assert_eq!(inst.invoke("fold",vec![I32(0),I32(3)]).unwrap(),vec![I32(7)]);
+        // 运行时改写表里的元素
+        inst.invoke("patch",vec![I32(1)]).unwrap();
+        assert_eq!(inst.invoke("unary",vec![I32(1),I32(0)]).unwrap(),vec![I32(0)]);
+
+        let trap = |inst:&mut Instance,name:&str,args:Vec<ArgsEnum>|{
+            inst.invoke(name,args).unwrap_err().0
+        };
+        // 没有初始化的元素、ref.null、越界、签名不一致
+        assert_eq!(trap(&mut inst,"apply",vec![I32(4),I32(1),I32(1)]),"errUninitializedElement");
+        assert_eq!(trap(&mut inst,"apply",vec![I32(6),I32(1),I32(1)]),"errUninitializedElement");
+        assert_eq!(trap(&mut inst,"apply",vec![I32(8),I32(1),I32(1)]),"errUndefinedElement");
+        assert_eq!(trap(&mut inst,"apply",vec![I32(-1),I32(1),I32(1)]),"errUndefinedElement");
+        assert_eq!(trap(&mut inst,"apply",vec![I32(3),I32(1),I32(1)]),"errIndirectCallTypeMismatch");
+        assert_eq!(trap(&mut inst,"unary",vec![I32(2),I32(1)]),"errUndefinedElement");
+        let vm = inst.vm();
+        assert!(vm.frames.is_empty() && vm.ctrl_stack.is_empty() && vm.operand_stack.is_empty());
+        assert_eq!(inst.invoke("apply",vec![I32(0),I32(1),I32(1)]).unwrap(),vec![I32(2)]);
+
+        // 表在实例之间共享时,元素带着所属的实例,调用的是放进去的那个实例的函数
+        let limits = binary::module::Limits{ tag: Some(0), min: Some(2), max: None };
+        let table = Table::new(binary::module::TableType{ elem_type: Some(binary::module::FUNC_REF), limits: Some(limits) });
+        let mut imports = Imports::new();
+        imports.define("env","tab",Extern::Table(table.clone()));
+        let instantiate = |slot:u32,id:i32|{
+            let m = crate::text::parse(&format!(r#"(module
+                (import "env" "tab" (table 2 funcref))
+                (global $id i32 (i32.const {}))
+                (func $id (result i32) (global.get $id))
+                (elem (i32.const {}) func $id)
+                (func (export "call") (param i32) (result i32) (call_indirect (result i32) (local.get 0)))
+                (func (export "tail") (param i32) (result i32) (return_call_indirect (result i32) (local.get 0))))"#,id,slot)).unwrap();
+            crate::validator::validate(&m).unwrap();
+            Instance::new(&m,&imports).unwrap()
+        };
+        let mut a = instantiate(0,10);
+        let mut b = instantiate(1,20);
+        assert_eq!(b.invoke("call",vec![I32(0)]).unwrap(),vec![I32(10)]);
+        assert_eq!(b.invoke("call",vec![I32(1)]).unwrap(),vec![I32(20)]);
+        assert_eq!(a.invoke("call",vec![I32(1)]).unwrap(),vec![I32(20)]);
+        assert_eq!(b.invoke("tail",vec![I32(0)]).unwrap(),vec![I32(10)]);
+        assert_eq!(table.get(0),a.vm().func_ref(0));
+        // 实例释放后,留在表里的引用不能再调用
+        drop(a);
+        assert_eq!(trap(&mut b,"call",vec![I32(0)]),"errDroppedInstance");
+        assert_eq!(b.invoke("call",vec![I32(1)]).unwrap(),vec![I32(20)]);
+    }
 }
\ No newline at end of file
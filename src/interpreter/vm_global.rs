@@ -0,0 +1,64 @@
+use crate::binary::instruction::ArgsEnum;
+use crate::binary::module;
+use crate::common::common_error::Trap;
+use std::sync::{Arc, RwLock};
+
+/// 全局变量,克隆出来的句柄共享同一个值
+/// 导出给宿主、导入到别的实例都是传句柄,一边写入另一边马上能读到
+#[derive(Debug,Clone)]
+pub struct Global{
+    pub _type:module::GlobalType,
+    val:Arc<RwLock<ArgsEnum>>,
+}
+
+/// 值是否属于这个值类型
+fn is_val_type(t:u8,v:&ArgsEnum) -> bool{
+    matches!((t,v),
+        (module::VAL_TYPE_I32,ArgsEnum::I32(_))
+        |(module::VAL_TYPE_I64,ArgsEnum::I64(_))
+        |(module::VAL_TYPE_F32,ArgsEnum::F32(_))
+        |(module::VAL_TYPE_F64,ArgsEnum::F64(_))
+        |(module::VAL_TYPE_V128,ArgsEnum::V128(_))
+        |(module::FUNC_REF,ArgsEnum::FuncRef(_))
+        |(module::EXTERN_REF,ArgsEnum::ExternRef(_))
+        |(module::EXN_REF,ArgsEnum::ExnRef(_)))
+}
+
+impl Global{
+    pub fn new(gt:module::GlobalType,val:ArgsEnum) -> Global{
+        let t = gt.val_type.expect("global has no value type");
+        if !is_val_type(t,&val) {
+            panic!("errGlobalTypeMismatch")
+        }
+        Global{ _type: gt, val: Arc::new(RwLock::new(val)) }
+    }
+
+    pub fn val_type(&self) -> u8{
+        self._type.val_type.unwrap()
+    }
+
+    pub fn is_mut(&self) -> bool{
+        self._type.m == Some(module::MUT_VAR)
+    }
+
+    pub fn get(&self) -> ArgsEnum{
+        self.val.read().unwrap().clone()
+    }
+
+    /// 写入,不可变的全局变量和类型不符的值都返回错误,模块里的global.set由调用方陷入
+    pub fn set(&self,val:ArgsEnum) -> Result<(),Trap>{
+        if !self.is_mut() {
+            return Err(Trap("errImmutableGlobal".to_string()));
+        }
+        if !is_val_type(self.val_type(),&val) {
+            return Err(Trap("errGlobalTypeMismatch".to_string()));
+        }
+        *self.val.write().unwrap() = val;
+        Ok(())
+    }
+
+    /// 两个句柄是不是同一个全局变量
+    pub fn same(&self,other:&Global) -> bool{
+        Arc::ptr_eq(&self.val,&other.val)
+    }
+}
@@ -1,75 +1,236 @@
 
 use crate::binary::module;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
 
+/// 线性内存,clone出来的句柄还是同一块内存
+/// 导出给宿主、导入到别的实例都是传句柄;共享内存还可以交给其他线程上的Vm使用
 #[derive(Debug,Clone)]
 pub struct Memory{
     pub _type:module::MemType,
-    pub data:Vec<u8>
+    inner:Arc<Inner>,
+}
+
+#[derive(Debug,Default)]
+struct Inner{
+    data:RwLock<Vec<u8>>,
+    /// memory.atomic.wait挂起的线程,按地址排队
+    waiters:Mutex<HashMap<usize,Vec<Arc<Waiter>>>>,
+}
+
+#[derive(Debug,Default)]
+struct Waiter{
+    woken:Mutex<bool>,
+    cond:Condvar,
+}
+
+/// 校验是否越界,长度为0时偏移也不能超过内存大小
+fn check_bounds(len:usize,offset:usize,length:usize){
+    match offset.checked_add(length) {
+        Some(end) if end <= len => {}
+        _ => panic!("errMemOutOfBounds"),
+    }
+}
+
+/// 小端读出不超过8字节的整数
+fn load_le(bytes:&[u8]) -> u64{
+    let mut buf = [0u8;8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+/// 页数换成字节数,超出宿主地址空间返回None
+fn page_bytes(pages:u64) -> Option<usize>{
+    pages.checked_mul(module::PAGE_SIZE as u64).filter(|&v|v <= usize::MAX as u64).map(|v|v as usize)
 }
 
 impl Memory{
     pub fn new(mt:module::MemType) -> Memory{
-        let mut v:Vec<u8> = Vec::new();
-        v.resize((mt.min.unwrap() as usize * module::PAGE_SIZE) as usize,0);
-        Memory{
+        Memory::try_new(mt).expect("memory min too large")
+    }
+
+    /// 按初始大小分配内存,超出宿主地址空间或者分配不出来时返回None
+    pub fn try_new(mt:module::MemType) -> Option<Memory>{
+        let len = page_bytes(mt.min.unwrap_or(0))?;
+        let mut data = vec![];
+        data.try_reserve_exact(len).ok()?;
+        data.resize(len,0);
+        Some(Memory{
             _type: mt,
-            data: v,
-        }
+            inner: Arc::new(Inner{ data: RwLock::new(data), waiters: Mutex::default() }),
+        })
+    }
+
+    /// 两个句柄是不是同一块内存
+    pub fn same(&self,other:&Memory) -> bool{
+        Arc::ptr_eq(&self.inner,&other.inner)
+    }
+
+    /// 当前的内存内容,持有期间其他线程不能写
+    /// 越界陷入是panic,拿着锁时陷入锁会中毒,但改数据前都先查过边界,数据还是完整的,所以忽略中毒
+    pub fn data(&self) -> RwLockReadGuard<'_,Vec<u8>>{
+        self.inner.data.read().unwrap_or_else(|e|e.into_inner())
+    }
+
+    fn data_mut(&self) -> RwLockWriteGuard<'_,Vec<u8>>{
+        self.inner.data.write().unwrap_or_else(|e|e.into_inner())
     }
 
     /// 计算页数,数组长度/一页长度
     pub fn size(&self) -> usize{
-        self.data.len() / module::PAGE_SIZE
+        self.data().len() / module::PAGE_SIZE
     }
 
-    /// 增长页数
-    pub fn grow(&mut self,n:usize) -> usize{
-        let old_size = self.size();
+    /// 最多能增长到的页数,memory64是2^48页
+    pub fn max_page_count(&self) -> u64{
+        let limit = if self._type.is_64() {module::MAX_PAGE_COUNT_64} else {module::MAX_PAGE_COUNT as u64};
+        self._type.max.map_or(limit,|v|v.min(limit))
+    }
+
+    /// 增长页数,成功返回原来的页数
+    /// 超过上限或者宿主分配不出内存都返回None,由指令压入-1
+    pub fn grow(&self,n:u64) -> Option<u64>{
+        let mut data = self.data_mut();
+        let old_size = (data.len() / module::PAGE_SIZE) as u64;
         if n == 0 {
-            return old_size
-        }
-        let mut max_page_count = module::MAX_PAGE_COUNT;
-        self._type.max.and_then(|v|{
-            if v >= 0 {
-                max_page_count = v as usize;
-            }
-            Some(())
-        }).or_else(||{
-            println!("mem max is none");
-            None
-        });
-
-        if old_size + n > max_page_count {
-            return 0xFFFFFFFF
+            return Some(old_size)
         }
-        let mut new_v:Vec<u8> = Vec::new();
-        new_v.resize((old_size+n)*module::PAGE_SIZE,0);
-        let (left,right) = new_v.split_at_mut(self.data.len());
-        left.clone_from_slice(self.data.as_slice());
-        self.data = new_v;
-        old_size
+        let new_size = old_size.checked_add(n).filter(|&v|v <= self.max_page_count())?;
+        let new_len = page_bytes(new_size)?;
+        let extra = new_len - data.len();
+        data.try_reserve_exact(extra).ok()?;
+        data.resize(new_len,0);
+        Some(old_size)
     }
 
     /// 读数据
-    pub fn read(&mut self, offset:usize, buf: &mut [u8]){
-        self.check_offset(offset,buf.len());
-        let (left,right) = self.data.split_at_mut(offset);
-        let (r_left,r_right) = right.split_at_mut(buf.len());
-        buf.clone_from_slice(r_left)
+    pub fn read(&self, offset:usize, buf: &mut [u8]){
+        let data = self.data();
+        check_bounds(data.len(),offset,buf.len());
+        buf.copy_from_slice(&data[offset..offset + buf.len()]);
     }
 
     /// 写数据
-    pub fn write(&mut self,offset:usize,data:&[u8]){
-        self.check_offset(offset,data.len());
-        let (left,right) = self.data.split_at_mut(offset);
-        let (r_left,r_right) = right.split_at_mut(data.len());
-        r_left.clone_from_slice(data);
+    pub fn write(&self,offset:usize,bytes:&[u8]){
+        let mut data = self.data_mut();
+        check_bounds(data.len(),offset,bytes.len());
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// memory.fill,从offset开始n个字节都写成val
+    pub fn fill(&self,offset:usize,val:u8,n:usize){
+        let mut data = self.data_mut();
+        check_bounds(data.len(),offset,n);
+        for b in &mut data[offset..offset + n] {
+            *b = val;
+        }
+    }
+
+    /// memory.copy,源和目标可以重叠
+    pub fn copy(&self,dst:usize,src:usize,n:usize){
+        let mut data = self.data_mut();
+        check_bounds(data.len(),src,n);
+        check_bounds(data.len(),dst,n);
+        data.copy_within(src..src + n,dst);
+    }
+}
+
+/// 原子访问,所有访问都经过同一把读写锁,所以读改写之间不会插进别的访问
+impl Memory{
+    /// 先查对齐再查越界
+    fn check_atomic(&self,offset:usize,size:usize){
+        if !offset.is_multiple_of(size) {
+            panic!("errUnalignedAtomic")
+        }
+        check_bounds(self.data().len(),offset,size);
+    }
+
+    pub fn atomic_load(&self,offset:usize,size:usize) -> u64{
+        self.check_atomic(offset,size);
+        load_le(&self.data()[offset..offset + size])
+    }
+
+    pub fn atomic_store(&self,offset:usize,size:usize,v:u64){
+        self.check_atomic(offset,size);
+        self.write(offset,&v.to_le_bytes()[..size]);
+    }
+
+    /// 读改写,f拿到旧值返回新值,新值只写低size个字节,返回旧值
+    pub fn atomic_rmw(&self,offset:usize,size:usize,f:impl FnOnce(u64) -> u64) -> u64{
+        self.check_atomic(offset,size);
+        let mut data = self.data_mut();
+        let bytes = &mut data[offset..offset + size];
+        let old = load_le(bytes);
+        bytes.copy_from_slice(&f(old).to_le_bytes()[..size]);
+        old
     }
 
-    /// 校验是否越界
-    fn check_offset(&mut self,offset:usize,length:usize){
-        if self.data.len() - length < offset {
-            panic!("errMemOutOfBounds")
+    /// memory.atomic.wait32/64,返回0被唤醒,1值不相等,2超时
+    /// timeout是纳秒,负数表示一直等;非共享内存上等待会陷入
+    pub fn wait(&self,offset:usize,size:usize,expected:u64,timeout:i64) -> u32{
+        self.check_atomic(offset,size);
+        if !self._type.is_shared() {
+            panic!("errExpectedSharedMemory")
+        }
+        // 比较和入队都在队列锁里做,notify不会在两者之间漏掉
+        let waiter = {
+            let mut waiters = self.inner.waiters.lock().unwrap();
+            if load_le(&self.data()[offset..offset + size]) != expected {
+                return 1
+            }
+            let w = Arc::new(Waiter::default());
+            waiters.entry(offset).or_default().push(w.clone());
+            w
+        };
+        let deadline = (timeout >= 0).then(||Instant::now() + Duration::from_nanos(timeout as u64));
+        let mut woken = waiter.woken.lock().unwrap();
+        while !*woken {
+            match deadline {
+                None => woken = waiter.cond.wait(woken).unwrap(),
+                Some(d) => {
+                    let now = Instant::now();
+                    if now >= d {
+                        break
+                    }
+                    woken = waiter.cond.wait_timeout(woken,d - now).unwrap().0;
+                }
+            }
         }
+        if *woken {
+            return 0
+        }
+        drop(woken);
+        // 超时了要出队,出队前可能刚好被notify唤醒
+        let mut waiters = self.inner.waiters.lock().unwrap();
+        if *waiter.woken.lock().unwrap() {
+            return 0
+        }
+        if let Some(queue) = waiters.get_mut(&offset) {
+            queue.retain(|w|!Arc::ptr_eq(w,&waiter));
+            if queue.is_empty() {
+                waiters.remove(&offset);
+            }
+        }
+        2
+    }
+
+    /// memory.atomic.notify,按等待顺序唤醒最多count个,返回唤醒的个数
+    pub fn notify(&self,offset:usize,count:u32) -> u32{
+        self.check_atomic(offset,4);
+        let mut waiters = self.inner.waiters.lock().unwrap();
+        let queue = match waiters.get_mut(&offset) {
+            Some(queue) => queue,
+            None => return 0,
+        };
+        let n = queue.len().min(count as usize);
+        for w in queue.drain(..n) {
+            *w.woken.lock().unwrap() = true;
+            w.cond.notify_one();
+        }
+        if queue.is_empty() {
+            waiters.remove(&offset);
+        }
+        n as u32
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,101 @@
+use crate::binary::instruction::ArgsEnum;
+use crate::binary::module;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// 表最多能增长到的元素个数,表类型没有上限时用它兜底
+pub const MAX_TABLE_SIZE:u32 = 10_000_000;
+
+/// 表,clone出来的句柄还是同一张表
+/// 导出给宿主、导入到别的实例都是传句柄,一边写入另一边马上能读到
+#[derive(Debug,Clone)]
+pub struct Table{
+    pub _type:module::TableType,
+    elems:Arc<RwLock<Vec<ArgsEnum>>>,
+}
+
+/// 引用类型的空值
+pub fn null_ref(t:u8) -> ArgsEnum{
+    match t {
+        module::FUNC_REF => ArgsEnum::FuncRef(None),
+        module::EXTERN_REF => ArgsEnum::ExternRef(None),
+        module::EXN_REF => ArgsEnum::ExnRef(None),
+        t => panic!("unknown reference type:{:#04x}",t),
+    }
+}
+
+/// 校验是否越界,长度为0时偏移也不能超过表的长度
+fn check_bounds(len:usize,offset:u32,length:u32){
+    match (offset as usize).checked_add(length as usize) {
+        Some(end) if end <= len => {}
+        _ => panic!("errTableOutOfBounds"),
+    }
+}
+
+impl Table{
+    /// 按最小长度创建,元素都是空引用
+    pub fn new(tt:module::TableType) -> Table{
+        let min = tt.limits.as_ref().and_then(|l|l.min).unwrap_or(0);
+        let null = null_ref(tt.elem_type.unwrap_or(module::FUNC_REF));
+        Table{
+            _type: tt,
+            elems: Arc::new(RwLock::new(vec![null;min as usize])),
+        }
+    }
+
+    /// 和Memory一样,越界陷入前没有改过数据,锁中毒可以忽略
+    fn elems(&self) -> RwLockReadGuard<'_,Vec<ArgsEnum>>{
+        self.elems.read().unwrap_or_else(|e|e.into_inner())
+    }
+
+    fn elems_mut(&self) -> RwLockWriteGuard<'_,Vec<ArgsEnum>>{
+        self.elems.write().unwrap_or_else(|e|e.into_inner())
+    }
+
+    pub fn elem_type(&self) -> u8{
+        self._type.elem_type.unwrap_or(module::FUNC_REF)
+    }
+
+    pub fn size(&self) -> u32{
+        self.elems().len() as u32
+    }
+
+    /// 增长n个元素,新元素都是init,成功返回原来的长度,失败返回0xFFFFFFFF
+    pub fn grow(&self,n:u32,init:ArgsEnum) -> u32{
+        let mut elems = self.elems_mut();
+        let old_size = elems.len() as u32;
+        let max = self._type.limits.as_ref().and_then(|l|l.max).map_or(MAX_TABLE_SIZE,|v|v.min(MAX_TABLE_SIZE as u64) as u32);
+        match old_size.checked_add(n) {
+            Some(new_size) if new_size <= max => {
+                elems.resize(new_size as usize,init);
+                old_size
+            }
+            _ => 0xFFFFFFFF,
+        }
+    }
+
+    pub fn get(&self,idx:u32) -> ArgsEnum{
+        let elems = self.elems();
+        check_bounds(elems.len(),idx,1);
+        elems[idx as usize].clone()
+    }
+
+    pub fn set(&self,idx:u32,val:ArgsEnum){
+        let mut elems = self.elems_mut();
+        check_bounds(elems.len(),idx,1);
+        elems[idx as usize] = val;
+    }
+
+    /// table.fill,从offset开始n个元素都写成val
+    pub fn fill(&self,offset:u32,val:ArgsEnum,n:u32){
+        let mut elems = self.elems_mut();
+        check_bounds(elems.len(),offset,n);
+        for e in &mut elems[offset as usize..(offset + n) as usize] {
+            *e = val.clone();
+        }
+    }
+
+    /// 两个句柄是不是同一张表
+    pub fn same(&self,other:&Table) -> bool{
+        Arc::ptr_eq(&self.elems,&other.elems)
+    }
+}
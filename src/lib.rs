@@ -0,0 +1,11 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+
+
+pub mod binary;
+pub mod common;
+pub mod interpreter;
+pub mod text;
+pub mod utils;
+pub mod validator;
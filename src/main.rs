@@ -1,13 +1,4 @@
-#![feature(array_methods)]
-#![allow(dead_code)]
-#![allow(unused_imports)]
-#![allow(unused_variables)]
-
-
-mod binary;
-mod common;
-mod interpreter;
-mod utils;
+use wasm_vm::binary;
 
 fn main() {
     binary::init();
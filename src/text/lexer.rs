@@ -0,0 +1,264 @@
+use crate::common::common_error::TextError;
+
+/// 源码中的位置,line/col从1开始
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Pos{
+    pub line:usize,
+    pub col:usize,
+}
+
+impl Pos{
+    pub fn err(&self,message:String) -> TextError{
+        TextError{ line: self.line, col: self.col, message }
+    }
+}
+
+/// 词法单元
+/// Atom 是关键字和数字,例如 i32.add / offset=8 / 0x10 / nan:0x200000,由语法分析再区分
+/// Id 是以$开头的名字,保存时去掉了$
+#[derive(Debug,Clone,PartialEq)]
+pub enum Token{
+    LParen,
+    RParen,
+    Atom(String),
+    Id(String),
+    Str(Vec<u8>),
+}
+
+/// S表达式
+#[derive(Debug,Clone,PartialEq)]
+pub enum SExpr{
+    Atom(Token,Pos),
+    List(Vec<SExpr>,Pos),
+}
+
+impl SExpr{
+    pub fn pos(&self) -> Pos{
+        match self {
+            SExpr::Atom(_,pos) => *pos,
+            SExpr::List(_,pos) => *pos,
+        }
+    }
+}
+
+pub fn is_id_char(c:char) -> bool{
+    c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c)
+}
+
+struct Lexer<'a>{
+    chars:std::iter::Peekable<std::str::Chars<'a>>,
+    line:usize,
+    col:usize,
+}
+
+impl<'a> Lexer<'a>{
+    fn pos(&self) -> Pos{
+        Pos{ line: self.line, col: self.col }
+    }
+
+    fn peek(&mut self) -> Option<char>{
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) -> Option<char>{
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    /// 跳过空白和注释, ;; 行注释, (; ;) 块注释可以嵌套
+    fn skip_trivia(&mut self) -> Result<(),TextError>{
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {self.bump();}
+                Some(';') => {
+                    let pos = self.pos();
+                    self.bump();
+                    if self.bump() != Some(';') {
+                        return Err(pos.err("unexpected character ';'".to_string()));
+                    }
+                    while let Some(c) = self.bump() {
+                        if c == '\n' { break }
+                    }
+                }
+                Some('(') => {
+                    let mut ahead = self.chars.clone();
+                    ahead.next();
+                    if ahead.next() != Some(';') {
+                        return Ok(());
+                    }
+                    let pos = self.pos();
+                    self.bump();
+                    self.bump();
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match self.bump() {
+                            None => return Err(pos.err("unclosed block comment".to_string())),
+                            Some('(') if self.peek() == Some(';') => {self.bump(); depth += 1;}
+                            Some(';') if self.peek() == Some(')') => {self.bump(); depth -= 1;}
+                            _ => {}
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read_string(&mut self,pos:Pos) -> Result<Vec<u8>,TextError>{
+        let mut v = Vec::new();
+        loop {
+            let c = match self.bump() {
+                None|Some('\n') => return Err(pos.err("unclosed string".to_string())),
+                Some(c) => c,
+            };
+            match c {
+                '"' => return Ok(v),
+                '\\' => {
+                    let epos = self.pos();
+                    match self.bump() {
+                        Some('t') => v.push(b'\t'),
+                        Some('n') => v.push(b'\n'),
+                        Some('r') => v.push(b'\r'),
+                        Some('"') => v.push(b'"'),
+                        Some('\'') => v.push(b'\''),
+                        Some('\\') => v.push(b'\\'),
+                        Some('u') => {
+                            if self.bump() != Some('{') {
+                                return Err(epos.err("malformed unicode escape".to_string()));
+                            }
+                            let mut n = 0u32;
+                            loop {
+                                match self.bump() {
+                                    Some('}') => break,
+                                    Some(c) if c.is_ascii_hexdigit() => {
+                                        n = n.checked_mul(16).and_then(|n|n.checked_add(c.to_digit(16).unwrap()))
+                                            .ok_or_else(||epos.err("malformed unicode escape".to_string()))?;
+                                    }
+                                    _ => return Err(epos.err("malformed unicode escape".to_string())),
+                                }
+                            }
+                            let c = std::char::from_u32(n).ok_or_else(||epos.err("malformed unicode escape".to_string()))?;
+                            let mut b = [0u8;4];
+                            v.extend_from_slice(c.encode_utf8(&mut b).as_bytes());
+                        }
+                        Some(h) if h.is_ascii_hexdigit() => {
+                            match self.bump() {
+                                Some(l) if l.is_ascii_hexdigit() => {
+                                    v.push((h.to_digit(16).unwrap() * 16 + l.to_digit(16).unwrap()) as u8);
+                                }
+                                _ => return Err(epos.err("malformed escape".to_string())),
+                            }
+                        }
+                        _ => return Err(epos.err("malformed escape".to_string())),
+                    }
+                }
+                c => {
+                    let mut b = [0u8;4];
+                    v.extend_from_slice(c.encode_utf8(&mut b).as_bytes());
+                }
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<(Token,Pos)>,TextError>{
+        self.skip_trivia()?;
+        let pos = self.pos();
+        let c = match self.peek() {
+            None => return Ok(None),
+            Some(c) => c,
+        };
+        let token = match c {
+            '(' => {self.bump(); Token::LParen}
+            ')' => {self.bump(); Token::RParen}
+            '"' => {
+                self.bump();
+                Token::Str(self.read_string(pos)?)
+            }
+            c if is_id_char(c) => {
+                let mut s = String::new();
+                while let Some(c) = self.peek() {
+                    if !is_id_char(c) { break }
+                    s.push(c);
+                    self.bump();
+                }
+                if let Some(id) = s.strip_prefix('$') {
+                    if id.is_empty() {
+                        return Err(pos.err("empty identifier".to_string()));
+                    }
+                    Token::Id(id.to_string())
+                } else {
+                    Token::Atom(s)
+                }
+            }
+            c => return Err(pos.err(format!("unexpected character {:?}",c))),
+        };
+        Ok(Some((token,pos)))
+    }
+}
+
+pub fn tokenize(src:&str) -> Result<Vec<(Token,Pos)>,TextError>{
+    let mut lexer = Lexer{ chars: src.chars().peekable(), line: 1, col: 1 };
+    let mut v = Vec::new();
+    while let Some(t) = lexer.next_token()? {
+        v.push(t);
+    }
+    Ok(v)
+}
+
+/// 把词法单元组装成S表达式
+pub fn parse_sexprs(src:&str) -> Result<Vec<SExpr>,TextError>{
+    let tokens = tokenize(src)?;
+    let mut stack:Vec<(Vec<SExpr>,Pos)> = vec![(vec![],Pos{ line: 1, col: 1 })];
+    for (token,pos) in tokens {
+        match token {
+            Token::LParen => stack.push((vec![],pos)),
+            Token::RParen => {
+                if stack.len() == 1 {
+                    return Err(pos.err("unexpected ')'".to_string()));
+                }
+                let (items,start) = stack.pop().unwrap();
+                stack.last_mut().unwrap().0.push(SExpr::List(items,start));
+            }
+            t => stack.last_mut().unwrap().0.push(SExpr::Atom(t,pos)),
+        }
+    }
+    if stack.len() > 1 {
+        return Err(stack.last().unwrap().1.err("unclosed '('".to_string()));
+    }
+    Ok(stack.pop().unwrap().0)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::text::lexer::{parse_sexprs, tokenize, SExpr, Token};
+
+    #[test]
+    fn test1(){
+        let tokens:Vec<Token> = tokenize("(module ;; comment\n (; block (; nested ;) ;) $f \"a\\n\\41\\u{4e2d}\" i32.const -0x1_0)")
+            .unwrap().into_iter().map(|(t,_)|t).collect();
+        assert_eq!(tokens,vec![
+            Token::LParen,
+            Token::Atom("module".to_string()),
+            Token::Id("f".to_string()),
+            Token::Str("a\nA中".as_bytes().to_vec()),
+            Token::Atom("i32.const".to_string()),
+            Token::Atom("-0x1_0".to_string()),
+            Token::RParen,
+        ]);
+
+        let e = parse_sexprs("(module\n  (func))\n)").unwrap_err();
+        assert_eq!((e.line,e.col),(3,1));
+        let e = parse_sexprs("(module \"abc").unwrap_err();
+        assert_eq!((e.line,e.col),(1,9));
+        match &parse_sexprs("(a (b c))").unwrap()[0] {
+            SExpr::List(items,_) => assert_eq!(items.len(),2),
+            _ => panic!(),
+        }
+    }
+}
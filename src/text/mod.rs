@@ -0,0 +1,12 @@
+pub mod lexer;
+pub mod number;
+pub mod parser;
+pub mod printer;
+
+pub use parser::parse;
+pub use printer::print;
+
+pub fn parse_file(path:String) -> anyhow::Result<crate::binary::module::Module>{
+    let src = std::fs::read_to_string(path)?;
+    Ok(parse(&src)?)
+}
@@ -0,0 +1,232 @@
+//! 文本格式里的数字字面量
+//! 整数支持十进制和0x十六进制,浮点数还支持十六进制浮点、inf、nan和nan:0x负载
+//! 数字之间可以有下划线
+
+fn split_sign(s:&str) -> (bool,&str){
+    if let Some(rest) = s.strip_prefix('-') {
+        (true,rest)
+    } else if let Some(rest) = s.strip_prefix('+') {
+        (false,rest)
+    } else {
+        (false,s)
+    }
+}
+
+/// 去掉数字间的下划线,下划线不能在开头结尾或者连续出现
+fn strip_underscores(s:&str) -> Option<String>{
+    if s.starts_with('_') || s.ends_with('_') || s.contains("__") {
+        return None;
+    }
+    Some(s.replace('_',""))
+}
+
+fn parse_digits(s:&str,radix:u32) -> Option<u64>{
+    let s = strip_underscores(s)?;
+    if s.is_empty() {
+        return None;
+    }
+    let mut n = 0u64;
+    for c in s.chars() {
+        n = n.checked_mul(radix as u64)?.checked_add(c.to_digit(radix)? as u64)?;
+    }
+    Some(n)
+}
+
+/// 无符号整数,索引和对齐都用这个
+pub fn parse_uint(s:&str) -> Option<u64>{
+    match s.strip_prefix("0x") {
+        Some(hex) => parse_digits(hex,16),
+        None => parse_digits(s,10),
+    }
+}
+
+pub fn parse_u32(s:&str) -> Option<u32>{
+    let n = parse_uint(s)?;
+    if n > u32::MAX as u64 { None } else { Some(n as u32) }
+}
+
+/// 整数常量既可以按有符号也可以按无符号写,例如 -1 和 0xffffffff 都是i32的-1
+pub fn parse_int(s:&str,bits:u32) -> Option<i64>{
+    let (neg,rest) = split_sign(s);
+    let n = parse_uint(rest)?;
+    let max = if bits == 64 { u64::MAX } else { (1u64 << bits) - 1 };
+    if neg {
+        if n > (1u64 << (bits - 1)) {
+            return None;
+        }
+        Some((n as i64).wrapping_neg())
+    } else if n > max {
+        None
+    } else {
+        Some(n as i64)
+    }
+}
+
+pub fn parse_i32(s:&str) -> Option<i32>{
+    parse_int(s,32).map(|n|n as i32)
+}
+
+pub fn parse_i64(s:&str) -> Option<i64>{
+    parse_int(s,64)
+}
+
+/// 乘以2的e次方,分段乘避免中间结果溢出
+fn ldexp(mut x:f64,mut e:i64) -> f64{
+    while e > 1000 {
+        x *= 2f64.powi(1000);
+        e -= 1000;
+    }
+    while e < -1000 {
+        x *= 2f64.powi(-1000);
+        e += 1000;
+    }
+    x * 2f64.powi(e as i32)
+}
+
+/// 十六进制浮点数 例如 0x1.8p3,s不带0x前缀
+fn parse_hex_float(s:&str) -> Option<f64>{
+    let s = strip_underscores(s)?;
+    let (mantissa,exp) = match s.find(['p','P']) {
+        Some(i) => {
+            let (neg,e) = split_sign(&s[i + 1..]);
+            let e = parse_digits(e,10)? as i64;
+            (&s[..i],if neg { -e } else { e })
+        }
+        None => (&s[..],0),
+    };
+    let (int,frac) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i],&mantissa[i + 1..]),
+        None => (mantissa,""),
+    };
+    if int.is_empty() {
+        return None;
+    }
+    let mut m = 0u64;
+    let mut e = exp;
+    let mut sticky = false;
+    for (i,c) in int.chars().chain(frac.chars()).enumerate() {
+        let d = c.to_digit(16)? as u64;
+        if m >> 56 == 0 {
+            m = m * 16 + d;
+            if i >= int.len() {
+                e -= 4;
+            }
+        } else {
+            sticky |= d != 0;
+            if i < int.len() {
+                e += 4;
+            }
+        }
+    }
+    // 丢掉的低位只影响舍入,补一个最低位
+    if sticky {
+        m |= 1;
+    }
+    Some(ldexp(m as f64,e))
+}
+
+fn parse_float(s:&str) -> Option<(bool,Result<f64,u64>)>{
+    let (neg,rest) = split_sign(s);
+    if rest == "inf" {
+        return Some((neg,Ok(f64::INFINITY)));
+    }
+    if rest == "nan" {
+        return Some((neg,Err(0)));
+    }
+    if let Some(payload) = rest.strip_prefix("nan:0x") {
+        let n = parse_digits(payload,16)?;
+        if n == 0 {
+            return None;
+        }
+        return Some((neg,Err(n)));
+    }
+    let v = if let Some(hex) = rest.strip_prefix("0x") {
+        parse_hex_float(hex)?
+    } else {
+        if !rest.starts_with(|c:char|c.is_ascii_digit()) {
+            return None;
+        }
+        strip_underscores(rest)?.parse::<f64>().ok()?
+    };
+    Some((neg,Ok(v)))
+}
+
+pub fn parse_f32(s:&str) -> Option<f32>{
+    let (neg,rest) = split_sign(s);
+    // 十进制直接按f32解析,避免先转f64再转f32的两次舍入
+    if rest.starts_with(|c:char|c.is_ascii_digit()) && !rest.starts_with("0x") {
+        let v = strip_underscores(rest)?.parse::<f32>().ok()?;
+        if v.is_infinite() {
+            return None;
+        }
+        return Some(if neg { -v } else { v });
+    }
+    let sign = if neg { 0x8000_0000u32 } else { 0 };
+    match parse_float(s)? {
+        (_,Ok(v)) => {
+            let v = v as f32;
+            if v.is_infinite() && !rest.ends_with("inf") {
+                return None;
+            }
+            Some(if neg { -v } else { v })
+        }
+        (_,Err(0)) => Some(f32::from_bits(sign | 0x7fc0_0000)),
+        (_,Err(payload)) => {
+            if payload >= 1 << 23 {
+                return None;
+            }
+            Some(f32::from_bits(sign | 0x7f80_0000 | payload as u32))
+        }
+    }
+}
+
+pub fn parse_f64(s:&str) -> Option<f64>{
+    let (neg,rest) = split_sign(s);
+    let sign = if neg { 0x8000_0000_0000_0000u64 } else { 0 };
+    match parse_float(s)? {
+        (_,Ok(v)) => {
+            if v.is_infinite() && !rest.ends_with("inf") {
+                return None;
+            }
+            Some(if neg { -v } else { v })
+        }
+        (_,Err(0)) => Some(f64::from_bits(sign | 0x7ff8_0000_0000_0000)),
+        (_,Err(payload)) => {
+            if payload >= 1 << 52 {
+                return None;
+            }
+            Some(f64::from_bits(sign | 0x7ff0_0000_0000_0000 | payload))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::text::number::*;
+
+    #[test]
+    fn test1(){
+        assert_eq!(parse_u32("0x10"),Some(16));
+        assert_eq!(parse_u32("1_000"),Some(1000));
+        assert_eq!(parse_u32("4294967296"),None);
+        assert_eq!(parse_i32("-1"),Some(-1));
+        assert_eq!(parse_i32("0xffffffff"),Some(-1));
+        assert_eq!(parse_i32("-2147483648"),Some(i32::MIN));
+        assert_eq!(parse_i32("-2147483649"),None);
+        assert_eq!(parse_i64("-0x8000000000000000"),Some(i64::MIN));
+        assert_eq!(parse_i64("18446744073709551615"),Some(-1));
+        assert_eq!(parse_i32("1__0"),None);
+
+        assert_eq!(parse_f32("1.5"),Some(1.5));
+        assert_eq!(parse_f32("-0x1.8p3"),Some(-12.0));
+        assert_eq!(parse_f32("0x1p-149"),Some(f32::from_bits(1)));
+        assert_eq!(parse_f64("1e10"),Some(1e10));
+        assert_eq!(parse_f64("0x1.fffffffffffffp1023"),Some(f64::MAX));
+        assert_eq!(parse_f64("-inf"),Some(f64::NEG_INFINITY));
+        assert_eq!(parse_f32("nan").map(|v|v.to_bits()),Some(0x7fc0_0000));
+        assert_eq!(parse_f32("-nan:0x1").map(|v|v.to_bits()),Some(0xff80_0001));
+        assert_eq!(parse_f64("nan:0x8000000000000").map(|v|v.to_bits()),Some(0x7ff8_0000_0000_0000));
+        assert_eq!(parse_f32("1e40"),None);
+        assert_eq!(parse_f64("abc"),None);
+    }
+}
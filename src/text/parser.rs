@@ -0,0 +1,1448 @@
+use crate::binary::instruction::{ArgsEnum, BlockArgs, BrTableArgs, CallIndirectArgs, Catch, Expr, IfArgs, Instruction, MemArg, PrefixArgs, SimdArgs, AtomicArgs, TryTableArgs, CATCH, CATCH_REF, CATCH_ALL, CATCH_ALL_REF};
+use crate::binary::module::{self, BlockType, Code, Data, Elem, Export, ExportDesc, FuncType, GlobalSec, GlobalType, Import, ImportDesc, Limits, Locals, Module, TableType, TagType};
+use crate::binary::{atomic, opcodes};
+use crate::binary::simd::{self, SimdImm};
+use crate::common::common_error::TextError;
+use crate::text::lexer::{Pos, SExpr, Token};
+use crate::text::number;
+use std::collections::HashMap;
+
+pub type TextResult<T> = Result<T,TextError>;
+
+/// 列表元素的游标
+/// end 是列表本身的位置,元素不够时用它报错
+struct Items<'a>{
+    items:&'a [SExpr],
+    pos:usize,
+    end:Pos,
+}
+
+impl<'a> Items<'a>{
+    fn of(e:&'a SExpr) -> TextResult<Items<'a>>{
+        match e {
+            SExpr::List(items,pos) => Ok(Items{ items, pos: 0, end: *pos }),
+            e => Err(e.pos().err("expected '('".to_string())),
+        }
+    }
+
+    fn is_empty(&self) -> bool{
+        self.pos >= self.items.len()
+    }
+
+    fn peek(&self) -> Option<&'a SExpr>{
+        self.items.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a SExpr>{
+        let e = self.items.get(self.pos);
+        self.pos += 1;
+        e
+    }
+
+    /// 当前位置,读完了就用列表的位置
+    fn cur_pos(&self) -> Pos{
+        self.peek().map(|e|e.pos()).unwrap_or(self.end)
+    }
+
+    fn err<T>(&self,message:&str) -> TextResult<T>{
+        Err(self.cur_pos().err(message.to_string()))
+    }
+
+    fn peek_atom(&self) -> Option<&'a str>{
+        match self.peek() {
+            Some(SExpr::Atom(Token::Atom(s),_)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// 下一个元素是列表时返回它的关键字
+    fn peek_list_kw(&self) -> Option<&'a str>{
+        match self.peek() {
+            Some(SExpr::List(items,_)) => match items.first() {
+                Some(SExpr::Atom(Token::Atom(s),_)) => Some(s),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn atom(&mut self) -> TextResult<(&'a str,Pos)>{
+        match self.peek() {
+            Some(SExpr::Atom(Token::Atom(s),pos)) => {
+                self.pos += 1;
+                Ok((s,*pos))
+            }
+            _ => self.err("expected keyword"),
+        }
+    }
+
+    fn keyword(&mut self,kw:&str) -> TextResult<()>{
+        if self.peek_atom() == Some(kw) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.err(&format!("expected '{}'",kw))
+        }
+    }
+
+    fn opt_id(&mut self) -> Option<String>{
+        match self.peek() {
+            Some(SExpr::Atom(Token::Id(id),_)) => {
+                self.pos += 1;
+                Some(id.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> TextResult<Vec<u8>>{
+        match self.peek() {
+            Some(SExpr::Atom(Token::Str(s),_)) => {
+                self.pos += 1;
+                Ok(s.clone())
+            }
+            _ => self.err("expected string"),
+        }
+    }
+
+    fn name(&mut self) -> TextResult<String>{
+        let pos = self.cur_pos();
+        String::from_utf8(self.string()?).map_err(|_|pos.err("malformed UTF-8 encoding".to_string()))
+    }
+
+    fn list(&mut self) -> TextResult<Items<'a>>{
+        match self.peek() {
+            Some(e@SExpr::List(..)) => {
+                self.pos += 1;
+                Items::of(e)
+            }
+            _ => self.err("expected '('"),
+        }
+    }
+
+    /// 往后第n个元素是不是索引,数字或者$名字
+    fn is_idx_at(&self,n:usize) -> bool{
+        match self.items.get(self.pos + n) {
+            Some(SExpr::Atom(Token::Id(_),_)) => true,
+            Some(SExpr::Atom(Token::Atom(s),_)) => number::parse_u32(s).is_some(),
+            _ => false,
+        }
+    }
+
+    /// 从往后第n个元素开始连续的offset=/align=个数
+    fn mem_arg_len(&self,n:usize) -> usize{
+        self.items[(self.pos + n).min(self.items.len())..].iter().take_while(|e|matches!(e,
+            SExpr::Atom(Token::Atom(s),_) if s.starts_with("offset=") || s.starts_with("align="))).count()
+    }
+
+    fn u32(&mut self) -> TextResult<u32>{
+        let (s,pos) = self.atom()?;
+        number::parse_u32(s).ok_or_else(||pos.err(format!("invalid number {}",s)))
+    }
+
+    fn u64(&mut self) -> TextResult<u64>{
+        let (s,pos) = self.atom()?;
+        number::parse_uint(s).ok_or_else(||pos.err(format!("invalid number {}",s)))
+    }
+
+    fn expect_end(&self) -> TextResult<()>{
+        if self.is_empty() { Ok(()) } else { self.err("unexpected token") }
+    }
+}
+
+/// 一个索引空间里的名字
+#[derive(Default)]
+struct Space{
+    names:HashMap<String,u32>,
+    count:u32,
+}
+
+impl Space{
+    fn define(&mut self,id:Option<String>,pos:Pos) -> TextResult<u32>{
+        let idx = self.count;
+        if let Some(id) = id {
+            if self.names.insert(id.clone(),idx).is_some() {
+                return Err(pos.err(format!("duplicate identifier ${}",id)));
+            }
+        }
+        self.count += 1;
+        Ok(idx)
+    }
+
+    /// 数字索引或者$名字
+    fn resolve(&self,it:&mut Items) -> TextResult<u32>{
+        let pos = it.cur_pos();
+        if let Some(id) = it.opt_id() {
+            return self.names.get(&id).copied().ok_or_else(||pos.err(format!("unknown identifier ${}",id)));
+        }
+        it.u32()
+    }
+
+    /// 可以省略的索引,省略时是0
+    fn resolve_opt(&self,it:&mut Items) -> TextResult<u32>{
+        if it.is_idx_at(0) { self.resolve(it) } else { Ok(0) }
+    }
+}
+
+/// 解析函数体时的上下文
+/// labels 从外到内,br用的深度从最内层往外数
+#[derive(Default)]
+struct FuncCtx{
+    locals:Space,
+    labels:Vec<Option<String>>,
+}
+
+impl FuncCtx{
+    fn label(&self,it:&mut Items) -> TextResult<u32>{
+        let pos = it.cur_pos();
+        if let Some(id) = it.opt_id() {
+            return self.labels.iter().rev().position(|l|l.as_ref() == Some(&id))
+                .map(|depth|depth as u32)
+                .ok_or_else(||pos.err(format!("unknown label ${}",id)));
+        }
+        it.u32()
+    }
+}
+
+/// 文本格式解析器
+/// 先扫描一遍收集所有名字和显式的类型定义,第二遍再生成各个段,这样可以引用后面定义的函数
+/// 隐式的函数类型按出现顺序追加在显式类型后面
+#[derive(Default)]
+pub struct ModuleParser{
+    m:Option<Module>,
+    types:Space,
+    funcs:Space,
+    tables:Space,
+    mems:Space,
+    globals:Space,
+    tags:Space,
+    datas:Space,
+    /// 用到memory.init/data.drop时要生成数据计数段
+    uses_data_count:bool,
+    has_def:bool,
+    func_idx:u32,
+    table_idx:u32,
+    mem_idx:u32,
+    global_idx:u32,
+    tag_idx:u32,
+}
+
+fn val_type(s:&str) -> Option<u8>{
+    match s {
+        "i32" => Some(module::VAL_TYPE_I32),
+        "i64" => Some(module::VAL_TYPE_I64),
+        "f32" => Some(module::VAL_TYPE_F32),
+        "f64" => Some(module::VAL_TYPE_F64),
+        "v128" => Some(module::VAL_TYPE_V128),
+        "funcref" => Some(module::FUNC_REF),
+        "externref" => Some(module::EXTERN_REF),
+        "exnref" => Some(module::EXN_REF),
+        _ => None,
+    }
+}
+
+/// 内存的地址类型,写了i64就是memory64
+fn addr_type(it:&mut Items) -> bool{
+    match it.peek_atom() {
+        Some("i64") => {it.next(); true},
+        Some("i32") => {it.next(); false},
+        _ => false,
+    }
+}
+
+fn lane_idx(it:&mut Items) -> TextResult<u8>{
+    let (s,pos) = it.atom()?;
+    number::parse_u32(s).filter(|n|*n < 256).map(|n|n as u8)
+        .ok_or_else(||pos.err(format!("invalid lane index {}",s)))
+}
+
+/// v128.const的形状和各个车道,按小端拼成128位
+fn v128_const(it:&mut Items) -> TextResult<u128>{
+    let (shape,pos) = it.atom()?;
+    let (bits,float) = match shape {
+        "i8x16" => (8,false),
+        "i16x8" => (16,false),
+        "i32x4" => (32,false),
+        "i64x2" => (64,false),
+        "f32x4" => (32,true),
+        "f64x2" => (64,true),
+        _ => return Err(pos.err(format!("unknown vector shape {}",shape))),
+    };
+    let mut v:u128 = 0;
+    for i in 0..128 / bits {
+        let (s,pos) = it.atom()?;
+        let lane = match (float,bits) {
+            (true,32) => number::parse_f32(s).map(|f|f.to_bits() as u64),
+            (true,_) => number::parse_f64(s).map(|f|f.to_bits()),
+            (false,_) => number::parse_int(s,bits).map(|n|n as u64),
+        }.ok_or_else(||pos.err(format!("invalid {} lane {}",shape,s)))?;
+        let mask = if bits == 64 { u64::MAX } else { (1u64 << bits) - 1 };
+        v |= ((lane & mask) as u128) << (i * bits);
+    }
+    Ok(v)
+}
+
+fn try_table(bt:BlockType,catches:Vec<Catch>,instrs:Vec<Instruction>) -> Instruction{
+    let args = TryTableArgs{ bt: Some(bt), catches: Some(catches), instrs: Some(instrs) };
+    Instruction{ opcode: Some(opcodes::TryTable), sub: 0, args: Some(ArgsEnum::TryTableArgs(Box::new(args))) }
+}
+
+fn ref_type(s:&str) -> Option<u8>{
+    match s {
+        "funcref"|"anyfunc" => Some(module::FUNC_REF),
+        "externref" => Some(module::EXTERN_REF),
+        "exnref" => Some(module::EXN_REF),
+        _ => None,
+    }
+}
+
+/// ref.null后面的堆类型
+fn heap_type(s:&str) -> Option<u8>{
+    match s {
+        "func" => Some(module::FUNC_REF),
+        "extern" => Some(module::EXTERN_REF),
+        "exn" => Some(module::EXN_REF),
+        _ => None,
+    }
+}
+
+/// 单字节指令的指令名到操作码,块指令和else/end单独处理
+fn opcode_by_name(name:&str) -> Option<u8>{
+    opcodes::by_name(name).filter(|op|!op.is_prefixed()).map(|op|op.prefix)
+}
+
+/// load/store的自然对齐,按2的幂次
+fn natural_align(opcode:u8) -> u32{
+    match opcode {
+        opcodes::I32Load8S|opcodes::I32Load8U|opcodes::I64Load8S|opcodes::I64Load8U|
+        opcodes::I32Store8|opcodes::I64Store8 => 0,
+        opcodes::I32Load16S|opcodes::I32Load16U|opcodes::I64Load16S|opcodes::I64Load16U|
+        opcodes::I32Store16|opcodes::I64Store16 => 1,
+        opcodes::I32Load|opcodes::F32Load|opcodes::I64Load32S|opcodes::I64Load32U|
+        opcodes::I32Store|opcodes::F32Store|opcodes::I64Store32 => 2,
+        _ => 3,
+    }
+}
+
+pub fn parse(src:&str) -> TextResult<Module>{
+    if opcodes::OPCODE_MAP.get().is_none() {
+        opcodes::init();
+    }
+    let exprs = crate::text::lexer::parse_sexprs(src)?;
+    let mut parser = ModuleParser::default();
+    // 可以是(module ...),也可以直接写一串字段
+    let fields:Vec<&SExpr> = match exprs.as_slice() {
+        [e@SExpr::List(items,_)] if matches!(items.first(),Some(SExpr::Atom(Token::Atom(kw),_)) if kw == "module") => {
+            let mut it = Items::of(e)?;
+            it.keyword("module")?;
+            it.opt_id();
+            it.items[it.pos..].iter().collect()
+        }
+        _ => exprs.iter().collect(),
+    };
+    parser.parse_module(&fields)
+}
+
+impl ModuleParser{
+
+    fn m(&mut self) -> &mut Module{
+        self.m.as_mut().unwrap()
+    }
+
+    pub fn parse_module(&mut self,fields:&[&SExpr]) -> TextResult<Module>{
+        let mut m = Module::new();
+        m.magic = Some(module::MAGIC_NUMBER);
+        m.version = Some(module::VERSION);
+        self.m = Some(m);
+        for f in fields {
+            self.collect(f)?;
+        }
+        for f in fields {
+            self.field(f)?;
+        }
+        if self.uses_data_count {
+            let n = self.datas.count;
+            self.m().data_count_sec = Some(n);
+        }
+        Ok(self.m.take().unwrap())
+    }
+
+    fn space(&mut self,kind:&str) -> Option<&mut Space>{
+        match kind {
+            "func" => Some(&mut self.funcs),
+            "table" => Some(&mut self.tables),
+            "memory" => Some(&mut self.mems),
+            "global" => Some(&mut self.globals),
+            "tag" => Some(&mut self.tags),
+            _ => None,
+        }
+    }
+
+    /// 导入必须在所有函数/表/内存/全局变量/标签定义之前
+    fn check_import_order(&self,pos:Pos) -> TextResult<()>{
+        if self.has_def {
+            return Err(pos.err("import after function, table, memory, global or tag definition".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 第一遍: 登记名字,解析显式类型
+    fn collect(&mut self,field:&SExpr) -> TextResult<()>{
+        let mut it = Items::of(field)?;
+        let (kw,pos) = it.atom()?;
+        match kw {
+            "type" => {
+                let id = it.opt_id();
+                let mut f = it.list()?;
+                f.keyword("func")?;
+                let (params,_) = self.params(&mut f,"param")?;
+                let (results,_) = self.params(&mut f,"result")?;
+                f.expect_end()?;
+                it.expect_end()?;
+                self.types.define(id,pos)?;
+                self.m().type_sec.get_or_insert_with(Vec::new).push(FuncType{
+                    tag: Some(module::FT_TAG),
+                    param_types: Some(params),
+                    result_types: Some(results)
+                });
+            }
+            "import" => {
+                it.string()?;
+                it.string()?;
+                let mut desc = it.list()?;
+                let (kind,dpos) = desc.atom()?;
+                let id = desc.opt_id();
+                self.check_import_order(pos)?;
+                match self.space(kind) {
+                    Some(space) => {space.define(id,dpos)?;}
+                    None => return Err(dpos.err(format!("unknown import kind {}",kind))),
+                }
+            }
+            "func"|"table"|"memory"|"global"|"tag" => {
+                let id = it.opt_id();
+                while it.peek_list_kw() == Some("export") {
+                    it.next();
+                }
+                if it.peek_list_kw() == Some("import") {
+                    self.check_import_order(pos)?;
+                } else {
+                    self.has_def = true;
+                }
+                // (memory (data ...)) 里的数据段也占一个数据段索引
+                if kw == "memory" && it.peek_list_kw() == Some("data") {
+                    self.datas.define(None,pos)?;
+                }
+                self.space(kw).unwrap().define(id,pos)?;
+            }
+            "data" => {
+                let id = it.opt_id();
+                self.datas.define(id,pos)?;
+            }
+            "export"|"start"|"elem" => {}
+            _ => return Err(pos.err(format!("unknown module field {}",kw))),
+        }
+        Ok(())
+    }
+
+    /// 第二遍: 生成各个段
+    fn field(&mut self,field:&SExpr) -> TextResult<()>{
+        let mut it = Items::of(field)?;
+        let (kw,_) = it.atom()?;
+        match kw {
+            "type" => {}
+            "import" => {
+                let module_name = it.name()?;
+                let name = it.name()?;
+                let mut desc = it.list()?;
+                let (kind,_) = desc.atom()?;
+                desc.opt_id();
+                let desc = self.import_desc(kind,&mut desc)?;
+                it.expect_end()?;
+                self.push_import(module_name,name,desc);
+            }
+            "func" => self.func(&mut it)?,
+            "table" => self.table(&mut it)?,
+            "memory" => self.memory(&mut it)?,
+            "global" => self.global(&mut it)?,
+            "tag" => self.tag(&mut it)?,
+            "export" => {
+                let name = it.name()?;
+                let mut desc = it.list()?;
+                let (kind,pos) = desc.atom()?;
+                let (tag,space) = match kind {
+                    "func" => (module::EXPORT_TAG_FUNC,&self.funcs),
+                    "table" => (module::EXPORT_TAG_TABLE,&self.tables),
+                    "memory" => (module::EXPORT_TAG_MEM,&self.mems),
+                    "global" => (module::EXPORT_TAG_GLOBAL,&self.globals),
+                    "tag" => (module::EXPORT_TAG_TAG,&self.tags),
+                    _ => return Err(pos.err(format!("unknown export kind {}",kind))),
+                };
+                let idx = space.resolve(&mut desc)?;
+                desc.expect_end()?;
+                it.expect_end()?;
+                self.push_export(name,tag,idx);
+            }
+            "start" => {
+                let idx = self.funcs.resolve(&mut it)?;
+                it.expect_end()?;
+                self.m().start_sec = Some(idx);
+            }
+            "elem" => self.elem(&mut it)?,
+            "data" => self.data(&mut it)?,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn push_import(&mut self,module_name:String,name:String,desc:ImportDesc){
+        self.m().import_sec.get_or_insert_with(Vec::new).push(Import{
+            module: Some(module_name),
+            name: Some(name),
+            import_desc: Some(desc)
+        });
+    }
+
+    fn push_export(&mut self,name:String,tag:u8,idx:u32){
+        self.m().export_sec.get_or_insert_with(Vec::new).push(Export{
+            name: Some(name),
+            desc: Some(ExportDesc{ tag: Some(tag), idx: Some(idx) })
+        });
+    }
+
+    /// 内联的(export "name")和(import "m" "n"),返回导入的模块名和名字
+    fn inline_export_import(&mut self,it:&mut Items,tag:u8,idx:u32) -> TextResult<Option<(String,String)>>{
+        while it.peek_list_kw() == Some("export") {
+            let mut e = it.list()?;
+            e.keyword("export")?;
+            let name = e.name()?;
+            e.expect_end()?;
+            self.push_export(name,tag,idx);
+        }
+        if it.peek_list_kw() == Some("import") {
+            let mut i = it.list()?;
+            i.keyword("import")?;
+            let r = (i.name()?,i.name()?);
+            i.expect_end()?;
+            return Ok(Some(r));
+        }
+        Ok(None)
+    }
+
+    fn import_desc(&mut self,kind:&str,it:&mut Items) -> TextResult<ImportDesc>{
+        let mut desc = ImportDesc{ tag: None, fun_type: None, table: None, mem: None, global: None, tag_type: None };
+        match kind {
+            "func" => {
+                desc.tag = Some(module::IMPORT_TAG_FUNC);
+                desc.fun_type = Some(self.type_use(it,None)?);
+                self.func_idx += 1;
+            }
+            "table" => {
+                desc.tag = Some(module::IMPORT_TAG_TABLE);
+                desc.table = Some(self.table_type(it)?);
+                self.table_idx += 1;
+            }
+            "memory" => {
+                desc.tag = Some(module::IMPORT_TAG_MEM);
+                desc.mem = Some(self.mem_type(it)?);
+                self.mem_idx += 1;
+            }
+            "global" => {
+                desc.tag = Some(module::IMPORT_TAG_GLOBAL);
+                desc.global = Some(self.global_type(it)?);
+                self.global_idx += 1;
+            }
+            "tag" => {
+                desc.tag = Some(module::IMPORT_TAG_TAG);
+                desc.tag_type = Some(TagType::new(self.type_use(it,None)?));
+                self.tag_idx += 1;
+            }
+            _ => return it.err(&format!("unknown import kind {}",kind)),
+        }
+        it.expect_end()?;
+        Ok(desc)
+    }
+
+    fn func(&mut self,it:&mut Items) -> TextResult<()>{
+        it.opt_id();
+        let idx = self.func_idx;
+        if let Some((module_name,name)) = self.inline_export_import(it,module::EXPORT_TAG_FUNC,idx)? {
+            let desc = self.import_desc("func",it)?;
+            self.push_import(module_name,name,desc);
+            return Ok(());
+        }
+        self.func_idx += 1;
+        let mut ctx = FuncCtx::default();
+        let type_idx = self.type_use(it,Some(&mut ctx.locals))?;
+        let mut locals:Vec<Locals> = Vec::new();
+        while it.peek_list_kw() == Some("local") {
+            let mut l = it.list()?;
+            l.keyword("local")?;
+            let pos = l.cur_pos();
+            let mut types = vec![];
+            if let Some(id) = l.opt_id() {
+                types.push(self.val_type(&mut l)?);
+                ctx.locals.define(Some(id),pos)?;
+            } else {
+                while !l.is_empty() {
+                    types.push(self.val_type(&mut l)?);
+                    ctx.locals.define(None,pos)?;
+                }
+            }
+            l.expect_end()?;
+            for t in types {
+                match locals.last_mut() {
+                    Some(last) if last.ty == Some(t) => last.n = Some(last.n.unwrap() + 1),
+                    _ => locals.push(Locals{ n: Some(1), ty: Some(t) }),
+                }
+            }
+        }
+        let mut expr = vec![];
+        self.instrs(it,&mut ctx,&mut expr)?;
+        it.expect_end()?;
+        let m = self.m();
+        m.func_sec.get_or_insert_with(Vec::new).push(type_idx);
+        m.code_sec.get_or_insert_with(Vec::new).push(Code{ locals: Some(locals), expr: Some(expr), lazy: None });
+        Ok(())
+    }
+
+    fn table(&mut self,it:&mut Items) -> TextResult<()>{
+        it.opt_id();
+        let idx = self.table_idx;
+        if let Some((module_name,name)) = self.inline_export_import(it,module::EXPORT_TAG_TABLE,idx)? {
+            let desc = self.import_desc("table",it)?;
+            self.push_import(module_name,name,desc);
+            return Ok(());
+        }
+        self.table_idx += 1;
+        // (table funcref (elem $f1 $f2)) 的缩写,大小就是元素个数
+        if it.peek_atom() == Some("funcref") || it.peek_atom() == Some("anyfunc") {
+            it.next();
+            let mut e = it.list()?;
+            e.keyword("elem")?;
+            let mut init = vec![];
+            while !e.is_empty() {
+                init.push(self.funcs.resolve(&mut e)?);
+            }
+            it.expect_end()?;
+            let n = init.len() as u64;
+            let m = self.m();
+            m.table_sec.get_or_insert_with(Vec::new).push(TableType{
+                elem_type: Some(module::FUNC_REF),
+                limits: Some(Limits{ tag: Some(1), min: Some(n), max: Some(n) })
+            });
+            m.elem_sec.get_or_insert_with(Vec::new).push(Elem::new(
+                idx,
+                vec![Instruction{ opcode: Some(opcodes::I32Const), sub: 0, args: Some(ArgsEnum::I32(0)) }],
+                init
+            ));
+            return Ok(());
+        }
+        let t = self.table_type(it)?;
+        it.expect_end()?;
+        self.m().table_sec.get_or_insert_with(Vec::new).push(t);
+        Ok(())
+    }
+
+    fn memory(&mut self,it:&mut Items) -> TextResult<()>{
+        it.opt_id();
+        let idx = self.mem_idx;
+        if let Some((module_name,name)) = self.inline_export_import(it,module::EXPORT_TAG_MEM,idx)? {
+            let desc = self.import_desc("memory",it)?;
+            self.push_import(module_name,name,desc);
+            return Ok(());
+        }
+        self.mem_idx += 1;
+        // (memory i64 (data "...")) 的缩写,页数按数据长度向上取整
+        let is_64 = addr_type(it);
+        if it.peek_list_kw() == Some("data") {
+            let tag = if is_64 { module::LIMITS_HAS_MAX | module::LIMITS_MEM64 } else { module::LIMITS_HAS_MAX };
+            let mut d = it.list()?;
+            d.keyword("data")?;
+            let mut init = vec![];
+            while !d.is_empty() {
+                init.extend(d.string()?);
+            }
+            it.expect_end()?;
+            let pages = init.len().div_ceil(module::PAGE_SIZE) as u64;
+            let offset = if is_64 {
+                Instruction{ opcode: Some(opcodes::I64Const), sub: 0, args: Some(ArgsEnum::I64(0)) }
+            } else {
+                Instruction{ opcode: Some(opcodes::I32Const), sub: 0, args: Some(ArgsEnum::I32(0)) }
+            };
+            let m = self.m();
+            m.mem_sec.get_or_insert_with(Vec::new).push(Limits{ tag: Some(tag), min: Some(pages), max: Some(pages) });
+            m.data_sec.get_or_insert_with(Vec::new).push(Data{
+                mem: Some(idx),
+                offset: Some(vec![offset]),
+                init: Some(init)
+            });
+            return Ok(());
+        }
+        let limits = self.limits(it,is_64)?;
+        it.expect_end()?;
+        self.m().mem_sec.get_or_insert_with(Vec::new).push(limits);
+        Ok(())
+    }
+
+    fn global(&mut self,it:&mut Items) -> TextResult<()>{
+        it.opt_id();
+        let idx = self.global_idx;
+        if let Some((module_name,name)) = self.inline_export_import(it,module::EXPORT_TAG_GLOBAL,idx)? {
+            let desc = self.import_desc("global",it)?;
+            self.push_import(module_name,name,desc);
+            return Ok(());
+        }
+        self.global_idx += 1;
+        let ty = self.global_type(it)?;
+        let mut init = vec![];
+        self.instrs(it,&mut FuncCtx::default(),&mut init)?;
+        it.expect_end()?;
+        self.m().global_sec.get_or_insert_with(Vec::new).push(GlobalSec{ ty: Some(ty), init: Some(init) });
+        Ok(())
+    }
+
+    /// (tag $e (param i32)),参数就是异常携带的值
+    fn tag(&mut self,it:&mut Items) -> TextResult<()>{
+        it.opt_id();
+        let idx = self.tag_idx;
+        if let Some((module_name,name)) = self.inline_export_import(it,module::EXPORT_TAG_TAG,idx)? {
+            let desc = self.import_desc("tag",it)?;
+            self.push_import(module_name,name,desc);
+            return Ok(());
+        }
+        self.tag_idx += 1;
+        let type_idx = self.type_use(it,None)?;
+        it.expect_end()?;
+        self.m().tag_sec.get_or_insert_with(Vec::new).push(TagType::new(type_idx));
+        Ok(())
+    }
+
+    /// 偏移表达式 (offset instr*) 或者一条折叠指令
+    fn offset_expr(&mut self,it:&mut Items) -> TextResult<Expr>{
+        let mut expr = vec![];
+        if it.peek_list_kw() == Some("offset") {
+            let mut o = it.list()?;
+            o.keyword("offset")?;
+            self.instrs(&mut o,&mut FuncCtx::default(),&mut expr)?;
+            o.expect_end()?;
+        } else {
+            let e = match it.peek() {
+                Some(e@SExpr::List(..)) => e,
+                _ => return it.err("expected offset expression"),
+            };
+            it.next();
+            self.folded(e,&mut FuncCtx::default(),&mut expr)?;
+        }
+        Ok(expr)
+    }
+
+    /// 主动段 (elem (table x)? offset elemlist),表索引也可以直接写数字
+    /// 被动段 (elem elemlist),声明段 (elem declare elemlist)
+    /// elemlist 是 func? funcidx* 或者 reftype 后面跟 (item expr) / 折叠的表达式
+    fn elem(&mut self,it:&mut Items) -> TextResult<()>{
+        it.opt_id();
+        let mut elem = Elem{ table: None, offset: None, init: None, elem_type: None, exprs: None, declarative: false };
+        if it.peek_atom() == Some("declare") {
+            it.next();
+            elem.declarative = true;
+        } else {
+            let mut table = None;
+            if it.peek_list_kw() == Some("table") {
+                let mut t = it.list()?;
+                t.keyword("table")?;
+                table = Some(self.tables.resolve(&mut t)?);
+                t.expect_end()?;
+            } else if matches!(it.peek(),Some(SExpr::Atom(Token::Id(_),_)))
+                || it.peek_atom().map(|s|number::parse_u32(s).is_some()).unwrap_or(false) {
+                table = Some(self.tables.resolve(it)?);
+            }
+            if table.is_some() || matches!(it.peek(),Some(SExpr::List(..))) {
+                elem.table = Some(table.unwrap_or(0));
+                elem.offset = Some(self.offset_expr(it)?);
+            }
+        }
+        let list_type = match it.peek_atom() {
+            Some("func") => {
+                it.next();
+                None
+            }
+            Some(s) => match ref_type(s) {
+                Some(t) => {
+                    it.next();
+                    Some(t)
+                }
+                None => None,
+            },
+            None => None,
+        };
+        let elem_type = list_type.unwrap_or(module::FUNC_REF);
+        // 表0的funcref主动段在二进制里可以省略元素类型
+        if !elem.is_active() || elem.table != Some(0) || elem_type != module::FUNC_REF {
+            elem.elem_type = Some(elem_type);
+        }
+        match list_type {
+            Some(_) => {
+                let mut exprs = vec![];
+                while !it.is_empty() {
+                    let mut expr = vec![];
+                    if it.peek_list_kw() == Some("item") {
+                        let mut item = it.list()?;
+                        item.keyword("item")?;
+                        self.instrs(&mut item,&mut FuncCtx::default(),&mut expr)?;
+                        item.expect_end()?;
+                    } else {
+                        let e = match it.next() {
+                            Some(e@SExpr::List(..)) => e,
+                            _ => return it.err("expected element expression"),
+                        };
+                        self.folded(e,&mut FuncCtx::default(),&mut expr)?;
+                    }
+                    exprs.push(expr);
+                }
+                elem.exprs = Some(exprs);
+            }
+            None => {
+                let mut init = vec![];
+                while !it.is_empty() {
+                    init.push(self.funcs.resolve(it)?);
+                }
+                elem.init = Some(init);
+            }
+        }
+        self.m().elem_sec.get_or_insert_with(Vec::new).push(elem);
+        Ok(())
+    }
+
+    /// (data (memory x)? offset string*),内存索引也可以直接写数字
+    /// 没有内存和偏移的是被动段 (data string*)
+    fn data(&mut self,it:&mut Items) -> TextResult<()>{
+        it.opt_id();
+        let mut mem = None;
+        if it.peek_list_kw() == Some("memory") {
+            let mut t = it.list()?;
+            t.keyword("memory")?;
+            mem = Some(self.mems.resolve(&mut t)?);
+            t.expect_end()?;
+        } else if let Some(SExpr::Atom(Token::Atom(_),_))|Some(SExpr::Atom(Token::Id(_),_)) = it.peek() {
+            mem = Some(self.mems.resolve(it)?);
+        }
+        let offset = match (mem,it.peek()) {
+            (None,None)|(None,Some(SExpr::Atom(Token::Str(_),_))) => None,
+            _ => Some(self.offset_expr(it)?),
+        };
+        let mut init = vec![];
+        while !it.is_empty() {
+            init.extend(it.string()?);
+        }
+        let mem = offset.as_ref().map(|_|mem.unwrap_or(0));
+        self.m().data_sec.get_or_insert_with(Vec::new).push(Data{ mem, offset, init: Some(init) });
+        Ok(())
+    }
+}
+
+/// 类型
+impl ModuleParser{
+
+    fn val_type(&self,it:&mut Items) -> TextResult<u8>{
+        let (s,pos) = it.atom()?;
+        val_type(s).ok_or_else(||pos.err(format!("unknown value type {}",s)))
+    }
+
+    /// 连续的(param ...)或(result ...),locals不为None时登记参数名
+    fn params(&mut self,it:&mut Items,kw:&str) -> TextResult<(Vec<u8>,Vec<Option<String>>)>{
+        let mut types = vec![];
+        let mut names = vec![];
+        while it.peek_list_kw() == Some(kw) {
+            let mut p = it.list()?;
+            p.keyword(kw)?;
+            if kw == "param" {
+                if let Some(id) = p.opt_id() {
+                    types.push(self.val_type(&mut p)?);
+                    names.push(Some(id));
+                    p.expect_end()?;
+                    continue;
+                }
+            }
+            while !p.is_empty() {
+                types.push(self.val_type(&mut p)?);
+                names.push(None);
+            }
+        }
+        Ok((types,names))
+    }
+
+    /// 找一个相同的函数类型,没有就追加一个
+    fn find_or_add_type(&mut self,params:Vec<u8>,results:Vec<u8>) -> u32{
+        let types = self.m().type_sec.get_or_insert_with(Vec::new);
+        let found = types.iter().position(|t|{
+            t.param_types.as_ref() == Some(&params) && t.result_types.as_ref() == Some(&results)
+        });
+        match found {
+            Some(idx) => idx as u32,
+            None => {
+                types.push(FuncType{ tag: Some(module::FT_TAG), param_types: Some(params), result_types: Some(results) });
+                self.types.count += 1;
+                self.types.count - 1
+            }
+        }
+    }
+
+    /// 类型引用 (type x)? (param ...)* (result ...)*
+    fn type_use(&mut self,it:&mut Items,locals:Option<&mut Space>) -> TextResult<u32>{
+        let mut idx = None;
+        if it.peek_list_kw() == Some("type") {
+            let mut t = it.list()?;
+            t.keyword("type")?;
+            idx = Some((self.types.resolve(&mut t)?,t.end));
+            t.expect_end()?;
+        }
+        let pos = it.cur_pos();
+        let (params,names) = self.params(it,"param")?;
+        let (results,_) = self.params(it,"result")?;
+        let idx = match idx {
+            Some((idx,tpos)) => {
+                let ft = self.m().type_sec.as_ref().and_then(|v|v.get(idx as usize)).cloned()
+                    .ok_or_else(||tpos.err(format!("unknown type {}",idx)))?;
+                let declared = (ft.param_types.unwrap_or_default(),ft.result_types.unwrap_or_default());
+                if !params.is_empty() || !results.is_empty() {
+                    if declared != (params.clone(),results) {
+                        return Err(pos.err("inline function type does not match".to_string()));
+                    }
+                } else if let Some(locals) = locals {
+                    for _ in declared.0.iter() {
+                        locals.define(None,pos)?;
+                    }
+                    return Ok(idx);
+                }
+                idx
+            }
+            None => self.find_or_add_type(params,results),
+        };
+        if let Some(locals) = locals {
+            for name in names {
+                locals.define(name,pos)?;
+            }
+        }
+        Ok(idx)
+    }
+
+    /// 块类型,单个结果用简写,其他情况用类型索引
+    fn block_type(&mut self,it:&mut Items) -> TextResult<BlockType>{
+        if it.peek_list_kw() == Some("type") {
+            return Ok(BlockType::TypeIdx(self.type_use(it,None)?));
+        }
+        let (params,_) = self.params(it,"param")?;
+        let (results,_) = self.params(it,"result")?;
+        if !params.is_empty() || results.len() > 1 {
+            return Ok(BlockType::TypeIdx(self.find_or_add_type(params,results)));
+        }
+        Ok(match results.first() {
+            None => BlockType::Empty,
+            Some(&t) => BlockType::Value(t),
+        })
+    }
+
+    /// 内存类型,开头可以写地址类型i32或i64
+    fn mem_type(&mut self,it:&mut Items) -> TextResult<Limits>{
+        let is_64 = addr_type(it);
+        self.limits(it,is_64)
+    }
+
+    /// min max? shared?,memory64的上下限按u64读
+    fn limits(&mut self,it:&mut Items,is_64:bool) -> TextResult<Limits>{
+        let bound = |it:&mut Items| if is_64 { it.u64() } else { it.u32().map(|v|v as u64) };
+        let min = bound(it)?;
+        let tag = if is_64 { module::LIMITS_MEM64 } else { 0 };
+        let mut limits = Limits{ tag: Some(tag), min: Some(min), max: None };
+        if let Some(s) = it.peek_atom() {
+            if number::parse_uint(s).is_some() {
+                limits.tag = Some(tag | module::LIMITS_HAS_MAX);
+                limits.max = Some(bound(it)?);
+            }
+        }
+        if it.peek_atom() == Some("shared") {
+            it.next();
+            limits.tag = limits.tag.map(|t|t | module::LIMITS_SHARED);
+        }
+        Ok(limits)
+    }
+
+    fn table_type(&mut self,it:&mut Items) -> TextResult<TableType>{
+        let limits = self.limits(it,false)?;
+        let (s,pos) = it.atom()?;
+        let elem_type = ref_type(s).ok_or_else(||pos.err(format!("unknown element type {}",s)))?;
+        Ok(TableType{ elem_type: Some(elem_type), limits: Some(limits) })
+    }
+
+    /// 全局变量类型 t 或者 (mut t)
+    fn global_type(&mut self,it:&mut Items) -> TextResult<GlobalType>{
+        if it.peek_list_kw() == Some("mut") {
+            let mut l = it.list()?;
+            l.keyword("mut")?;
+            let t = self.val_type(&mut l)?;
+            l.expect_end()?;
+            return Ok(GlobalType{ val_type: Some(t), m: Some(module::MUT_VAR) });
+        }
+        Ok(GlobalType{ val_type: Some(self.val_type(it)?), m: Some(module::MUT_CONST) })
+    }
+}
+
+/// 指令
+impl ModuleParser{
+
+    /// 读指令直到列表结束或者遇到else/end
+    fn instrs(&mut self,it:&mut Items,f:&mut FuncCtx,out:&mut Vec<Instruction>) -> TextResult<()>{
+        while let Some(e) = it.peek() {
+            match e {
+                SExpr::List(..) => {
+                    it.next();
+                    self.folded(e,f,out)?;
+                }
+                SExpr::Atom(Token::Atom(kw),pos) => {
+                    if kw == "end" || kw == "else" {
+                        return Ok(());
+                    }
+                    it.next();
+                    self.plain(kw,*pos,it,f,out)?;
+                }
+                e => return Err(e.pos().err("unexpected token".to_string())),
+            }
+        }
+        Ok(())
+    }
+
+    /// end/else后面可以再写一遍标签名
+    fn end_label(&mut self,it:&mut Items,label:&Option<String>) -> TextResult<()>{
+        let pos = it.cur_pos();
+        if let Some(id) = it.opt_id() {
+            if Some(&id) != label.as_ref() {
+                return Err(pos.err(format!("mismatching label ${}",id)));
+            }
+        }
+        Ok(())
+    }
+
+    /// 平铺形式的指令
+    fn plain(&mut self,kw:&str,pos:Pos,it:&mut Items,f:&mut FuncCtx,out:&mut Vec<Instruction>) -> TextResult<()>{
+        match kw {
+            "block"|"loop" => {
+                let label = it.opt_id();
+                let bt = self.block_type(it)?;
+                f.labels.push(label.clone());
+                let mut instrs = vec![];
+                self.instrs(it,f,&mut instrs)?;
+                it.keyword("end")?;
+                self.end_label(it,&label)?;
+                f.labels.pop();
+                let opcode = if kw == "block" { opcodes::Block } else { opcodes::Loop };
+                out.push(Instruction{ opcode: Some(opcode), sub: 0, args: Some(ArgsEnum::BlockArgs(BlockArgs{ bt: Some(bt), instrs: Some(instrs) })) });
+            }
+            "if" => {
+                let label = it.opt_id();
+                let bt = self.block_type(it)?;
+                f.labels.push(label.clone());
+                let mut instrs1 = vec![];
+                self.instrs(it,f,&mut instrs1)?;
+                let mut instrs2 = None;
+                if it.peek_atom() == Some("else") {
+                    it.next();
+                    self.end_label(it,&label)?;
+                    let mut v = vec![];
+                    self.instrs(it,f,&mut v)?;
+                    instrs2 = Some(v);
+                }
+                it.keyword("end")?;
+                self.end_label(it,&label)?;
+                f.labels.pop();
+                out.push(Instruction{ opcode: Some(opcodes::If), sub: 0, args: Some(ArgsEnum::IfArgs(IfArgs{ bt: Some(bt), instrs1: Some(instrs1), instrs2 })) });
+            }
+            "try_table" => {
+                let label = it.opt_id();
+                let bt = self.block_type(it)?;
+                let catches = self.catches(it,f)?;
+                f.labels.push(label.clone());
+                let mut instrs = vec![];
+                self.instrs(it,f,&mut instrs)?;
+                it.keyword("end")?;
+                self.end_label(it,&label)?;
+                f.labels.pop();
+                out.push(try_table(bt,catches,instrs));
+            }
+            _ => {
+                let instr = self.simple(kw,pos,it,f)?;
+                out.push(instr);
+            }
+        }
+        Ok(())
+    }
+
+    /// 折叠形式的指令,操作数指令先输出
+    fn folded(&mut self,e:&SExpr,f:&mut FuncCtx,out:&mut Vec<Instruction>) -> TextResult<()>{
+        let mut it = Items::of(e)?;
+        let (kw,pos) = it.atom()?;
+        match kw {
+            "block"|"loop" => {
+                let label = it.opt_id();
+                let bt = self.block_type(&mut it)?;
+                f.labels.push(label);
+                let mut instrs = vec![];
+                self.instrs(&mut it,f,&mut instrs)?;
+                it.expect_end()?;
+                f.labels.pop();
+                let opcode = if kw == "block" { opcodes::Block } else { opcodes::Loop };
+                out.push(Instruction{ opcode: Some(opcode), sub: 0, args: Some(ArgsEnum::BlockArgs(BlockArgs{ bt: Some(bt), instrs: Some(instrs) })) });
+            }
+            "if" => {
+                let label = it.opt_id();
+                let bt = self.block_type(&mut it)?;
+                while it.peek_list_kw() != Some("then") {
+                    match it.next() {
+                        Some(c@SExpr::List(..)) => self.folded(c,f,out)?,
+                        _ => return Err(pos.err("expected (then ...)".to_string())),
+                    }
+                }
+                f.labels.push(label);
+                let mut then = it.list()?;
+                then.keyword("then")?;
+                let mut instrs1 = vec![];
+                self.instrs(&mut then,f,&mut instrs1)?;
+                then.expect_end()?;
+                let mut instrs2 = None;
+                if it.peek_list_kw() == Some("else") {
+                    let mut els = it.list()?;
+                    els.keyword("else")?;
+                    let mut v = vec![];
+                    self.instrs(&mut els,f,&mut v)?;
+                    els.expect_end()?;
+                    instrs2 = Some(v);
+                }
+                it.expect_end()?;
+                f.labels.pop();
+                out.push(Instruction{ opcode: Some(opcodes::If), sub: 0, args: Some(ArgsEnum::IfArgs(IfArgs{ bt: Some(bt), instrs1: Some(instrs1), instrs2 })) });
+            }
+            "try_table" => {
+                let label = it.opt_id();
+                let bt = self.block_type(&mut it)?;
+                let catches = self.catches(&mut it,f)?;
+                f.labels.push(label);
+                let mut instrs = vec![];
+                self.instrs(&mut it,f,&mut instrs)?;
+                it.expect_end()?;
+                f.labels.pop();
+                out.push(try_table(bt,catches,instrs));
+            }
+            _ => {
+                let instr = self.simple(kw,pos,&mut it,f)?;
+                while let Some(c) = it.next() {
+                    match c {
+                        SExpr::List(..) => self.folded(c,f,out)?,
+                        c => return Err(c.pos().err("unexpected token".to_string())),
+                    }
+                }
+                out.push(instr);
+            }
+        }
+        Ok(())
+    }
+
+    /// try_table的catch子句,标签在压入try_table自己的标签之前解析
+    fn catches(&mut self,it:&mut Items,f:&mut FuncCtx) -> TextResult<Vec<Catch>>{
+        let mut v = vec![];
+        while let Some(kw) = it.peek_list_kw() {
+            let kind = match kw {
+                "catch" => CATCH,
+                "catch_ref" => CATCH_REF,
+                "catch_all" => CATCH_ALL,
+                "catch_all_ref" => CATCH_ALL_REF,
+                _ => break,
+            };
+            let mut c = it.list()?;
+            c.next();
+            let tag = match kind {
+                CATCH|CATCH_REF => Some(self.tags.resolve(&mut c)?),
+                _ => None,
+            };
+            let label = f.label(&mut c)?;
+            c.expect_end()?;
+            v.push(Catch{ kind, tag, label });
+        }
+        Ok(v)
+    }
+
+    /// 非块指令和它的立即数
+    fn simple(&mut self,kw:&str,pos:Pos,it:&mut Items,f:&mut FuncCtx) -> TextResult<Instruction>{
+        if let Some(op) = opcodes::by_name(kw).filter(|op|op.prefix == opcodes::MiscPrefix) {
+            let sub = op.sub;
+            let args = match sub {
+                // 写了两个索引时前面的是内存索引
+                opcodes::MemoryInit => {
+                    self.uses_data_count = true;
+                    let mem = if it.is_idx_at(1) { self.mems.resolve(it)? } else { 0 };
+                    ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(self.datas.resolve(it)?), idx2: Some(mem) })
+                }
+                opcodes::DataDrop => {
+                    self.uses_data_count = true;
+                    ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(self.datas.resolve(it)?), idx2: None })
+                }
+                // memory.copy的目标和源内存要么都写要么都省略
+                opcodes::MemoryCopy => {
+                    let dst = self.mems.resolve_opt(it)?;
+                    let src = if it.is_idx_at(0) { self.mems.resolve(it)? } else { dst };
+                    ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(dst), idx2: Some(src) })
+                }
+                opcodes::MemoryFill => {
+                    ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(self.mems.resolve_opt(it)?), idx2: None })
+                }
+                opcodes::TableGrow|opcodes::TableSize|opcodes::TableFill => {
+                    ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(self.tables.resolve_opt(it)?), idx2: None })
+                }
+                _ => ArgsEnum::PrefixArgs(PrefixArgs{ idx: None, idx2: None }),
+            };
+            return Ok(Instruction::prefixed(opcodes::MiscPrefix,sub,args));
+        }
+        // select和带类型的select同名,有(result t)的是带类型的
+        if kw == "select" {
+            let (types,_) = self.params(it,"result")?;
+            return Ok(match types.as_slice() {
+                [] => Instruction{ opcode: Some(opcodes::Select), sub: 0, args: None },
+                [t] => Instruction{ opcode: Some(opcodes::SelectT), sub: 0, args: Some(ArgsEnum::U8(*t)) },
+                _ => return Err(pos.err("invalid result arity".to_string())),
+            });
+        }
+        if let Some(sub) = simd::sub_opcode(kw) {
+            return self.simd(sub,it);
+        }
+        if let Some(sub) = atomic::sub_opcode(kw) {
+            let mem_arg = match sub {
+                atomic::AtomicFence => None,
+                _ => Some(self.mem_arg(it,atomic::natural_align(sub),true)?),
+            };
+            let args = ArgsEnum::AtomicArgs(AtomicArgs{ mem_arg });
+            return Ok(Instruction::prefixed(opcodes::AtomicPrefix,sub,args));
+        }
+        let opcode = match opcode_by_name(kw) {
+            Some(op) if op != opcodes::Block && op != opcodes::Loop && op != opcodes::If
+                && op != opcodes::TryTable && op != opcodes::Else_ && op != opcodes::End_ => op,
+            _ => return Err(pos.err(format!("unknown operator {}",kw))),
+        };
+        let args = match opcode {
+            opcodes::Br|opcodes::BrIf => Some(ArgsEnum::U32(f.label(it)?)),
+            opcodes::BrTable => {
+                let mut labels = vec![];
+                while matches!(it.peek(),Some(SExpr::Atom(Token::Id(_),_)))
+                    || it.peek_atom().map(|s|number::parse_u32(s).is_some()).unwrap_or(false) {
+                    labels.push(f.label(it)?);
+                }
+                let default = match labels.pop() {
+                    Some(l) => l,
+                    None => return it.err("expected label"),
+                };
+                Some(ArgsEnum::BrTableArgs(BrTableArgs{ labels: Some(labels), default: Some(default) }))
+            }
+            opcodes::Call|opcodes::ReturnCall => Some(ArgsEnum::U32(self.funcs.resolve(it)?)),
+            opcodes::Throw => Some(ArgsEnum::U32(self.tags.resolve(it)?)),
+            opcodes::CallIndirect|opcodes::ReturnCallIndirect => {
+                // 表索引省略时是0
+                let table = self.tables.resolve_opt(it)?;
+                let type_idx = self.type_use(it,None)?;
+                Some(ArgsEnum::CallIndirectArgs(CallIndirectArgs{ type_idx: Some(type_idx), table: Some(table) }))
+            }
+            opcodes::TableGet|opcodes::TableSet => Some(ArgsEnum::U32(self.tables.resolve_opt(it)?)),
+            opcodes::RefNull => {
+                let (s,pos) = it.atom()?;
+                Some(ArgsEnum::U8(heap_type(s).ok_or_else(||pos.err(format!("unknown heap type {}",s)))?))
+            }
+            opcodes::RefFunc => Some(ArgsEnum::U32(self.funcs.resolve(it)?)),
+            opcodes::LocalGet|opcodes::LocalSet|opcodes::LocalTee => Some(ArgsEnum::U32(f.locals.resolve(it)?)),
+            opcodes::GlobalGet|opcodes::GlobalSet => Some(ArgsEnum::U32(self.globals.resolve(it)?)),
+            opcodes::MemorySize|opcodes::MemoryGrow => Some(ArgsEnum::U32(self.mems.resolve_opt(it)?)),
+            opcodes::I32Const => {
+                let (s,pos) = it.atom()?;
+                Some(ArgsEnum::I32(number::parse_i32(s).ok_or_else(||pos.err(format!("invalid i32 {}",s)))?))
+            }
+            opcodes::I64Const => {
+                let (s,pos) = it.atom()?;
+                Some(ArgsEnum::I64(number::parse_i64(s).ok_or_else(||pos.err(format!("invalid i64 {}",s)))?))
+            }
+            opcodes::F32Const => {
+                let (s,pos) = it.atom()?;
+                Some(ArgsEnum::F32(number::parse_f32(s).ok_or_else(||pos.err(format!("invalid f32 {}",s)))?))
+            }
+            opcodes::F64Const => {
+                let (s,pos) = it.atom()?;
+                Some(ArgsEnum::F64(number::parse_f64(s).ok_or_else(||pos.err(format!("invalid f64 {}",s)))?))
+            }
+            op if (opcodes::I32Load..=opcodes::I64Store32).contains(&op) => {
+                Some(ArgsEnum::MemArg(self.mem_arg(it,natural_align(op),true)?))
+            }
+            _ => None,
+        };
+        Ok(Instruction{ opcode: Some(opcode), sub: 0, args })
+    }
+
+    /// SIMD指令的立即数
+    /// v128.const先写形状再写各个车道,例如 v128.const i32x4 1 2 3 4
+    fn simd(&mut self,sub:u32,it:&mut Items) -> TextResult<Instruction>{
+        let mut args = SimdArgs{ mem_arg: None, lane: None, v128: None };
+        let imm = simd::imm(sub);
+        if imm == SimdImm::MemArg || imm == SimdImm::MemArgLane {
+            // load_lane/store_lane最后的数字是车道下标,跳过offset/align后还有下标时前面的才是内存索引
+            let has_mem = imm == SimdImm::MemArg || (it.is_idx_at(0) && it.is_idx_at(it.mem_arg_len(1) + 1));
+            args.mem_arg = Some(self.mem_arg(it,simd::natural_align(sub),has_mem)?);
+        }
+        match imm {
+            SimdImm::Lane|SimdImm::MemArgLane => args.lane = Some(lane_idx(it)?),
+            SimdImm::Shuffle => {
+                let mut bytes = [0u8;16];
+                for b in bytes.iter_mut() {
+                    *b = lane_idx(it)?;
+                }
+                args.v128 = Some(u128::from_le_bytes(bytes));
+            }
+            SimdImm::V128 => args.v128 = Some(v128_const(it)?),
+            _ => {}
+        }
+        Ok(Instruction::prefixed(opcodes::SimdPrefix,sub,ArgsEnum::SimdArgs(Box::new(args))))
+    }
+
+    /// memidx? offset=N align=N,align写的是字节数,要转成2的幂次
+    fn mem_arg(&mut self,it:&mut Items,natural:u32,has_mem:bool) -> TextResult<MemArg>{
+        let mem = if has_mem { self.mems.resolve_opt(it)? } else { 0 };
+        let mut arg = MemArg{ align: Some(natural), offset: Some(0), mem: Some(mem).filter(|m|*m != 0) };
+        if let Some(s) = it.peek_atom().and_then(|s|s.strip_prefix("offset=")) {
+            let pos = it.cur_pos();
+            it.next();
+            arg.offset = Some(number::parse_uint(s).ok_or_else(||pos.err(format!("invalid offset {}",s)))?);
+        }
+        if let Some(s) = it.peek_atom().and_then(|s|s.strip_prefix("align=")) {
+            let pos = it.cur_pos();
+            it.next();
+            let n = number::parse_u32(s).filter(|n|n.is_power_of_two())
+                .ok_or_else(||pos.err(format!("alignment must be a power of two: {}",s)))?;
+            arg.align = Some(n.trailing_zeros());
+        }
+        Ok(arg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::binary::{module, opcodes, reader, writer};
+    use crate::binary::instruction::ArgsEnum;
+    use crate::text::parse;
+    use crate::validator::validate;
+
+    #[test]
+    fn test1(){
+        // 同一个函数的平铺写法和折叠写法
+        let flat = parse(r#"
+            (module
+              (func $fac (export "fac") (param $n i64) (result i64)
+                local.get $n
+                i64.const 1
+                i64.lt_s
+                if (result i64)
+                  i64.const 1
+                else
+                  local.get $n
+                  local.get $n
+                  i64.const 1
+                  i64.sub
+                  call $fac
+                  i64.mul
+                end))
+        "#).unwrap();
+        let folded = parse(r#"
+            (func $fac (export "fac") (param $n i64) (result i64)
+              (if (result i64) (i64.lt_s (local.get $n) (i64.const 1))
+                (then (i64.const 1))
+                (else (i64.mul (local.get $n) (call $fac (i64.sub (local.get $n) (i64.const 1)))))))
+        "#).unwrap();
+        assert_eq!(format!("{:?}",flat),format!("{:?}",folded));
+        validate(&flat).unwrap();
+
+        let body = flat.code_sec.as_ref().unwrap()[0].expr.clone().unwrap();
+        assert_eq!(body.len(),4);
+        assert_eq!(body[3].opcode,Some(opcodes::If));
+        let exports = flat.export_sec.as_ref().unwrap();
+        assert_eq!((exports[0].name.as_deref(),exports[0].desc.as_ref().unwrap().idx),(Some("fac"),Some(0)));
+
+        // 写成二进制再读回来
+        let m = reader::decode(writer::encode(&flat)).unwrap();
+        assert_eq!(format!("{:?}",m),format!("{:?}",flat));
+    }
+
+    #[test]
+    fn test2(){
+        let m = parse(r#"
+            (module
+              (type $v (func))
+              (import "env" "print" (func $print (param i32)))
+              (global $g (import "env" "g") i32)
+              (memory (export "mem") 1 2)
+              (table 2 funcref)
+              (global $counter (mut i32) (global.get $g))
+              (func $main (type $v) (local $i i32) (local f32 f32)
+                (block $out
+                  (loop $top
+                    (br_if $out (i32.ge_u (local.get $i) (i32.const 10)))
+                    (call $print (i32.load8_u offset=3 (local.get $i)))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br $top)))
+                (f32.store align=4 (i32.const 0) (f32.const -0x1.8p1))
+                (global.set $counter (i32.const 7))
+                (block $b (br_table $b 0 1 (i32.const 0)))
+                (call_indirect (type $v) (i32.const 1)))
+              (func $other)
+              (elem (i32.const 0) $main $other)
+              (data (i32.const 16) "hi\00" "\ff")
+              (start $other))
+        "#).unwrap();
+        validate(&m).unwrap();
+
+        let imports = m.import_sec.as_ref().unwrap();
+        assert_eq!(imports.len(),2);
+        assert_eq!(imports[0].import_desc.as_ref().unwrap().fun_type,Some(1));
+        assert_eq!(imports[1].import_desc.as_ref().unwrap().tag,Some(module::IMPORT_TAG_GLOBAL));
+        // 显式类型在前,隐式的(param i32)追加在后面
+        assert_eq!(m.type_sec.as_ref().unwrap().len(),2);
+        assert_eq!(m.func_sec,Some(vec![0,0]));
+        assert_eq!(m.start_sec,Some(2));
+        assert_eq!(m.elem_sec.as_ref().unwrap()[0].init,Some(vec![1,2]));
+        assert_eq!(m.data_sec.as_ref().unwrap()[0].init,Some(vec![b'h',b'i',0,0xff]));
+        assert_eq!(m.export_sec.as_ref().unwrap()[0].desc.as_ref().unwrap().tag,Some(module::EXPORT_TAG_MEM));
+        assert_eq!(m.global_sec.as_ref().unwrap()[0].ty.as_ref().unwrap().m,Some(module::MUT_VAR));
+
+        let code = &m.code_sec.as_ref().unwrap()[0];
+        assert_eq!(code.get_local_count(),Some(3));
+        let body = code.expr.as_ref().unwrap();
+        let block = body[0].args.as_ref().unwrap().get_block_args();
+        let lp = block.instrs.as_ref().unwrap()[0].args.as_ref().unwrap().get_block_args();
+        let instrs = lp.instrs.unwrap();
+        // br_if $out 在loop里面,深度是1
+        assert_eq!(instrs[3].opcode,Some(opcodes::BrIf));
+        assert_eq!(instrs[3].args,Some(ArgsEnum::U32(1)));
+        let load = instrs[5].args.as_ref().unwrap().get_mem_args();
+        assert_eq!((load.align,load.offset),(Some(0),Some(3)));
+        assert_eq!(body[3].args.as_ref().unwrap().get_mem_args().align,Some(2));
+        assert_eq!(body[2].args,Some(ArgsEnum::F32(-3.0)));
+        let inner = body[6].args.as_ref().unwrap().get_block_args().instrs.unwrap();
+        let br_table = inner[1].args.as_ref().unwrap().get_br_table_args();
+        assert_eq!((br_table.labels,br_table.default),(Some(vec![0,0]),Some(1)));
+    }
+
+    #[test]
+    fn test3(){
+        let e = parse("(module\n  (func\n    i32.const 1\n    i32.foo))").unwrap_err();
+        assert_eq!((e.line,e.col),(4,5));
+        assert!(e.message.contains("i32.foo"));
+        let e = parse("(func (call $nope))").unwrap_err();
+        assert_eq!((e.line,e.col),(1,13));
+        let e = parse("(func $f) (func $f)").unwrap_err();
+        assert!(e.message.contains("duplicate"));
+        let e = parse("(func) (import \"a\" \"b\" (func))").unwrap_err();
+        assert!(e.message.contains("import after"));
+        let e = parse("(func block end $x)").unwrap_err();
+        assert!(e.message.contains("mismatching label"));
+    }
+}
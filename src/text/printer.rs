@@ -0,0 +1,644 @@
+use crate::binary::instruction::{ArgsEnum, AtomicArgs, Instruction, MemArg, PrefixArgs, SimdArgs};
+use crate::binary::module::{self, BlockType, FuncType, GlobalType, Limits, Module, TableType};
+use crate::binary::{atomic, opcodes};
+use crate::binary::simd::{self, SimdImm};
+use crate::text::lexer::is_id_char;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+/// 把Module打印成文本格式
+/// 指令用平铺形式,标签用数字深度,有name段时函数和局部变量用名字
+/// 打印出来的文本可以再用text::parse解析回来,自定义段不打印
+pub fn print(m:&Module) -> String{
+    WatPrinter::new(m).print()
+}
+
+pub struct WatPrinter<'a>{
+    m:&'a Module,
+    out:String,
+    indent:usize,
+    func_names:HashMap<u32,String>,
+    local_names:HashMap<u32,HashMap<u32,String>>,
+}
+
+/// name段里的名字不一定是合法的标识符,不合法的字符换成下划线,重名的加上索引
+fn to_ids(names:&[(u32,String)]) -> HashMap<u32,String>{
+    let mut used = HashSet::new();
+    let mut map = HashMap::new();
+    for (idx,name) in names {
+        if name.is_empty() {
+            continue;
+        }
+        let mut id:String = name.chars().map(|c|if is_id_char(c) { c } else { '_' }).collect();
+        if !used.insert(id.clone()) {
+            id = format!("{}.{}",id,idx);
+            used.insert(id.clone());
+        }
+        map.insert(*idx,id);
+    }
+    map
+}
+
+fn val_type_name(t:u8) -> &'static str{
+    match t {
+        module::VAL_TYPE_I32 => "i32",
+        module::VAL_TYPE_I64 => "i64",
+        module::VAL_TYPE_F32 => "f32",
+        module::VAL_TYPE_F64 => "f64",
+        module::VAL_TYPE_V128 => "v128",
+        module::FUNC_REF => "funcref",
+        module::EXTERN_REF => "externref",
+        module::EXN_REF => "exnref",
+        _ => "unknown",
+    }
+}
+
+/// ref.null后面的堆类型
+fn heap_type_name(t:u8) -> &'static str{
+    match t {
+        module::EXTERN_REF => "extern",
+        module::EXN_REF => "exn",
+        _ => "func",
+    }
+}
+
+/// 浮点数按能精确还原的形式打印,nan带上负载
+pub fn f32_str(v:f32) -> String{
+    let bits = v.to_bits();
+    let sign = if bits >> 31 != 0 { "-" } else { "" };
+    if v.is_nan() {
+        let payload = bits & 0x7f_ffff;
+        if payload == 0x40_0000 {
+            return format!("{}nan",sign);
+        }
+        return format!("{}nan:{:#x}",sign,payload);
+    }
+    if v.is_infinite() {
+        return format!("{}inf",sign);
+    }
+    format!("{:?}",v)
+}
+
+pub fn f64_str(v:f64) -> String{
+    let bits = v.to_bits();
+    let sign = if bits >> 63 != 0 { "-" } else { "" };
+    if v.is_nan() {
+        let payload = bits & 0xf_ffff_ffff_ffff;
+        if payload == 0x8_0000_0000_0000 {
+            return format!("{}nan",sign);
+        }
+        return format!("{}nan:{:#x}",sign,payload);
+    }
+    if v.is_infinite() {
+        return format!("{}inf",sign);
+    }
+    format!("{:?}",v)
+}
+
+/// 字节串,可打印的ASCII原样输出,其他用\hh
+pub fn bytes_str(bytes:&[u8]) -> String{
+    let mut s = String::from("\"");
+    for b in bytes {
+        match *b {
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            0x20..=0x7e => s.push(*b as char),
+            b => {let _ = write!(s,"\\{:02x}",b);}
+        }
+    }
+    s.push('"');
+    s
+}
+
+/// memory64在最前面写上地址类型i64
+fn limits_str(l:&Limits) -> String{
+    let s = match l.max {
+        Some(max) => format!("{} {}",l.min.unwrap_or(0),max),
+        None => format!("{}",l.min.unwrap_or(0)),
+    };
+    let s = if l.is_64() { format!("i64 {}",s) } else { s };
+    if l.is_shared() { s + " shared" } else { s }
+}
+
+fn table_type_str(t:&TableType) -> String{
+    format!("{} {}",limits_str(t.limits.as_ref().unwrap()),val_type_name(t.elem_type.unwrap_or(module::FUNC_REF)))
+}
+
+fn global_type_str(t:&GlobalType) -> String{
+    let ty = val_type_name(t.val_type.unwrap());
+    if t.m == Some(module::MUT_VAR) { format!("(mut {})",ty) } else { ty.to_string() }
+}
+
+/// load/store的自然对齐,按2的幂次
+fn natural_align(opcode:u8) -> u32{
+    match opcode {
+        opcodes::I32Load8S|opcodes::I32Load8U|opcodes::I64Load8S|opcodes::I64Load8U|
+        opcodes::I32Store8|opcodes::I64Store8 => 0,
+        opcodes::I32Load16S|opcodes::I32Load16U|opcodes::I64Load16S|opcodes::I64Load16U|
+        opcodes::I32Store16|opcodes::I64Store16 => 1,
+        opcodes::I32Load|opcodes::F32Load|opcodes::I64Load32S|opcodes::I64Load32U|
+        opcodes::I32Store|opcodes::F32Store|opcodes::I64Store32 => 2,
+        _ => 3,
+    }
+}
+
+/// memidx offset=N align=N,是默认值的省略
+fn mem_arg_str(arg:&MemArg,natural:u32) -> String{
+    let mut v = vec![];
+    if let Some(mem) = arg.mem.filter(|m|*m != 0) {
+        v.push(mem.to_string());
+    }
+    let offset = arg.offset.unwrap_or(0);
+    if offset != 0 {
+        v.push(format!("offset={}",offset));
+    }
+    let align = arg.align.unwrap_or(0);
+    if align != natural {
+        v.push(format!("align={}",1u64 << align.min(63)));
+    }
+    v.join(" ")
+}
+
+/// 0xFC指令的索引按文本格式的顺序,memory.init的内存索引写在数据段索引前面
+/// 内存索引是0时省略
+fn prefix_idxs(sub:u32,args:&PrefixArgs) -> Vec<u32>{
+    let idx = args.idx.unwrap_or(0);
+    let idx2 = args.idx2.unwrap_or(0);
+    match sub {
+        opcodes::I32TruncSatF32S..=opcodes::I64TruncSatF64U => vec![],
+        opcodes::MemoryInit if idx2 != 0 => vec![idx2,idx],
+        opcodes::MemoryCopy if idx != 0 || idx2 != 0 => vec![idx,idx2],
+        opcodes::MemoryCopy|opcodes::MemoryFill if idx == 0 => vec![],
+        _ => vec![idx],
+    }
+}
+
+/// SIMD指令,v128.const统一按i32x4打印
+fn simd_str(sub:u32,args:&SimdArgs) -> String{
+    let mut v = vec![simd::name(sub).unwrap_or("simd").to_string()];
+    if let Some(arg) = &args.mem_arg {
+        let s = mem_arg_str(arg,simd::natural_align(sub));
+        if !s.is_empty() {
+            v.push(s);
+        }
+    }
+    if let Some(lane) = args.lane {
+        v.push(lane.to_string());
+    }
+    match (simd::imm(sub),args.v128) {
+        (SimdImm::V128,Some(n)) => {
+            v.push("i32x4".to_string());
+            v.extend((0..4).map(|i|format!("{:#010x}",(n >> (i * 32)) as u32)));
+        }
+        (SimdImm::Shuffle,Some(n)) => v.extend(n.to_le_bytes().iter().map(|b|b.to_string())),
+        _ => {}
+    }
+    v.join(" ")
+}
+
+fn atomic_str(sub:u32,args:&AtomicArgs) -> String{
+    let name = atomic::name(sub).unwrap_or("atomic");
+    match args.mem_arg.as_ref().map(|arg|mem_arg_str(arg,atomic::natural_align(sub))) {
+        Some(s) if !s.is_empty() => format!("{} {}",name,s),
+        _ => name.to_string(),
+    }
+}
+
+impl<'a> WatPrinter<'a>{
+    pub fn new(m:&'a Module) -> WatPrinter<'a>{
+        if opcodes::OPCODE_MAP.get().is_none() {
+            opcodes::init();
+        }
+        let mut p = WatPrinter{ m, out: String::new(), indent: 0, func_names: HashMap::new(), local_names: HashMap::new() };
+        if let Some(names) = &m.name_sec {
+            p.func_names = to_ids(&names.func_names);
+            for (func_idx,locals) in names.local_names.iter() {
+                p.local_names.insert(*func_idx,to_ids(locals));
+            }
+        }
+        p
+    }
+
+    fn line(&mut self,s:&str){
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(s);
+        self.out.push('\n');
+    }
+
+    fn func_ref(&self,idx:u32) -> String{
+        match self.func_names.get(&idx) {
+            Some(name) => format!("${}",name),
+            None => idx.to_string(),
+        }
+    }
+
+    fn local_ref(&self,func_idx:u32,idx:u32) -> String{
+        match self.local_names.get(&func_idx).and_then(|m|m.get(&idx)) {
+            Some(name) => format!("${}",name),
+            None => idx.to_string(),
+        }
+    }
+
+    /// 函数定义或导入的开头,$名字 (;索引;)
+    fn func_head(&self,idx:u32) -> String{
+        match self.func_names.get(&idx) {
+            Some(name) => format!("func ${} (;{};)",name,idx),
+            None => format!("func (;{};)",idx),
+        }
+    }
+
+    fn func_type(&self,idx:u32) -> FuncType{
+        self.m.type_sec.as_ref().and_then(|v|v.get(idx as usize)).cloned()
+            .unwrap_or(FuncType{ tag: None, param_types: None, result_types: None })
+    }
+
+    /// (type N) (param ...) (result ...),有参数名时每个参数单独写
+    fn type_use(&self,type_idx:u32,func_idx:Option<u32>) -> String{
+        let ft = self.func_type(type_idx);
+        let mut s = format!("(type {})",type_idx);
+        let params = ft.param_types.unwrap_or_default();
+        let named = func_idx.map(|f|self.local_names.contains_key(&f)).unwrap_or(false);
+        if named {
+            for (i,t) in params.iter().enumerate() {
+                let name = self.local_names.get(&func_idx.unwrap()).and_then(|m|m.get(&(i as u32)));
+                match name {
+                    Some(name) => {let _ = write!(s," (param ${} {})",name,val_type_name(*t));}
+                    None => {let _ = write!(s," (param {})",val_type_name(*t));}
+                }
+            }
+        } else if !params.is_empty() {
+            let types:Vec<&str> = params.iter().map(|t|val_type_name(*t)).collect();
+            let _ = write!(s," (param {})",types.join(" "));
+        }
+        let results = ft.result_types.unwrap_or_default();
+        if !results.is_empty() {
+            let types:Vec<&str> = results.iter().map(|t|val_type_name(*t)).collect();
+            let _ = write!(s," (result {})",types.join(" "));
+        }
+        s
+    }
+
+    fn block_type_str(&self,bt:BlockType) -> String{
+        match bt {
+            BlockType::Empty => String::new(),
+            BlockType::Value(t) => format!(" (result {})",val_type_name(t)),
+            BlockType::TypeIdx(idx) => format!(" {}",self.type_use(idx,None)),
+        }
+    }
+
+    /// 常量表达式,每条指令写成折叠形式
+    fn const_expr(&self,expr:&[Instruction]) -> String{
+        let v:Vec<String> = expr.iter().map(|i|format!("({})",self.instr_str(i,None))).collect();
+        v.join(" ")
+    }
+
+    /// elem/data的偏移,多条指令时用(offset ...)
+    fn offset_expr(&self,expr:&[Instruction]) -> String{
+        if expr.len() == 1 {
+            return self.const_expr(expr);
+        }
+        let v:Vec<String> = expr.iter().map(|i|self.instr_str(i,None)).collect();
+        format!("(offset {})",v.join(" "))
+    }
+
+    pub fn print(mut self) -> String{
+        let m = self.m;
+        self.line("(module");
+        self.indent += 1;
+        for (i,t) in m.type_sec.iter().flatten().enumerate() {
+            let mut s = format!("(type (;{};) (func",i);
+            let params = t.param_types.clone().unwrap_or_default();
+            if !params.is_empty() {
+                let types:Vec<&str> = params.iter().map(|t|val_type_name(*t)).collect();
+                let _ = write!(s," (param {})",types.join(" "));
+            }
+            let results = t.result_types.clone().unwrap_or_default();
+            if !results.is_empty() {
+                let types:Vec<&str> = results.iter().map(|t|val_type_name(*t)).collect();
+                let _ = write!(s," (result {})",types.join(" "));
+            }
+            s.push_str("))");
+            self.line(&s);
+        }
+
+        let (mut funcs,mut tables,mut mems,mut globals,mut tags) = (0u32,0u32,0u32,0u32,0u32);
+        for import in m.import_sec.iter().flatten() {
+            let desc = import.import_desc.as_ref().unwrap();
+            let what = match desc.tag {
+                Some(module::IMPORT_TAG_FUNC) => {
+                    funcs += 1;
+                    format!("({} {})",self.func_head(funcs - 1),self.type_use(desc.fun_type.unwrap(),Some(funcs - 1)))
+                }
+                Some(module::IMPORT_TAG_TABLE) => {
+                    tables += 1;
+                    format!("(table (;{};) {})",tables - 1,table_type_str(desc.table.as_ref().unwrap()))
+                }
+                Some(module::IMPORT_TAG_MEM) => {
+                    mems += 1;
+                    format!("(memory (;{};) {})",mems - 1,limits_str(desc.mem.as_ref().unwrap()))
+                }
+                Some(module::IMPORT_TAG_TAG) => {
+                    tags += 1;
+                    format!("(tag (;{};) {})",tags - 1,self.type_use(desc.tag_type.as_ref().unwrap().type_idx.unwrap(),None))
+                }
+                _ => {
+                    globals += 1;
+                    format!("(global (;{};) {})",globals - 1,global_type_str(desc.global.as_ref().unwrap()))
+                }
+            };
+            self.line(&format!("(import {} {} {})",
+                bytes_str(import.module.as_deref().unwrap_or("").as_bytes()),
+                bytes_str(import.name.as_deref().unwrap_or("").as_bytes()),
+                what));
+        }
+
+        let codes = m.code_sec.as_deref().unwrap_or(&[]);
+        for (i,type_idx) in m.func_sec.iter().flatten().enumerate() {
+            let func_idx = funcs + i as u32;
+            self.line(&format!("({} {}",self.func_head(func_idx),self.type_use(*type_idx,Some(func_idx))));
+            self.indent += 1;
+            if let Some(code) = codes.get(i) {
+                self.func_body(func_idx,*type_idx,code);
+            }
+            self.indent -= 1;
+            self.line(")");
+        }
+
+        for t in m.table_sec.iter().flatten() {
+            self.line(&format!("(table (;{};) {})",tables,table_type_str(t)));
+            tables += 1;
+        }
+        for l in m.mem_sec.iter().flatten() {
+            self.line(&format!("(memory (;{};) {})",mems,limits_str(l)));
+            mems += 1;
+        }
+        for t in m.tag_sec.iter().flatten() {
+            self.line(&format!("(tag (;{};) {})",tags,self.type_use(t.type_idx.unwrap(),None)));
+            tags += 1;
+        }
+        for g in m.global_sec.iter().flatten() {
+            let s = format!("(global (;{};) {} {})",globals,global_type_str(g.ty.as_ref().unwrap()),
+                self.const_expr(g.init.as_deref().unwrap_or(&[])));
+            self.line(&s);
+            globals += 1;
+        }
+        for e in m.export_sec.iter().flatten() {
+            let desc = e.desc.as_ref().unwrap();
+            let idx = desc.idx.unwrap();
+            let what = match desc.tag {
+                Some(module::EXPORT_TAG_FUNC) => format!("(func {})",self.func_ref(idx)),
+                Some(module::EXPORT_TAG_TABLE) => format!("(table {})",idx),
+                Some(module::EXPORT_TAG_MEM) => format!("(memory {})",idx),
+                Some(module::EXPORT_TAG_TAG) => format!("(tag {})",idx),
+                _ => format!("(global {})",idx),
+            };
+            self.line(&format!("(export {} {})",bytes_str(e.name.as_deref().unwrap_or("").as_bytes()),what));
+        }
+        if let Some(idx) = m.start_sec {
+            self.line(&format!("(start {})",self.func_ref(idx)));
+        }
+        for (i,e) in m.elem_sec.iter().flatten().enumerate() {
+            let mut s = format!("(elem (;{};)",i);
+            match &e.offset {
+                Some(offset) => {
+                    if e.elem_type.is_some() || e.table.unwrap_or(0) != 0 {
+                        let _ = write!(s," (table {})",e.table.unwrap_or(0));
+                    }
+                    let _ = write!(s," {}",self.offset_expr(offset));
+                }
+                None if e.declarative => s.push_str(" declare"),
+                None => {}
+            }
+            match &e.exprs {
+                Some(exprs) => {
+                    let _ = write!(s," {}",val_type_name(e.get_elem_type()));
+                    for expr in exprs {
+                        let v:Vec<String> = expr.iter().map(|i|self.instr_str(i,None)).collect();
+                        let _ = write!(s," (item {})",v.join(" "));
+                    }
+                }
+                None => {
+                    s.push_str(" func");
+                    for idx in e.init.iter().flatten() {
+                        let _ = write!(s," {}",self.func_ref(*idx));
+                    }
+                }
+            }
+            s.push(')');
+            self.line(&s);
+        }
+        for (i,d) in m.data_sec.iter().flatten().enumerate() {
+            let mut s = format!("(data (;{};)",i);
+            if d.mem.unwrap_or(0) != 0 {
+                let _ = write!(s," (memory {})",d.mem.unwrap());
+            }
+            if let Some(offset) = &d.offset {
+                let _ = write!(s," {}",self.offset_expr(offset));
+            }
+            let _ = write!(s," {})",bytes_str(d.init.as_deref().unwrap_or(&[])));
+            self.line(&s);
+        }
+        self.indent -= 1;
+        self.line(")");
+        self.out
+    }
+
+    fn func_body(&mut self,func_idx:u32,type_idx:u32,code:&module::Code){
+        let param_count = self.func_type(type_idx).param_types.map(|v|v.len()).unwrap_or(0) as u32;
+        let mut local_idx = param_count;
+        let mut run:Vec<&str> = vec![];
+        for l in code.locals.iter().flatten() {
+            let ty = val_type_name(l.ty.unwrap());
+            for _ in 0..l.n.unwrap_or(0) {
+                match self.local_names.get(&func_idx).and_then(|m|m.get(&local_idx)).cloned() {
+                    Some(name) => {
+                        if !run.is_empty() {
+                            self.line(&format!("(local {})",run.join(" ")));
+                            run.clear();
+                        }
+                        self.line(&format!("(local ${} {})",name,ty));
+                    }
+                    None => run.push(ty),
+                }
+                local_idx += 1;
+            }
+        }
+        if !run.is_empty() {
+            self.line(&format!("(local {})",run.join(" ")));
+        }
+        match code.get_expr() {
+            Ok(expr) => self.instrs(expr,func_idx),
+            Err(e) => self.line(&format!(";; {}",e)),
+        }
+    }
+
+    fn instrs(&mut self,instrs:&[Instruction],func_idx:u32){
+        for i in instrs {
+            self.instr(i,func_idx);
+        }
+    }
+
+    fn instr(&mut self,i:&Instruction,func_idx:u32){
+        match &i.args {
+            Some(ArgsEnum::BlockArgs(args)) => {
+                self.line(&format!("{}{}",i.get_op_name(),self.block_type_str(args.bt.unwrap_or(BlockType::Empty))));
+                self.indent += 1;
+                self.instrs(args.instrs.as_deref().unwrap_or(&[]),func_idx);
+                self.indent -= 1;
+                self.line("end");
+            }
+            Some(ArgsEnum::TryTableArgs(args)) => {
+                let mut s = format!("try_table{}",self.block_type_str(args.bt.unwrap_or(BlockType::Empty)));
+                for c in args.catches.iter().flatten() {
+                    match c.tag {
+                        Some(tag) => {let _ = write!(s," ({} {} {})",c.name(),tag,c.label);}
+                        None => {let _ = write!(s," ({} {})",c.name(),c.label);}
+                    }
+                }
+                self.line(&s);
+                self.indent += 1;
+                self.instrs(args.instrs.as_deref().unwrap_or(&[]),func_idx);
+                self.indent -= 1;
+                self.line("end");
+            }
+            Some(ArgsEnum::IfArgs(args)) => {
+                self.line(&format!("if{}",self.block_type_str(args.bt.unwrap_or(BlockType::Empty))));
+                self.indent += 1;
+                self.instrs(args.instrs1.as_deref().unwrap_or(&[]),func_idx);
+                self.indent -= 1;
+                if let Some(instrs2) = &args.instrs2 {
+                    self.line("else");
+                    self.indent += 1;
+                    self.instrs(instrs2,func_idx);
+                    self.indent -= 1;
+                }
+                self.line("end");
+            }
+            _ => {
+                let s = self.instr_str(i,Some(func_idx));
+                self.line(&s);
+            }
+        }
+    }
+
+    /// 非块指令,func_idx用来查局部变量名
+    fn instr_str(&self,i:&Instruction,func_idx:Option<u32>) -> String{
+        let opcode = i.opcode.unwrap();
+        if let Some(ArgsEnum::PrefixArgs(args)) = &i.args {
+            let idxs = prefix_idxs(i.sub,args);
+            let mut v = vec![opcodes::name(i.op()).unwrap_or("misc").to_string()];
+            v.extend(idxs.iter().map(|idx|idx.to_string()));
+            return v.join(" ");
+        }
+        if let Some(ArgsEnum::SimdArgs(args)) = &i.args {
+            return simd_str(i.sub,args);
+        }
+        if let Some(ArgsEnum::AtomicArgs(args)) = &i.args {
+            return atomic_str(i.sub,args);
+        }
+        let name = i.get_op_name();
+        let args = match (opcode,&i.args) {
+            (opcodes::Call,Some(ArgsEnum::U32(idx)))|
+            (opcodes::ReturnCall,Some(ArgsEnum::U32(idx))) => self.func_ref(*idx),
+            (opcodes::CallIndirect,Some(ArgsEnum::CallIndirectArgs(args)))|
+            (opcodes::ReturnCallIndirect,Some(ArgsEnum::CallIndirectArgs(args))) => {
+                let type_use = self.type_use(args.type_idx.unwrap_or(0),None);
+                match args.table.unwrap_or(0) {
+                    0 => type_use,
+                    table => format!("{} {}",table,type_use),
+                }
+            }
+            (opcodes::SelectT,Some(ArgsEnum::U8(t))) => format!("(result {})",val_type_name(*t)),
+            (opcodes::RefNull,Some(ArgsEnum::U8(t))) => heap_type_name(*t).to_string(),
+            (opcodes::RefFunc,Some(ArgsEnum::U32(idx))) => self.func_ref(*idx),
+            (opcodes::LocalGet,Some(ArgsEnum::U32(idx)))|
+            (opcodes::LocalSet,Some(ArgsEnum::U32(idx)))|
+            (opcodes::LocalTee,Some(ArgsEnum::U32(idx))) => {
+                match func_idx {
+                    Some(f) => self.local_ref(f,*idx),
+                    None => idx.to_string(),
+                }
+            }
+            (opcodes::MemorySize,Some(ArgsEnum::U32(0)))|(opcodes::MemoryGrow,Some(ArgsEnum::U32(0))) => String::new(),
+            (_,Some(ArgsEnum::U32(n))) => n.to_string(),
+            (_,Some(ArgsEnum::I32(n))) => n.to_string(),
+            (_,Some(ArgsEnum::I64(n))) => n.to_string(),
+            (_,Some(ArgsEnum::F32(v))) => f32_str(*v),
+            (_,Some(ArgsEnum::F64(v))) => f64_str(*v),
+            (_,Some(ArgsEnum::BrTableArgs(args))) => {
+                let mut v:Vec<String> = args.labels.iter().flatten().map(|l|l.to_string()).collect();
+                v.push(args.default.unwrap_or(0).to_string());
+                v.join(" ")
+            }
+            (_,Some(ArgsEnum::MemArg(arg))) => mem_arg_str(arg,natural_align(opcode)),
+            _ => String::new(),
+        };
+        if args.is_empty() { name.to_string() } else { format!("{} {}",name,args) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::binary::reader;
+    use crate::binary::module::Module;
+    use crate::text::{parse, print};
+
+    /// 比较时忽略自定义段
+    fn without_custom(m:&Module) -> String{
+        let mut m = m.clone();
+        m.custom_secs = None;
+        m.name_sec = None;
+        format!("{:?}",m)
+    }
+
+    #[test]
+    fn test1(){
+        let src = r#"(module
+  (type (;0;) (func (param i32) (result i32)))
+  (import "env" "g" (global (;0;) (mut i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    (local i64 i64)
+    local.get 0
+    if (result i32)
+      block
+        loop
+          br_table 0 1 1
+        end
+      end
+      i32.const -1
+    else
+      i32.const 0
+      i32.load8_u offset=4 align=1
+    end
+    f32.const -nan:0x200000
+    drop
+    f64.const 0x1p-1074
+    i64.trunc_sat_f64_u
+    drop
+    memory.size
+    drop
+  )
+  (memory (;0;) 1 2)
+  (export "f" (func 0))
+  (data (;0;) (i32.const 8) "a\"\00")
+)
+"#;
+        let m = parse(src).unwrap();
+        let text = print(&m);
+        assert_eq!(text,src.replace("f64.const 0x1p-1074","f64.const 5e-324").replace(" align=1",""));
+        assert_eq!(without_custom(&parse(&text).unwrap()),without_custom(&m));
+    }
+
+    #[test]
+    fn test2(){
+        // 带name段的真实二进制,打印出来再解析回去要得到同样的模块
+        let m = reader::decode_file("./hw_rust.wasm".to_string()).unwrap();
+        let text = print(&m);
+        assert!(text.contains("(func $std::rt::lang_start::hec3e200a8398bde9 (;0;) (type 6) (param i32 i32 i32) (result i32)"));
+        assert!(text.contains("call $"));
+        let m2 = parse(&text).unwrap();
+        assert_eq!(without_custom(&m2),without_custom(&m));
+    }
+}
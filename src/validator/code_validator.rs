@@ -0,0 +1,668 @@
+use crate::binary::instruction::{ArgsEnum, AtomicArgs, IfArgs, Instruction, MemArg, SimdArgs, TryTableArgs};
+use crate::binary::module::{self, BlockType, Code};
+use crate::binary::{atomic, opcodes, simd};
+use crate::common::common_error::{val_type_name, ValidationError, ValidationErrorKind};
+use crate::validator::module_validator::{ModuleContext, ValidationResult};
+
+const I32:u8 = module::VAL_TYPE_I32;
+const I64:u8 = module::VAL_TYPE_I64;
+const F32:u8 = module::VAL_TYPE_F32;
+const F64:u8 = module::VAL_TYPE_F64;
+
+/// 控制帧
+/// start_types 进入块时的参数类型, end_types 块结束时的结果类型
+/// height 进入块时操作数栈的高度, unreachable 表示块内后面的代码不可达
+#[derive(Debug,Clone)]
+struct CtrlFrame{
+    opcode:u8,
+    start_types:Vec<u8>,
+    end_types:Vec<u8>,
+    height:usize,
+    unreachable:bool,
+}
+
+impl CtrlFrame{
+    /// 跳转到这个标签时需要的类型,loop跳回开头,其他块跳到结尾
+    fn label_types(&self) -> &Vec<u8>{
+        if self.opcode == opcodes::Loop { &self.start_types } else { &self.end_types }
+    }
+}
+
+/// 函数体校验
+/// 按规范附录的算法模拟操作数栈,栈上的None表示不可达代码中类型未知的值
+pub struct CodeValidator<'a>{
+    ctx:&'a ModuleContext<'a>,
+    func_idx:u32,
+    params:Vec<u8>,
+    locals:Vec<(u32,u8)>,
+    results:Vec<u8>,
+    vals:Vec<Option<u8>>,
+    ctrls:Vec<CtrlFrame>,
+    instr_idx:usize,
+    next_idx:usize,
+}
+
+impl<'a> CodeValidator<'a>{
+
+    pub fn new(ctx:&'a ModuleContext<'a>,func_idx:u32,code:&Code) -> ValidationResult<CodeValidator<'a>>{
+        let ft = ctx.get_func_type(func_idx)?;
+        let locals = code.locals.iter().flatten()
+            .map(|l|(l.n.unwrap_or(0),l.ty.unwrap()))
+            .collect();
+        Ok(CodeValidator{
+            ctx,
+            func_idx,
+            params: ft.param_types.clone().unwrap_or_default(),
+            locals,
+            results: ft.result_types.clone().unwrap_or_default(),
+            vals: vec![],
+            ctrls: vec![],
+            instr_idx: 0,
+            next_idx: 0
+        })
+    }
+
+    pub fn validate(&mut self,expr:&[Instruction]) -> ValidationResult<()>{
+        let results = self.results.clone();
+        self.push_ctrl(opcodes::Block,vec![],results);
+        self.validate_instrs(expr)?;
+        // 函数结尾的end没有对应的指令,下标记为指令总数
+        self.instr_idx = self.next_idx;
+        self.pop_ctrl()?;
+        Ok(())
+    }
+
+    fn err(&self,kind:ValidationErrorKind) -> ValidationError{
+        ValidationError{ func_idx: Some(self.func_idx), instr_idx: Some(self.instr_idx), kind }
+    }
+
+    /// 给ModuleContext返回的错误补上位置
+    fn locate(&self,mut e:ValidationError) -> ValidationError{
+        e.func_idx = Some(self.func_idx);
+        e.instr_idx = Some(self.instr_idx);
+        e
+    }
+
+    fn get_local(&self,idx:u32) -> ValidationResult<u8>{
+        if let Some(t) = self.params.get(idx as usize) {
+            return Ok(*t);
+        }
+        let mut base = self.params.len() as u64;
+        for (n,t) in self.locals.iter() {
+            base += *n as u64;
+            if (idx as u64) < base {
+                return Ok(*t);
+            }
+        }
+        Err(self.err(ValidationErrorKind::UnknownLocal(idx)))
+    }
+
+    fn push_val(&mut self,t:Option<u8>){
+        self.vals.push(t);
+    }
+
+    fn push_vals(&mut self,types:&[u8]){
+        for t in types {
+            self.vals.push(Some(*t));
+        }
+    }
+
+    /// 弹出一个值,expected为None表示任意类型
+    fn pop_val(&mut self,expected:Option<u8>) -> ValidationResult<Option<u8>>{
+        let frame = self.ctrls.last().unwrap();
+        if self.vals.len() == frame.height {
+            if frame.unreachable {
+                return Ok(expected);
+            }
+            return Err(match expected {
+                Some(_) => self.err(ValidationErrorKind::TypeMismatch{ expected, actual: None }),
+                None => self.err(ValidationErrorKind::Invalid("type mismatch: operand stack is empty".to_string())),
+            });
+        }
+        let actual = self.vals.pop().unwrap();
+        match (expected,actual) {
+            (Some(e),Some(a)) if e != a => {
+                Err(self.err(ValidationErrorKind::TypeMismatch{ expected, actual }))
+            }
+            (Some(_),None) => Ok(expected),
+            _ => Ok(actual),
+        }
+    }
+
+    fn pop_vals(&mut self,types:&[u8]) -> ValidationResult<()>{
+        for t in types.iter().rev() {
+            self.pop_val(Some(*t))?;
+        }
+        Ok(())
+    }
+
+    fn push_ctrl(&mut self,opcode:u8,start_types:Vec<u8>,end_types:Vec<u8>){
+        let height = self.vals.len();
+        self.push_vals(&start_types);
+        self.ctrls.push(CtrlFrame{ opcode, start_types, end_types, height, unreachable: false });
+    }
+
+    fn pop_ctrl(&mut self) -> ValidationResult<CtrlFrame>{
+        let end_types = self.ctrls.last().unwrap().end_types.clone();
+        self.pop_vals(&end_types)?;
+        let height = self.ctrls.last().unwrap().height;
+        if self.vals.len() != height {
+            return Err(self.err(ValidationErrorKind::ExtraValues(self.vals.len() - height)));
+        }
+        Ok(self.ctrls.pop().unwrap())
+    }
+
+    fn set_unreachable(&mut self){
+        let frame = self.ctrls.last_mut().unwrap();
+        self.vals.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn get_label(&self,l:u32) -> ValidationResult<Vec<u8>>{
+        let n = self.ctrls.len();
+        if (l as usize) >= n {
+            return Err(self.err(ValidationErrorKind::UnknownLabel(l)));
+        }
+        Ok(self.ctrls[n - 1 - l as usize].label_types().clone())
+    }
+
+    /// 块类型展开成(参数,结果)
+    fn block_type(&self,bt:BlockType) -> ValidationResult<(Vec<u8>,Vec<u8>)>{
+        Ok(match bt {
+            BlockType::Empty => (vec![],vec![]),
+            BlockType::Value(t) => (vec![],vec![t]),
+            BlockType::TypeIdx(idx) => {
+                let ft = self.ctx.get_type(idx).map_err(|e|self.locate(e))?;
+                (ft.params().to_vec(),ft.results().to_vec())
+            }
+        })
+    }
+
+    /// 0xFC前缀的memory.init/data.drop/memory.copy/memory.fill和table.grow/table.size/table.fill
+    fn check_bulk_memory(&mut self,instr:&Instruction) -> ValidationResult<()>{
+        match &instr.args {
+            Some(ArgsEnum::PrefixArgs(args)) if instr.sub == opcodes::MemoryInit => {
+                let addr = self.ctx.get_mem_addr_type(args.idx2.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                self.ctx.check_data(args.idx.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                self.pop_vals(&[addr,I32,I32])?;
+            }
+            Some(ArgsEnum::PrefixArgs(args)) if instr.sub == opcodes::DataDrop => {
+                self.ctx.check_data(args.idx.unwrap_or(0)).map_err(|e|self.locate(e))?;
+            }
+            // 两块内存的地址类型不同时,长度按较小的i32算
+            Some(ArgsEnum::PrefixArgs(args)) if instr.sub == opcodes::MemoryCopy => {
+                let dst = self.ctx.get_mem_addr_type(args.idx.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                let src = self.ctx.get_mem_addr_type(args.idx2.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                let n = if dst == src { dst } else { I32 };
+                self.pop_vals(&[dst,src,n])?;
+            }
+            Some(ArgsEnum::PrefixArgs(args)) if instr.sub == opcodes::MemoryFill => {
+                let addr = self.ctx.get_mem_addr_type(args.idx.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                self.pop_vals(&[addr,I32,addr])?;
+            }
+            Some(ArgsEnum::PrefixArgs(args)) if instr.sub == opcodes::TableGrow => {
+                let t = self.ctx.get_table_type(args.idx.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                self.pop_vals(&[t,I32])?;
+                self.push_val(Some(I32));
+            }
+            Some(ArgsEnum::PrefixArgs(args)) if instr.sub == opcodes::TableSize => {
+                self.ctx.check_table(args.idx.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                self.push_val(Some(I32));
+            }
+            Some(ArgsEnum::PrefixArgs(args)) if instr.sub == opcodes::TableFill => {
+                let t = self.ctx.get_table_type(args.idx.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                self.pop_vals(&[I32,t,I32])?;
+            }
+            _ => return Err(self.bad_args(instr)),
+        }
+        Ok(())
+    }
+
+    /// 0xFD前缀的SIMD指令,类型由binary::simd::signature给出,带memarg的第一个参数是地址
+    fn validate_simd(&mut self,sub:u32,args:&SimdArgs) -> ValidationResult<()>{
+        let (mut params,results) = simd::signature(sub);
+        if let Some(arg) = &args.mem_arg {
+            params[0] = self.check_mem_arg(arg,simd::mem_size(sub))?;
+        }
+        if let Some(lane) = args.lane {
+            if lane >= simd::lane_count(sub) {
+                return Err(self.err(ValidationErrorKind::InvalidLaneIndex(lane)));
+            }
+        }
+        if sub == simd::I8x16Shuffle {
+            if let Some(lane) = args.v128.unwrap_or(0).to_le_bytes().iter().find(|l|**l >= 32) {
+                return Err(self.err(ValidationErrorKind::InvalidLaneIndex(*lane)));
+            }
+        }
+        self.pop_vals(&params)?;
+        self.push_vals(&results);
+        Ok(())
+    }
+
+    /// 原子指令不管内存是不是共享的都能用,对齐必须正好是自然对齐
+    fn validate_atomic(&mut self,sub:u32,args:&AtomicArgs) -> ValidationResult<()>{
+        let (mut params,results) = atomic::signature(sub);
+        if let Some(arg) = &args.mem_arg {
+            params[0] = self.check_offset(arg)?;
+            let align = arg.align.unwrap_or(0);
+            if align != atomic::natural_align(sub) {
+                return Err(self.err(ValidationErrorKind::InvalidAtomicAlignment(align)));
+            }
+        }
+        self.pop_vals(&params)?;
+        self.push_vals(&results);
+        Ok(())
+    }
+
+    /// 尾调用的结果直接作为当前函数的结果,类型必须完全一样,之后的代码不可达
+    fn check_tail_call(&mut self,results:&[u8]) -> ValidationResult<()>{
+        if results != self.results.as_slice() {
+            let names = |v:&[u8]|v.iter().map(|t|val_type_name(Some(*t))).collect::<Vec<_>>().join(" ");
+            return Err(self.err(ValidationErrorKind::Invalid(format!(
+                "type mismatch: tail call results [{}] do not match function results [{}]",names(results),names(&self.results)))));
+        }
+        self.set_unreachable();
+        Ok(())
+    }
+
+    fn bad_args(&self,instr:&Instruction) -> ValidationError{
+        self.err(ValidationErrorKind::Invalid(format!("invalid immediate:{:?}",instr.args)))
+    }
+
+    fn arg_u32(&self,instr:&Instruction) -> ValidationResult<u32>{
+        match &instr.args {
+            Some(ArgsEnum::U32(v)) => Ok(*v),
+            _ => Err(self.bad_args(instr)),
+        }
+    }
+
+    fn validate_instrs(&mut self,instrs:&[Instruction]) -> ValidationResult<()>{
+        for instr in instrs {
+            self.instr_idx = self.next_idx;
+            self.next_idx += 1;
+            // 块在这里递归,不经过validate_instr,每层嵌套占的本地栈少一些
+            match (instr.opcode.unwrap(),&instr.args) {
+                (opcodes::Block,Some(ArgsEnum::BlockArgs(args)))|(opcodes::Loop,Some(ArgsEnum::BlockArgs(args))) => {
+                    self.validate_block(instr.opcode.unwrap(),args.bt,args.instrs.as_deref().unwrap_or(&[]))?
+                }
+                (opcodes::If,Some(ArgsEnum::IfArgs(args))) => self.validate_if(args)?,
+                (opcodes::TryTable,Some(ArgsEnum::TryTableArgs(args))) => self.validate_try_table(args)?,
+                _ => self.validate_instr(instr)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_block(&mut self,opcode:u8,bt:Option<BlockType>,instrs:&[Instruction]) -> ValidationResult<()>{
+        let idx = self.instr_idx;
+        let (params,results) = self.block_type(bt.unwrap_or(BlockType::Empty))?;
+        self.pop_vals(&params)?;
+        self.push_ctrl(opcode,params,results);
+        self.validate_instrs(instrs)?;
+        self.instr_idx = idx;
+        let frame = self.pop_ctrl()?;
+        self.push_vals(&frame.end_types);
+        Ok(())
+    }
+
+    /// catch子句的标签在try_table外面,跳转时带的值要和标签类型一致
+    fn validate_try_table(&mut self,args:&TryTableArgs) -> ValidationResult<()>{
+        for c in args.catches.iter().flatten() {
+            let mut types = match c.tag {
+                Some(tag) => self.ctx.get_tag_type(tag).map_err(|e|self.locate(e))?.params().to_vec(),
+                None => vec![],
+            };
+            if c.is_ref() {
+                types.push(module::EXN_REF);
+            }
+            let label_types = self.get_label(c.label)?;
+            if label_types != types {
+                let names = |v:&[u8]|v.iter().map(|t|val_type_name(Some(*t))).collect::<Vec<_>>().join(" ");
+                return Err(self.err(ValidationErrorKind::Invalid(format!(
+                    "type mismatch: {} passes [{}] to label with [{}]",c.name(),names(&types),names(&label_types)))));
+            }
+        }
+        self.validate_block(opcodes::TryTable,args.bt,args.instrs.as_deref().unwrap_or(&[]))
+    }
+
+    fn validate_if(&mut self,args:&IfArgs) -> ValidationResult<()>{
+        let idx = self.instr_idx;
+        let (params,results) = self.block_type(args.bt.unwrap_or(BlockType::Empty))?;
+        self.pop_val(Some(I32))?;
+        self.pop_vals(&params)?;
+        self.push_ctrl(opcodes::If,params,results);
+        self.validate_instrs(args.instrs1.as_deref().unwrap_or(&[]))?;
+        self.instr_idx = idx;
+        let frame = self.pop_ctrl()?;
+        // 没有else分支时相当于空的else,要求参数类型和结果类型一致
+        self.push_ctrl(opcodes::Else_,frame.start_types,frame.end_types);
+        self.validate_instrs(args.instrs2.as_deref().unwrap_or(&[]))?;
+        self.instr_idx = idx;
+        let frame = self.pop_ctrl()?;
+        self.push_vals(&frame.end_types);
+        Ok(())
+    }
+
+    fn validate_mem_arg(&self,instr:&Instruction,natural:u32) -> ValidationResult<u8>{
+        match &instr.args {
+            Some(ArgsEnum::MemArg(arg)) => self.check_mem_arg(arg,natural),
+            _ => Err(self.bad_args(instr)),
+        }
+    }
+
+    /// 有内存,对齐不超过自然对齐,返回地址类型
+    fn check_mem_arg(&self,arg:&MemArg,natural:u32) -> ValidationResult<u8>{
+        let addr = self.check_offset(arg)?;
+        let align = arg.align.unwrap_or(0);
+        if align >= 32 || (1u32 << align) > natural {
+            return Err(self.err(ValidationErrorKind::InvalidAlignment(align)));
+        }
+        Ok(addr)
+    }
+
+    /// 32位内存的偏移不能超过u32,返回地址类型
+    fn check_offset(&self,arg:&MemArg) -> ValidationResult<u8>{
+        let addr = self.ctx.get_mem_addr_type(arg.mem.unwrap_or(0)).map_err(|e|self.locate(e))?;
+        let offset = arg.offset.unwrap_or(0);
+        if addr == I32 && offset > u32::MAX as u64 {
+            return Err(self.err(ValidationErrorKind::Invalid(format!("offset {} out of range for 32-bit memory",offset))));
+        }
+        Ok(addr)
+    }
+
+    fn validate_instr(&mut self,instr:&Instruction) -> ValidationResult<()>{
+        let opcode = instr.opcode.unwrap();
+        if let Some((params,result)) = numeric_type(opcode) {
+            self.pop_vals(params)?;
+            self.push_val(Some(result));
+            return Ok(());
+        }
+        if let Some((result,natural)) = load_type(opcode) {
+            let addr = self.validate_mem_arg(instr,natural)?;
+            self.pop_val(Some(addr))?;
+            self.push_val(Some(result));
+            return Ok(());
+        }
+        if let Some((t,natural)) = store_type(opcode) {
+            let addr = self.validate_mem_arg(instr,natural)?;
+            self.pop_val(Some(t))?;
+            self.pop_val(Some(addr))?;
+            return Ok(());
+        }
+        match opcode {
+            opcodes::Unreachable => {self.set_unreachable();}
+            opcodes::Nop => {}
+            opcodes::Block|opcodes::Loop => {
+                match &instr.args {
+                    Some(ArgsEnum::BlockArgs(args)) => self.validate_block(opcode,args.bt,args.instrs.as_deref().unwrap_or(&[]))?,
+                    _ => return Err(self.bad_args(instr)),
+                }
+            }
+            opcodes::If => {
+                match &instr.args {
+                    Some(ArgsEnum::IfArgs(args)) => self.validate_if(args)?,
+                    _ => return Err(self.bad_args(instr)),
+                }
+            }
+            opcodes::TryTable => {
+                match &instr.args {
+                    Some(ArgsEnum::TryTableArgs(args)) => self.validate_try_table(args)?,
+                    _ => return Err(self.bad_args(instr)),
+                }
+            }
+            opcodes::Throw => {
+                let ft = self.ctx.get_tag_type(self.arg_u32(instr)?).map_err(|e|self.locate(e))?;
+                self.pop_vals(ft.params())?;
+                self.set_unreachable();
+            }
+            opcodes::ThrowRef => {
+                self.pop_val(Some(module::EXN_REF))?;
+                self.set_unreachable();
+            }
+            opcodes::Br => {
+                let types = self.get_label(self.arg_u32(instr)?)?;
+                self.pop_vals(&types)?;
+                self.set_unreachable();
+            }
+            opcodes::BrIf => {
+                self.pop_val(Some(I32))?;
+                let types = self.get_label(self.arg_u32(instr)?)?;
+                self.pop_vals(&types)?;
+                self.push_vals(&types);
+            }
+            opcodes::BrTable => {
+                let args = match &instr.args {
+                    Some(ArgsEnum::BrTableArgs(args)) => args,
+                    _ => return Err(self.bad_args(instr)),
+                };
+                self.pop_val(Some(I32))?;
+                let types = self.get_label(args.default.unwrap_or(0))?;
+                for l in args.labels.iter().flatten() {
+                    if self.get_label(*l)? != types {
+                        return Err(self.err(ValidationErrorKind::Invalid("type mismatch: br_table targets have inconsistent types".to_string())));
+                    }
+                }
+                self.pop_vals(&types)?;
+                self.set_unreachable();
+            }
+            opcodes::Return => {
+                let results = self.results.clone();
+                self.pop_vals(&results)?;
+                self.set_unreachable();
+            }
+            opcodes::Call => {
+                let ft = self.ctx.get_func_type(self.arg_u32(instr)?).map_err(|e|self.locate(e))?;
+                self.pop_vals(ft.param_types.as_deref().unwrap_or(&[]))?;
+                self.push_vals(ft.result_types.as_deref().unwrap_or(&[]));
+            }
+            opcodes::CallIndirect|opcodes::ReturnCallIndirect => {
+                let args = match &instr.args {
+                    Some(ArgsEnum::CallIndirectArgs(args)) => args,
+                    _ => return Err(self.bad_args(instr)),
+                };
+                let table_type = self.ctx.get_table_type(args.table.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                if table_type != module::FUNC_REF {
+                    return Err(self.err(ValidationErrorKind::TypeMismatch{ expected: Some(module::FUNC_REF), actual: Some(table_type) }));
+                }
+                let ft = self.ctx.get_type(args.type_idx.unwrap_or(0)).map_err(|e|self.locate(e))?;
+                self.pop_val(Some(I32))?;
+                self.pop_vals(ft.param_types.as_deref().unwrap_or(&[]))?;
+                if opcode == opcodes::ReturnCallIndirect {
+                    self.check_tail_call(ft.results())?;
+                } else {
+                    self.push_vals(ft.result_types.as_deref().unwrap_or(&[]));
+                }
+            }
+            opcodes::ReturnCall => {
+                let ft = self.ctx.get_func_type(self.arg_u32(instr)?).map_err(|e|self.locate(e))?;
+                self.pop_vals(ft.params())?;
+                self.check_tail_call(ft.results())?;
+            }
+            opcodes::Drop => {self.pop_val(None)?;}
+            opcodes::Select => {
+                self.pop_val(Some(I32))?;
+                let t1 = self.pop_val(None)?;
+                let t2 = self.pop_val(t1)?;
+                // 不带类型的select只能选数值
+                if let Some(t) = t1.or(t2).filter(|t|module::is_ref_type(*t)) {
+                    return Err(self.err(ValidationErrorKind::Invalid(format!("type mismatch: select without type on {:#04x}",t))));
+                }
+                self.push_val(t1.or(t2));
+            }
+            opcodes::SelectT => {
+                let t = match &instr.args {
+                    Some(ArgsEnum::U8(t)) => *t,
+                    _ => return Err(self.bad_args(instr)),
+                };
+                self.pop_val(Some(I32))?;
+                self.pop_vals(&[t,t])?;
+                self.push_val(Some(t));
+            }
+            opcodes::TableGet => {
+                let t = self.ctx.get_table_type(self.arg_u32(instr)?).map_err(|e|self.locate(e))?;
+                self.pop_val(Some(I32))?;
+                self.push_val(Some(t));
+            }
+            opcodes::TableSet => {
+                let t = self.ctx.get_table_type(self.arg_u32(instr)?).map_err(|e|self.locate(e))?;
+                self.pop_vals(&[I32,t])?;
+            }
+            opcodes::RefNull => {
+                match &instr.args {
+                    Some(ArgsEnum::U8(t)) => self.push_val(Some(*t)),
+                    _ => return Err(self.bad_args(instr)),
+                }
+            }
+            opcodes::RefIsNull => {
+                if let Some(t) = self.pop_val(None)?.filter(|t|!module::is_ref_type(*t)) {
+                    return Err(self.err(ValidationErrorKind::Invalid(format!("type mismatch: ref.is_null on {:#04x}",t))));
+                }
+                self.push_val(Some(I32));
+            }
+            opcodes::RefFunc => {
+                let idx = self.arg_u32(instr)?;
+                self.ctx.get_func_type(idx).map_err(|e|self.locate(e))?;
+                if !self.ctx.refs.contains(&idx) {
+                    return Err(self.err(ValidationErrorKind::Invalid(format!("undeclared function reference {}",idx))));
+                }
+                self.push_val(Some(module::FUNC_REF));
+            }
+            opcodes::LocalGet => {
+                let t = self.get_local(self.arg_u32(instr)?)?;
+                self.push_val(Some(t));
+            }
+            opcodes::LocalSet => {
+                let t = self.get_local(self.arg_u32(instr)?)?;
+                self.pop_val(Some(t))?;
+            }
+            opcodes::LocalTee => {
+                let t = self.get_local(self.arg_u32(instr)?)?;
+                self.pop_val(Some(t))?;
+                self.push_val(Some(t));
+            }
+            opcodes::GlobalGet => {
+                let g = self.ctx.get_global(self.arg_u32(instr)?).map_err(|e|self.locate(e))?;
+                self.push_val(g.val_type);
+            }
+            opcodes::GlobalSet => {
+                let idx = self.arg_u32(instr)?;
+                let g = self.ctx.get_global(idx).map_err(|e|self.locate(e))?.clone();
+                if g.m != Some(module::MUT_VAR) {
+                    return Err(self.err(ValidationErrorKind::ImmutableGlobal(idx)));
+                }
+                self.pop_val(g.val_type)?;
+            }
+            opcodes::MemorySize => {
+                let addr = self.ctx.get_mem_addr_type(self.arg_u32(instr)?).map_err(|e|self.locate(e))?;
+                self.push_val(Some(addr));
+            }
+            opcodes::MemoryGrow => {
+                let addr = self.ctx.get_mem_addr_type(self.arg_u32(instr)?).map_err(|e|self.locate(e))?;
+                self.pop_val(Some(addr))?;
+                self.push_val(Some(addr));
+            }
+            opcodes::I32Const => {self.push_val(Some(I32));}
+            opcodes::I64Const => {self.push_val(Some(I64));}
+            opcodes::F32Const => {self.push_val(Some(F32));}
+            opcodes::F64Const => {self.push_val(Some(F64));}
+            opcodes::SimdPrefix => {
+                match &instr.args {
+                    Some(ArgsEnum::SimdArgs(args)) => self.validate_simd(instr.sub,args)?,
+                    _ => return Err(self.bad_args(instr)),
+                }
+            }
+            opcodes::AtomicPrefix => {
+                match &instr.args {
+                    Some(ArgsEnum::AtomicArgs(args)) => self.validate_atomic(instr.sub,args)?,
+                    _ => return Err(self.bad_args(instr)),
+                }
+            }
+            opcodes::MiscPrefix => {
+                let (param,result) = match instr.sub {
+                    opcodes::I32TruncSatF32S|opcodes::I32TruncSatF32U => (F32,I32),
+                    opcodes::I32TruncSatF64S|opcodes::I32TruncSatF64U => (F64,I32),
+                    opcodes::I64TruncSatF32S|opcodes::I64TruncSatF32U => (F32,I64),
+                    opcodes::I64TruncSatF64S|opcodes::I64TruncSatF64U => (F64,I64),
+                    _ => return self.check_bulk_memory(instr),
+                };
+                self.pop_val(Some(param))?;
+                self.push_val(Some(result));
+            }
+            _ => {
+                return Err(self.err(ValidationErrorKind::Invalid(format!("unknown instruction:{:#04x}",opcode))));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 数值指令的类型 (参数,结果)
+fn numeric_type(opcode:u8) -> Option<(&'static [u8],u8)>{
+    Some(match opcode {
+        opcodes::I32Eqz => (&[I32],I32),
+        0x46..=0x4F => (&[I32,I32],I32),
+        opcodes::I64Eqz => (&[I64],I32),
+        0x51..=0x5A => (&[I64,I64],I32),
+        0x5B..=0x60 => (&[F32,F32],I32),
+        0x61..=0x66 => (&[F64,F64],I32),
+        0x67..=0x69 => (&[I32],I32),
+        0x6A..=0x78 => (&[I32,I32],I32),
+        0x79..=0x7B => (&[I64],I64),
+        0x7C..=0x8A => (&[I64,I64],I64),
+        0x8B..=0x91 => (&[F32],F32),
+        0x92..=0x98 => (&[F32,F32],F32),
+        0x99..=0x9F => (&[F64],F64),
+        0xA0..=0xA6 => (&[F64,F64],F64),
+        0xA7 => (&[I64],I32),
+        0xA8|0xA9 => (&[F32],I32),
+        0xAA|0xAB => (&[F64],I32),
+        0xAC|0xAD => (&[I32],I64),
+        0xAE|0xAF => (&[F32],I64),
+        0xB0|0xB1 => (&[F64],I64),
+        0xB2|0xB3 => (&[I32],F32),
+        0xB4|0xB5 => (&[I64],F32),
+        0xB6 => (&[F64],F32),
+        0xB7|0xB8 => (&[I32],F64),
+        0xB9|0xBA => (&[I64],F64),
+        0xBB => (&[F32],F64),
+        0xBC => (&[F32],I32),
+        0xBD => (&[F64],I64),
+        0xBE => (&[I32],F32),
+        0xBF => (&[I64],F64),
+        0xC0|0xC1 => (&[I32],I32),
+        0xC2..=0xC4 => (&[I64],I64),
+        _ => return None,
+    })
+}
+
+/// load指令的 (结果类型,自然对齐字节数)
+fn load_type(opcode:u8) -> Option<(u8,u32)>{
+    Some(match opcode {
+        opcodes::I32Load => (I32,4),
+        opcodes::I64Load => (I64,8),
+        opcodes::F32Load => (F32,4),
+        opcodes::F64Load => (F64,8),
+        opcodes::I32Load8S|opcodes::I32Load8U => (I32,1),
+        opcodes::I32Load16S|opcodes::I32Load16U => (I32,2),
+        opcodes::I64Load8S|opcodes::I64Load8U => (I64,1),
+        opcodes::I64Load16S|opcodes::I64Load16U => (I64,2),
+        opcodes::I64Load32S|opcodes::I64Load32U => (I64,4),
+        _ => return None,
+    })
+}
+
+/// store指令的 (值类型,自然对齐字节数)
+fn store_type(opcode:u8) -> Option<(u8,u32)>{
+    Some(match opcode {
+        opcodes::I32Store => (I32,4),
+        opcodes::I64Store => (I64,8),
+        opcodes::F32Store => (F32,4),
+        opcodes::F64Store => (F64,8),
+        opcodes::I32Store8 => (I32,1),
+        opcodes::I32Store16 => (I32,2),
+        opcodes::I64Store8 => (I64,1),
+        opcodes::I64Store16 => (I64,2),
+        opcodes::I64Store32 => (I64,4),
+        _ => return None,
+    })
+}
@@ -0,0 +1,4 @@
+pub mod module_validator;
+pub mod code_validator;
+
+pub use module_validator::validate;
@@ -0,0 +1,797 @@
+use crate::binary::instruction::{ArgsEnum, Expr};
+use crate::binary::module::{self, FuncType, GlobalType, Limits, Module, TableType, TagType, TypeIdx};
+use crate::binary::{opcodes, simd};
+use crate::common::common_error::{ValidationError, ValidationErrorKind};
+use crate::validator::code_validator::CodeValidator;
+use std::collections::HashSet;
+
+pub type ValidationResult<T> = Result<T,ValidationError>;
+
+/// 表的上限最大值
+pub const MAX_TABLE_SIZE:u64 = u32::MAX as u64;
+
+/// 校验整个模块
+/// 通过校验的模块,解释器可以不再检查类型和索引
+pub fn validate(m:&Module) -> ValidationResult<()>{
+    let ctx = ModuleContext::new(m)?;
+    ctx.validate_exports()?;
+    ctx.validate_start()?;
+    ctx.validate_elems()?;
+    ctx.validate_datas()?;
+    ctx.validate_codes()?;
+    Ok(())
+}
+
+fn invalid<T>(msg:String) -> ValidationResult<T>{
+    Err(ValidationError::new(ValidationErrorKind::Invalid(msg)))
+}
+
+/// 模块的索引空间,导入的部分排在前面
+/// globals 在校验全局段的过程中逐个加入,初始化表达式只能引用前面的全局变量
+pub struct ModuleContext<'a>{
+    pub module:&'a Module,
+    pub types:&'a [FuncType],
+    pub funcs:Vec<TypeIdx>,
+    pub tables:Vec<TableType>,
+    pub mems:Vec<Limits>,
+    pub globals:Vec<GlobalType>,
+    /// 标签的函数类型索引
+    pub tags:Vec<TypeIdx>,
+    pub import_func_count:u32,
+    pub import_global_count:u32,
+    /// 在元素段、导出和全局变量里声明过的函数,函数体里的ref.func只能引用这些函数
+    pub refs:HashSet<u32>,
+}
+
+impl<'a> ModuleContext<'a>{
+
+    /// 收集索引空间,同时校验类型段/导入段/函数段/表段/内存段/全局段
+    pub fn new(m:&'a Module) -> ValidationResult<ModuleContext<'a>>{
+        let mut ctx = ModuleContext{
+            module: m,
+            types: m.type_sec.as_deref().unwrap_or(&[]),
+            funcs: vec![],
+            tables: vec![],
+            mems: vec![],
+            globals: vec![],
+            tags: vec![],
+            import_func_count: 0,
+            import_global_count: 0,
+            refs: HashSet::new(),
+        };
+        for import in m.import_sec.iter().flatten() {
+            let desc = match &import.import_desc {
+                Some(desc) => desc,
+                None => return invalid("import has no desc".to_string()),
+            };
+            match desc.tag {
+                Some(module::IMPORT_TAG_FUNC) => {
+                    let idx = desc.fun_type.unwrap_or(0);
+                    ctx.get_type(idx)?;
+                    ctx.funcs.push(idx);
+                    ctx.import_func_count += 1;
+                }
+                Some(module::IMPORT_TAG_TABLE) => {
+                    let t = desc.table.clone().unwrap();
+                    check_table_type(&t)?;
+                    ctx.tables.push(t);
+                }
+                Some(module::IMPORT_TAG_MEM) => {
+                    let mem = desc.mem.clone().unwrap();
+                    check_mem_type(&mem)?;
+                    ctx.mems.push(mem);
+                }
+                Some(module::IMPORT_TAG_GLOBAL) => {
+                    ctx.globals.push(desc.global.clone().unwrap());
+                    ctx.import_global_count += 1;
+                }
+                Some(module::IMPORT_TAG_TAG) => {
+                    let t = desc.tag_type.as_ref().unwrap();
+                    ctx.check_tag_type(t)?;
+                    ctx.tags.push(t.type_idx.unwrap());
+                }
+                tag => return invalid(format!("invalid import tag:{:?}",tag)),
+            }
+        }
+        for idx in m.func_sec.iter().flatten() {
+            ctx.get_type(*idx)?;
+            ctx.funcs.push(*idx);
+        }
+        for t in m.table_sec.iter().flatten() {
+            check_table_type(t)?;
+            ctx.tables.push(t.clone());
+        }
+        for mem in m.mem_sec.iter().flatten() {
+            check_mem_type(mem)?;
+            ctx.mems.push(mem.clone());
+        }
+        for t in m.tag_sec.iter().flatten() {
+            ctx.check_tag_type(t)?;
+            ctx.tags.push(t.type_idx.unwrap());
+        }
+        for g in m.global_sec.iter().flatten() {
+            let ty = g.ty.clone().unwrap();
+            ctx.check_const_expr(g.init.as_ref().unwrap(),ty.val_type.unwrap())?;
+            ctx.globals.push(ty);
+        }
+        ctx.collect_refs();
+        Ok(ctx)
+    }
+
+    fn collect_refs(&mut self){
+        let m = self.module;
+        let mut exprs:Vec<&Expr> = m.global_sec.iter().flatten().filter_map(|g|g.init.as_ref()).collect();
+        for elem in m.elem_sec.iter().flatten() {
+            self.refs.extend(elem.init.iter().flatten());
+            exprs.extend(elem.exprs.iter().flatten());
+        }
+        for instr in exprs.into_iter().flatten() {
+            if let (Some(opcodes::RefFunc),Some(ArgsEnum::U32(idx))) = (instr.opcode,&instr.args) {
+                self.refs.insert(*idx);
+            }
+        }
+        for export in m.export_sec.iter().flatten() {
+            let desc = export.desc.as_ref().unwrap();
+            if desc.tag == Some(module::EXPORT_TAG_FUNC) {
+                self.refs.insert(desc.idx.unwrap());
+            }
+        }
+    }
+
+    pub fn get_type(&self,idx:TypeIdx) -> ValidationResult<&'a FuncType>{
+        self.types.get(idx as usize)
+            .ok_or_else(||ValidationError::new(ValidationErrorKind::UnknownType(idx)))
+    }
+
+    pub fn get_func_type(&self,idx:u32) -> ValidationResult<&'a FuncType>{
+        match self.funcs.get(idx as usize) {
+            Some(t) => self.get_type(*t),
+            None => Err(ValidationError::new(ValidationErrorKind::UnknownFunc(idx))),
+        }
+    }
+
+    pub fn get_global(&self,idx:u32) -> ValidationResult<&GlobalType>{
+        self.globals.get(idx as usize)
+            .ok_or_else(||ValidationError::new(ValidationErrorKind::UnknownGlobal(idx)))
+    }
+
+    /// 标签的函数类型,参数是异常携带的值
+    pub fn get_tag_type(&self,idx:u32) -> ValidationResult<&'a FuncType>{
+        match self.tags.get(idx as usize) {
+            Some(t) => self.get_type(*t),
+            None => Err(ValidationError::new(ValidationErrorKind::UnknownTag(idx))),
+        }
+    }
+
+    /// 标签的类型必须存在且结果为空
+    fn check_tag_type(&self,t:&TagType) -> ValidationResult<()>{
+        let ft = self.get_type(t.type_idx.unwrap_or(0))?;
+        if !ft.results().is_empty() {
+            return invalid("non-empty tag result type".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn check_table(&self,idx:u32) -> ValidationResult<()>{
+        self.get_table(idx).map(|_|())
+    }
+
+    pub fn get_table(&self,idx:u32) -> ValidationResult<&TableType>{
+        self.tables.get(idx as usize)
+            .ok_or_else(||ValidationError::new(ValidationErrorKind::UnknownTable(idx)))
+    }
+
+    /// 表的元素类型
+    pub fn get_table_type(&self,idx:u32) -> ValidationResult<u8>{
+        Ok(self.get_table(idx)?.elem_type.unwrap_or(module::FUNC_REF))
+    }
+
+    pub fn check_mem(&self,idx:u32) -> ValidationResult<()>{
+        if (idx as usize) < self.mems.len() { Ok(()) }
+        else { Err(ValidationError::new(ValidationErrorKind::UnknownMemory(idx))) }
+    }
+
+    /// 内存的地址类型,memory64是i64
+    pub fn get_mem_addr_type(&self,idx:u32) -> ValidationResult<u8>{
+        self.check_mem(idx)?;
+        Ok(self.mems[idx as usize].addr_type())
+    }
+
+    /// memory.init/data.drop引用的数据段,索引按数据计数段检查
+    pub fn check_data(&self,idx:u32) -> ValidationResult<()>{
+        match self.module.data_count_sec {
+            None => Err(ValidationError::new(ValidationErrorKind::DataCountRequired)),
+            Some(n) if idx >= n => Err(ValidationError::new(ValidationErrorKind::UnknownData(idx))),
+            _ => Ok(()),
+        }
+    }
+
+    /// 常量表达式由const/v128.const/ref.null/ref.func、读取导入的或前面定义的不可变全局变量
+    /// 和扩展常量的i32/i64 add/sub/mul组成,最后留下一个expected类型的值
+    pub fn check_const_expr(&self,expr:&Expr,expected:u8) -> ValidationResult<()>{
+        let mismatch = |expected:u8,actual:Option<u8>|ValidationError::new(ValidationErrorKind::TypeMismatch{ expected: Some(expected), actual });
+        let mut stack = vec![];
+        for instr in expr {
+            let t = match (instr.opcode,&instr.args) {
+                (Some(opcodes::I32Const),_) => module::VAL_TYPE_I32,
+                (Some(opcodes::I64Const),_) => module::VAL_TYPE_I64,
+                (Some(opcodes::F32Const),_) => module::VAL_TYPE_F32,
+                (Some(opcodes::F64Const),_) => module::VAL_TYPE_F64,
+                (Some(opcodes::SimdPrefix),Some(ArgsEnum::SimdArgs(args))) if instr.sub == simd::V128Const => module::VAL_TYPE_V128,
+                (Some(opcodes::RefNull),Some(ArgsEnum::U8(t))) => *t,
+                (Some(opcodes::RefFunc),Some(ArgsEnum::U32(idx))) => {
+                    self.get_func_type(*idx)?;
+                    module::FUNC_REF
+                }
+                (Some(opcodes::GlobalGet),Some(ArgsEnum::U32(idx))) => {
+                    let g = self.get_global(*idx)?;
+                    if g.m != Some(module::MUT_CONST) {
+                        return Err(ValidationError::new(ValidationErrorKind::ConstantExprRequired));
+                    }
+                    g.val_type.unwrap()
+                }
+                (Some(op@(opcodes::I32Add|opcodes::I32Sub|opcodes::I32Mul|opcodes::I64Add|opcodes::I64Sub|opcodes::I64Mul)),_) => {
+                    let t = if matches!(op,opcodes::I32Add|opcodes::I32Sub|opcodes::I32Mul) { module::VAL_TYPE_I32 } else { module::VAL_TYPE_I64 };
+                    for _ in 0..2 {
+                        match stack.pop() {
+                            Some(actual) if actual == t => {}
+                            actual => return Err(mismatch(t,actual)),
+                        }
+                    }
+                    t
+                }
+                _ => return Err(ValidationError::new(ValidationErrorKind::ConstantExprRequired)),
+            };
+            stack.push(t);
+        }
+        match stack.as_slice() {
+            [t] if *t == expected => Ok(()),
+            [] => Err(mismatch(expected,None)),
+            [t] => Err(mismatch(expected,Some(*t))),
+            _ => invalid(format!("type mismatch: {} values left in constant expression",stack.len())),
+        }
+    }
+
+    fn validate_exports(&self) -> ValidationResult<()>{
+        let mut names = HashSet::new();
+        for export in self.module.export_sec.iter().flatten() {
+            let name = export.name.clone().unwrap_or_default();
+            if !names.insert(name.clone()) {
+                return Err(ValidationError::new(ValidationErrorKind::DuplicateExport(name)));
+            }
+            let desc = export.desc.as_ref().unwrap();
+            let idx = desc.idx.unwrap();
+            match desc.tag {
+                Some(module::EXPORT_TAG_FUNC) => {self.get_func_type(idx)?;}
+                Some(module::EXPORT_TAG_TABLE) => {self.check_table(idx)?;}
+                Some(module::EXPORT_TAG_MEM) => {self.check_mem(idx)?;}
+                Some(module::EXPORT_TAG_GLOBAL) => {self.get_global(idx)?;}
+                Some(module::EXPORT_TAG_TAG) => {self.get_tag_type(idx)?;}
+                tag => return invalid(format!("invalid export tag:{:?}",tag)),
+            }
+        }
+        Ok(())
+    }
+
+    /// 起始函数必须是 [] -> []
+    fn validate_start(&self) -> ValidationResult<()>{
+        if let Some(idx) = self.module.start_sec {
+            let ft = self.get_func_type(idx)?;
+            if ft.param_types.as_ref().map(|v|v.len()).unwrap_or(0) != 0
+                || ft.result_types.as_ref().map(|v|v.len()).unwrap_or(0) != 0 {
+                return invalid(format!("start function {} must have type [] -> []",idx));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_elems(&self) -> ValidationResult<()>{
+        for elem in self.module.elem_sec.iter().flatten() {
+            let elem_type = elem.get_elem_type();
+            // 被动段和声明段没有表和偏移
+            if let Some(offset) = &elem.offset {
+                let table_type = self.get_table_type(elem.table.unwrap_or(0))?;
+                if table_type != elem_type {
+                    return Err(ValidationError::new(ValidationErrorKind::TypeMismatch{ expected: Some(table_type), actual: Some(elem_type) }));
+                }
+                self.check_const_expr(offset,module::VAL_TYPE_I32)?;
+            }
+            for idx in elem.init.iter().flatten() {
+                self.get_func_type(*idx)?;
+            }
+            for expr in elem.exprs.iter().flatten() {
+                self.check_const_expr(expr,elem_type)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_datas(&self) -> ValidationResult<()>{
+        for data in self.module.data_sec.iter().flatten() {
+            // 被动段没有内存和偏移
+            if let Some(offset) = &data.offset {
+                let addr_type = self.get_mem_addr_type(data.mem.unwrap_or(0))?;
+                self.check_const_expr(offset,addr_type)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_codes(&self) -> ValidationResult<()>{
+        let func_count = self.module.func_sec.as_ref().map(|v|v.len()).unwrap_or(0);
+        let code_count = self.module.code_sec.as_ref().map(|v|v.len()).unwrap_or(0);
+        if func_count != code_count {
+            return invalid(format!("function and code section have inconsistent lengths: {} {}",func_count,code_count));
+        }
+        for (i,code) in self.module.code_sec.iter().flatten().enumerate() {
+            let func_idx = self.import_func_count + i as u32;
+            let expr = code.get_expr().map_err(|e|ValidationError{
+                func_idx: Some(func_idx),
+                instr_idx: None,
+                kind: ValidationErrorKind::Decode(e)
+            })?;
+            CodeValidator::new(self,func_idx,code)?.validate(expr)?;
+        }
+        Ok(())
+    }
+}
+
+/// 表不能共享,也没有64位的表
+fn check_table_type(t:&TableType) -> ValidationResult<()>{
+    let limits = t.limits.as_ref().unwrap();
+    if limits.is_shared() {
+        return Err(ValidationError::new(ValidationErrorKind::InvalidLimits("tables cannot be shared".to_string())));
+    }
+    if limits.is_64() {
+        return Err(ValidationError::new(ValidationErrorKind::InvalidLimits("tables cannot be 64-bit".to_string())));
+    }
+    check_limits(limits,MAX_TABLE_SIZE,"table")
+}
+
+/// 共享内存必须有上限,memory64的页数上限是2^48
+fn check_mem_type(mem:&Limits) -> ValidationResult<()>{
+    if mem.is_shared() && mem.max.is_none() {
+        return Err(ValidationError::new(ValidationErrorKind::InvalidLimits("shared memory must have maximum".to_string())));
+    }
+    let range = if mem.is_64() { module::MAX_PAGE_COUNT_64 } else { module::MAX_PAGE_COUNT as u64 };
+    check_limits(mem,range,"memory")
+}
+
+/// 限制的下限和上限都不能超过range,下限不能大于上限
+pub fn check_limits(limits:&Limits,range:u64,what:&str) -> ValidationResult<()>{
+    let min = limits.min.unwrap_or(0);
+    if min > range {
+        return Err(ValidationError::new(ValidationErrorKind::InvalidLimits(format!("{} min {} exceeds {}",what,min,range))));
+    }
+    if let Some(max) = limits.max {
+        if max > range {
+            return Err(ValidationError::new(ValidationErrorKind::InvalidLimits(format!("{} max {} exceeds {}",what,max,range))));
+        }
+        if min > max {
+            return Err(ValidationError::new(ValidationErrorKind::InvalidLimits(format!("{} min {} larger than max {}",what,min,max))));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test{
+    use crate::binary::instruction::{ArgsEnum, BlockArgs, Instruction};
+    use crate::binary::module::{self, BlockType, Code, Export, ExportDesc, FuncType, GlobalSec, GlobalType, Limits, Locals, Module};
+    use crate::binary::{opcodes, reader};
+    use crate::common::common_error::ValidationErrorKind;
+    use crate::validator::validate;
+
+    fn instr(opcode:u8,args:Option<ArgsEnum>) -> Instruction{
+        Instruction{ opcode: Some(opcode), sub: 0, args }
+    }
+
+    /// 只有一个函数的模块
+    fn func_module(params:Vec<u8>,results:Vec<u8>,locals:Vec<u8>,body:Vec<Instruction>) -> Module{
+        let mut m = Module::new();
+        m.type_sec = Some(vec![FuncType{ tag: Some(module::FT_TAG), param_types: Some(params), result_types: Some(results) }]);
+        m.func_sec = Some(vec![0]);
+        m.code_sec = Some(vec![Code{
+            locals: Some(locals.into_iter().map(|t|Locals{ n: Some(1), ty: Some(t) }).collect()),
+            expr: Some(body),
+            lazy: None
+        }]);
+        m
+    }
+
+    #[test]
+    fn test1(){
+        crate::binary::init();
+        let m = reader::decode_file("./hw_rust.wasm".to_string()).unwrap();
+        validate(&m).unwrap();
+        let data = std::fs::read("./hw_rust.wasm").unwrap();
+        let m = reader::decode_lazy(&data).unwrap();
+        validate(&m).unwrap();
+    }
+
+    #[test]
+    fn test2(){
+        use module::{VAL_TYPE_I32 as I32, VAL_TYPE_I64 as I64};
+
+        // i32.add 的第二个操作数是i64
+        let m = func_module(vec![],vec![I32],vec![],vec![
+            instr(opcodes::I32Const,Some(ArgsEnum::I32(1))),
+            instr(opcodes::I64Const,Some(ArgsEnum::I64(2))),
+            instr(opcodes::I32Add,None),
+        ]);
+        let e = validate(&m).unwrap_err();
+        assert_eq!(e.kind,ValidationErrorKind::TypeMismatch{ expected: Some(I32), actual: Some(I64) });
+        assert_eq!((e.func_idx,e.instr_idx),(Some(0),Some(2)));
+        assert_eq!(e.to_string(),"type mismatch: expected i32 got i64, func 0, instr 2");
+
+        // 局部变量索引越界,参数和局部变量一起算
+        let m = func_module(vec![I32],vec![],vec![I64],vec![
+            instr(opcodes::LocalGet,Some(ArgsEnum::U32(1))),
+            instr(opcodes::Drop,None),
+            instr(opcodes::LocalGet,Some(ArgsEnum::U32(2))),
+        ]);
+        let e = validate(&m).unwrap_err();
+        assert_eq!((e.kind,e.instr_idx),(ValidationErrorKind::UnknownLocal(2),Some(2)));
+
+        // 标签索引越界
+        let m = func_module(vec![],vec![],vec![],vec![instr(opcodes::Br,Some(ArgsEnum::U32(1)))]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::UnknownLabel(1));
+
+        // 块声明了i32结果却什么都没留下,错误指向块指令本身
+        let m = func_module(vec![],vec![],vec![],vec![
+            instr(opcodes::Nop,None),
+            instr(opcodes::Block,Some(ArgsEnum::BlockArgs(BlockArgs{ bt: Some(BlockType::Value(I32)), instrs: Some(vec![instr(opcodes::Nop,None)]) }))),
+        ]);
+        let e = validate(&m).unwrap_err();
+        assert_eq!((e.kind,e.instr_idx),(ValidationErrorKind::TypeMismatch{ expected: Some(I32), actual: None },Some(1)));
+
+        // 函数结束时栈上多了值
+        let m = func_module(vec![],vec![],vec![],vec![instr(opcodes::I32Const,Some(ArgsEnum::I32(1)))]);
+        let e = validate(&m).unwrap_err();
+        assert_eq!((e.kind,e.instr_idx),(ValidationErrorKind::ExtraValues(1),Some(1)));
+
+        // unreachable之后的栈是多态的
+        let m = func_module(vec![],vec![I64],vec![],vec![
+            instr(opcodes::Unreachable,None),
+            instr(opcodes::I32Add,None),
+            instr(opcodes::Drop,None),
+        ]);
+        validate(&m).unwrap();
+
+        // 没有内存时不能访问内存,对齐不能超过自然对齐
+        let load = instr(opcodes::I32Load,Some(ArgsEnum::MemArg(crate::binary::instruction::MemArg{ align: Some(3), offset: Some(0), mem: None })));
+        let mut m = func_module(vec![],vec![I32],vec![],vec![instr(opcodes::I32Const,Some(ArgsEnum::I32(0))),load]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::UnknownMemory(0));
+        m.mem_sec = Some(vec![Limits{ tag: Some(0), min: Some(1), max: None }]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::InvalidAlignment(3));
+    }
+
+    #[test]
+    fn test3(){
+        use module::VAL_TYPE_I32 as I32;
+
+        // 不可变全局变量不能赋值
+        let mut m = func_module(vec![],vec![],vec![],vec![
+            instr(opcodes::I32Const,Some(ArgsEnum::I32(1))),
+            instr(opcodes::GlobalSet,Some(ArgsEnum::U32(0))),
+        ]);
+        m.global_sec = Some(vec![GlobalSec{
+            ty: Some(GlobalType{ val_type: Some(I32), m: Some(module::MUT_CONST) }),
+            init: Some(vec![instr(opcodes::I32Const,Some(ArgsEnum::I32(0)))])
+        }]);
+        let e = validate(&m).unwrap_err();
+        assert_eq!((e.kind,e.func_idx,e.instr_idx),(ValidationErrorKind::ImmutableGlobal(0),Some(0),Some(1)));
+
+        // 初始化表达式必须是常量表达式,类型要对
+        m.global_sec.as_mut().unwrap()[0].ty.as_mut().unwrap().m = Some(module::MUT_VAR);
+        validate(&m).unwrap();
+        m.global_sec.as_mut().unwrap()[0].init = Some(vec![
+            instr(opcodes::I32Const,Some(ArgsEnum::I32(0))),
+            instr(opcodes::I32Const,Some(ArgsEnum::I32(0))),
+            instr(opcodes::I32DivS,None),
+        ]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::ConstantExprRequired);
+        m.global_sec.as_mut().unwrap()[0].init = Some(vec![instr(opcodes::F32Const,Some(ArgsEnum::F32(0.0)))]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::TypeMismatch{ expected: Some(I32), actual: Some(module::VAL_TYPE_F32) });
+        m.global_sec.as_mut().unwrap()[0].init = Some(vec![instr(opcodes::GlobalGet,Some(ArgsEnum::U32(0)))]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::UnknownGlobal(0));
+        m.global_sec = None;
+
+        // 导出名不能重复
+        let export = |name:&str|Export{ name: Some(name.to_string()), desc: Some(ExportDesc{ tag: Some(module::EXPORT_TAG_FUNC), idx: Some(0) }) };
+        m.code_sec.as_mut().unwrap()[0].expr = Some(vec![]);
+        m.export_sec = Some(vec![export("a"),export("b"),export("a")]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::DuplicateExport("a".to_string()));
+        m.export_sec = Some(vec![Export{ name: Some("f".to_string()), desc: Some(ExportDesc{ tag: Some(module::EXPORT_TAG_FUNC), idx: Some(1) }) }]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::UnknownFunc(1));
+        m.export_sec = None;
+
+        // 内存限制
+        m.mem_sec = Some(vec![Limits{ tag: Some(1), min: Some(2), max: Some(1) }]);
+        assert!(matches!(validate(&m).unwrap_err().kind,ValidationErrorKind::InvalidLimits(_)));
+        m.mem_sec = Some(vec![Limits{ tag: Some(0), min: Some(65537), max: None }]);
+        assert!(matches!(validate(&m).unwrap_err().kind,ValidationErrorKind::InvalidLimits(_)));
+        m.mem_sec = None;
+
+        // 函数签名索引越界
+        m.func_sec = Some(vec![1]);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::UnknownType(1));
+    }
+
+    #[test]
+    fn test4(){
+        use crate::binary::instruction::PrefixArgs;
+        use crate::binary::module::Data;
+        use module::VAL_TYPE_I32 as I32;
+
+        // 批量内存指令
+        let i32_const = |v:i32|instr(opcodes::I32Const,Some(ArgsEnum::I32(v)));
+        let data_op = |sub:u32,idx:u32|Instruction::prefixed(opcodes::MiscPrefix,sub,ArgsEnum::PrefixArgs(PrefixArgs{ idx: Some(idx), idx2: None }));
+        let mut m = func_module(vec![],vec![],vec![],vec![
+            i32_const(0), i32_const(0), i32_const(0),
+            data_op(opcodes::MemoryInit,0),
+            data_op(opcodes::DataDrop,0),
+            i32_const(0), i32_const(0), i32_const(0),
+            data_op(opcodes::MemoryCopy,0),
+            i32_const(0), i32_const(0), i32_const(0),
+            data_op(opcodes::MemoryFill,0),
+        ]);
+        m.mem_sec = Some(vec![Limits{ tag: Some(0), min: Some(1), max: None }]);
+        m.data_sec = Some(vec![Data{ mem: None, offset: None, init: Some(vec![1,2,3]) }]);
+        m.data_count_sec = Some(1);
+        validate(&m).unwrap();
+
+        // 没有数据计数段
+        m.data_count_sec = None;
+        let e = validate(&m).unwrap_err();
+        assert_eq!((e.kind,e.instr_idx),(ValidationErrorKind::DataCountRequired,Some(3)));
+        m.data_count_sec = Some(1);
+
+        // 数据段索引越界
+        m.code_sec.as_mut().unwrap()[0].expr.as_mut().unwrap()[4] = data_op(opcodes::DataDrop,1);
+        let e = validate(&m).unwrap_err();
+        assert_eq!((e.kind,e.instr_idx),(ValidationErrorKind::UnknownData(1),Some(4)));
+        m.code_sec.as_mut().unwrap()[0].expr.as_mut().unwrap()[4] = data_op(opcodes::DataDrop,0);
+
+        // 操作数类型
+        m.code_sec.as_mut().unwrap()[0].expr.as_mut().unwrap()[11] = instr(opcodes::F32Const,Some(ArgsEnum::F32(0.0)));
+        let e = validate(&m).unwrap_err();
+        assert_eq!((e.kind,e.instr_idx),(ValidationErrorKind::TypeMismatch{ expected: Some(I32), actual: Some(module::VAL_TYPE_F32) },Some(12)));
+
+        // 没有内存
+        m.mem_sec = None;
+        let e = validate(&m).unwrap_err();
+        assert_eq!((e.kind,e.instr_idx),(ValidationErrorKind::UnknownMemory(0),Some(3)));
+    }
+
+    #[test]
+    fn test5(){
+        use crate::text;
+
+        // 引用类型
+        let check = |wat:&str|validate(&text::parse(wat).unwrap()).map_err(|e|e.kind);
+        check(r#"(module (table 1 externref) (table 1 funcref)
+            (func (param externref) (result i32)
+                i32.const 0 local.get 0 table.set 0
+                i32.const 0 table.get 0 ref.is_null)
+            (func (result funcref) ref.func 0)
+            (elem declare func 0))"#).unwrap();
+        assert_eq!(check(r#"(module (table 1 externref)
+            (func i32.const 0 ref.null func table.set 0))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::EXTERN_REF), actual: Some(module::FUNC_REF) }));
+        assert!(matches!(check(r#"(module (func (result i32) i32.const 0 ref.is_null))"#),
+            Err(ValidationErrorKind::Invalid(_))));
+        // 不带类型的select不能选引用
+        assert!(matches!(check(r#"(module (func (param externref externref) (result externref)
+            local.get 0 local.get 1 i32.const 0 select))"#),Err(ValidationErrorKind::Invalid(_))));
+        // ref.func引用的函数要先声明
+        assert!(matches!(check(r#"(module (func (result funcref) ref.func 0))"#),
+            Err(ValidationErrorKind::Invalid(_))));
+        assert_eq!(check(r#"(module (table 1 funcref) (func (result i32) i32.const 0 table.size 1))"#),
+            Err(ValidationErrorKind::UnknownTable(1)));
+        // call_indirect的表必须是funcref
+        assert!(matches!(check(r#"(module (type (func)) (table 1 externref)
+            (func i32.const 0 call_indirect (type 0)))"#),Err(ValidationErrorKind::TypeMismatch{ .. })));
+        // 元素段的类型要和表一致
+        assert!(matches!(check(r#"(module (table 1 externref) (func) (elem (i32.const 0) func 0))"#),
+            Err(ValidationErrorKind::TypeMismatch{ .. })));
+    }
+
+    #[test]
+    fn test6(){
+        use crate::text;
+
+        // SIMD
+        let check = |wat:&str|validate(&text::parse(wat).unwrap()).map_err(|e|e.kind);
+        check(r#"(module (memory 1) (global v128 (v128.const i64x2 1 2))
+            (func (param v128 i32) (result i32)
+                local.get 0 local.get 1 i8x16.replace_lane 15
+                i32.const 0 v128.load64_splat align=8
+                i8x16.shuffle 0 1 2 3 4 5 6 7 16 17 18 19 20 21 22 31
+                local.get 1 i64x2.shl
+                i32.const 0 local.get 0 v128.store32_lane 3
+                i32x4.extract_lane 0))"#).unwrap();
+        assert_eq!(check(r#"(module (func (param v128) (result i32) local.get 0 i32x4.extract_lane 4))"#),
+            Err(ValidationErrorKind::InvalidLaneIndex(4)));
+        assert_eq!(check(r#"(module (func (param v128) (result v128) local.get 0 local.get 0
+            i8x16.shuffle 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 32))"#),
+            Err(ValidationErrorKind::InvalidLaneIndex(32)));
+        assert_eq!(check(r#"(module (memory 1) (func (result v128) i32.const 0 v128.load32_zero align=8))"#),
+            Err(ValidationErrorKind::InvalidAlignment(3)));
+        assert_eq!(check(r#"(module (func (result v128) i32.const 0 v128.load))"#),
+            Err(ValidationErrorKind::UnknownMemory(0)));
+        assert_eq!(check(r#"(module (func (param v128) (result v128) local.get 0 f32.const 1 f64x2.replace_lane 0))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_F64), actual: Some(module::VAL_TYPE_F32) }));
+        assert_eq!(check(r#"(module (func (param i32) (result v128) local.get 0 local.get 0 i32x4.add))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_V128), actual: Some(module::VAL_TYPE_I32) }));
+    }
+
+    #[test]
+    fn test7(){
+        use crate::text;
+
+        // 共享内存和原子指令
+        let check = |wat:&str|validate(&text::parse(wat).unwrap()).map_err(|e|e.kind);
+        check(r#"(module (memory 1 1 shared)
+            (func (param i32) (result i32)
+                local.get 0 i32.const 1 i64.const -1 memory.atomic.wait32 drop
+                local.get 0 i64.const 0 i64.const 2 i64.atomic.rmw16.cmpxchg_u drop
+                atomic.fence
+                local.get 0 i32.const 1 memory.atomic.notify))"#).unwrap();
+        // 非共享内存也能用原子指令
+        check(r#"(module (memory 1) (func (result i32) i32.const 0 i32.const 1 i32.atomic.rmw8.xchg_u offset=3))"#).unwrap();
+        assert!(matches!(check(r#"(module (memory 1 shared))"#),Err(ValidationErrorKind::InvalidLimits(_))));
+        assert!(matches!(check(r#"(module (table 1 2 shared funcref))"#),Err(ValidationErrorKind::InvalidLimits(_))));
+        assert_eq!(check(r#"(module (memory 1 1 shared) (func (result i64) i32.const 0 i64.atomic.load align=4))"#),
+            Err(ValidationErrorKind::InvalidAtomicAlignment(2)));
+        assert_eq!(check(r#"(module (memory 1 1 shared) (func (result i32) i32.const 0 i32.atomic.load16_u align=4))"#),
+            Err(ValidationErrorKind::InvalidAtomicAlignment(2)));
+        assert_eq!(check(r#"(module (func i32.const 0 i32.const 0 i32.atomic.store))"#),
+            Err(ValidationErrorKind::UnknownMemory(0)));
+        assert_eq!(check(r#"(module (memory 1 1 shared) (func (result i32) i32.const 0 i32.const 0 i32.const 0 memory.atomic.wait32))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I64), actual: Some(module::VAL_TYPE_I32) }));
+    }
+
+    #[test]
+    fn test8(){
+        use crate::text;
+
+        // 尾调用的结果类型必须和当前函数的完全一样,之后的代码不可达
+        let check = |wat:&str|validate(&text::parse(wat).unwrap()).map_err(|e|e.kind);
+        check(r#"(module (table 1 funcref)
+            (func $f (param i32) (result i64) i64.const 0)
+            (func (result i64) i32.const 1 return_call $f i64.add)
+            (func (result i64) i32.const 1 i32.const 0 return_call_indirect (param i32) (result i64)))"#).unwrap();
+        assert_eq!(check(r#"(module (func $f (result i32) i32.const 0) (func (result i64) return_call $f))"#),
+            Err(ValidationErrorKind::Invalid("type mismatch: tail call results [i32] do not match function results [i64]".to_string())));
+        assert!(matches!(check(r#"(module (func $f (result i32 i32) i32.const 0 i32.const 0) (func (result i32) i32.const 1 return_call $f))"#),
+            Err(ValidationErrorKind::Invalid(_))));
+        assert!(matches!(check(r#"(module (table 1 funcref) (func (result i32) i32.const 0 return_call_indirect))"#),
+            Err(ValidationErrorKind::Invalid(_))));
+        assert_eq!(check(r#"(module (func (param i32) (result i32) i64.const 0 return_call 0))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I32), actual: Some(module::VAL_TYPE_I64) }));
+        assert_eq!(check(r#"(module (func return_call 1))"#),Err(ValidationErrorKind::UnknownFunc(1)));
+    }
+
+    #[test]
+    fn test9(){
+        use crate::text;
+
+        // catch的标签类型要和异常的值一致,catch_ref多一个exnref,throw之后不可达
+        let check = |wat:&str|validate(&text::parse(wat).unwrap()).map_err(|e|e.kind);
+        check(r#"(module (tag $e (param i32 i64)) (tag $f (export "f"))
+            (func (result i32)
+                (block $ref (result exnref)
+                    (block $c (result i32 i64)
+                        (try_table (result i32 i64) (catch $e $c) (catch_ref $f $ref) (catch_all_ref $ref)
+                            (throw $e (i32.const 1) (i64.const 2))))
+                    drop return)
+                throw_ref))"#).unwrap();
+        assert_eq!(check(r#"(module (tag $e (param i32 i64)) (tag $f (export "f"))
+            (func (block $ref (result exnref) (try_table (catch_all $ref)) unreachable) drop))"#),
+            Err(ValidationErrorKind::Invalid("type mismatch: catch_all passes [] to label with [exnref]".to_string())));
+        assert_eq!(check(r#"(module (tag $e (param i32)) (func (block (result i64) (try_table (catch $e 0)) unreachable) drop))"#),
+            Err(ValidationErrorKind::Invalid("type mismatch: catch passes [i32] to label with [i64]".to_string())));
+        assert_eq!(check(r#"(module (tag $e (param i32)) (func (throw $e (i64.const 0))))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I32), actual: Some(module::VAL_TYPE_I64) }));
+        assert_eq!(check(r#"(module (func (i32.const 0) throw_ref))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::EXN_REF), actual: Some(module::VAL_TYPE_I32) }));
+        assert_eq!(check(r#"(module (func (throw 0)))"#),Err(ValidationErrorKind::UnknownTag(0)));
+        assert_eq!(check(r#"(module (tag (export "t") (param i32)) (export "u" (tag 1)))"#),Err(ValidationErrorKind::UnknownTag(1)));
+        assert_eq!(check(r#"(module (type (func (result i32))) (tag (type 0)))"#),
+            Err(ValidationErrorKind::Invalid("non-empty tag result type".to_string())));
+    }
+
+    #[test]
+    fn test10(){
+        use crate::text;
+
+        // memory64的地址、memory.size/grow和数据段偏移都是i64
+        let check = |wat:&str|validate(&text::parse(wat).unwrap()).map_err(|e|e.kind);
+        check(r#"(module (memory i64 1 281474976710656) (data (i64.const 8) "hi")
+            (func (param i64) (result i64)
+                (i64.store offset=4294967296 (local.get 0) (i64.load8_u (local.get 0)))
+                (v128.store (local.get 0) (v128.load (local.get 0)))
+                (drop (i32.atomic.rmw.add (local.get 0) (i32.const 1)))
+                (memory.fill (local.get 0) (i32.const 0) (i64.const 4))
+                (memory.copy (local.get 0) (local.get 0) (i64.const 4))
+                (drop (memory.grow (i64.const 1)))
+                memory.size))"#).unwrap();
+        assert_eq!(check(r#"(module (memory i64 1) (func (result i32) (i32.load (i32.const 0))))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I64), actual: Some(module::VAL_TYPE_I32) }));
+        assert_eq!(check(r#"(module (memory i64 1) (func (result i32) memory.size))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I32), actual: Some(module::VAL_TYPE_I64) }));
+        assert_eq!(check(r#"(module (memory i64 1) (data (i32.const 0) "hi"))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I64), actual: Some(module::VAL_TYPE_I32) }));
+        // 32位内存的偏移不能超过u32
+        assert_eq!(check(r#"(module (memory 1) (func (result i32) (i32.load offset=4294967296 (i32.const 0))))"#),
+            Err(ValidationErrorKind::Invalid("offset 4294967296 out of range for 32-bit memory".to_string())));
+        assert!(matches!(check(r#"(module (memory i64 281474976710657))"#),Err(ValidationErrorKind::InvalidLimits(_))));
+        assert!(matches!(check(r#"(module (memory 65537))"#),Err(ValidationErrorKind::InvalidLimits(_))));
+        let mut m = text::parse(r#"(module (table 1 funcref))"#).unwrap();
+        m.table_sec.as_mut().unwrap()[0].limits.as_mut().unwrap().tag = Some(module::LIMITS_MEM64);
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::InvalidLimits("tables cannot be 64-bit".to_string()));
+    }
+
+    #[test]
+    fn test11(){
+        use crate::text;
+
+        // 多内存,每条内存指令按自己的内存索引检查
+        let check = |wat:&str|validate(&text::parse(wat).unwrap()).map_err(|e|e.kind);
+        check(r#"(module (import "env" "m" (memory 1)) (memory $b i64 1) (data (memory $b) (i64.const 0) "hi")
+            (func (result i64)
+                (i64.store $b (i64.const 0) (i64.extend_i32_u (i32.load (i32.const 0))))
+                (memory.copy $b 0 (i64.const 0) (i32.const 0) (i32.const 4))
+                (memory.copy 0 $b (i32.const 0) (i64.const 0) (i32.const 4))
+                (memory.fill $b (i64.const 0) (i32.const 0) (i64.const 4))
+                (drop (memory.grow 0 (i32.const 1)))
+                (memory.size $b)))"#).unwrap();
+        assert_eq!(check(r#"(module (memory 1) (memory i64 1) (func (drop (i32.load 1 (i32.const 0)))))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I64), actual: Some(module::VAL_TYPE_I32) }));
+        // 两个内存地址类型不同时长度是i32
+        assert_eq!(check(r#"(module (memory 1) (memory i64 1) (func (memory.copy 1 0 (i64.const 0) (i32.const 0) (i64.const 4))))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I32), actual: Some(module::VAL_TYPE_I64) }));
+        let mut m = text::parse(r#"(module (memory 1) (func (drop (i32.load (i32.const 0)))))"#).unwrap();
+        if let Some(ArgsEnum::MemArg(arg)) = &mut m.code_sec.as_mut().unwrap()[0].expr.as_mut().unwrap()[1].args {
+            arg.mem = Some(1);
+        }
+        assert_eq!(validate(&m).unwrap_err().kind,ValidationErrorKind::UnknownMemory(1));
+    }
+
+    #[test]
+    fn test12(){
+        use crate::text;
+
+        // 扩展常量表达式,可以读导入的和前面定义的不可变全局变量
+        let check = |wat:&str|validate(&text::parse(wat).unwrap()).map_err(|e|e.kind);
+        check(r#"(module (import "env" "base" (global $base i32)) (memory 1) (table 4 funcref) (func)
+            (global $off i32 (i32.add (global.get $base) (i32.const 16)))
+            (global i64 (i64.sub (i64.mul (i64.const 3) (i64.const 5)) (i64.const 1)))
+            (data (i32.add (global.get $off) (i32.mul (i32.const 2) (i32.const 8))) "hi")
+            (elem (offset (i32.sub (global.get $base) (global.get $base))) func 0))"#).unwrap();
+        assert_eq!(check(r#"(module (global $a (mut i32) (i32.const 0)) (global i32 (global.get $a)))"#),
+            Err(ValidationErrorKind::ConstantExprRequired));
+        assert_eq!(check(r#"(module (global i32 (global.get 1)) (global i32 (i32.const 0)))"#),
+            Err(ValidationErrorKind::UnknownGlobal(1)));
+        assert_eq!(check(r#"(module (global i32 (i32.add (i32.const 0) (i64.const 0))))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I32), actual: Some(module::VAL_TYPE_I64) }));
+        assert_eq!(check(r#"(module (global i64 (i32.mul (i32.const 0) (i32.const 0))))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I64), actual: Some(module::VAL_TYPE_I32) }));
+        assert_eq!(check(r#"(module (global i32 (i32.add (i32.const 0))))"#),
+            Err(ValidationErrorKind::TypeMismatch{ expected: Some(module::VAL_TYPE_I32), actual: None }));
+        assert_eq!(check(r#"(module (memory 1) (data (i32.and (i32.const 0) (i32.const 0)) ""))"#),
+            Err(ValidationErrorKind::ConstantExprRequired));
+        assert!(check(r#"(module (global i32 i32.const 0 i32.const 1))"#).is_err());
+    }
+
+    #[test]
+    fn test13(){
+        // 解码允许的最深嵌套,在测试线程默认大小的栈上也能校验
+        let mut body = vec![];
+        for _ in 0..reader::MAX_BLOCK_DEPTH {
+            body = vec![instr(opcodes::Block,Some(ArgsEnum::BlockArgs(BlockArgs{ bt: Some(BlockType::Empty), instrs: Some(body) })))];
+        }
+        validate(&func_module(vec![],vec![],vec![],body)).unwrap();
+    }
+}